@@ -0,0 +1,229 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::net::IpAddr;
+
+use micro_http::{Method, Peer};
+
+/// Grants the clients it applies to the use of some methods on some paths of the API.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AccessRule {
+    /// The user ID of the clients the rule applies to. When missing, the rule applies to
+    /// clients of any user.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// The group ID of the clients the rule applies to. When missing, the rule applies to
+    /// clients of any group.
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// The IP address of the TCP clients the rule applies to. When missing, the rule applies to
+    /// clients of any address.
+    #[serde(default)]
+    pub ip: Option<IpAddr>,
+    /// The context ID of the vsock clients the rule applies to. When missing, the rule applies
+    /// to clients of any context ID.
+    #[serde(default)]
+    pub cid: Option<u32>,
+    /// The allowed HTTP methods, e.g. `GET`.
+    pub methods: Vec<String>,
+    /// The allowed paths. A path also allows the ones below it, so `/drives` allows
+    /// `/drives/rootfs` too.
+    pub paths: Vec<String>,
+}
+
+impl AccessRule {
+    // Returns `true` if the rule applies to `peer`. Each kind of client is identified
+    // differently: by its user and group for the unix domain socket, by its address for TCP and
+    // by its context ID for vsock. A rule restricted to some identity does not apply to the
+    // clients which can't have it.
+    fn applies_to(&self, peer: &Peer) -> bool {
+        let unix_selectors = self.uid.is_none() && self.gid.is_none();
+        match peer {
+            Peer::Unix(credentials) => {
+                self.uid.map_or(true, |uid| uid == credentials.uid)
+                    && self.gid.map_or(true, |gid| gid == credentials.gid)
+                    && self.ip.is_none()
+                    && self.cid.is_none()
+            }
+            Peer::Tcp(address) => {
+                unix_selectors
+                    && self.cid.is_none()
+                    && self.ip.map_or(true, |ip| ip == address.ip())
+            }
+            Peer::Vsock { cid, .. } => {
+                unix_selectors && self.ip.is_none() && self.cid.map_or(true, |c| c == *cid)
+            }
+        }
+    }
+
+    fn allows(&self, method: Method, path: &str) -> bool {
+        let method = String::from_utf8_lossy(method.raw());
+        self.methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&method))
+            && self.paths.iter().any(|allowed| {
+                let prefix = allowed.trim_end_matches('/');
+                path == allowed
+                    || path == prefix
+                    || (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+            })
+    }
+}
+
+/// Decides which API requests are served, based on the client which sent them. A request
+/// is served if at least one of the rules which apply to its client allows it.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AccessPolicy {
+    /// The access rules.
+    pub rules: Vec<AccessRule>,
+}
+
+impl AccessPolicy {
+    /// Parses an access policy from its JSON description.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Returns `true` if `peer` is allowed to use `method` on `path`.
+    pub fn allows(&self, peer: &Peer, method: Method, path: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.applies_to(peer) && rule.allows(method, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use micro_http::PeerCredentials;
+
+    fn unix_peer(uid: u32, gid: u32) -> Peer {
+        Peer::Unix(PeerCredentials { pid: 1, uid, gid })
+    }
+
+    #[test]
+    fn test_from_json() {
+        let policy = AccessPolicy::from_json(
+            r#"{
+                "rules": [
+                    { "uid": 1000, "methods": ["GET"], "paths": ["/metrics"] },
+                    { "gid": 0, "methods": ["GET", "PUT"], "paths": ["/"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy,
+            AccessPolicy {
+                rules: vec![
+                    AccessRule {
+                        uid: Some(1000),
+                        gid: None,
+                        ip: None,
+                        cid: None,
+                        methods: vec!["GET".to_string()],
+                        paths: vec!["/metrics".to_string()],
+                    },
+                    AccessRule {
+                        uid: None,
+                        gid: Some(0),
+                        ip: None,
+                        cid: None,
+                        methods: vec!["GET".to_string(), "PUT".to_string()],
+                        paths: vec!["/".to_string()],
+                    },
+                ]
+            }
+        );
+
+        assert!(AccessPolicy::from_json(r#"{ "rules": [{ "uid": 1 }] }"#).is_err());
+        assert!(AccessPolicy::from_json(
+            r#"{ "rules": [{ "user": 1, "methods": [], "paths": [] }] }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_allows() {
+        let policy = AccessPolicy {
+            rules: vec![
+                AccessRule {
+                    uid: Some(1000),
+                    gid: None,
+                    ip: None,
+                    cid: None,
+                    methods: vec!["get".to_string()],
+                    paths: vec!["/metrics".to_string(), "/drives/".to_string()],
+                },
+                AccessRule {
+                    uid: Some(0),
+                    gid: Some(0),
+                    ip: None,
+                    cid: None,
+                    methods: vec!["GET".to_string(), "PUT".to_string()],
+                    paths: vec!["/".to_string()],
+                },
+            ],
+        };
+
+        // The monitoring user may only read some paths.
+        let monitor = unix_peer(1000, 1000);
+        assert!(policy.allows(&monitor, Method::Get, "/metrics"));
+        assert!(policy.allows(&monitor, Method::Get, "/drives"));
+        assert!(policy.allows(&monitor, Method::Get, "/drives/rootfs"));
+        assert!(!policy.allows(&monitor, Method::Get, "/metricsfoo"));
+        assert!(!policy.allows(&monitor, Method::Get, "/machine-config"));
+        assert!(!policy.allows(&monitor, Method::Put, "/metrics"));
+
+        // Root may read and write everything, but not patch.
+        let root = unix_peer(0, 0);
+        assert!(policy.allows(&root, Method::Put, "/actions"));
+        assert!(policy.allows(&root, Method::Get, "/"));
+        assert!(!policy.allows(&root, Method::Patch, "/machine-config"));
+        // Both the user and the group have to match.
+        assert!(!policy.allows(&unix_peer(0, 1), Method::Get, "/"));
+        assert!(!policy.allows(&unix_peer(1, 0), Method::Get, "/"));
+
+        // Clients without credentials are only subject to rules which apply to anyone.
+        let tcp_peer = Peer::Tcp("127.0.0.1:4000".parse().unwrap());
+        assert!(!policy.allows(&tcp_peer, Method::Get, "/metrics"));
+        let policy = AccessPolicy {
+            rules: vec![AccessRule {
+                uid: None,
+                gid: None,
+                ip: None,
+                cid: None,
+                methods: vec!["GET".to_string()],
+                paths: vec!["/".to_string()],
+            }],
+        };
+        assert!(policy.allows(&tcp_peer, Method::Get, "/machine-config"));
+        assert!(policy.allows(&monitor, Method::Get, "/machine-config"));
+
+        // TCP clients are selected by their address, and vsock ones by their context ID.
+        let policy = AccessPolicy::from_json(
+            r#"{
+                "rules": [
+                    { "ip": "127.0.0.1", "methods": ["GET"], "paths": ["/"] },
+                    { "cid": 3, "methods": ["GET"], "paths": ["/mmds"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(policy.allows(&tcp_peer, Method::Get, "/machine-config"));
+        let other_tcp_peer = Peer::Tcp("127.0.0.2:4000".parse().unwrap());
+        assert!(!policy.allows(&other_tcp_peer, Method::Get, "/machine-config"));
+        let vsock_peer = Peer::Vsock { cid: 3, port: 1024 };
+        assert!(policy.allows(&vsock_peer, Method::Get, "/mmds"));
+        assert!(!policy.allows(&vsock_peer, Method::Get, "/machine-config"));
+        assert!(!policy.allows(&Peer::Vsock { cid: 4, port: 1024 }, Method::Get, "/mmds"));
+        // These rules don't apply to the clients of the unix domain socket.
+        assert!(!policy.allows(&root, Method::Get, "/"));
+
+        // An empty policy denies everything.
+        assert!(!AccessPolicy::default().allows(&root, Method::Get, "/"));
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
+extern crate libc;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -13,22 +14,31 @@ extern crate seccomp;
 extern crate utils;
 extern crate vmm;
 
+mod access_policy;
+mod openapi;
 mod parsed_request;
 mod request;
+mod schema;
 
+use std::net::{Ipv4Addr, TcpListener};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixListener;
 use std::path::PathBuf;
 use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::{fmt, io};
 
-use logger::{Metric, METRICS};
+pub use access_policy::{AccessPolicy, AccessRule};
+use logger::{Metric, EVENTS, METRICS};
 pub use micro_http::{
     Body, HttpServer, Method, Request, RequestError, Response, ServerError, ServerRequest,
     ServerResponse, StatusCode, Version,
 };
+use micro_http::{Listener, MediaType, Peer, VsockListener};
 use mmds::data_store;
 use mmds::data_store::Mmds;
 use parsed_request::ParsedRequest;
 use seccomp::{BpfProgram, SeccompFilter};
+use utils::epoll;
 use utils::eventfd::EventFd;
 use vmm::rpc_interface::{VmmAction, VmmActionError, VmmData};
 use vmm::vmm_config::instance_info::InstanceInfo;
@@ -38,6 +48,10 @@ pub type ApiRequest = Box<VmmAction>;
 /// Shorthand type for a response containing a boxed Result.
 pub type ApiResponse = Box<std::result::Result<VmmData, VmmActionError>>;
 
+// Tokens of the sources the API thread waits on.
+const HTTP_SERVER_TOKEN: u64 = 0;
+const VMM_EVENTS_TOKEN: u64 = 1;
+
 pub enum Error {
     Io(io::Error),
     Eventfd(io::Error),
@@ -63,6 +77,17 @@ impl fmt::Debug for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The socket on which the API server listens for connections.
+pub enum ApiSocket {
+    /// A Unix domain socket bound to the given path.
+    Unix(PathBuf),
+    /// A TCP socket bound to the given port of the loopback interface.
+    Tcp(u16),
+    /// A vsock socket bound to the given port, on any context ID of the host. Any guest can
+    /// connect to it, so the clients should be selected by an access policy.
+    Vsock(u32),
+}
+
 pub struct ApiServer {
     /// MMDS info directly accessible from the API thread.
     mmds_info: Arc<Mutex<Mmds>>,
@@ -75,6 +100,8 @@ pub struct ApiServer {
     /// FD on which we notify the VMM that we have sent at least one
     /// `VmmRequest`.
     to_vmm_fd: EventFd,
+    /// Restricts the requests each client may send. All requests are served when missing.
+    access_policy: Option<AccessPolicy>,
 }
 
 impl ApiServer {
@@ -91,17 +118,53 @@ impl ApiServer {
             api_request_sender,
             vmm_response_receiver,
             to_vmm_fd,
+            access_policy: None,
         })
     }
 
+    /// Restricts the requests served to the ones `access_policy` allows.
+    pub fn set_access_policy(&mut self, access_policy: AccessPolicy) {
+        self.access_policy = Some(access_policy);
+    }
+
     pub fn bind_and_run(
         &mut self,
-        path: PathBuf,
+        socket: ApiSocket,
         start_time_us: Option<u64>,
         start_time_cpu_us: Option<u64>,
         seccomp_filter: BpfProgram,
     ) -> Result<()> {
-        let mut server = HttpServer::new(path).expect("Error creating the HTTP server");
+        match socket {
+            ApiSocket::Unix(path) => self.run(
+                UnixListener::bind(path).map_err(Error::Io)?,
+                start_time_us,
+                start_time_cpu_us,
+                seccomp_filter,
+            ),
+            ApiSocket::Tcp(port) => self.run(
+                TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(Error::Io)?,
+                start_time_us,
+                start_time_cpu_us,
+                seccomp_filter,
+            ),
+            ApiSocket::Vsock(port) => self.run(
+                VsockListener::bind(port).map_err(Error::Io)?,
+                start_time_us,
+                start_time_cpu_us,
+                seccomp_filter,
+            ),
+        }
+    }
+
+    fn run<L: Listener>(
+        &mut self,
+        listener: L,
+        start_time_us: Option<u64>,
+        start_time_cpu_us: Option<u64>,
+        seccomp_filter: BpfProgram,
+    ) -> Result<()> {
+        let mut server =
+            HttpServer::with_listener(listener).expect("Error creating the HTTP server");
 
         if let Some(start_time) = start_time_us {
             let delta_us =
@@ -121,6 +184,27 @@ impl ApiServer {
                 .add(delta_us as usize);
         }
 
+        // The events of the VMM are sent to the clients of `GET /events` as they come. The
+        // API thread waits for both them and the HTTP server through an epoll structure,
+        // which is created before the seccomp filters forbid it.
+        let events_fd = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::Eventfd)?;
+        EVENTS.subscribe(events_fd.try_clone().map_err(Error::Eventfd)?);
+        let epoll = epoll::Epoll::new().map_err(Error::Io)?;
+        epoll
+            .ctl(
+                epoll::ControlOperation::Add,
+                server.epoll().as_raw_fd(),
+                &epoll::EpollEvent::new(epoll::EventSet::IN, HTTP_SERVER_TOKEN),
+            )
+            .map_err(Error::Io)?;
+        epoll
+            .ctl(
+                epoll::ControlOperation::Add,
+                events_fd.as_raw_fd(),
+                &epoll::EpollEvent::new(epoll::EventSet::IN, VMM_EVENTS_TOKEN),
+            )
+            .map_err(Error::Io)?;
+
         // Load seccomp filters on the API thread.
         // Execution panics if filters cannot be loaded, use --seccomp-level=0 if skipping filters
         // altogether is the desired behaviour.
@@ -132,14 +216,33 @@ impl ApiServer {
         }
 
         server.start_server().unwrap();
+        let mut events = vec![epoll::EpollEvent::default(); 2];
         loop {
+            // Wake up when idle connections time out too, for the server to close them.
+            let event_count = match epoll.wait(events.len(), server.epoll_timeout(), &mut events) {
+                Ok(event_count) => event_count,
+                Err(e) if e.raw_os_error() == Some(libc::EINTR) => continue,
+                Err(e) => return Err(Error::Io(e)),
+            };
+            let vmm_events = events[..event_count]
+                .iter()
+                .any(|event| event.data() == VMM_EVENTS_TOKEN);
+            if vmm_events {
+                self.send_vmm_events(&mut server);
+            }
+            if vmm_events && event_count == 1 {
+                continue;
+            }
+
             match server.requests() {
                 Ok(request_vec) => {
                     for server_request in request_vec {
+                        let peer = *server_request.peer();
                         server
                             .respond(
-                                // Use `self.handle_request()` as the processing callback.
-                                server_request.process(|request| self.handle_request(request)),
+                                // Use `self.handle_request_from()` as the processing callback.
+                                server_request
+                                    .process(|request| self.handle_request_from(&peer, request)),
                             )
                             .or_else(|e| {
                                 error!("API Server encountered an error on response: {}", e);
@@ -157,11 +260,47 @@ impl ApiServer {
         }
     }
 
+    // Sends the events recorded by the VMM to the clients of `GET /events`, as server-sent
+    // events.
+    fn send_vmm_events<L: Listener>(&self, server: &mut HttpServer<L>) {
+        EVENTS.consume(|record| match serde_json::to_string(record) {
+            Ok(json) => {
+                if let Err(e) = server.send_to_streams(format!("data: {}\n\n", json).as_bytes()) {
+                    error!("API Server failed to send an event: {}", e);
+                }
+            }
+            Err(e) => error!("API Server failed to serialize an event: {}", e),
+        });
+    }
+
+    // Serves `request` if the access policy allows `peer` to send it.
+    fn handle_request_from(&self, peer: &Peer, request: &Request) -> Response {
+        if let Some(ref access_policy) = self.access_policy {
+            let path = request.uri().get_abs_path();
+            if !access_policy.allows(peer, request.method(), path) {
+                METRICS.api_server.access_denied_count.inc();
+                warn!(
+                    "Denied the {:?} request on {} sent by {:?}.",
+                    request.method(),
+                    path,
+                    peer
+                );
+                return ApiServer::json_response(
+                    StatusCode::Forbidden,
+                    ApiServer::json_fault_message("The access policy does not allow this request."),
+                );
+            }
+        }
+        self.handle_request(request)
+    }
+
     fn handle_request(&self, request: &Request) -> Response {
         match ParsedRequest::try_from_request(request) {
             Ok(ParsedRequest::Sync(vmm_action)) => self.serve_vmm_action_request(vmm_action),
+            Ok(ParsedRequest::GetEvents) => ApiServer::events_response(),
             Ok(ParsedRequest::GetInstanceInfo) => self.get_instance_info(),
             Ok(ParsedRequest::GetMMDS) => self.get_mmds(),
+            Ok(ParsedRequest::GetOpenApi) => self.get_openapi(),
             Ok(ParsedRequest::PatchMMDS(value)) => self.patch_mmds(value),
             Ok(ParsedRequest::PutMMDS(value)) => self.put_mmds(value),
             Err(e) => {
@@ -209,6 +348,16 @@ impl ApiServer {
         )
     }
 
+    fn get_openapi(&self) -> Response {
+        let version = self
+            .vmm_shared_info
+            .read()
+            .expect("Failed to read shared_info due to poisoned lock")
+            .vmm_version
+            .clone();
+        ApiServer::json_response(StatusCode::OK, openapi::document(&version).to_string())
+    }
+
     fn patch_mmds(&self, value: serde_json::Value) -> Response {
         let mmds_response = self
             .mmds_info
@@ -222,6 +371,10 @@ impl ApiServer {
                     StatusCode::NotFound,
                     ApiServer::json_fault_message(e.to_string()),
                 ),
+                data_store::Error::DataStoreLimitExceeded => ApiServer::json_response(
+                    StatusCode::PayloadTooLarge,
+                    ApiServer::json_fault_message(e.to_string()),
+                ),
                 data_store::Error::UnsupportedValueType => ApiServer::json_response(
                     StatusCode::BadRequest,
                     ApiServer::json_fault_message(e.to_string()),
@@ -238,13 +391,27 @@ impl ApiServer {
             .put_data(value);
         match mmds_response {
             Ok(_) => Response::new(Version::Http11, StatusCode::NoContent),
-            Err(e) => ApiServer::json_response(
-                StatusCode::BadRequest,
-                ApiServer::json_fault_message(e.to_string()),
-            ),
+            Err(e) => match e {
+                data_store::Error::DataStoreLimitExceeded => ApiServer::json_response(
+                    StatusCode::PayloadTooLarge,
+                    ApiServer::json_fault_message(e.to_string()),
+                ),
+                _ => ApiServer::json_response(
+                    StatusCode::BadRequest,
+                    ApiServer::json_fault_message(e.to_string()),
+                ),
+            },
         }
     }
 
+    // The response which starts the stream of the events of the VMM.
+    fn events_response() -> Response {
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_content_type(MediaType::EventStream);
+        response.set_streaming();
+        response
+    }
+
     /// An HTTP response which also includes a body.
     pub fn json_response<T: Into<String>>(status: StatusCode, body: T) -> Response {
         let mut response = Response::new(Version::Http11, status);
@@ -278,7 +445,8 @@ mod tests {
     use std::{fs, thread};
 
     use super::*;
-    use micro_http::HttpConnection;
+    use logger::Event;
+    use micro_http::{HttpConnection, PeerCredentials};
     use mmds::MMDS;
     use vmm::builder::StartMicrovmError;
     use vmm::rpc_interface::VmmActionError;
@@ -314,12 +482,11 @@ mod tests {
 
     #[test]
     fn test_serve_vmm_action_request() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_serve_action_req".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_serve_action_req".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -346,12 +513,11 @@ mod tests {
 
     #[test]
     fn test_get_instance_info() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_get_instance_info".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_get_instance_info".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -373,12 +539,11 @@ mod tests {
 
     #[test]
     fn test_get_mmds() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_get_mmds".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_get_mmds".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -400,12 +565,11 @@ mod tests {
 
     #[test]
     fn test_put_mmds() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_put_mmds".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_put_mmds".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -425,17 +589,16 @@ mod tests {
         assert_eq!(response.status(), StatusCode::NoContent);
 
         let response = api_server.put_mmds(serde_json::Value::Bool(true));
-        assert_eq!(response.status(), StatusCode::BadRequest);
+        assert_eq!(response.status(), StatusCode::NoContent);
     }
 
     #[test]
     fn test_patch_mmds() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_patch_mmds".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_patch_mmds".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -458,17 +621,54 @@ mod tests {
         assert_eq!(response.status(), StatusCode::NoContent);
 
         let response = api_server.patch_mmds(serde_json::Value::Bool(true));
-        assert_eq!(response.status(), StatusCode::BadRequest);
+        assert_eq!(response.status(), StatusCode::NoContent);
+    }
+
+    #[test]
+    fn test_mmds_data_store_limit() {
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_mmds_data_store_limit".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
+
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (_to_api, vmm_response_receiver) = channel();
+        // Use a separate data store, so that the limit does not affect the other tests.
+        let mut mmds = Mmds::default();
+        mmds.set_data_store_limit(10);
+        let mmds_info = Arc::new(Mutex::new(mmds));
+
+        let api_server = ApiServer::new(
+            mmds_info,
+            vmm_shared_info,
+            api_request_sender,
+            vmm_response_receiver,
+            to_vmm_fd,
+        )
+        .unwrap();
+
+        let response = api_server.put_mmds(serde_json::json!({"a": "b"}));
+        assert_eq!(response.status(), StatusCode::NoContent);
+
+        let response = api_server.put_mmds(serde_json::json!({"name": "John"}));
+        assert_eq!(response.status(), StatusCode::PayloadTooLarge);
+
+        let response = api_server.patch_mmds(serde_json::json!({"c": "d"}));
+        assert_eq!(response.status(), StatusCode::PayloadTooLarge);
+
+        let response = api_server.patch_mmds(serde_json::json!({"a": "c"}));
+        assert_eq!(response.status(), StatusCode::NoContent);
     }
 
     #[test]
     fn test_handle_request() {
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_handle_request".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_handle_request".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -561,16 +761,67 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BadRequest);
     }
 
+    #[test]
+    fn test_handle_request_from() {
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_handle_request_from".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
+
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (_to_api, vmm_response_receiver) = channel();
+        let mmds_info = MMDS.clone();
+
+        let mut api_server = ApiServer::new(
+            mmds_info,
+            vmm_shared_info,
+            api_request_sender,
+            vmm_response_receiver,
+            to_vmm_fd,
+        )
+        .unwrap();
+
+        let monitor = Peer::Unix(PeerCredentials {
+            pid: 1,
+            uid: 1000,
+            gid: 1000,
+        });
+        let request = Request::try_from(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        // Without an access policy, every request is served.
+        let response = api_server.handle_request_from(&monitor, &request);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        api_server.set_access_policy(
+            AccessPolicy::from_json(
+                r#"{ "rules": [{ "uid": 1000, "methods": ["GET"], "paths": ["/mmds"] }] }"#,
+            )
+            .unwrap(),
+        );
+        let denied_count = METRICS.api_server.access_denied_count.count();
+        let response = api_server.handle_request_from(&monitor, &request);
+        assert_eq!(response.status(), StatusCode::Forbidden);
+        assert_eq!(
+            METRICS.api_server.access_denied_count.count(),
+            denied_count + 1
+        );
+
+        let request = Request::try_from(b"GET /mmds HTTP/1.1\r\n\r\n").unwrap();
+        let response = api_server.handle_request_from(&monitor, &request);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
     #[test]
     fn test_bind_and_run() {
         let path_to_socket = "/tmp/api_server_test_socket.sock";
         fs::remove_file(path_to_socket).unwrap_or_default();
-        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
-            started: false,
-            id: "test_handle_request".to_string(),
-            vmm_version: "version 0.1.0".to_string(),
-            app_name: "app name".to_string(),
-        }));
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo::new(
+            "test_handle_request".to_string(),
+            "version 0.1.0".to_string(),
+            "app name".to_string(),
+        )));
 
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
@@ -589,7 +840,7 @@ mod tests {
                 )
                 .expect("Cannot create API server")
                 .bind_and_run(
-                    PathBuf::from(path_to_socket.to_string()),
+                    ApiSocket::Unix(PathBuf::from(path_to_socket.to_string())),
                     Some(1),
                     Some(1),
                     SeccompFilter::empty().try_into().unwrap(),
@@ -611,5 +862,26 @@ mod tests {
         assert!(sock.write_all(b"OPTIONS / HTTP/1.1\r\n\r\n").is_ok());
         let mut buf: [u8; 100] = [0; 100];
         assert!(sock.read(&mut buf[..]).unwrap() > 0);
+
+        // Subscribe to the events of the VMM, which are then sent as they are recorded.
+        let mut events_sock =
+            UnixStream::connect(PathBuf::from(path_to_socket.to_string())).unwrap();
+        assert!(events_sock
+            .write_all(b"GET /events HTTP/1.1\r\n\r\n")
+            .is_ok());
+        let expected_response = "HTTP/1.1 200 \r\n\
+                                 Server: Firecracker API\r\n\
+                                 Connection: close\r\n\
+                                 Content-Type: text/event-stream\r\n\r\n";
+        let mut buf = vec![0u8; expected_response.len()];
+        events_sock.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected_response.as_bytes());
+
+        EVENTS.emit(Event::VmmStop { exit_code: 0 });
+        let mut buf: [u8; 100] = [0; 100];
+        let len = events_sock.read(&mut buf[..]).unwrap();
+        let data = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(data.starts_with("data: {\"utc_timestamp_ms\":"));
+        assert!(data.ends_with(",\"event\":\"vmm_stop\",\"exit_code\":0}\n\n"));
     }
 }
@@ -0,0 +1,519 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The OpenAPI (Swagger 2.0) description of the API, served at `GET /openapi.json`.
+//!
+//! The paths are listed by hand, while the schemas of the bodies are generated from the types
+//! the requests are parsed into, so they cannot go out of date.
+
+use micro_http::Method;
+use serde_json::{json, Map, Value};
+
+use request::actions::ActionBody;
+use request::drive::PartialDrive;
+use schema::SchemaGenerator;
+use vmm::resources::VmmConfig;
+use vmm::vmm_config::boot_source::BootSourceConfig;
+use vmm::vmm_config::drive::BlockDeviceConfig;
+use vmm::vmm_config::logger::LoggerConfig;
+use vmm::vmm_config::machine_config::VmConfig;
+use vmm::vmm_config::metrics::MetricsConfig;
+use vmm::vmm_config::mmds::MmdsConfig;
+use vmm::vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceUpdateConfig};
+use vmm::vmm_config::vsock::VsockDeviceConfig;
+
+type BodySchema = fn(&mut SchemaGenerator) -> Value;
+
+// A request accepted by the API server.
+struct Operation {
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    // Builds the schema of the body, for the requests which have one.
+    body: Option<BodySchema>,
+    // The fields which are optional in the type of the body, but required by this request.
+    required: &'static [&'static str],
+}
+
+const fn operation(method: Method, path: &'static str, summary: &'static str) -> Operation {
+    Operation {
+        method,
+        path,
+        summary,
+        body: None,
+        required: &[],
+    }
+}
+
+const fn operation_with_body(
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    body: BodySchema,
+) -> Operation {
+    Operation {
+        method,
+        path,
+        summary,
+        body: Some(body),
+        required: &[],
+    }
+}
+
+// The body of `PUT /vm` is a configuration file, along with a flag to start the microVM.
+fn vm_body(generator: &mut SchemaGenerator) -> Value {
+    json!({"allOf": [
+        generator.schema_for::<VmmConfig>(),
+        {"properties": {"start": {"type": "boolean", "default": false}}}
+    ]})
+}
+
+// The MMDS data store holds arbitrary JSON objects.
+fn mmds_data(_: &mut SchemaGenerator) -> Value {
+    json!({"type": "object"})
+}
+
+const OPERATIONS: &[Operation] = &[
+    operation(
+        Method::Get,
+        "/",
+        "Returns general information about an instance.",
+    ),
+    operation_with_body(
+        Method::Put,
+        "/actions",
+        "Creates a synchronous action.",
+        SchemaGenerator::schema_for::<ActionBody>,
+    ),
+    operation(Method::Get, "/boot-source", "Returns the boot source."),
+    operation_with_body(
+        Method::Put,
+        "/boot-source",
+        "Creates or updates the boot source. Pre-boot only.",
+        SchemaGenerator::schema_for::<BootSourceConfig>,
+    ),
+    operation(Method::Get, "/drives", "Returns the configured drives."),
+    operation(Method::Get, "/drives/{drive_id}", "Returns a drive."),
+    operation_with_body(
+        Method::Put,
+        "/drives/{drive_id}",
+        "Creates or updates a drive. Pre-boot only.",
+        SchemaGenerator::schema_for::<BlockDeviceConfig>,
+    ),
+    operation_with_body(
+        Method::Patch,
+        "/drives/{drive_id}",
+        "Updates the path on the host of a drive. Post-boot only.",
+        SchemaGenerator::schema_for::<PartialDrive>,
+    ),
+    operation(
+        Method::Delete,
+        "/drives/{drive_id}",
+        "Removes a drive. Pre-boot only.",
+    ),
+    operation(
+        Method::Get,
+        "/events",
+        "Streams the lifecycle events of the VMM as server-sent events.",
+    ),
+    operation(Method::Get, "/logger", "Returns the logger configuration."),
+    operation_with_body(
+        Method::Put,
+        "/logger",
+        "Initializes the logger.",
+        SchemaGenerator::schema_for::<LoggerConfig>,
+    ),
+    operation(
+        Method::Get,
+        "/machine-config",
+        "Returns the machine configuration.",
+    ),
+    Operation {
+        required: &["vcpu_count", "mem_size_mib", "ht_enabled"],
+        ..operation_with_body(
+            Method::Put,
+            "/machine-config",
+            "Updates the machine configuration. Pre-boot only.",
+            SchemaGenerator::schema_for::<VmConfig>,
+        )
+    },
+    operation_with_body(
+        Method::Patch,
+        "/machine-config",
+        "Partially updates the machine configuration. Pre-boot only.",
+        SchemaGenerator::schema_for::<VmConfig>,
+    ),
+    operation_with_body(
+        Method::Put,
+        "/metrics",
+        "Initializes the metrics system.",
+        SchemaGenerator::schema_for::<MetricsConfig>,
+    ),
+    operation(Method::Get, "/mmds", "Returns the MMDS data store."),
+    operation_with_body(
+        Method::Put,
+        "/mmds",
+        "Creates the MMDS data store.",
+        mmds_data,
+    ),
+    operation_with_body(
+        Method::Patch,
+        "/mmds",
+        "Updates the MMDS data store.",
+        mmds_data,
+    ),
+    operation_with_body(
+        Method::Put,
+        "/mmds/config",
+        "Configures the MMDS. Pre-boot only.",
+        SchemaGenerator::schema_for::<MmdsConfig>,
+    ),
+    operation(
+        Method::Get,
+        "/network-interfaces",
+        "Returns the configured network interfaces.",
+    ),
+    operation(
+        Method::Get,
+        "/network-interfaces/{iface_id}",
+        "Returns a network interface.",
+    ),
+    operation_with_body(
+        Method::Put,
+        "/network-interfaces/{iface_id}",
+        "Creates a network interface. Pre-boot only.",
+        SchemaGenerator::schema_for::<NetworkInterfaceConfig>,
+    ),
+    operation_with_body(
+        Method::Patch,
+        "/network-interfaces/{iface_id}",
+        "Updates the rate limiters of a network interface. Post-boot only.",
+        SchemaGenerator::schema_for::<NetworkInterfaceUpdateConfig>,
+    ),
+    operation(
+        Method::Delete,
+        "/network-interfaces/{iface_id}",
+        "Removes a network interface. Pre-boot only.",
+    ),
+    operation(
+        Method::Get,
+        "/openapi.json",
+        "Returns this description of the API.",
+    ),
+    operation_with_body(
+        Method::Put,
+        "/vm",
+        "Replaces the whole configuration of the microVM, and optionally starts it. Pre-boot only.",
+        vm_body,
+    ),
+    operation(
+        Method::Get,
+        "/vm/config",
+        "Returns the full configuration of the microVM.",
+    ),
+    operation(Method::Get, "/vsock", "Returns the vsock device."),
+    operation_with_body(
+        Method::Put,
+        "/vsock",
+        "Creates or updates the vsock device. Pre-boot only.",
+        SchemaGenerator::schema_for::<VsockDeviceConfig>,
+    ),
+    operation(
+        Method::Delete,
+        "/vsock",
+        "Removes the vsock device. Pre-boot only.",
+    ),
+];
+
+fn method_name(method: Method) -> &'static str {
+    match method {
+        Method::Get => "get",
+        Method::Put => "put",
+        Method::Patch => "patch",
+        Method::Delete => "delete",
+    }
+}
+
+// The names of the parameters in `path`, such as `drive_id` in `/drives/{drive_id}`.
+fn path_parameters(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|token| token.starts_with('{') && token.ends_with('}'))
+        .map(|token| &token[1..token.len() - 1])
+        .collect()
+}
+
+impl Operation {
+    fn body_schema(&self, generator: &mut SchemaGenerator) -> Option<Value> {
+        self.body.map(|body| {
+            let schema = body(generator);
+            if self.required.is_empty() {
+                schema
+            } else {
+                json!({"allOf": [schema, {"required": self.required}]})
+            }
+        })
+    }
+
+    fn describe(&self, generator: &mut SchemaGenerator) -> Value {
+        let mut parameters: Vec<Value> = path_parameters(self.path)
+            .into_iter()
+            .map(|name| json!({"name": name, "in": "path", "required": true, "type": "string"}))
+            .collect();
+        if let Some(schema) = self.body_schema(generator) {
+            parameters
+                .push(json!({"name": "body", "in": "body", "required": true, "schema": schema}));
+        }
+        let success = if self.method == Method::Get {
+            json!({"200": {"description": "The requested resource."}})
+        } else {
+            json!({"204": {"description": "The request was performed."}})
+        };
+        let mut responses = success.as_object().cloned().unwrap_or_default();
+        responses.insert(
+            "default".to_string(),
+            json!({
+                "description": "The request failed.",
+                "schema": {"$ref": "#/definitions/Error"}
+            }),
+        );
+        json!({
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses,
+        })
+    }
+}
+
+/// Returns the description of the API, for the given version of the VMM.
+pub fn document(version: &str) -> Value {
+    let mut generator = SchemaGenerator::default();
+    let mut paths = Map::new();
+    for operation in OPERATIONS {
+        let description = operation.describe(&mut generator);
+        if let Some(path) = paths
+            .entry(operation.path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+        {
+            path.insert(method_name(operation.method).to_string(), description);
+        }
+    }
+    let mut definitions = generator.into_definitions();
+    definitions.insert(
+        "Error".to_string(),
+        json!({
+            "type": "object",
+            "properties": {"fault_message": {"type": "string"}}
+        }),
+    );
+    json!({
+        "swagger": "2.0",
+        "info": {
+            "title": "Firecracker API",
+            "description": "RESTful public-facing API. The API is accessible through HTTP calls on specific URLs carrying JSON modeled data. The transport medium is a Unix Domain Socket.",
+            "version": version,
+        },
+        "basePath": "/",
+        "consumes": ["application/json"],
+        "produces": ["application/json"],
+        "paths": paths,
+        "definitions": definitions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use micro_http::Request;
+    use parsed_request::ParsedRequest;
+
+    // The value of the path parameters. Ids in the bodies get the same value.
+    const SAMPLE_ID: &str = "sample";
+
+    fn resolve<'a>(schema: &'a Value, definitions: &'a Map<String, Value>) -> &'a Value {
+        match schema["$ref"].as_str() {
+            Some(reference) => &definitions[&reference["#/definitions/".len()..]],
+            None => schema,
+        }
+    }
+
+    // Builds a value matching `schema`, with all the properties of the objects.
+    fn example(schema: &Value, definitions: &Map<String, Value>) -> Value {
+        let schema = resolve(schema, definitions);
+        if let Some(schemas) = schema["allOf"].as_array() {
+            return example(&schemas[0], definitions);
+        }
+        match schema["type"].as_str() {
+            Some("object") => Value::Object(
+                schema["properties"]
+                    .as_object()
+                    .map(|properties| {
+                        properties
+                            .iter()
+                            .map(|(name, property)| (name.clone(), example(property, definitions)))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            ),
+            Some("array") => json!([example(&schema["items"], definitions)]),
+            Some("boolean") => json!(false),
+            Some("integer") | Some("number") => json!(0),
+            Some("string") => schema["enum"]
+                .get(0)
+                .or_else(|| schema.get("example"))
+                .cloned()
+                .unwrap_or_else(|| json!(SAMPLE_ID)),
+            _ => Value::Null,
+        }
+    }
+
+    // The object schema of a body, along with the properties it requires.
+    fn object_schema<'a>(
+        schema: &'a Value,
+        definitions: &'a Map<String, Value>,
+    ) -> (&'a Value, Vec<String>) {
+        let (schema, extra) = match schema["allOf"].as_array() {
+            Some(schemas) => (resolve(&schemas[0], definitions), &schemas[1]["required"]),
+            None => (resolve(schema, definitions), &Value::Null),
+        };
+        let required = schema["required"]
+            .as_array()
+            .into_iter()
+            .chain(extra.as_array())
+            .flatten()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect();
+        (schema, required)
+    }
+
+    fn parse(method: &str, path: &str, body: Option<&Value>) -> bool {
+        let raw = match body {
+            Some(body) => {
+                let body = body.to_string();
+                format!(
+                    "{} {} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    method,
+                    path,
+                    body.len(),
+                    body
+                )
+            }
+            None => format!("{} {} HTTP/1.1\r\n\r\n", method, path),
+        };
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        ParsedRequest::try_from_request(&request).is_ok()
+    }
+
+    #[test]
+    fn test_document() {
+        let document = document("0.1.0");
+        assert_eq!(document["info"]["version"], "0.1.0");
+        assert_eq!(
+            document["paths"]["/drives/{drive_id}"]["put"]["parameters"][1]["schema"],
+            json!({"$ref": "#/definitions/BlockDeviceConfig"})
+        );
+        let drive = &document["definitions"]["BlockDeviceConfig"];
+        assert_eq!(drive["additionalProperties"], false);
+        assert_eq!(drive["properties"]["is_root_device"]["type"], "boolean");
+        assert!(drive["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("path_on_host")));
+        assert_eq!(
+            document["paths"]["/openapi.json"]["get"]["parameters"],
+            json!([])
+        );
+    }
+
+    #[test]
+    fn test_document_matches_parser() {
+        let document = document("0.1.0");
+        let definitions = document["definitions"].as_object().unwrap();
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            let uri = path
+                .replace("{drive_id}", SAMPLE_ID)
+                .replace("{iface_id}", SAMPLE_ID);
+            for (method, operation) in operations.as_object().unwrap() {
+                let method = method.to_uppercase();
+                let parameters = operation["parameters"].as_array().unwrap();
+                let schema = match parameters.iter().find(|p| p["in"] == "body") {
+                    Some(parameter) => &parameter["schema"],
+                    None => {
+                        assert!(parse(&method, &uri, None), "{} {}", method, path);
+                        continue;
+                    }
+                };
+
+                // A body with all the documented properties is accepted.
+                let body = example(schema, definitions);
+                assert!(parse(&method, &uri, Some(&body)), "{} {}", method, path);
+
+                let (object, required) = object_schema(schema, definitions);
+                let properties = match object["properties"].as_object() {
+                    Some(properties) => properties,
+                    None => continue,
+                };
+                // Only the required properties are needed.
+                for name in properties.keys() {
+                    let mut partial = body.clone();
+                    partial.as_object_mut().unwrap().remove(name);
+                    assert_eq!(
+                        parse(&method, &uri, Some(&partial)),
+                        !required.contains(name),
+                        "{} {} without {}",
+                        method,
+                        path,
+                        name
+                    );
+                }
+                // Undocumented properties are rejected.
+                if object["additionalProperties"] == false {
+                    let mut extended = body.clone();
+                    extended
+                        .as_object_mut()
+                        .unwrap()
+                        .insert("undocumented".to_string(), json!(0));
+                    assert!(
+                        !parse(&method, &uri, Some(&extended)),
+                        "{} {}",
+                        method,
+                        path
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_undocumented_methods_are_rejected() {
+        let document = document("0.1.0");
+        let paths = document["paths"].as_object().unwrap();
+        // The resource a path belongs to, such as `drives` for `/drives/{drive_id}`.
+        let resource = |path: &str| path.split('/').nth(1).unwrap_or("").to_string();
+        for path in paths.keys() {
+            let uri = path
+                .replace("{drive_id}", SAMPLE_ID)
+                .replace("{iface_id}", SAMPLE_ID);
+            for method in &["get", "put", "patch", "delete"] {
+                // Skip the methods documented on any path of the resource.
+                if paths.iter().any(|(other, item)| {
+                    resource(other) == resource(path) && item.get(*method).is_some()
+                }) {
+                    continue;
+                }
+                let body = if *method == "put" || *method == "patch" {
+                    Some(json!({}))
+                } else {
+                    None
+                };
+                assert!(
+                    !parse(&method.to_uppercase(), &uri, body.as_ref()),
+                    "{} {}",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
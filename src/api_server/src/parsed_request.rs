@@ -1,30 +1,37 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use serde::Serialize;
 use serde_json::Value;
 
 use super::VmmData;
+use logger::{Metric, METRICS};
 use micro_http::{Body, Method, Request, Response, StatusCode, Version};
 use request::actions::parse_put_actions;
-use request::boot_source::parse_put_boot_source;
-use request::drive::{parse_patch_drive, parse_put_drive};
+use request::boot_source::{parse_get_boot_source, parse_put_boot_source};
+use request::drive::{parse_delete_drive, parse_get_drive, parse_patch_drive, parse_put_drive};
+use request::events::parse_get_events;
 use request::instance_info::parse_get_instance_info;
-use request::logger::parse_put_logger;
+use request::logger::{parse_get_logger, parse_put_logger};
 use request::machine_configuration::{
     parse_get_machine_config, parse_patch_machine_config, parse_put_machine_config,
 };
 use request::metrics::parse_put_metrics;
 use request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
-use request::net::{parse_patch_net, parse_put_net};
-use request::vsock::parse_put_vsock;
+use request::net::{parse_delete_net, parse_get_net, parse_patch_net, parse_put_net};
+use request::openapi::parse_get_openapi;
+use request::vm::{parse_get_vm, parse_put_vm};
+use request::vsock::{parse_delete_vsock, parse_get_vsock, parse_put_vsock};
 use ApiServer;
 
 use vmm::rpc_interface::{VmmAction, VmmActionError};
 
 #[allow(clippy::large_enum_variant)]
 pub enum ParsedRequest {
+    GetEvents,
     GetInstanceInfo,
     GetMMDS,
+    GetOpenApi,
     PatchMMDS(Value),
     PutMMDS(Value),
     Sync(VmmAction),
@@ -47,8 +54,16 @@ impl ParsedRequest {
 
         match (request.method(), path, request.body.as_ref()) {
             (Method::Get, "", None) => parse_get_instance_info(),
+            (Method::Get, "boot-source", None) => parse_get_boot_source(),
+            (Method::Get, "drives", None) => parse_get_drive(path_tokens.get(1)),
+            (Method::Get, "events", None) => parse_get_events(),
+            (Method::Get, "logger", None) => parse_get_logger(),
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
             (Method::Get, "mmds", None) => parse_get_mmds(),
+            (Method::Get, "network-interfaces", None) => parse_get_net(path_tokens.get(1)),
+            (Method::Get, "openapi.json", None) => parse_get_openapi(),
+            (Method::Get, "vm", None) => parse_get_vm(path_tokens.get(1)),
+            (Method::Get, "vsock", None) => parse_get_vsock(),
             (Method::Get, _, Some(_)) => method_to_error(Method::Get),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
@@ -56,10 +71,11 @@ impl ParsedRequest {
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
             (Method::Put, "machine-config", Some(body)) => parse_put_machine_config(body),
             (Method::Put, "metrics", Some(body)) => parse_put_metrics(body),
-            (Method::Put, "mmds", Some(body)) => parse_put_mmds(body),
+            (Method::Put, "mmds", Some(body)) => parse_put_mmds(body, path_tokens.get(1)),
             (Method::Put, "network-interfaces", Some(body)) => {
                 parse_put_net(body, path_tokens.get(1))
             }
+            (Method::Put, "vm", Some(body)) => parse_put_vm(body, path_tokens.get(1)),
             (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
             (Method::Put, _, None) => method_to_error(Method::Put),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.get(1)),
@@ -69,12 +85,37 @@ impl ParsedRequest {
                 parse_patch_net(body, path_tokens.get(1))
             }
             (Method::Patch, _, None) => method_to_error(Method::Patch),
+            (Method::Delete, "drives", None) => parse_delete_drive(path_tokens.get(1)),
+            (Method::Delete, "network-interfaces", None) => parse_delete_net(path_tokens.get(1)),
+            (Method::Delete, "vsock", None) => parse_delete_vsock(),
+            (Method::Delete, _, Some(_)) => method_to_error(Method::Delete),
             (method, unknown_uri, _) => {
                 Err(Error::InvalidPathMethod(unknown_uri.to_string(), method))
             }
         }
     }
 
+    // Builds a `200 OK` response holding `data` serialized as json.
+    fn serialized_response<T: Serialize>(data: &T) -> Response {
+        match serde_json::to_string(data) {
+            Ok(body) => {
+                info!("The request was executed successfully. Status code: 200 OK.");
+                let mut response = Response::new(Version::Http11, StatusCode::OK);
+                response.set_body(Body::new(body));
+                response
+            }
+            Err(e) => {
+                error!(
+                    "Received Error. Status code: 400 Bad Request. Message: {}",
+                    e
+                );
+                let mut response = Response::new(Version::Http11, StatusCode::BadRequest);
+                response.set_body(Body::new(ApiServer::json_fault_message(e.to_string())));
+                response
+            }
+        }
+    }
+
     pub fn convert_to_response(
         request_outcome: std::result::Result<VmmData, VmmActionError>,
     ) -> Response {
@@ -84,12 +125,52 @@ impl ParsedRequest {
                     info!("The request was executed successfully. Status code: 204 No Content.");
                     Response::new(Version::Http11, StatusCode::NoContent)
                 }
+                VmmData::BlockDevice(status) => ParsedRequest::serialized_response(&status),
+                VmmData::BlockDevices(statuses) => ParsedRequest::serialized_response(&statuses),
+                VmmData::BootSource(config) => ParsedRequest::serialized_response(&config),
+                VmmData::Logger(config) => ParsedRequest::serialized_response(&config),
+                VmmData::NetworkInterface(status) => ParsedRequest::serialized_response(&status),
+                VmmData::NetworkInterfaces(statuses) => {
+                    ParsedRequest::serialized_response(&statuses)
+                }
+                VmmData::VsockDevice(status) => ParsedRequest::serialized_response(&status),
                 VmmData::MachineConfiguration(vm_config) => {
                     info!("The request was executed successfully. Status code: 200 OK.");
                     let mut response = Response::new(Version::Http11, StatusCode::OK);
                     response.set_body(Body::new(vm_config.to_string()));
                     response
                 }
+                VmmData::FullVmConfiguration(vmm_config) => {
+                    match serde_json::to_string(&vmm_config) {
+                        Ok(body) => {
+                            info!("The request was executed successfully. Status code: 200 OK.");
+                            let mut response = Response::new(Version::Http11, StatusCode::OK);
+                            response.set_body(Body::new(body));
+                            response
+                        }
+                        Err(e) => {
+                            METRICS.get_api_requests.vm_cfg_fails.inc();
+                            error!(
+                                "Received Error. Status code: 400 Bad Request. Message: {}",
+                                e
+                            );
+                            let mut response =
+                                Response::new(Version::Http11, StatusCode::BadRequest);
+                            response
+                                .set_body(Body::new(ApiServer::json_fault_message(e.to_string())));
+                            response
+                        }
+                    }
+                }
+                VmmData::MmioDeviceLocation(location) => {
+                    info!("The request was executed successfully. Status code: 200 OK.");
+                    let mut response = Response::new(Version::Http11, StatusCode::OK);
+                    response.set_body(Body::new(format!(
+                        "{{\"virtio_mmio_device\":\"{}\"}}",
+                        location
+                    )));
+                    response
+                }
             },
             Err(vmm_action_error) => {
                 error!(
@@ -118,7 +199,7 @@ fn log_received_api_request(api_description: String) {
 ///
 /// # Arguments
 ///
-/// * `method` - one of `GET`, `PATCH`, `PUT`
+/// * `method` - one of `GET`, `PATCH`, `PUT`, `DELETE`
 /// * `path` - path of the API request
 /// * `body` - body of the API request
 fn describe(method: Method, path: &str, body: Option<&Body>) -> String {
@@ -150,6 +231,10 @@ pub fn method_to_error(method: Method) -> Result<ParsedRequest, Error> {
             StatusCode::BadRequest,
             "Empty PATCH request.".to_string(),
         )),
+        Method::Delete => Err(Error::Generic(
+            StatusCode::BadRequest,
+            "DELETE request cannot have a body.".to_string(),
+        )),
     }
 }
 
@@ -230,7 +315,9 @@ mod tests {
 
     use micro_http::HttpConnection;
     use vmm::builder::StartMicrovmError;
+    use vmm::resources::VmResources;
     use vmm::rpc_interface::VmmActionError;
+    use vmm::vmm_config::boot_source::BootSourceConfig;
     use vmm::vmm_config::machine_config::VmConfig;
 
     impl PartialEq for ParsedRequest {
@@ -239,8 +326,10 @@ mod tests {
                 (&ParsedRequest::Sync(ref sync_req), &ParsedRequest::Sync(ref other_sync_req)) => {
                     sync_req == other_sync_req
                 }
+                (&ParsedRequest::GetEvents, &ParsedRequest::GetEvents) => true,
                 (&ParsedRequest::GetInstanceInfo, &ParsedRequest::GetInstanceInfo) => true,
                 (&ParsedRequest::GetMMDS, &ParsedRequest::GetMMDS) => true,
+                (&ParsedRequest::GetOpenApi, &ParsedRequest::GetOpenApi) => true,
                 (&ParsedRequest::PutMMDS(ref val), &ParsedRequest::PutMMDS(ref other_val)) => {
                     val == other_val
                 }
@@ -457,7 +546,7 @@ mod tests {
         assert_eq!(&buf[..], expected_response.as_bytes());
 
         // With Vmm data.
-        let mut buf: [u8; 214] = [0; 214];
+        let mut buf: [u8; 262] = [0; 262];
         let response = ParsedRequest::convert_to_response(Ok(VmmData::MachineConfiguration(
             VmConfig::default(),
         )));
@@ -467,11 +556,68 @@ mod tests {
              Server: Firecracker API\r\n\
              Connection: keep-alive\r\n\
              Content-Type: application/json\r\n\
-             Content-Length: 96\r\n\r\n{}",
+             Content-Length: 143\r\n\r\n{}",
             VmConfig::default().to_string()
         );
         assert_eq!(&buf[..], expected_response.as_bytes());
 
+        // With the configuration of a resource.
+        let boot_source = BootSourceConfig {
+            kernel_image_path: "vmlinux".to_string(),
+            initrd_path: None,
+            boot_args: None,
+        };
+        let body = serde_json::to_string(&boot_source).unwrap();
+        let expected_response = format!(
+            "HTTP/1.1 200 \r\n\
+             Server: Firecracker API\r\n\
+             Connection: keep-alive\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut buf = vec![0; expected_response.len()];
+        let response = ParsedRequest::convert_to_response(Ok(VmmData::BootSource(boot_source)));
+        assert!(response.write_all(&mut buf.as_mut_slice()).is_ok());
+        assert_eq!(&buf[..], expected_response.as_bytes());
+
+        // With the full configuration of the microVM.
+        let vmm_config = VmResources::default().vmm_config();
+        let body = serde_json::to_string(&vmm_config).unwrap();
+        let expected_response = format!(
+            "HTTP/1.1 200 \r\n\
+             Server: Firecracker API\r\n\
+             Connection: keep-alive\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut buf = vec![0; expected_response.len()];
+        let response =
+            ParsedRequest::convert_to_response(Ok(VmmData::FullVmConfiguration(vmm_config)));
+        assert!(response.write_all(&mut buf.as_mut_slice()).is_ok());
+        assert_eq!(&buf[..], expected_response.as_bytes());
+
+        // With the location of a hot-plugged device.
+        let body = "{\"virtio_mmio_device\":\"4K@0xd0000000:5\"}";
+        let expected_response = format!(
+            "HTTP/1.1 200 \r\n\
+             Server: Firecracker API\r\n\
+             Connection: keep-alive\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut buf = vec![0; expected_response.len()];
+        let response = ParsedRequest::convert_to_response(Ok(VmmData::MmioDeviceLocation(
+            "4K@0xd0000000:5".to_string(),
+        )));
+        assert!(response.write_all(&mut buf.as_mut_slice()).is_ok());
+        assert_eq!(&buf[..], expected_response.as_bytes());
+
         // Error.
         let error = VmmActionError::StartMicrovm(StartMicrovmError::MissingKernelConfig);
         let mut buf: [u8; 193] = [0; 193];
@@ -501,6 +647,79 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_get_events() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender.write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::GetEvents) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
+    #[test]
+    fn test_try_from_get_openapi() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /openapi.json HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::GetOpenApi) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
+    #[test]
+    fn test_try_from_get_device_config() {
+        let requests = vec![
+            ("/boot-source", VmmAction::GetBootSource),
+            ("/drives", VmmAction::GetBlockDevices),
+            (
+                "/drives/rootfs",
+                VmmAction::GetBlockDevice("rootfs".to_string()),
+            ),
+            ("/logger", VmmAction::GetLogger),
+            ("/network-interfaces", VmmAction::GetNetworkInterfaces),
+            (
+                "/network-interfaces/eth0",
+                VmmAction::GetNetworkInterface("eth0".to_string()),
+            ),
+            ("/vsock", VmmAction::GetVsockDevice),
+        ];
+        for (path, action) in requests {
+            let (mut sender, receiver) = UnixStream::pair().unwrap();
+            let mut connection = HttpConnection::new(receiver);
+            sender
+                .write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes())
+                .unwrap();
+            assert!(connection.try_read().is_ok());
+            let req = connection.pop_parsed_request().unwrap();
+            assert!(ParsedRequest::try_from_request(&req)
+                .unwrap()
+                .eq(&ParsedRequest::Sync(action)));
+        }
+    }
+
+    #[test]
+    fn test_try_from_get_vm_config() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /vm/config HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req)
+            .unwrap()
+            .eq(&ParsedRequest::Sync(VmmAction::GetFullVmConfiguration)));
+    }
+
     #[test]
     fn test_try_from_get_machine_config() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -668,6 +887,25 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_put_mmds_config() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT /mmds/config HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 33\r\n\r\n{\"ipv4_address\": \"169.254.170.2\"}",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::Sync(VmmAction::SetMmdsConfiguration(_))) => (),
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_try_from_put_netif() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -806,4 +1044,58 @@ mod tests {
         let req = connection.pop_parsed_request().unwrap();
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
+
+    #[test]
+    fn test_try_from_delete_netif() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"DELETE /network-interfaces/string HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveNetworkDevice(iface_id))) => {
+                assert_eq!(iface_id, "string")
+            }
+            _ => panic!("Test failed."),
+        }
+
+        // DELETE requests cannot have a body.
+        sender
+            .write_all(
+                b"DELETE /network-interfaces/string HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 2\r\n\r\n{}",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_err());
+    }
+
+    #[test]
+    fn test_try_from_delete_drive_and_vsock() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"DELETE /drives/string HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveBlockDevice(drive_id))) => {
+                assert_eq!(drive_id, "string")
+            }
+            _ => panic!("Test failed."),
+        }
+
+        sender.write_all(b"DELETE /vsock HTTP/1.1\r\n\r\n").unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveVsockDevice)) => {}
+            _ => panic!("Test failed."),
+        }
+    }
 }
@@ -1,11 +1,14 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::time::Duration;
+
 use super::super::VmmAction;
 use logger::{Metric, METRICS};
-#[cfg(target_arch = "aarch64")]
-use request::StatusCode;
-use request::{Body, Error, ParsedRequest};
+use request::{Body, Error, ParsedRequest, StatusCode};
+
+// How long the guest has to shut down, unless told otherwise.
+const DEFAULT_SHUTDOWN_TIMEOUT_MS: u64 = 10_000;
 
 // The names of the members from this enum must precisely correspond (as a string) to the possible
 // values of "action_type" from the json request body. This is useful to get a strongly typed
@@ -15,14 +18,18 @@ enum ActionType {
     FlushMetrics,
     InstanceStart,
     SendCtrlAltDel,
+    ShutdownGracefully,
 }
 
 // The model of the json body from a sync request. We use Serde to transform each associated
 // json body into this.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
-struct ActionBody {
+pub struct ActionBody {
     action_type: ActionType,
+    // How long `ShutdownGracefully` waits for the guest before stopping the VMM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
 }
 
 pub fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
@@ -46,6 +53,21 @@ pub fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
             #[cfg(target_arch = "x86_64")]
             Ok(ParsedRequest::Sync(VmmAction::SendCtrlAltDel))
         }
+        ActionType::ShutdownGracefully => {
+            let timeout_ms = action_body
+                .timeout_ms
+                .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_MS);
+            if timeout_ms == 0 {
+                METRICS.put_api_requests.actions_fails.inc();
+                return Err(Error::Generic(
+                    StatusCode::BadRequest,
+                    "The shutdown timeout must be greater than 0.".to_string(),
+                ));
+            }
+            Ok(ParsedRequest::Sync(VmmAction::ShutdownGracefully(
+                Duration::from_millis(timeout_ms),
+            )))
+        }
     }
 }
 
@@ -90,6 +112,35 @@ mod tests {
             assert!(result.is_err());
         }
 
+        {
+            let json = r#"{
+                "action_type": "ShutdownGracefully"
+            }"#;
+
+            let req: ParsedRequest = ParsedRequest::Sync(VmmAction::ShutdownGracefully(
+                Duration::from_millis(DEFAULT_SHUTDOWN_TIMEOUT_MS),
+            ));
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.unwrap().eq(&req));
+
+            let json = r#"{
+                "action_type": "ShutdownGracefully",
+                "timeout_ms": 500
+            }"#;
+
+            let req: ParsedRequest =
+                ParsedRequest::Sync(VmmAction::ShutdownGracefully(Duration::from_millis(500)));
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.unwrap().eq(&req));
+
+            let json = r#"{
+                "action_type": "ShutdownGracefully",
+                "timeout_ms": 0
+            }"#;
+
+            assert!(parse_put_actions(&Body::new(json)).is_err());
+        }
+
         {
             let json = r#"{
                 "action_type": "FlushMetrics"
@@ -6,6 +6,11 @@ use logger::{Metric, METRICS};
 use request::{Body, Error, ParsedRequest};
 use vmm::vmm_config::boot_source::BootSourceConfig;
 
+pub fn parse_get_boot_source() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.boot_source_count.inc();
+    Ok(ParsedRequest::Sync(VmmAction::GetBootSource))
+}
+
 pub fn parse_put_boot_source(body: &Body) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.boot_source_count.inc();
     Ok(ParsedRequest::Sync(VmmAction::ConfigureBootSource(
@@ -20,6 +25,14 @@ pub fn parse_put_boot_source(body: &Body) -> Result<ParsedRequest, Error> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_get_boot_source_request() {
+        match parse_get_boot_source() {
+            Ok(ParsedRequest::Sync(VmmAction::GetBootSource)) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_parse_boot_request() {
         assert!(parse_put_boot_source(&Body::new("invalid_payload")).is_err());
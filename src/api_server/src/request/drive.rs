@@ -62,16 +62,23 @@ impl PatchDrivePayload {
             )),
         }
     }
+}
+
+/// The body of a PATCH request on a drive. Only the path on the host can be updated.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialDrive {
+    drive_id: String,
+    path_on_host: String,
+}
 
-    /// Returns the field specified by `field_key` as a string. This is unsafe if validate
-    /// is not called prior to calling this method.
-    fn get_string_field_unchecked(&self, field_key: &str) -> String {
-        self.fields
-            .get(field_key)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string()
+pub fn parse_get_drive(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.drive_count.inc();
+    match id_from_path {
+        Some(id) => Ok(ParsedRequest::Sync(VmmAction::GetBlockDevice(
+            checked_id(id)?.to_string(),
+        ))),
+        None => Ok(ParsedRequest::Sync(VmmAction::GetBlockDevices)),
     }
 }
 
@@ -119,10 +126,13 @@ pub fn parse_patch_drive(body: &Body, id_from_path: Option<&&str>) -> Result<Par
     };
 
     patch_drive_payload.validate()?;
-    let drive_id: String = patch_drive_payload.get_string_field_unchecked("drive_id");
-    let path_on_host: String = patch_drive_payload.get_string_field_unchecked("path_on_host");
+    let partial_drive: PartialDrive =
+        serde_json::from_value(patch_drive_payload.fields).map_err(|e| {
+            METRICS.patch_api_requests.drive_fails.inc();
+            Error::SerdeJson(e)
+        })?;
 
-    if id != drive_id.as_str() {
+    if id != partial_drive.drive_id.as_str() {
         METRICS.patch_api_requests.drive_fails.inc();
         return Err(Error::Generic(
             StatusCode::BadRequest,
@@ -131,8 +141,25 @@ pub fn parse_patch_drive(body: &Body, id_from_path: Option<&&str>) -> Result<Par
     }
 
     Ok(ParsedRequest::Sync(VmmAction::UpdateBlockDevicePath(
-        drive_id,
-        path_on_host,
+        partial_drive.drive_id,
+        partial_drive.path_on_host,
+    )))
+}
+
+pub fn parse_delete_drive(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.delete_api_requests.drive_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id).map_err(|e| {
+            METRICS.delete_api_requests.drive_fails.inc();
+            e
+        })?
+    } else {
+        METRICS.delete_api_requests.drive_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    Ok(ParsedRequest::Sync(VmmAction::RemoveBlockDevice(
+        id.to_string(),
     )))
 }
 
@@ -140,6 +167,21 @@ pub fn parse_patch_drive(body: &Body, id_from_path: Option<&&str>) -> Result<Par
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_get_drive_request() {
+        match parse_get_drive(None) {
+            Ok(ParsedRequest::Sync(VmmAction::GetBlockDevices)) => {}
+            _ => panic!("Test failed."),
+        }
+        match parse_get_drive(Some(&"rootfs")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetBlockDevice(drive_id))) => {
+                assert_eq!(drive_id, "rootfs")
+            }
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_get_drive(Some(&"root-fs")).is_err());
+    }
+
     #[test]
     fn test_parse_patch_drive_request() {
         assert!(parse_patch_drive(&Body::new("invalid_payload"), None).is_err());
@@ -254,6 +296,21 @@ mod tests {
         assert!(parse_put_drive(&Body::new(body), Some(&"foo")).is_err());
     }
 
+    #[test]
+    fn test_parse_delete_drive_request() {
+        // 1. The `id_from_path` cannot be None or invalid.
+        assert!(parse_delete_drive(None).is_err());
+        assert!(parse_delete_drive(Some(&"root-fs")).is_err());
+
+        // 2. Success case.
+        match parse_delete_drive(Some(&"rootfs")) {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveBlockDevice(drive_id))) => {
+                assert_eq!(drive_id, "rootfs")
+            }
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_validate() {
         let pdp = PatchDrivePayload {
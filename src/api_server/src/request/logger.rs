@@ -6,6 +6,11 @@ use logger::{Metric, METRICS};
 use request::{Body, Error, ParsedRequest};
 use vmm::vmm_config::logger::LoggerConfig;
 
+pub fn parse_get_logger() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.logger_count.inc();
+    Ok(ParsedRequest::Sync(VmmAction::GetLogger))
+}
+
 pub fn parse_put_logger(body: &Body) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.logger_count.inc();
     Ok(ParsedRequest::Sync(VmmAction::ConfigureLogger(
@@ -23,6 +28,14 @@ mod tests {
     use super::*;
     use vmm::vmm_config::logger::LoggerLevel;
 
+    #[test]
+    fn test_parse_get_logger_request() {
+        match parse_get_logger() {
+            Ok(ParsedRequest::Sync(VmmAction::GetLogger)) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_parse_put_logger_request() {
         let body = r#"{
@@ -41,6 +41,8 @@ pub fn parse_patch_machine_config(body: &Body) -> Result<ParsedRequest, Error> {
         && vm_config.mem_size_mib.is_none()
         && vm_config.cpu_template.is_none()
         && vm_config.ht_enabled.is_none()
+        && vm_config.reboot_policy.is_none()
+        && vm_config.hotplug_slots.is_none()
     {
         return method_to_error(Method::Patch);
     }
@@ -53,7 +55,7 @@ pub fn parse_patch_machine_config(body: &Body) -> Result<ParsedRequest, Error> {
 mod tests {
     use super::*;
 
-    use vmm::vmm_config::machine_config::CpuFeaturesTemplate;
+    use vmm::vmm_config::machine_config::{CpuFeaturesTemplate, RebootPolicy};
 
     #[test]
     fn test_parse_get_machine_config_request() {
@@ -69,12 +71,16 @@ mod tests {
             mem_size_mib: Some(1024),
             ht_enabled: Some(true),
             cpu_template: Some(CpuFeaturesTemplate::T2),
+            reboot_policy: Some(RebootPolicy::Restart),
+            hotplug_slots: Some(2),
         };
         let body = r#"{
                 "vcpu_count": 8,
                 "mem_size_mib": 1024,
                 "ht_enabled": true,
-                "cpu_template": "T2"
+                "cpu_template": "T2",
+                "reboot_policy": "restart",
+                "hotplug_slots": 2
               }"#;
         match parse_put_machine_config(&Body::new(body)) {
             Ok(ParsedRequest::Sync(VmmAction::SetVmConfiguration(config))) => {
@@ -1,16 +1,30 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use request::{Body, Error, ParsedRequest};
+use super::super::VmmAction;
+use request::{Body, Error, Method, ParsedRequest};
+use vmm::vmm_config::mmds::MmdsConfig;
 
 pub fn parse_get_mmds() -> Result<ParsedRequest, Error> {
     Ok(ParsedRequest::GetMMDS)
 }
 
-pub fn parse_put_mmds(body: &Body) -> Result<ParsedRequest, Error> {
-    Ok(ParsedRequest::PutMMDS(
-        serde_json::from_slice(body.raw()).map_err(Error::SerdeJson)?,
-    ))
+pub fn parse_put_mmds(
+    body: &Body,
+    path_second_token: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    match path_second_token {
+        None => Ok(ParsedRequest::PutMMDS(
+            serde_json::from_slice(body.raw()).map_err(Error::SerdeJson)?,
+        )),
+        Some(&"config") => Ok(ParsedRequest::Sync(VmmAction::SetMmdsConfiguration(
+            serde_json::from_slice::<MmdsConfig>(body.raw()).map_err(Error::SerdeJson)?,
+        ))),
+        Some(&unrecognized) => Err(Error::InvalidPathMethod(
+            format!("/mmds/{}", unrecognized),
+            Method::Put,
+        )),
+    }
 }
 
 pub fn parse_patch_mmds(body: &Body) -> Result<ParsedRequest, Error> {
@@ -33,10 +47,54 @@ mod tests {
         let body = r#"{
                 "foo": "bar"
               }"#;
-        assert!(parse_put_mmds(&Body::new(body)).is_ok());
+        assert!(parse_put_mmds(&Body::new(body), None).is_ok());
 
         let body = "invalid_body";
-        assert!(parse_put_mmds(&Body::new(body)).is_err());
+        assert!(parse_put_mmds(&Body::new(body), None).is_err());
+
+        assert!(parse_put_mmds(&Body::new("{}"), Some(&"foo")).is_err());
+    }
+
+    #[test]
+    fn test_parse_put_mmds_config_request() {
+        use vmm::vmm_config::mmds::MmdsVersion;
+
+        let body = r#"{
+                "version": "V2",
+                "ipv4_address": "169.254.170.2",
+                "ipv6_address": "fe80::a9fe:aa02",
+                "network_interfaces": ["eth0"],
+                "dns_enabled": true
+              }"#;
+        let expected_config = MmdsConfig {
+            version: MmdsVersion::V2,
+            ipv4_address: Some("169.254.170.2".parse().unwrap()),
+            ipv6_address: Some("fe80::a9fe:aa02".parse().unwrap()),
+            network_interfaces: Some(vec!["eth0".to_string()]),
+            dns_enabled: true,
+            sntp_enabled: false,
+        };
+        match parse_put_mmds(&Body::new(body), Some(&"config")) {
+            Ok(ParsedRequest::Sync(VmmAction::SetMmdsConfiguration(config))) => {
+                assert_eq!(config, expected_config)
+            }
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+                "ipv4_address": "not_an_address"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&"config")).is_err());
+
+        let body = r#"{
+                "ipv6_address": "169.254.170.2"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&"config")).is_err());
+
+        let body = r#"{
+                "invalid_field": true
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&"config")).is_err());
     }
 
     #[test]
@@ -44,9 +102,9 @@ mod tests {
         let body = r#"{
                 "foo": "bar"
               }"#;
-        assert!(parse_put_mmds(&Body::new(body)).is_ok());
+        assert!(parse_patch_mmds(&Body::new(body)).is_ok());
 
         let body = "invalid_body";
-        assert!(parse_put_mmds(&Body::new(body)).is_err());
+        assert!(parse_patch_mmds(&Body::new(body)).is_err());
     }
 }
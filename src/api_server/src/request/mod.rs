@@ -4,12 +4,15 @@
 pub mod actions;
 pub mod boot_source;
 pub mod drive;
+pub mod events;
 pub mod instance_info;
 pub mod logger;
 pub mod machine_configuration;
 pub mod metrics;
 pub mod mmds;
 pub mod net;
+pub mod openapi;
+pub mod vm;
 pub mod vsock;
 pub use micro_http::{
     Body, HttpServer, Method, Request, RequestError, Response, StatusCode, Version,
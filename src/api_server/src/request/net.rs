@@ -6,6 +6,16 @@ use logger::{Metric, METRICS};
 use request::{checked_id, Body, Error, ParsedRequest, StatusCode};
 use vmm::vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceUpdateConfig};
 
+pub fn parse_get_net(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.network_count.inc();
+    match id_from_path {
+        Some(id) => Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterface(
+            checked_id(id)?.to_string(),
+        ))),
+        None => Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterfaces)),
+    }
+}
+
 pub fn parse_put_net(body: &Body, id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.network_count.inc();
     let id = if let Some(id) = id_from_path {
@@ -55,12 +65,44 @@ pub fn parse_patch_net(body: &Body, id_from_path: Option<&&str>) -> Result<Parse
     )))
 }
 
+pub fn parse_delete_net(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.delete_api_requests.network_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id).map_err(|e| {
+            METRICS.delete_api_requests.network_fails.inc();
+            e
+        })?
+    } else {
+        METRICS.delete_api_requests.network_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    Ok(ParsedRequest::Sync(VmmAction::RemoveNetworkDevice(
+        id.to_string(),
+    )))
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json;
 
     use super::*;
 
+    #[test]
+    fn test_parse_get_net_request() {
+        match parse_get_net(None) {
+            Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterfaces)) => {}
+            _ => panic!("Test failed."),
+        }
+        match parse_get_net(Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterface(iface_id))) => {
+                assert_eq!(iface_id, "foo")
+            }
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_get_net(Some(&"foo-bar")).is_err());
+    }
+
     #[test]
     fn test_parse_put_net_request() {
         let body = r#"{
@@ -146,4 +188,19 @@ mod tests {
         }"#;
         assert!(parse_patch_net(&Body::new(body), Some(&"foo")).is_err());
     }
+
+    #[test]
+    fn test_parse_delete_net_request() {
+        // 1. The `id_from_path` cannot be None or invalid.
+        assert!(parse_delete_net(None).is_err());
+        assert!(parse_delete_net(Some(&"foo-bar")).is_err());
+
+        // 2. Success case.
+        match parse_delete_net(Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveNetworkDevice(iface_id))) => {
+                assert_eq!(iface_id, "foo")
+            }
+            _ => panic!("Test failed."),
+        }
+    }
 }
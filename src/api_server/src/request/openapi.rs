@@ -0,0 +1,23 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use logger::{Metric, METRICS};
+use request::{Error, ParsedRequest};
+
+pub fn parse_get_openapi() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.openapi_count.inc();
+    Ok(ParsedRequest::GetOpenApi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_get_openapi_request() {
+        match parse_get_openapi() {
+            Ok(ParsedRequest::GetOpenApi) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+}
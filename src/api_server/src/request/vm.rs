@@ -0,0 +1,97 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::VmmAction;
+use logger::{Metric, METRICS};
+use request::{Body, Error, Method, ParsedRequest};
+use vmm::resources::VmmConfig;
+
+/// The body of a PUT request on `/vm`: the configuration of the microVM, in the format accepted
+/// by `--config-file`, and whether to start the microVM once it is configured.
+#[derive(Deserialize)]
+struct VmBody {
+    #[serde(flatten)]
+    config: VmmConfig,
+    #[serde(default)]
+    start: bool,
+}
+
+pub fn parse_get_vm(path_second_token: Option<&&str>) -> Result<ParsedRequest, Error> {
+    match path_second_token {
+        Some(&"config") => {
+            METRICS.get_api_requests.vm_cfg_count.inc();
+            Ok(ParsedRequest::Sync(VmmAction::GetFullVmConfiguration))
+        }
+        Some(&unrecognized) => Err(Error::InvalidPathMethod(
+            format!("/vm/{}", unrecognized),
+            Method::Get,
+        )),
+        None => Err(Error::InvalidPathMethod("/vm".to_string(), Method::Get)),
+    }
+}
+
+pub fn parse_put_vm(body: &Body, path_second_token: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.put_api_requests.vm_count.inc();
+    if let Some(&unrecognized) = path_second_token {
+        METRICS.put_api_requests.vm_fails.inc();
+        return Err(Error::InvalidPathMethod(
+            format!("/vm/{}", unrecognized),
+            Method::Put,
+        ));
+    }
+
+    let vm_body = serde_json::from_slice::<VmBody>(body.raw()).map_err(|e| {
+        METRICS.put_api_requests.vm_fails.inc();
+        Error::SerdeJson(e)
+    })?;
+    Ok(ParsedRequest::Sync(VmmAction::ConfigureMicroVm(
+        vm_body.config,
+        vm_body.start,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_get_vm_request() {
+        match parse_get_vm(Some(&"config")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetFullVmConfiguration)) => {}
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_get_vm(Some(&"invalid")).is_err());
+        assert!(parse_get_vm(None).is_err());
+    }
+
+    #[test]
+    fn test_parse_put_vm_request() {
+        let config = r#"
+            "boot-source": { "kernel_image_path": "vmlinux.bin" },
+            "drives": [{
+                "drive_id": "rootfs",
+                "path_on_host": "rootfs.ext4",
+                "is_root_device": true,
+                "is_read_only": false
+            }]"#;
+
+        let body = format!("{{{}}}", config);
+        match parse_put_vm(&Body::new(body.clone()), None) {
+            Ok(ParsedRequest::Sync(VmmAction::ConfigureMicroVm(_, false))) => {}
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_put_vm(&Body::new(body), Some(&"config")).is_err());
+
+        let body = format!("{{{}, \"start\": true}}", config);
+        match parse_put_vm(&Body::new(body), None) {
+            Ok(ParsedRequest::Sync(VmmAction::ConfigureMicroVm(_, true))) => {}
+            _ => panic!("Test failed."),
+        }
+
+        // The boot source and the drives are mandatory.
+        let body = r#"{ "boot-source": { "kernel_image_path": "vmlinux.bin" } }"#;
+        assert!(parse_put_vm(&Body::new(body), None).is_err());
+        let body = format!("{{{}, \"start\": \"yes\"}}", config);
+        assert!(parse_put_vm(&Body::new(body), None).is_err());
+    }
+}
@@ -2,9 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::super::VmmAction;
+use logger::{Metric, METRICS};
 use request::{Body, Error, ParsedRequest};
 use vmm::vmm_config::vsock::VsockDeviceConfig;
 
+pub fn parse_get_vsock() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.vsock_count.inc();
+    Ok(ParsedRequest::Sync(VmmAction::GetVsockDevice))
+}
+
+pub fn parse_delete_vsock() -> Result<ParsedRequest, Error> {
+    METRICS.delete_api_requests.vsock_count.inc();
+    Ok(ParsedRequest::Sync(VmmAction::RemoveVsockDevice))
+}
+
 pub fn parse_put_vsock(body: &Body) -> Result<ParsedRequest, Error> {
     Ok(ParsedRequest::Sync(VmmAction::SetVsockDevice(
         serde_json::from_slice::<VsockDeviceConfig>(body.raw()).map_err(Error::SerdeJson)?,
@@ -15,6 +26,22 @@ pub fn parse_put_vsock(body: &Body) -> Result<ParsedRequest, Error> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_get_vsock_request() {
+        match parse_get_vsock() {
+            Ok(ParsedRequest::Sync(VmmAction::GetVsockDevice)) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
+    #[test]
+    fn test_parse_delete_vsock_request() {
+        match parse_delete_vsock() {
+            Ok(ParsedRequest::Sync(VmmAction::RemoveVsockDevice)) => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_parse_put_vsock_request() {
         let body = r#"{
@@ -0,0 +1,594 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Builds the JSON schemas of the request bodies out of their `Deserialize` implementations.
+//!
+//! A body type is deserialized from a tracing `Deserializer`, which records the shape of every
+//! value serde asks for and hands out sample values. The fields are then omitted one at a time
+//! to find out which ones are required, and an unknown field is added to every object to find
+//! out whether it is rejected. The schemas thus describe exactly what the API server accepts.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde_json::{json, Map, Value};
+
+// The name of the field added to objects to check that unknown fields are rejected.
+const UNKNOWN_FIELD: &str = "x-unknown-field";
+
+// String values handed out to the body types, in order. When a string is rejected, the next
+// one is tried; the one that is accepted becomes the example of the schema.
+const STRING_SAMPLES: &[&str] = &[
+    "sample",
+    "06:00:00:00:00:01",
+    "169.254.169.254",
+    "fe80::a9fe:a9fe",
+];
+
+#[derive(Debug)]
+struct TraceError(String);
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, TraceError>;
+
+// The shape of a value, as requested by the `Deserialize` implementation of its type.
+#[derive(Clone)]
+enum Shape {
+    Any,
+    Enum(&'static [&'static str]),
+    Map,
+    Option,
+    Primitive(Value),
+    Seq,
+    String,
+    Struct(&'static str, &'static [&'static str]),
+}
+
+#[derive(Default)]
+struct Trace {
+    // The shapes of the values, by path.
+    shapes: HashMap<String, Shape>,
+    // The index in `STRING_SAMPLES` of the value handed out for a string, by path.
+    samples: HashMap<String, usize>,
+    // The path of the last string handed out that was not accepted yet.
+    pending_string: Option<String>,
+    // The path of a struct field to leave out.
+    skip: Option<String>,
+    // The path of an object to add an unknown field to.
+    extend: Option<String>,
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+struct Tracer<'a> {
+    trace: &'a mut Trace,
+    path: String,
+}
+
+impl<'a> Tracer<'a> {
+    fn record(&mut self, shape: Shape) {
+        self.trace.shapes.insert(self.path.clone(), shape);
+    }
+
+    fn primitive(&mut self, schema: Value) {
+        self.record(Shape::Primitive(schema));
+    }
+
+    fn sample_string(&mut self) -> &'static str {
+        self.record(Shape::String);
+        self.trace.pending_string = Some(self.path.clone());
+        STRING_SAMPLES[*self.trace.samples.get(&self.path).unwrap_or(&0)]
+    }
+}
+
+macro_rules! trace_unsigned {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+                let maximum = <$ty>::max_value();
+                self.primitive(json!({"type": "integer", "minimum": 0, "maximum": maximum}));
+                visitor.$visit(0)
+            }
+        )*
+    };
+}
+
+macro_rules! trace_signed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+                self.primitive(json!({"type": "integer"}));
+                visitor.$visit(0)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Tracer<'a> {
+    type Error = TraceError;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.record(Shape::Any);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.primitive(json!({"type": "boolean"}));
+        visitor.visit_bool(false)
+    }
+
+    trace_unsigned! {
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+    }
+
+    trace_signed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.primitive(json!({"type": "number"}));
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.primitive(json!({"type": "number"}));
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.primitive(json!({"type": "string", "minLength": 1, "maxLength": 1}));
+        visitor.visit_char('a')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        visitor.visit_str(self.sample_string())
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.sample_string().to_string())
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        visitor.visit_str(self.sample_string())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.sample_string().to_string())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.record(Shape::Option);
+        let path = format!("{}?", self.path);
+        visitor.visit_some(Tracer {
+            trace: self.trace,
+            path,
+        })
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.primitive(json!({"type": "null"}));
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.record(Shape::Seq);
+        let path = format!("{}[]", self.path);
+        visitor.visit_seq(SeqTracer {
+            trace: self.trace,
+            path,
+            done: false,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        self.record(Shape::Map);
+        visitor.visit_map(MapTracer::new(self.trace, self.path, &[]))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        mut self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.record(Shape::Struct(name, fields));
+        visitor.visit_map(MapTracer::new(self.trace, self.path, fields))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        mut self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.record(Shape::Enum(variants));
+        visitor.visit_enum(EnumTracer {
+            variant: variants.first().cloned().unwrap_or_default(),
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+// Hands out a single element.
+struct SeqTracer<'a> {
+    trace: &'a mut Trace,
+    path: String,
+    done: bool,
+}
+
+impl<'de, 'a> SeqAccess<'de> for SeqTracer<'a> {
+    type Error = TraceError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        seed.deserialize(Tracer {
+            trace: &mut *self.trace,
+            path: self.path.clone(),
+        })
+        .map(Some)
+    }
+}
+
+// Hands out the fields of a struct, leaving out the one to skip and adding an unknown field
+// if the object is the one to extend.
+struct MapTracer<'a> {
+    trace: &'a mut Trace,
+    path: String,
+    keys: Vec<&'static str>,
+    next: usize,
+}
+
+impl<'a> MapTracer<'a> {
+    fn new(trace: &'a mut Trace, path: String, fields: &'static [&'static str]) -> Self {
+        let mut keys: Vec<&'static str> = fields
+            .iter()
+            .cloned()
+            .filter(|field| trace.skip.as_ref() != Some(&child_path(&path, field)))
+            .collect();
+        if trace.extend.as_ref() == Some(&path) {
+            keys.push(UNKNOWN_FIELD);
+        }
+        MapTracer {
+            trace,
+            path,
+            keys,
+            next: 0,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for MapTracer<'a> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.keys.get(self.next) {
+            Some(key) => seed.deserialize(key.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let path = child_path(&self.path, self.keys[self.next]);
+        self.next += 1;
+        let value = seed.deserialize(Tracer {
+            trace: &mut *self.trace,
+            path,
+        })?;
+        // The value was accepted, so the string samples handed out for it are valid.
+        self.trace.pending_string = None;
+        Ok(value)
+    }
+}
+
+// Picks the first variant of an enum.
+struct EnumTracer {
+    variant: &'static str,
+}
+
+impl<'de> EnumAccess<'de> for EnumTracer {
+    type Error = TraceError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let variant: de::value::StrDeserializer<TraceError> = self.variant.into_deserializer();
+        seed.deserialize(variant).map(|value| (value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for EnumTracer {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, _seed: T) -> Result<T::Value> {
+        Err(de::Error::custom("only unit variants are supported"))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value> {
+        Err(de::Error::custom("only unit variants are supported"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value> {
+        Err(de::Error::custom("only unit variants are supported"))
+    }
+}
+
+/// Builds the schemas of body types, sharing the definitions of the objects they contain.
+#[derive(Default)]
+pub struct SchemaGenerator {
+    definitions: Map<String, Value>,
+}
+
+impl SchemaGenerator {
+    /// Returns the schema of the values accepted by the `Deserialize` implementation of `T`.
+    /// Objects are referenced from the definitions.
+    pub fn schema_for<T: DeserializeOwned>(&mut self) -> Value {
+        let mut trace = Trace::default();
+        while let Err(e) = T::deserialize(Tracer {
+            trace: &mut trace,
+            path: String::new(),
+        }) {
+            // Try the next sample for a rejected string.
+            let path = trace.pending_string.take().unwrap_or_else(|| {
+                panic!("Cannot derive a schema: {}", e);
+            });
+            let sample = trace.samples.entry(path).or_insert(0);
+            *sample += 1;
+            if *sample == STRING_SAMPLES.len() {
+                panic!("Cannot derive a schema: {}", e);
+            }
+        }
+        let shapes = trace.shapes.clone();
+        self.schema_at::<T>(&mut trace, &shapes, "")
+    }
+
+    /// Returns the definitions of the objects referenced by the schemas.
+    pub fn into_definitions(self) -> Map<String, Value> {
+        self.definitions
+    }
+
+    fn schema_at<T: DeserializeOwned>(
+        &mut self,
+        trace: &mut Trace,
+        shapes: &HashMap<String, Shape>,
+        path: &str,
+    ) -> Value {
+        match shapes.get(path).cloned().unwrap_or(Shape::Any) {
+            Shape::Any => json!({}),
+            Shape::Enum(variants) => json!({"type": "string", "enum": variants}),
+            Shape::Map => json!({"type": "object"}),
+            Shape::Option => self.schema_at::<T>(trace, shapes, &format!("{}?", path)),
+            Shape::Primitive(schema) => schema,
+            Shape::Seq => json!({
+                "type": "array",
+                "items": self.schema_at::<T>(trace, shapes, &format!("{}[]", path)),
+            }),
+            Shape::String => match trace.samples.get(path) {
+                Some(&sample) if sample > 0 => {
+                    json!({"type": "string", "example": STRING_SAMPLES[sample]})
+                }
+                _ => json!({"type": "string"}),
+            },
+            Shape::Struct(name, fields) => {
+                if !self.definitions.contains_key(name) {
+                    // Reserve the name, in case the struct contains itself.
+                    self.definitions.insert(name.to_string(), Value::Null);
+                    let definition = self.struct_schema::<T>(trace, shapes, path, fields);
+                    self.definitions.insert(name.to_string(), definition);
+                }
+                json!({ "$ref": format!("#/definitions/{}", name) })
+            }
+        }
+    }
+
+    fn struct_schema<T: DeserializeOwned>(
+        &mut self,
+        trace: &mut Trace,
+        shapes: &HashMap<String, Shape>,
+        path: &str,
+        fields: &'static [&'static str],
+    ) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for field in fields {
+            let field_path = child_path(path, field);
+            properties.insert(
+                field.to_string(),
+                self.schema_at::<T>(trace, shapes, &field_path),
+            );
+            if !Self::accepts::<T>(trace, Some(field_path), None) {
+                required.push(field.to_string());
+            }
+        }
+
+        let mut schema = json!({
+            "type": "object",
+            "properties": properties,
+        });
+        if !required.is_empty() {
+            schema["required"] = json!(required);
+        }
+        if !Self::accepts::<T>(trace, None, Some(path.to_string())) {
+            schema["additionalProperties"] = json!(false);
+        }
+        schema
+    }
+
+    // Checks whether `T` is accepted when the field at `skip` is left out or an unknown field
+    // is added to the object at `extend`.
+    fn accepts<T: DeserializeOwned>(
+        trace: &mut Trace,
+        skip: Option<String>,
+        extend: Option<String>,
+    ) -> bool {
+        trace.skip = skip;
+        trace.extend = extend;
+        let result = T::deserialize(Tracer {
+            trace: &mut *trace,
+            path: String::new(),
+        });
+        trace.skip = None;
+        trace.extend = None;
+        result.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    enum Kind {
+        First,
+        Second,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Inner {
+        size: u32,
+        #[serde(default)]
+        enabled: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Outer {
+        id: String,
+        address: Option<Ipv4Addr>,
+        kind: Kind,
+        inner: Option<Inner>,
+        list: Vec<Inner>,
+    }
+
+    #[test]
+    fn test_schema_for() {
+        let mut generator = SchemaGenerator::default();
+        assert_eq!(
+            generator.schema_for::<Outer>(),
+            json!({"$ref": "#/definitions/Outer"})
+        );
+        assert_eq!(
+            generator.schema_for::<u8>(),
+            json!({
+                "type": "integer",
+                "minimum": 0,
+                "maximum": 255,
+            })
+        );
+
+        let definitions = generator.into_definitions();
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(
+            definitions["Outer"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string"},
+                    "address": {"type": "string", "example": "169.254.169.254"},
+                    "kind": {"type": "string", "enum": ["First", "Second"]},
+                    "inner": {"$ref": "#/definitions/Inner"},
+                    "list": {
+                        "type": "array",
+                        "items": {"$ref": "#/definitions/Inner"},
+                    },
+                },
+                "required": ["id", "kind", "list"],
+            })
+        );
+        assert_eq!(
+            definitions["Inner"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "size": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": u32::max_value(),
+                    },
+                    "enabled": {"type": "boolean"},
+                },
+                "required": ["size"],
+                "additionalProperties": false,
+            })
+        );
+    }
+}
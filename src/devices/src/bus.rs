@@ -82,6 +82,13 @@ pub struct Bus {
     devices: BTreeMap<BusRange, Arc<Mutex<dyn BusDevice>>>,
 }
 
+impl fmt::Debug for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The devices themselves are opaque, only show the address ranges they occupy.
+        f.debug_list().entries(self.devices.keys()).finish()
+    }
+}
+
 impl Bus {
     /// Constructs an a bus with an empty address space.
     pub fn new() -> Bus {
@@ -140,6 +147,12 @@ impl Bus {
         Ok(())
     }
 
+    /// Removes the device placed at the address space starting at `base` and returns it.
+    pub fn remove(&mut self, base: u64) -> Option<Arc<Mutex<dyn BusDevice>>> {
+        // Ranges are compared by their base address only.
+        self.devices.remove(&BusRange(base, 1))
+    }
+
     /// Reads data from the device that owns the range containing `addr` and puts it into `data`.
     ///
     /// Returns true on success, otherwise `data` is untouched.
@@ -214,6 +227,24 @@ mod tests {
         assert!(bus.insert(dummy.clone(), 0x0, 0x10).is_ok());
     }
 
+    #[test]
+    fn bus_remove() {
+        let mut bus = Bus::new();
+        let dummy = Arc::new(Mutex::new(DummyDevice));
+        assert!(bus.insert(dummy.clone(), 0x10, 0x10).is_ok());
+        assert!(bus.insert(dummy.clone(), 0x20, 0x10).is_ok());
+
+        // Only the base address of a range identifies a device.
+        assert!(bus.remove(0x15).is_none());
+        assert!(bus.remove(0x10).is_some());
+        assert!(bus.remove(0x10).is_none());
+        assert!(!bus.read(0x10, &mut [0, 0, 0, 0]));
+        assert!(bus.read(0x20, &mut [0, 0, 0, 0]));
+
+        // The freed range can be reused.
+        assert!(bus.insert(dummy.clone(), 0x10, 0x10).is_ok());
+    }
+
     #[test]
     fn bus_read_write() {
         let mut bus = Bus::new();
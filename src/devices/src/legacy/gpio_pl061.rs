@@ -0,0 +1,286 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! ARM PL061 General Purpose Input/Output
+//!
+//! This module implements a PL061 GPIO controller whose input lines are driven by the VMM. One of
+//! them is wired to a power key, through which the guest is asked to shut down gracefully.
+//!
+
+use std::fmt;
+use std::{io, result};
+
+use crate::BusDevice;
+use logger::{Metric, METRICS};
+use utils::byte_order;
+use utils::eventfd::EventFd;
+
+// As you can see in https://static.docs.arm.com/ddi0190/b/DDI0190.pdf at section 3.2 Summary
+// of PrimeCell GPIO registers, the total size occupied by this device is 0x000 -> 0xFFC + 4 =
+// 0x1000. The data register is mapped from 0x000 to 0x3FC: bits [9:2] of the address mask the
+// lines which are read or written.
+const GPIODATA_END: u64 = 0x3fc;
+const GPIODIR: u64 = 0x400; // Direction Register.
+const GPIOIS: u64 = 0x404; // Interrupt Sense Register.
+const GPIOIBE: u64 = 0x408; // Interrupt Both Edges Register.
+const GPIOIEV: u64 = 0x40c; // Interrupt Event Register.
+const GPIOIE: u64 = 0x410; // Interrupt Mask Register.
+const GPIORIS: u64 = 0x414; // Raw Interrupt Status Register.
+const GPIOMIS: u64 = 0x418; // Masked Interrupt Status Register.
+const GPIOIC: u64 = 0x41c; // Interrupt Clear Register.
+const GPIOAFSEL: u64 = 0x420; // Mode Control Select Register.
+                              // From 0x424 to 0xFDC => reserved space.
+                              // From 0xFE0 to 0x1000 => Peripheral and PrimeCell Identification
+                              // Registers, which the linux kernel checks to identify the device.
+const PL061_ID: [u8; 8] = [0x61, 0x10, 0x04, 0x00, 0x0d, 0xf0, 0x05, 0xb1];
+const AMBA_ID_LOW: u64 = 0xFE0;
+const AMBA_ID_HIGH: u64 = 0x1000;
+
+/// The line the power key is wired to.
+pub const POWER_KEY_LINE: u32 = 3;
+
+#[derive(Debug)]
+pub enum Error {
+    BadWriteOffset(u64),
+    InterruptFailure(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadWriteOffset(offset) => write!(f, "Bad Write Offset: {}", offset),
+            Error::InterruptFailure(e) => write!(f, "Failed to trigger interrupt: {}", e),
+        }
+    }
+}
+type Result<T> = result::Result<T, Error>;
+
+/// A GPIO controller following the PL061 specification.
+pub struct Gpio {
+    // The level of the 8 lines.
+    data: u8,
+    dir: u8,
+    is: u8,
+    ibe: u8,
+    iev: u8,
+    ie: u8,
+    ris: u8,
+    afsel: u8,
+    interrupt_evt: EventFd,
+}
+
+impl Gpio {
+    /// Constructs an AMBA PL061 GPIO controller.
+    pub fn new(interrupt_evt: EventFd) -> Gpio {
+        Gpio {
+            data: 0,
+            dir: 0,
+            is: 0,
+            ibe: 0,
+            iev: 0,
+            ie: 0,
+            ris: 0,
+            afsel: 0,
+            interrupt_evt,
+        }
+    }
+
+    /// Presses the power key. The key is not released afterwards, since the guest is expected
+    /// to shut down.
+    pub fn press_power_key(&mut self) -> Result<()> {
+        self.set_input(POWER_KEY_LINE, true)
+    }
+
+    // Drives the input `line` to the given level, raising an interrupt if the guest asked for
+    // one on this change.
+    fn set_input(&mut self, line: u32, high: bool) -> Result<()> {
+        let mask = 1u8 << line;
+        if (self.data & mask != 0) == high || self.dir & mask != 0 {
+            return Ok(());
+        }
+        if high {
+            self.data |= mask;
+        } else {
+            self.data &= !mask;
+        }
+
+        let triggered = if self.is & mask != 0 {
+            // Level-sensitive interrupt, on the high level when the event bit is set.
+            (self.iev & mask != 0) == high
+        } else {
+            // Edge-sensitive interrupt, on both edges or on the rising edge when the event bit
+            // is set.
+            self.ibe & mask != 0 || (self.iev & mask != 0) == high
+        };
+        if triggered {
+            self.ris |= mask;
+            if self.ie & mask != 0 {
+                self.trigger_interrupt()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trigger_interrupt(&mut self) -> Result<()> {
+        self.interrupt_evt.write(1).map_err(Error::InterruptFailure)
+    }
+
+    fn handle_write(&mut self, offset: u64, val: u8) -> Result<()> {
+        match offset {
+            o if o <= GPIODATA_END => {
+                // Only the output lines selected by the address can be written.
+                let mask = ((o >> 2) as u8) & self.dir;
+                self.data = (self.data & !mask) | (val & mask);
+            }
+            GPIODIR => self.dir = val,
+            GPIOIS => self.is = val,
+            GPIOIBE => self.ibe = val,
+            GPIOIEV => self.iev = val,
+            GPIOIE => {
+                self.ie = val;
+                if self.ris & self.ie != 0 {
+                    self.trigger_interrupt()?;
+                }
+            }
+            GPIOIC => self.ris &= !val,
+            GPIOAFSEL => self.afsel = val,
+            o => {
+                return Err(Error::BadWriteOffset(o));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BusDevice for Gpio {
+    fn read(&mut self, offset: u64, data: &mut [u8]) {
+        let mut read_ok = true;
+
+        let v = if (AMBA_ID_LOW..AMBA_ID_HIGH).contains(&offset) {
+            let index = ((offset - AMBA_ID_LOW) >> 2) as usize;
+            PL061_ID[index]
+        } else {
+            match offset {
+                o if o <= GPIODATA_END => self.data & ((o >> 2) as u8),
+                GPIODIR => self.dir,
+                GPIOIS => self.is,
+                GPIOIBE => self.ibe,
+                GPIOIEV => self.iev,
+                GPIOIE => self.ie,
+                GPIORIS => self.ris,
+                GPIOMIS => self.ris & self.ie,
+                GPIOAFSEL => self.afsel,
+                _ => {
+                    read_ok = false;
+                    0
+                }
+            }
+        };
+        if read_ok && data.len() <= 4 {
+            byte_order::write_le_u32(data, u32::from(v));
+        } else {
+            warn!(
+                "Invalid GPIO PL061 read: offset {}, data length {}",
+                offset,
+                data.len()
+            );
+            METRICS.gpio.error_count.inc();
+        }
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        if data.len() <= 4 {
+            let v = byte_order::read_le_u32(data);
+            if let Err(e) = self.handle_write(offset, v as u8) {
+                warn!("Failed to write to GPIO PL061 device: {}", e);
+                METRICS.gpio.error_count.inc();
+            }
+        } else {
+            warn!(
+                "Invalid GPIO PL061 write: offset {}, data length {}",
+                offset,
+                data.len()
+            );
+            METRICS.gpio.error_count.inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(gpio: &mut Gpio, offset: u64) -> u32 {
+        let mut data = [0; 4];
+        gpio.read(offset, &mut data);
+        byte_order::read_le_u32(&data)
+    }
+
+    fn write(gpio: &mut Gpio, offset: u64, val: u32) {
+        let mut data = [0; 4];
+        byte_order::write_le_u32(&mut data, val);
+        gpio.write(offset, &data);
+    }
+
+    #[test]
+    fn test_gpio_identification() {
+        let mut gpio = Gpio::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let ids: Vec<u32> = (0..8)
+            .map(|i| read(&mut gpio, AMBA_ID_LOW + i * 4))
+            .collect();
+        assert_eq!(
+            ids,
+            PL061_ID.iter().map(|id| u32::from(*id)).collect::<Vec<_>>()
+        );
+
+        // Invalid accesses are counted.
+        let errors = METRICS.gpio.error_count.count();
+        write(&mut gpio, 0x500, 1);
+        assert_eq!(read(&mut gpio, 0x500), 0);
+        assert_eq!(METRICS.gpio.error_count.count(), errors + 2);
+    }
+
+    #[test]
+    fn test_gpio_data() {
+        let mut gpio = Gpio::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        // Only the output lines selected by the address are written.
+        write(&mut gpio, GPIODIR, 0b0000_0011);
+        write(&mut gpio, 0b0000_0111 << 2, 0xff);
+        assert_eq!(read(&mut gpio, GPIODATA_END), 0b0000_0011);
+        assert_eq!(read(&mut gpio, 0b0000_0001 << 2), 0b0000_0001);
+
+        // Input lines are driven by the VMM.
+        gpio.press_power_key().unwrap();
+        assert_eq!(read(&mut gpio, GPIODATA_END), 0b0000_1011);
+    }
+
+    #[test]
+    fn test_gpio_power_key_interrupt() {
+        let mut gpio = Gpio::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let mask = 1 << POWER_KEY_LINE;
+
+        // The guest asks for interrupts on both edges of the power key line.
+        write(&mut gpio, GPIOIBE, mask);
+        write(&mut gpio, GPIOIE, mask);
+        assert!(gpio.interrupt_evt.read().is_err());
+
+        gpio.press_power_key().unwrap();
+        assert_eq!(gpio.interrupt_evt.read().unwrap(), 1);
+        assert_eq!(read(&mut gpio, GPIORIS), mask);
+        assert_eq!(read(&mut gpio, GPIOMIS), mask);
+
+        // Pressing the key again changes nothing.
+        gpio.press_power_key().unwrap();
+        assert!(gpio.interrupt_evt.read().is_err());
+
+        write(&mut gpio, GPIOIC, mask);
+        assert_eq!(read(&mut gpio, GPIORIS), 0);
+
+        // Falling edges do not interrupt when only rising ones are asked for.
+        write(&mut gpio, GPIOIBE, 0);
+        write(&mut gpio, GPIOIEV, mask);
+        gpio.set_input(POWER_KEY_LINE, false).unwrap();
+        assert_eq!(read(&mut gpio, GPIORIS), 0);
+        assert!(gpio.interrupt_evt.read().is_err());
+    }
+}
@@ -42,6 +42,7 @@ pub enum Error {
         underlying: io::Error,
     },
     FailedReadTap,
+    FailedSignalingConfigChange(io::Error),
     FailedSignalingUsedQueue(io::Error),
     RateLimited(RateLimiterError),
     PayloadExpected,
@@ -14,7 +14,7 @@ use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use logger::{Metric, METRICS};
+use logger::{Event, Metric, EVENTS, METRICS};
 use rate_limiter::{RateLimiter, TokenType};
 use utils::eventfd::EventFd;
 use virtio_gen::virtio_blk::*;
@@ -77,8 +77,20 @@ fn build_disk_image_id(disk_image: &File) -> Vec<u8> {
     default_disk_image_id
 }
 
+// Records that the rate limiter of the drive started or stopped throttling it.
+fn emit_throttle_event(drive_id: &str, throttled: bool) {
+    EVENTS.emit(Event::RateLimiterThrottle {
+        device_id: drive_id.to_string(),
+        queue: None,
+        throttled,
+    });
+}
+
 /// Virtio device for exposing block level read/write operations on a host file.
 pub struct Block {
+    // The ID of the drive, which identifies the device in events.
+    id: String,
+
     // Host file and properties.
     disk_image: File,
     disk_nsectors: u64,
@@ -107,6 +119,7 @@ impl Block {
     ///
     /// The given file must be seekable and sizable.
     pub fn new(
+        id: String,
         mem: GuestMemoryMmap,
         mut disk_image: File,
         is_disk_read_only: bool,
@@ -125,6 +138,7 @@ impl Block {
         let queues = QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
 
         Ok(Block {
+            id,
             disk_image_id: build_disk_image_id(&disk_image),
             disk_image,
             disk_nsectors: disk_size / SECTOR_SIZE,
@@ -155,8 +169,11 @@ impl Block {
         METRICS.block.rate_limiter_event_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
-        if self.rate_limiter.event_handler().is_ok() && self.process_queue(0) {
-            let _ = self.signal_used_queue();
+        if self.rate_limiter.event_handler().is_ok() {
+            emit_throttle_event(&self.id, false);
+            if self.process_queue(0) {
+                let _ = self.signal_used_queue();
+            }
         }
     }
 
@@ -170,6 +187,7 @@ impl Block {
                     // If limiter.consume() fails it means there is no more TokenType::Ops
                     // budget and rate limiting is in effect.
                     if !self.rate_limiter.consume(1, TokenType::Ops) {
+                        emit_throttle_event(&self.id, true);
                         // Stop processing the queue and return this descriptor chain to the
                         // avail ring, for later processing.
                         queue.undo_pop();
@@ -187,6 +205,7 @@ impl Block {
                         {
                             // Revert the OPS consume().
                             self.rate_limiter.manual_replenish(1, TokenType::Ops);
+                            emit_throttle_event(&self.id, true);
                             // Stop processing the queue and return this descriptor chain to the
                             // avail ring, for later processing.
                             queue.undo_pop();
@@ -206,6 +225,10 @@ impl Block {
                         Err(e) => {
                             error!("Failed to execute request: {:?}", e);
                             METRICS.block.invalid_reqs_count.inc();
+                            EVENTS.emit(Event::BlockDeviceError {
+                                drive_id: self.id.clone(),
+                                error: format!("Failed to execute request: {:?}", e),
+                            });
                             len = 1; // We need at least 1 byte for the status.
                             e.status()
                         }
@@ -217,6 +240,10 @@ impl Block {
                 Err(e) => {
                     error!("Failed to parse available descriptor chain: {:?}", e);
                     METRICS.block.execute_fails.inc();
+                    EVENTS.emit(Event::BlockDeviceError {
+                        drive_id: self.id.clone(),
+                        error: format!("Failed to parse available descriptor chain: {:?}", e),
+                    });
                     len = 0;
                 }
             }
@@ -234,11 +261,20 @@ impl Block {
         self.interrupt_evt.write(1).map_err(|e| {
             error!("Failed to signal used queue: {:?}", e);
             METRICS.block.event_fails.inc();
+            EVENTS.emit(Event::BlockDeviceError {
+                drive_id: self.id.clone(),
+                error: format!("Failed to signal used queue: {:?}", e),
+            });
             DeviceError::FailedSignalingUsedQueue(e)
         })?;
         Ok(())
     }
 
+    /// Returns the rate limiter of the device.
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
     /// Update the backing file for the Block device.
     pub fn update_disk_image(&mut self, disk_image: File) -> result::Result<(), DeviceError> {
         self.disk_image = disk_image;
@@ -355,10 +391,6 @@ mod tests {
         fn set_rate_limiter(&mut self, rl: RateLimiter) {
             self.rate_limiter = rl;
         }
-
-        fn rate_limiter(&mut self) -> &RateLimiter {
-            &self.rate_limiter
-        }
     }
 
     /// Create a default Block instance to be used in tests.
@@ -373,7 +405,7 @@ mod tests {
 
         let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x10000)]).unwrap();
 
-        Block::new(mem, block_file, true, rate_limiter).unwrap()
+        Block::new("rootfs".to_string(), mem, block_file, true, rate_limiter).unwrap()
     }
 
     fn initialize_virtqueue(vq: &VirtQueue) {
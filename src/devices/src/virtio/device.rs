@@ -97,4 +97,10 @@ pub trait VirtioDevice: AsAny + Send {
     fn reset(&mut self) -> Option<(EventFd, Vec<EventFd>)> {
         None
     }
+
+    /// Brings the device back to the state the driver of a restarted guest expects to find it in.
+    /// The device stays activated, but forgets what it negotiated with the previous driver.
+    fn restart(&mut self) {
+        self.set_acked_features(0);
+    }
 }
@@ -83,6 +83,11 @@ impl MmioTransport {
         self.device.clone()
     }
 
+    /// Whether the driver reset the device, or never started using it.
+    pub fn is_reset(&self) -> bool {
+        self.device_status == device_status::INIT
+    }
+
     fn check_device_status(&self, set: u32, clr: u32) -> bool {
         self.device_status & (set | clr) == set
     }
@@ -136,9 +141,6 @@ impl MmioTransport {
     }
 
     fn reset(&mut self) {
-        if self.locked_device().is_activated() {
-            warn!("reset device while it's still in active state");
-        }
         self.features_select = 0;
         self.acked_features_select = 0;
         self.queue_select = 0;
@@ -153,6 +155,14 @@ impl MmioTransport {
         }
     }
 
+    /// Brings the transport and the device back to the state the driver of a restarted guest
+    /// finds them in at boot. An activated device stays activated, and processes the queues the
+    /// driver sets up again.
+    pub fn restart(&mut self) {
+        self.reset();
+        self.locked_device().restart();
+    }
+
     /// Update device status according to the state machine defined by VirtIO Spec 1.0.
     /// Please refer to VirtIO Spec 1.0, section 2.1.1 and 3.1.1.
     ///
@@ -187,6 +197,9 @@ impl MmioTransport {
                 // TODO: notify backend driver to stop the device
                 self.device_status |= FAILED;
             }
+            // The driver of a restarted guest resets the device when probing it, after the VMM
+            // already reset the transport, so there is nothing to do for the activated device.
+            _ if status == 0 && self.device_status == INIT => (),
             _ if status == 0 => {
                 if self.locked_device().is_activated() {
                     let mut device_status = self.device_status;
@@ -203,6 +216,9 @@ impl MmioTransport {
                 // If the backend device driver doesn't support reset,
                 // just leave the device marked as FAILED.
                 if self.device_status & FAILED == 0 {
+                    if self.locked_device().is_activated() {
+                        warn!("reset device while it's still in active state");
+                    }
                     self.reset();
                 }
             }
@@ -845,6 +861,56 @@ mod tests {
         assert!(d.locked_device().is_activated());
     }
 
+    #[test]
+    fn test_restart_activated_device() {
+        let m = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x1000)]).unwrap();
+        let mut d = MmioTransport::new(m, Arc::new(Mutex::new(DummyDevice::new()))).unwrap();
+        activate_device(&mut d);
+        d.locked_device().set_acked_features(0x124);
+
+        // The VMM restarts the transport, the device stays activated but forgets its features.
+        d.restart();
+        assert_eq!(d.device_status, device_status::INIT);
+        assert!(!d.are_queues_valid());
+        assert!(d.locked_device().is_activated());
+        assert_eq!(d.locked_device().acked_features(), 0);
+
+        // The driver resets the device, then sets it up again.
+        set_device_status(&mut d, 0);
+        assert_eq!(d.device_status, device_status::INIT);
+        set_device_status(&mut d, device_status::ACKNOWLEDGE);
+        set_device_status(&mut d, device_status::ACKNOWLEDGE | device_status::DRIVER);
+        set_device_status(
+            &mut d,
+            device_status::ACKNOWLEDGE | device_status::DRIVER | device_status::FEATURES_OK,
+        );
+        let mut buf = [0; 4];
+        let queues_count = d.locked_device().queues().len();
+        for q in 0..queues_count {
+            d.queue_select = q as u32;
+            write_le_u32(&mut buf[..], 16);
+            d.write(0x38, &buf[..]);
+            write_le_u32(&mut buf[..], 1);
+            d.write(0x44, &buf[..]);
+        }
+        set_device_status(
+            &mut d,
+            device_status::ACKNOWLEDGE
+                | device_status::DRIVER
+                | device_status::FEATURES_OK
+                | device_status::DRIVER_OK,
+        );
+        assert!(d.are_queues_valid());
+        assert_eq!(
+            d.device_status,
+            device_status::ACKNOWLEDGE
+                | device_status::DRIVER
+                | device_status::FEATURES_OK
+                | device_status::DRIVER_OK
+        );
+        assert!(d.locked_device().is_activated());
+    }
+
     #[test]
     fn test_get_avail_features() {
         let dummy_dev = DummyDevice::new();
@@ -8,29 +8,38 @@
 use crate::virtio::net::Error;
 use crate::virtio::net::Result;
 use crate::virtio::net::{MAX_BUFFER_SIZE, QUEUE_SIZE, QUEUE_SIZES, RX_INDEX, TX_INDEX};
-use crate::virtio::{ActivateResult, Queue, VirtioDevice, TYPE_NET, VIRTIO_MMIO_INT_VRING};
+use crate::virtio::{
+    ActivateResult, Queue, VirtioDevice, TYPE_NET, VIRTIO_MMIO_INT_CONFIG, VIRTIO_MMIO_INT_VRING,
+};
 use crate::{report_net_event_fail, Error as DeviceError};
 use dumbo::ns::MmdsNetworkStack;
 use dumbo::{EthernetFrame, MacAddr, MAC_ADDR_LEN};
 use libc::EAGAIN;
-use logger::{Metric, METRICS};
+use logger::{Event, Metric, EVENTS, METRICS};
 use rate_limiter::{RateLimiter, TokenBucket, TokenType};
-#[cfg(not(test))]
 use std::io::Read;
 use std::io::Write;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{cmp, io, mem, result};
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::byte_order;
 use utils::eventfd::EventFd;
 use utils::net::Tap;
 use virtio_gen::virtio_net::{
     virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
     VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO,
-    VIRTIO_NET_F_MAC,
+    VIRTIO_NET_F_MAC, VIRTIO_NET_F_STATUS, VIRTIO_NET_S_LINK_UP,
 };
 use vm_memory::{Bytes, GuestAddress, GuestMemoryError, GuestMemoryMmap};
 
+// The device config space is laid out as `struct virtio_net_config`: the MAC address followed by
+// the 16-bit link status. We don't offer any of the features that would extend it further.
+const CONFIG_SPACE_STATUS_OFFSET: usize = MAC_ADDR_LEN;
+const CONFIG_SPACE_LEN: usize = CONFIG_SPACE_STATUS_OFFSET + 2;
+
 fn vnet_hdr_len() -> usize {
     mem::size_of::<virtio_net_hdr_v1>()
 }
@@ -54,7 +63,20 @@ fn init_vnet_hdr(buf: &mut [u8]) {
     }
 }
 
+// Records that the rate limiter of the `queue` of the interface started or stopped
+// throttling it.
+fn emit_throttle_event(iface_id: &str, queue: &str, throttled: bool) {
+    EVENTS.emit(Event::RateLimiterThrottle {
+        device_id: iface_id.to_string(),
+        queue: Some(queue.to_string()),
+        throttled,
+    });
+}
+
 pub struct Net {
+    // The ID of the network interface, which identifies the device in events.
+    id: String,
+
     pub(crate) tap: Tap,
     avail_features: u64,
     acked_features: u64,
@@ -81,34 +103,34 @@ pub struct Net {
 
     config_space: Vec<u8>,
     guest_mac: Option<MacAddr>,
+    link_up: bool,
 
     device_activated: bool,
 
     mmds_ns: Option<MmdsNetworkStack>,
+    // Signaled by the MMDS after every update of its data store, so that the long-poll requests
+    // held by `mmds_ns` get answered.
+    pub(crate) mmds_update_evt: Option<Arc<EventFd>>,
+    // Fires when the wait of the next long-poll request held by `mmds_ns` times out.
+    pub(crate) mmds_wait_timer: Option<TimerFd>,
 
     #[cfg(test)]
     test_mutators: tests::TestMutators,
 }
 
 impl Net {
-    /// Create a new virtio network device with the given TAP interface.
+    /// Create a new virtio network device with the given TAP interface. When `mmds_ns` is
+    /// provided, the device intercepts the guest traffic heading to the MMDS.
     pub fn new_with_tap(
+        id: String,
         tap: Tap,
         guest_mac: Option<&MacAddr>,
         mem: GuestMemoryMmap,
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
-        allow_mmds_requests: bool,
+        mmds_ns: Option<MmdsNetworkStack>,
     ) -> Result<Self> {
-        // Set offload flags to match the virtio features below.
-        tap.set_offload(
-            net_gen::TUN_F_CSUM | net_gen::TUN_F_UFO | net_gen::TUN_F_TSO4 | net_gen::TUN_F_TSO6,
-        )
-        .map_err(Error::TapSetOffload)?;
-
-        let vnet_hdr_size = vnet_hdr_len() as i32;
-        tap.set_vnet_hdr_size(vnet_hdr_size)
-            .map_err(Error::TapSetVnetHdrSize)?;
+        Self::configure_tap(&tap)?;
 
         let mut avail_features = 1 << VIRTIO_NET_F_GUEST_CSUM
             | 1 << VIRTIO_NET_F_CSUM
@@ -116,18 +138,22 @@ impl Net {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_STATUS
             | 1 << VIRTIO_F_VERSION_1;
 
-        let mut config_space;
+        // The status field always follows the MAC, so the config space is allocated in full even
+        // when no MAC address was provided.
+        let mut config_space = vec![0; CONFIG_SPACE_LEN];
         if let Some(mac) = guest_mac {
-            config_space = vec![0; MAC_ADDR_LEN];
-            config_space[..].copy_from_slice(mac.get_bytes());
+            config_space[..MAC_ADDR_LEN].copy_from_slice(mac.get_bytes());
             // When this feature isn't available, the driver generates a random MAC address.
             // Otherwise, it should attempt to read the device MAC address from the config space.
             avail_features |= 1 << VIRTIO_NET_F_MAC;
-        } else {
-            config_space = Vec::new();
         }
+        byte_order::write_le_u16(
+            &mut config_space[CONFIG_SPACE_STATUS_OFFSET..],
+            VIRTIO_NET_S_LINK_UP as u16,
+        );
 
         let guest_mac = guest_mac.copied();
 
@@ -138,13 +164,21 @@ impl Net {
 
         let queues = QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
 
-        let mmds_ns = if allow_mmds_requests {
-            Some(MmdsNetworkStack::new_with_defaults())
+        let (mmds_update_evt, mmds_wait_timer) = if mmds_ns.is_some() {
+            let update_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?);
+            mmds::MMDS
+                .lock()
+                .expect("Failed to acquire lock on MMDS")
+                .add_update_listener(Arc::downgrade(&update_evt));
+            let wait_timer =
+                TimerFd::new_custom(ClockId::Monotonic, true, true).map_err(Error::TimerFd)?;
+            (Some(update_evt), Some(wait_timer))
         } else {
-            None
+            (None, None)
         };
 
         Ok(Net {
+            id,
             tap,
             avail_features,
             acked_features: 0u64,
@@ -164,23 +198,128 @@ impl Net {
             device_activated: false,
             config_space,
             guest_mac,
+            link_up: true,
             mmds_ns,
+            mmds_update_evt,
+            mmds_wait_timer,
 
             #[cfg(test)]
             test_mutators: tests::TestMutators::default(),
         })
     }
 
+    // Sets the offload flags and the vnet header size of `tap` to match the virtio features
+    // offered by this device.
+    fn configure_tap(tap: &Tap) -> Result<()> {
+        tap.set_offload(
+            net_gen::TUN_F_CSUM | net_gen::TUN_F_UFO | net_gen::TUN_F_TSO4 | net_gen::TUN_F_TSO6,
+        )
+        .map_err(Error::TapSetOffload)?;
+
+        let vnet_hdr_size = vnet_hdr_len() as i32;
+        tap.set_vnet_hdr_size(vnet_hdr_size)
+            .map_err(Error::TapSetVnetHdrSize)
+    }
+
+    /// Returns a reference to the tap device backing this interface.
+    pub fn tap(&self) -> &Tap {
+        &self.tap
+    }
+
+    // Replaces the tap device backing this interface and returns the previous one.
+    // Callers are responsible for moving the tap event registration over to the new tap.
+    pub(crate) fn set_tap(&mut self, tap: Tap) -> Result<Tap> {
+        Self::configure_tap(&tap)?;
+        Ok(mem::replace(&mut self.tap, tap))
+    }
+
+    /// Returns the rate limiter of the RX queue.
+    pub fn rx_rate_limiter(&self) -> &RateLimiter {
+        &self.rx_rate_limiter
+    }
+
+    /// Returns the rate limiter of the TX queue.
+    pub fn tx_rate_limiter(&self) -> &RateLimiter {
+        &self.tx_rate_limiter
+    }
+
+    /// Returns the guest MAC address of this interface, if one was configured.
+    pub fn guest_mac(&self) -> Option<&MacAddr> {
+        self.guest_mac.as_ref()
+    }
+
+    /// Returns whether the guest reads its MAC address from the config space, which is only
+    /// the case when the device was created with one.
+    pub fn exposes_guest_mac(&self) -> bool {
+        self.avail_features & (1 << VIRTIO_NET_F_MAC) != 0
+    }
+
+    /// Updates the MAC address exposed to the guest through the config space. The driver
+    /// learns about it once notified with `signal_config_change`.
+    pub fn set_guest_mac(&mut self, mac: MacAddr) {
+        self.guest_mac = Some(mac);
+        self.config_space[..MAC_ADDR_LEN].copy_from_slice(mac.get_bytes());
+    }
+
+    /// Returns whether the link is currently reported as up to the guest.
+    pub fn is_link_up(&self) -> bool {
+        self.link_up
+    }
+
+    /// Sets the link status reported to the guest. The driver learns about it once notified
+    /// with `signal_config_change`. While the link is down, frames are neither sent to nor
+    /// received from the tap device. MMDS traffic is not affected.
+    pub fn set_link_up(&mut self, link_up: bool) {
+        let was_up = self.link_up;
+        self.link_up = link_up;
+        let status = if link_up { VIRTIO_NET_S_LINK_UP } else { 0 };
+        byte_order::write_le_u16(
+            &mut self.config_space[CONFIG_SPACE_STATUS_OFFSET..],
+            status as u16,
+        );
+
+        // The tap is edge-triggered, so the frames which arrived while the link was down would
+        // only be read along with the next one. Read them right away instead.
+        if link_up && !was_up && self.device_activated && !self.rx_rate_limiter.is_blocked() {
+            if self.rx_deferred_frame {
+                self.resume_rx()
+            } else {
+                self.process_rx()
+            }
+            .unwrap_or_else(report_net_event_fail);
+        }
+    }
+
+    /// Notifies the driver of a change of the config space.
+    pub fn signal_config_change(&self) -> result::Result<(), DeviceError> {
+        self.interrupt_status
+            .fetch_or(VIRTIO_MMIO_INT_CONFIG as usize, Ordering::SeqCst);
+        self.interrupt_evt.write(1).map_err(|e| {
+            error!("Failed to signal config change: {:?}", e);
+            METRICS.net.event_fails.inc();
+            self.emit_error_event(format!("Failed to signal config change: {:?}", e));
+            DeviceError::FailedSignalingConfigChange(e)
+        })
+    }
+
     fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
         self.interrupt_evt.write(1).map_err(|e| {
             error!("Failed to signal used queue: {:?}", e);
             METRICS.net.event_fails.inc();
+            self.emit_error_event(format!("Failed to signal used queue: {:?}", e));
             DeviceError::FailedSignalingUsedQueue(e)
         })
     }
 
+    fn emit_error_event(&self, error: String) {
+        EVENTS.emit(Event::NetDeviceError {
+            iface_id: self.id.clone(),
+            error,
+        });
+    }
+
     // Attempts to copy a single frame into the guest if there is enough
     // rate limiting budget.
     // Returns true on successful frame delivery.
@@ -188,6 +327,7 @@ impl Net {
         // If limiter.consume() fails it means there is no more TokenType::Ops
         // budget and rate limiting is in effect.
         if !self.rx_rate_limiter.consume(1, TokenType::Ops) {
+            emit_throttle_event(&self.id, "rx", true);
             return false;
         }
         // If limiter.consume() fails it means there is no more TokenType::Bytes
@@ -198,6 +338,7 @@ impl Net {
         {
             // revert the OPS consume()
             self.rx_rate_limiter.manual_replenish(1, TokenType::Ops);
+            emit_throttle_event(&self.id, "rx", true);
             return false;
         }
 
@@ -286,6 +427,7 @@ impl Net {
     }
 
     // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP.
+    // Frames heading to the TAP are dropped while the link is down.
     //
     // `frame_buf` should contain the frame bytes in a slice of exact length.
     // Returns whether MMDS consumed the frame.
@@ -295,6 +437,7 @@ impl Net {
         frame_buf: &[u8],
         tap: &mut Tap,
         guest_mac: Option<MacAddr>,
+        link_up: bool,
     ) -> bool {
         if let Some(ns) = mmds_ns {
             if ns.detour_frame(frame_bytes_from_buf(frame_buf)) {
@@ -310,6 +453,10 @@ impl Net {
         }
 
         // This frame goes to the TAP.
+        if !link_up {
+            METRICS.net.tx_link_down_drop_count.inc();
+            return false;
+        }
 
         // Check for guest MAC spoofing.
         if let Some(mac) = guest_mac {
@@ -349,6 +496,12 @@ impl Net {
             }
         }
 
+        // Frames are left in the TAP while the link is down. The TAP drops them once its queue
+        // fills up, much like a real link would.
+        if !self.link_up {
+            return Err(io::Error::from_raw_os_error(EAGAIN));
+        }
+
         self.read_tap()
     }
 
@@ -372,6 +525,7 @@ impl Net {
                         _ => {
                             error!("Failed to read tap: {:?}", e);
                             METRICS.net.rx_fails.inc();
+                            self.emit_error_event(format!("Failed to read tap: {:?}", e));
                             return Err(DeviceError::FailedReadTap);
                         }
                     };
@@ -418,6 +572,7 @@ impl Net {
             // If limiter.consume() fails it means there is no more TokenType::Ops
             // budget and rate limiting is in effect.
             if !self.tx_rate_limiter.consume(1, TokenType::Ops) {
+                emit_throttle_event(&self.id, "tx", true);
                 // Stop processing the queue and return this descriptor chain to the
                 // avail ring, for later processing.
                 tx_queue.undo_pop();
@@ -446,6 +601,7 @@ impl Net {
             {
                 // revert the OPS consume()
                 self.tx_rate_limiter.manual_replenish(1, TokenType::Ops);
+                emit_throttle_event(&self.id, "tx", true);
                 // Stop processing the queue and return this descriptor chain to the
                 // avail ring, for later processing.
                 tx_queue.undo_pop();
@@ -486,6 +642,7 @@ impl Net {
                 &self.tx_frame_buf[..read_count],
                 &mut self.tap,
                 self.guest_mac,
+                self.link_up,
             ) && !self.rx_deferred_frame
             {
                 // MMDS consumed this frame/request, let's also try to process the response.
@@ -500,14 +657,31 @@ impl Net {
             self.signal_used_queue()?;
         }
 
-        // An incoming frame for the MMDS may trigger the transmission of a new message.
+        // An incoming frame for the MMDS may trigger the transmission of a new message, or
+        // carry a long-poll request which has to be held.
         if process_rx_for_mmds {
+            self.arm_mmds_wait_timer();
             self.process_rx()
         } else {
             Ok(())
         }
     }
 
+    // Arms the MMDS wait timer to fire when the wait of the next long-poll request times out.
+    fn arm_mmds_wait_timer(&mut self) {
+        if let (Some(ns), Some(timer)) = (self.mmds_ns.as_ref(), self.mmds_wait_timer.as_mut()) {
+            let state = match ns.next_wait_deadline() {
+                // A zero duration would disarm the timer, so we fire it at least 1ms from now.
+                Some(deadline) => TimerState::Oneshot(cmp::max(
+                    deadline.saturating_duration_since(Instant::now()),
+                    Duration::from_millis(1),
+                )),
+                None => TimerState::Disarmed,
+            };
+            timer.set_state(state, SetTimeFlags::Default);
+        }
+    }
+
     /// Updates the parameters for the rate limiters
     pub fn patch_rate_limiters(
         &mut self,
@@ -570,6 +744,28 @@ impl Net {
         }
     }
 
+    /// Handles the MMDS update event and the expiry of the MMDS wait timer, by answering the
+    /// long-poll requests whose wait is over.
+    pub fn process_mmds_event(&mut self) {
+        METRICS.net.mmds_event_count.inc();
+        if let Some(evt) = self.mmds_update_evt.as_ref() {
+            // Nothing to read is not an error, as the event may be the timer firing instead.
+            let _ = evt.read();
+        }
+        if let Some(timer) = self.mmds_wait_timer.as_mut() {
+            timer.read();
+        }
+        if let Some(ns) = self.mmds_ns.as_mut() {
+            ns.check_waiting_requests();
+        }
+        self.arm_mmds_wait_timer();
+
+        // The responses are sent once any deferred frame makes it to the guest.
+        if !self.rx_rate_limiter.is_blocked() && !self.rx_deferred_frame {
+            self.process_rx().unwrap_or_else(report_net_event_fail);
+        }
+    }
+
     pub fn process_tx_queue_event(&mut self) {
         METRICS.net.tx_queue_event_count.inc();
         if let Err(e) = self.queue_evts[TX_INDEX].read() {
@@ -589,6 +785,7 @@ impl Net {
 
         match self.rx_rate_limiter.event_handler() {
             Ok(_) => {
+                emit_throttle_event(&self.id, "rx", false);
                 // There might be enough budget now to receive the frame.
                 self.resume_rx().unwrap_or_else(report_net_event_fail);
             }
@@ -605,6 +802,7 @@ impl Net {
         // and restart processing the queue.
         match self.tx_rate_limiter.event_handler() {
             Ok(_) => {
+                emit_throttle_event(&self.id, "tx", false);
                 // There might be enough budget now to send the frame.
                 self.process_tx().unwrap_or_else(report_net_event_fail);
             }
@@ -671,8 +869,7 @@ impl VirtioDevice for Net {
             METRICS.net.cfg_fails.inc();
             return;
         }
-        let (_, right) = self.config_space.split_at_mut(offset as usize);
-        right.copy_from_slice(&data[..]);
+        self.config_space[offset as usize..(offset + data_len) as usize].copy_from_slice(data);
     }
 
     fn is_activated(&self) -> bool {
@@ -683,6 +880,17 @@ impl VirtioDevice for Net {
         self.device_activated = true;
         Ok(())
     }
+
+    fn restart(&mut self) {
+        self.acked_features = 0;
+        // The frames received for the previous driver are dropped, like a real NIC drops its
+        // buffers on reset.
+        self.rx_deferred_frame = false;
+        self.rx_deferred_irqs = false;
+        self.rx_bytes_read = 0;
+        // The tap is edge-triggered, so it is emptied for the next frame to be notified.
+        while self.tap.read(&mut self.rx_frame_buf).is_ok() {}
+    }
 }
 
 #[cfg(test)]
@@ -690,6 +898,7 @@ mod tests {
     use std::net::Ipv4Addr;
     use std::os::unix::io::AsRawFd;
     use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
     use std::time::Duration;
     use std::{io, mem, thread};
 
@@ -715,7 +924,7 @@ mod tests {
     use virtio_gen::virtio_net::{
         virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
         VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4,
-        VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+        VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC, VIRTIO_NET_F_STATUS,
     };
 
     static NEXT_INDEX: AtomicUsize = AtomicUsize::new(1);
@@ -760,12 +969,13 @@ mod tests {
             let guest_mac = Net::default_guest_mac();
 
             let mut net = Net::new_with_tap(
+                format!("net-device{}", next_tap),
                 tap,
                 Some(&guest_mac),
                 Net::default_guest_memory(),
                 RateLimiter::default(),
                 RateLimiter::default(),
-                true,
+                Some(MmdsNetworkStack::new_with_defaults(None, None)),
             )
             .unwrap();
             net.test_mutators = test_mutators;
@@ -801,10 +1011,7 @@ mod tests {
 
         fn set_mac(&mut self, mac: MacAddr) {
             self.guest_mac = Some(mac);
-            let mut config_space;
-            config_space = vec![0; MAC_ADDR_LEN];
-            config_space[..].copy_from_slice(mac.get_bytes());
-            self.config_space = config_space;
+            self.config_space[..MAC_ADDR_LEN].copy_from_slice(mac.get_bytes());
         }
 
         // Assigns "guest virtio driver" activated queues to the net device.
@@ -879,6 +1086,7 @@ mod tests {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_STATUS
             | 1 << VIRTIO_F_VERSION_1;
 
         assert_eq!(net.avail_features_by_page(0), features as u32);
@@ -905,9 +1113,17 @@ mod tests {
         net.read_config(0, &mut config_mac);
         assert_eq!(config_mac, mac.get_bytes());
 
+        // The link status follows the MAC.
+        let mut status = [0u8; 2];
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(
+            byte_order::read_le_u16(&status[..]),
+            VIRTIO_NET_S_LINK_UP as u16
+        );
+
         // Invalid read.
         config_mac = [0u8; MAC_ADDR_LEN];
-        net.read_config(MAC_ADDR_LEN as u64 + 1, &mut config_mac);
+        net.read_config(CONFIG_SPACE_LEN as u64 + 1, &mut config_mac);
         assert_eq!(config_mac, [0u8, 0u8, 0u8, 0u8, 0u8, 0u8]);
     }
 
@@ -1132,6 +1348,7 @@ mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(sha),
+                true,
             ))
         );
 
@@ -1143,6 +1360,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mmds_event_handling() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mut net = Net::default_net(TestMutators::default());
+        let mem_clone = net.mem.clone();
+        let (rxq, txq) = Net::virtqueues(&mem_clone);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+
+        let update_fd = net.mmds_update_evt.as_ref().unwrap().as_raw_fd();
+        let wait_timer_fd = net.mmds_wait_timer.as_ref().unwrap().as_raw_fd();
+        let interest_list = net.interest_list();
+        assert!(interest_list.iter().any(|event| event.fd() == update_fd));
+        assert!(interest_list
+            .iter()
+            .any(|event| event.fd() == wait_timer_fd));
+
+        // The MMDS signals the device after updating its data store.
+        net.mmds_update_evt.as_ref().unwrap().write(1).unwrap();
+        let update_event = EpollEvent::new(EventSet::IN, update_fd as u64);
+        check_metric_after_block!(
+            &METRICS.net.mmds_event_count,
+            1,
+            net.process(&update_event, &mut event_manager)
+        );
+        // The event got consumed.
+        assert!(net.mmds_update_evt.as_ref().unwrap().read().is_err());
+
+        // No request is held, so the timer stays disarmed.
+        let wait_timer_event = EpollEvent::new(EventSet::IN, wait_timer_fd as u64);
+        check_metric_after_block!(
+            &METRICS.net.mmds_event_count,
+            1,
+            net.process(&wait_timer_event, &mut event_manager)
+        );
+        assert_eq!(
+            net.mmds_wait_timer.as_ref().unwrap().get_state(),
+            TimerState::Disarmed
+        );
+    }
+
     #[test]
     fn test_mac_spoofing_detection() {
         let mut net = Net::default_net(TestMutators::default());
@@ -1192,6 +1449,7 @@ mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(guest_mac),
+                true,
             )
         );
 
@@ -1205,6 +1463,7 @@ mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(not_guest_mac),
+                true,
             )
         );
     }
@@ -1595,6 +1854,142 @@ mod tests {
         compare_buckets(net.tx_rate_limiter.ops().unwrap(), &tx_ops);
     }
 
+    #[test]
+    fn test_set_link_up() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mut net = Net::default_net(TestMutators::default());
+        let mem_clone = net.mem.clone();
+        let (rxq, txq) = Net::virtqueues(&mem_clone);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+        assert!(net.is_link_up());
+
+        // Bringing the link down updates the status field.
+        net.set_link_up(false);
+        net.signal_config_change().unwrap();
+        assert!(!net.is_link_up());
+        let mut status = [0xffu8; 2];
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(byte_order::read_le_u16(&status[..]), 0);
+        assert_eq!(
+            net.interrupt_status().load(Ordering::SeqCst),
+            VIRTIO_MMIO_INT_CONFIG as usize
+        );
+        assert_eq!(net.interrupt_evt.read().unwrap(), 1);
+
+        // Frames heading to the tap are dropped while the link is down.
+        let daddr = 0x2000;
+        txq.avail.idx.set(1);
+        txq.avail.ring[0].set(0);
+        txq.dtable[0].set(daddr, 0x1000, 0, 0);
+        net.queue_evts[TX_INDEX].write(1).unwrap();
+        let tx_event = EpollEvent::new(EventSet::IN, net.queue_evts[TX_INDEX].as_raw_fd() as u64);
+        check_metric_after_block!(
+            &METRICS.net.tx_link_down_drop_count,
+            1,
+            net.process(&tx_event, &mut event_manager)
+        );
+        assert_eq!(txq.used.idx.get(), 1);
+
+        // Nothing is read from the tap while the link is down.
+        rxq.avail.idx.set(1);
+        rxq.avail.ring[0].set(0);
+        rxq.dtable[0].set(daddr, 0x1000, VIRTQ_DESC_F_WRITE, 0);
+        let tap_event = EpollEvent::new(EventSet::IN, net.tap.as_raw_fd() as u64);
+        net.process(&tap_event, &mut event_manager);
+        assert_eq!(rxq.used.idx.get(), 0);
+
+        // Bringing the link back up reads the frames which arrived meanwhile, without waiting
+        // for another tap event.
+        net.set_link_up(true);
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(
+            byte_order::read_le_u16(&status[..]),
+            VIRTIO_NET_S_LINK_UP as u16
+        );
+        assert_eq!(rxq.used.idx.get(), 1);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut net = Net::default_net(TestMutators::default());
+        let mem_clone = net.mem.clone();
+        let (rxq, txq) = Net::virtqueues(&mem_clone);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+        net.ack_features_by_page(0, net.avail_features() as u32);
+        assert_ne!(net.acked_features(), 0);
+        net.rx_deferred_frame = true;
+        net.rx_deferred_irqs = true;
+        net.rx_bytes_read = MAX_BUFFER_SIZE;
+
+        // The device stays activated, but drops what belonged to the previous driver.
+        net.restart();
+        assert!(net.is_activated());
+        assert_eq!(net.acked_features(), 0);
+        assert!(!net.rx_deferred_frame);
+        assert!(!net.rx_deferred_irqs);
+        assert_eq!(net.rx_bytes_read, 0);
+    }
+
+    #[test]
+    fn test_set_guest_mac() {
+        let mut net = Net::default_net(TestMutators::default());
+        let mac = MacAddr::parse_str("66:55:44:33:22:11").unwrap();
+
+        assert!(net.exposes_guest_mac());
+        net.set_guest_mac(mac);
+        net.signal_config_change().unwrap();
+        assert_eq!(net.guest_mac(), Some(&mac));
+        let mut config_mac = [0u8; MAC_ADDR_LEN];
+        net.read_config(0, &mut config_mac);
+        assert_eq!(config_mac, mac.get_bytes());
+        assert_eq!(
+            net.interrupt_status().load(Ordering::SeqCst),
+            VIRTIO_MMIO_INT_CONFIG as usize
+        );
+
+        // The guest only reads the MAC address of devices created with one.
+        let net = Net::new_with_tap(
+            "net-nomac".to_string(),
+            Tap::open_named("net-nomac").unwrap(),
+            None,
+            Net::default_guest_memory(),
+            RateLimiter::default(),
+            RateLimiter::default(),
+            None,
+        )
+        .unwrap();
+        assert!(!net.exposes_guest_mac());
+    }
+
+    #[test]
+    fn test_set_tap() {
+        let mut net = Net::default_net(TestMutators::default());
+        let old_tap_fd = net.tap().as_raw_fd();
+
+        let new_tap = Tap::open_named("net-swap1").unwrap();
+        let new_tap_fd = new_tap.as_raw_fd();
+        let old_tap = net.set_tap(new_tap).unwrap();
+        assert_eq!(old_tap.as_raw_fd(), old_tap_fd);
+        assert_eq!(net.tap().as_raw_fd(), new_tap_fd);
+    }
+
+    #[test]
+    fn test_swap_tap() {
+        let mut event_manager = EventManager::new().unwrap();
+        let net = Arc::new(Mutex::new(Net::default_net(TestMutators::default())));
+        event_manager.add_subscriber(net.clone()).unwrap();
+
+        let mut locked_net = net.lock().unwrap();
+        let old_tap_fd = locked_net.tap().as_raw_fd();
+        let new_tap = Tap::open_named("net-swap2").unwrap();
+        let new_tap_fd = new_tap.as_raw_fd();
+        locked_net.swap_tap(new_tap, &mut event_manager).unwrap();
+
+        assert_eq!(locked_net.tap().as_raw_fd(), new_tap_fd);
+        assert!(event_manager.subscriber(new_tap_fd).is_ok());
+        assert!(event_manager.subscriber(old_tap_fd).is_err());
+    }
+
     #[test]
     fn test_tx_queue_interrupt() {
         // Regression test for https://github.com/firecracker-microvm/firecracker/issues/1436 .
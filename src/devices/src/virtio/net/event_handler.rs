@@ -6,10 +6,37 @@ use std::os::unix::io::AsRawFd;
 use logger::{Metric, METRICS};
 use polly::event_manager::{EventManager, Subscriber};
 use utils::epoll::{EpollEvent, EventSet};
+use utils::net::Tap;
 
 use crate::virtio::net::device::Net;
+use crate::virtio::net::{Error, Result};
 use crate::virtio::{VirtioDevice, RX_INDEX, TX_INDEX};
 
+impl Net {
+    fn tap_epoll_event(&self) -> EpollEvent {
+        EpollEvent::new(
+            EventSet::IN | EventSet::EDGE_TRIGGERED,
+            self.tap.as_raw_fd() as u64,
+        )
+    }
+
+    /// Replaces the tap device backing this interface and moves the tap event
+    /// registration from the old tap over to the new one.
+    pub fn swap_tap(&mut self, tap: Tap, event_manager: &mut EventManager) -> Result<()> {
+        let subscriber = event_manager
+            .subscriber(self.tap.as_raw_fd())
+            .map_err(Error::EventManager)?;
+
+        let old_tap = self.set_tap(tap)?;
+        event_manager
+            .unregister(old_tap.as_raw_fd())
+            .map_err(Error::EventManager)?;
+        event_manager
+            .register(self.tap.as_raw_fd(), self.tap_epoll_event(), subscriber)
+            .map_err(Error::EventManager)
+    }
+}
+
 impl Subscriber for Net {
     fn process(&mut self, event: &EpollEvent, _: &mut EventManager) {
         if !self.is_activated() {
@@ -36,6 +63,8 @@ impl Subscriber for Net {
         let rx_rate_limiter_fd = self.rx_rate_limiter.as_raw_fd();
         let tx_rate_limiter_fd = self.tx_rate_limiter.as_raw_fd();
         let tap_fd = self.tap.as_raw_fd();
+        let mmds_update_fd = self.mmds_update_evt.as_ref().map(|evt| evt.as_raw_fd());
+        let mmds_wait_timer_fd = self.mmds_wait_timer.as_ref().map(|timer| timer.as_raw_fd());
 
         match source {
             _ if source == virtq_rx_ev_fd => self.process_rx_queue_event(),
@@ -43,6 +72,9 @@ impl Subscriber for Net {
             _ if source == virtq_tx_ev_fd => self.process_tx_queue_event(),
             _ if source == rx_rate_limiter_fd => self.process_rx_rate_limiter_event(),
             _ if source == tx_rate_limiter_fd => self.process_tx_rate_limiter_event(),
+            _ if Some(source) == mmds_update_fd || Some(source) == mmds_wait_timer_fd => {
+                self.process_mmds_event()
+            }
             _ => {
                 error!("Unknown event source.");
                 METRICS.net.event_fails.inc();
@@ -51,15 +83,19 @@ impl Subscriber for Net {
     }
 
     fn interest_list(&self) -> Vec<EpollEvent> {
-        vec![
-            EpollEvent::new(
-                EventSet::IN | EventSet::EDGE_TRIGGERED,
-                self.tap.as_raw_fd() as u64,
-            ),
+        let mut events = vec![
+            self.tap_epoll_event(),
             EpollEvent::new(EventSet::IN, self.queue_evts[RX_INDEX].as_raw_fd() as u64),
             EpollEvent::new(EventSet::IN, self.queue_evts[TX_INDEX].as_raw_fd() as u64),
             EpollEvent::new(EventSet::IN, self.rx_rate_limiter.as_raw_fd() as u64),
             EpollEvent::new(EventSet::IN, self.tx_rate_limiter.as_raw_fd() as u64),
-        ]
+        ];
+        if let Some(evt) = self.mmds_update_evt.as_ref() {
+            events.push(EpollEvent::new(EventSet::IN, evt.as_raw_fd() as u64));
+        }
+        if let Some(timer) = self.mmds_wait_timer.as_ref() {
+            events.push(EpollEvent::new(EventSet::IN, timer.as_raw_fd() as u64));
+        }
+        events
     }
 }
@@ -1,6 +1,7 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use polly::event_manager::Error as EventManagerError;
 use std::{io, result};
 use utils::net::TapError;
 
@@ -31,6 +32,10 @@ pub enum Error {
     TapEnable(TapError),
     /// EventFd
     EventFd(io::Error),
+    /// Creating the MMDS long-poll timer failed.
+    TimerFd(io::Error),
+    /// Moving the tap event registration failed.
+    EventManager(EventManagerError),
 }
 
 pub type Result<T> = result::Result<T, Error>;
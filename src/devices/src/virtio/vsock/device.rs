@@ -275,6 +275,11 @@ where
     fn is_activated(&self) -> bool {
         self.device_activated
     }
+
+    fn restart(&mut self) {
+        self.acked_features = 0;
+        self.backend.reset();
+    }
 }
 
 #[cfg(test)]
@@ -352,5 +357,12 @@ mod tests {
 
         // Test a correct activation.
         ctx.device.activate().unwrap();
+
+        // Restarting the device drops the acked features and the connections, while it stays
+        // activated.
+        ctx.device.restart();
+        assert_eq!(ctx.device.acked_features, 0);
+        assert_eq!(ctx.device.backend.reset_cnt, 1);
+        assert!(ctx.device.is_activated());
     }
 }
@@ -147,7 +147,10 @@ pub trait VsockChannel {
 /// The vsock backend, which is basically an epoll-event-driven vsock channel.
 /// Currently, the only implementation we have is `crate::virtio::unix::muxer::VsockMuxer`, which
 /// translates guest-side vsock connections to host-side Unix domain socket connections.
-pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {}
+pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {
+    /// Drops all the connections, for a guest which restarted and no longer knows about them.
+    fn reset(&mut self);
+}
 
 #[cfg(test)]
 mod tests {
@@ -170,6 +173,7 @@ mod tests {
         pub pending_rx: bool,
         pub rx_ok_cnt: usize,
         pub tx_ok_cnt: usize,
+        pub reset_cnt: usize,
         pub evset: Option<EventSet>,
     }
 
@@ -182,6 +186,7 @@ mod tests {
                 pending_rx: false,
                 rx_ok_cnt: 0,
                 tx_ok_cnt: 0,
+                reset_cnt: 0,
                 evset: None,
             }
         }
@@ -242,7 +247,11 @@ mod tests {
             self.evset = Some(evset);
         }
     }
-    impl VsockBackend for TestBackend {}
+    impl VsockBackend for TestBackend {
+        fn reset(&mut self) {
+            self.reset_cnt += 1;
+        }
+    }
 
     pub struct TestContext {
         pub cid: u64,
@@ -297,7 +297,19 @@ impl VsockEpollListener for VsockMuxer {
     }
 }
 
-impl VsockBackend for VsockMuxer {}
+impl VsockBackend for VsockMuxer {
+    /// Closes the host side of all the connections. The host-initiated connections which were
+    /// not forwarded to the guest yet are kept, and reach the restarted guest.
+    fn reset(&mut self) {
+        let keys: Vec<ConnMapKey> = self.conn_map.keys().copied().collect();
+        for key in keys {
+            self.remove_connection(key);
+        }
+        // The pending packets and kill timers are about the dropped connections.
+        self.rxq = MuxerRxQ::new();
+        self.killq = MuxerKillQ::new();
+    }
+}
 
 impl VsockMuxer {
     /// Muxer constructor.
@@ -1294,4 +1306,30 @@ mod tests {
 
         assert!(!ctx.muxer.has_pending_rx());
     }
+
+    #[test]
+    fn test_muxer_reset() {
+        let peer_port = 1025;
+        let mut ctx = MuxerTestContext::new("muxer_reset");
+        let (mut stream, local_port) = ctx.local_connect(peer_port);
+        // Leave some data pending for the guest.
+        stream.write_all(&[1, 2, 3, 4]).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+
+        ctx.muxer.reset();
+        assert!(ctx.muxer.conn_map.is_empty());
+        assert!(!ctx.muxer.local_port_set.contains(&local_port));
+        assert_eq!(ctx.count_epoll_listeners(), (0, 0));
+        assert!(!ctx.muxer.has_pending_rx());
+        // The host side of the connection was closed, with the data left unread.
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            std::io::ErrorKind::ConnectionReset
+        );
+
+        // The host can connect to the restarted guest.
+        ctx.local_connect(peer_port);
+    }
 }
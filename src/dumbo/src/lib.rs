@@ -3,7 +3,8 @@
 
 #![deny(missing_docs)]
 //! Provides helper logic for parsing and writing protocol data units, and minimalist
-//! implementations of a TCP listener, a TCP connection, and an HTTP/1.1 server.
+//! implementations of a TCP listener, a TCP connection, an HTTP/1.1 server, and a couple of UDP
+//! services.
 
 #[macro_use]
 extern crate bitflags;
@@ -17,15 +18,20 @@ mod mac;
 pub mod ns;
 mod pdu;
 mod tcp;
+mod udp;
 
 pub use mac::{MacAddr, MAC_ADDR_LEN};
 pub use pdu::arp::{EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
 pub use pdu::ethernet::{
-    EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, PAYLOAD_OFFSET as ETHERNET_PAYLOAD_OFFSET,
+    EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6,
+    PAYLOAD_OFFSET as ETHERNET_PAYLOAD_OFFSET,
 };
 pub use pdu::ipv4::{IPv4Packet, PROTOCOL_TCP, PROTOCOL_UDP};
+pub use pdu::ipv6::{IPv6Packet, PROTOCOL_ICMPV6};
+pub use pdu::ndp::NdpMessage;
 pub use pdu::udp::{UdpDatagram, UDP_HEADER_SIZE};
 use std::ops::Index;
+pub use udp::{DnsResponder, SntpResponder, UdpService, DNS_PORT, MAX_REPLY_SIZE, NTP_PORT};
 
 /// Represents a generalization of a borrowed `[u8]` slice.
 #[allow(clippy::len_without_is_empty)]
@@ -4,27 +4,43 @@
 // TODO: get rid of this when splitting dumbo into public and internal parts.
 #![allow(missing_docs)]
 
+use std::collections::VecDeque;
 use std::convert::From;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::result::Result;
+use std::time::Instant;
 
 use crate::MacAddr;
 use logger::{Metric, METRICS};
 use pdu::arp::{test_speculative_tpa, Error as ArpFrameError, EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
-use pdu::ethernet::{Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4};
-use pdu::ipv4::{test_speculative_dst_addr, Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP};
+use pdu::ethernet::{
+    Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6,
+};
+use pdu::ipv4::{
+    test_speculative_dst_addr, Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP, PROTOCOL_UDP,
+};
+use pdu::ipv6::{self, Error as IPv6PacketError, IPv6Packet, PROTOCOL_ICMPV6};
+use pdu::ndp::{
+    test_speculative_target_addr, Error as NdpMessageError, NdpMessage,
+    ETH_NEIGHBOR_ADVERTISEMENT_LEN, FLAG_OVERRIDE, FLAG_SOLICITED, NDP_HOP_LIMIT,
+};
 use pdu::tcp::Error as TcpSegmentError;
+use pdu::udp::{Error as UdpDatagramError, UdpDatagram, UDP_HEADER_SIZE};
 use pdu::Incomplete;
-use tcp::handler::{self, RecvEvent, TcpIPv4Handler, WriteEvent};
-use tcp::NextSegmentStatus;
+use tcp::handler::{self, RecvError, RecvEvent, TcpIPHandler, WriteEvent};
+use tcp::{NextSegmentStatus, DEFAULT_MAX_BODY_SIZE};
+use udp::{UdpService, MAX_REPLY_SIZE};
 use utils::time::timestamp_cycles;
 
 const DEFAULT_MAC_ADDR: &str = "06:01:23:45:67:01";
 const DEFAULT_IPV4_ADDR: [u8; 4] = [169, 254, 169, 254];
+const DEFAULT_IPV6_ADDR: [u16; 8] = [0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe];
 const DEFAULT_TCP_PORT: u16 = 80;
 const DEFAULT_MAX_CONNECTIONS: usize = 30;
 const DEFAULT_MAX_PENDING_RESETS: usize = 100;
+// Replies produced by the UDP services are dropped while this many are waiting to be sent.
+const MAX_PENDING_UDP_REPLIES: usize = 16;
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
 enum WriteArpFrameError {
@@ -32,81 +48,138 @@ enum WriteArpFrameError {
     Ethernet(EthernetFrameError),
 }
 
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum WriteNdpFrameError {
+    Ethernet(EthernetFrameError),
+    IPv6Packet(IPv6PacketError),
+    Ndp(NdpMessageError),
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 enum WritePacketError {
     IPv4Packet(IPv4PacketError),
+    IPv6Packet(IPv6PacketError),
     Ethernet(EthernetFrameError),
     TcpSegment(TcpSegmentError),
+    UdpDatagram(UdpDatagramError),
 }
 
 impl From<handler::WriteNextError> for WritePacketError {
     fn from(error: handler::WriteNextError) -> Self {
         match error {
             handler::WriteNextError::IPv4Packet(inner) => WritePacketError::IPv4Packet(inner),
+            handler::WriteNextError::IPv6Packet(inner) => WritePacketError::IPv6Packet(inner),
             handler::WriteNextError::TcpSegment(inner) => WritePacketError::TcpSegment(inner),
         }
     }
 }
 
+// A reply produced by a UDP service, waiting to be sent to the guest over IPv4.
+struct UdpReply {
+    remote_addr: Ipv4Addr,
+    remote_port: u16,
+    local_port: u16,
+    payload: Vec<u8>,
+}
+
 pub struct MmdsNetworkStack {
     // The Ethernet MAC address of the MMDS server.
     mac_addr: MacAddr,
     // Whenever we detour a frame, we update the value of the remote MAC address. We need this
-    // because we don't send ARP request ourselves.
+    // because we don't send ARP requests or neighbor solicitations ourselves.
     remote_mac_addr: MacAddr,
     // The IPv4 address of the MMDS server.
     ipv4_addr: Ipv4Addr,
+    // The (link-local) IPv6 address of the MMDS server.
+    ipv6_addr: Ipv6Addr,
     // We only remember the most recently received ARP request, and store the remote IPv4 address
     // here (we keep the remote MAC address in self.remote_mac_addr), to be used when the next
     // opportunity to send a frame presents itself.
     pending_arp_reply: Option<Ipv4Addr>,
-    // This handles MMDS<->guest interaction at the TCP level.
-    tcp_handler: TcpIPv4Handler,
+    // Same as above, but for the most recently received neighbor solicitation.
+    pending_ndp_reply: Option<Ipv6Addr>,
+    // This handles MMDS<->guest interaction at the TCP level, over IPv4.
+    tcp_handler: TcpIPHandler,
+    // This handles MMDS<->guest interaction at the TCP level, over IPv6.
+    tcp_ipv6_handler: TcpIPHandler,
+    // The services which answer UDP requests sent to the IPv4 address of the MMDS.
+    udp_services: Vec<Box<dyn UdpService + Send>>,
+    // The replies of the UDP services, in the order they should be sent.
+    pending_udp_replies: VecDeque<UdpReply>,
 }
 
 impl MmdsNetworkStack {
     pub fn new(
         mac_addr: MacAddr,
         ipv4_addr: Ipv4Addr,
+        ipv6_addr: Ipv6Addr,
         tcp_port: u16,
         max_connections: NonZeroUsize,
         max_pending_resets: NonZeroUsize,
+        max_body_size: usize,
     ) -> Self {
         MmdsNetworkStack {
             mac_addr,
             remote_mac_addr: mac_addr,
             ipv4_addr,
+            ipv6_addr,
             pending_arp_reply: None,
-            tcp_handler: TcpIPv4Handler::new(
-                ipv4_addr,
+            pending_ndp_reply: None,
+            tcp_handler: TcpIPHandler::new(
+                IpAddr::from(ipv4_addr),
+                tcp_port,
+                max_connections,
+                max_pending_resets,
+                max_body_size,
+            ),
+            tcp_ipv6_handler: TcpIPHandler::new(
+                IpAddr::from(ipv6_addr),
                 tcp_port,
                 max_connections,
                 max_pending_resets,
+                max_body_size,
             ),
+            udp_services: Vec::new(),
+            pending_udp_replies: VecDeque::new(),
         }
     }
 
-    pub fn new_with_defaults() -> Self {
+    /// Creates a stack with the default MAC address and limits. The stack answers on
+    /// `ipv4_addr` and `ipv6_addr` when they are provided, and on `169.254.169.254` and
+    /// `fe80::a9fe:a9fe` otherwise. The stack serves HTTP only, until UDP services are added.
+    pub fn new_with_defaults(ipv4_addr: Option<Ipv4Addr>, ipv6_addr: Option<Ipv6Addr>) -> Self {
         // The unwrap is safe if parse_str() is implemented properly.
         let mac_addr = MacAddr::parse_str(DEFAULT_MAC_ADDR).unwrap();
-        let ipv4_addr = Ipv4Addr::from(DEFAULT_IPV4_ADDR);
+        let ipv4_addr = ipv4_addr.unwrap_or_else(|| Ipv4Addr::from(DEFAULT_IPV4_ADDR));
+        let ipv6_addr = ipv6_addr.unwrap_or_else(|| Ipv6Addr::from(DEFAULT_IPV6_ADDR));
 
         // The unwrap()s are safe because the given literals are greater than 0.
         Self::new(
             mac_addr,
             ipv4_addr,
+            ipv6_addr,
             DEFAULT_TCP_PORT,
             NonZeroUsize::new(DEFAULT_MAX_CONNECTIONS).unwrap(),
             NonZeroUsize::new(DEFAULT_MAX_PENDING_RESETS).unwrap(),
+            DEFAULT_MAX_BODY_SIZE,
         )
     }
 
+    /// Registers a service which answers the UDP requests sent to its port at the IPv4 address
+    /// of the MMDS. When several services use the same port, the first one registered wins.
+    pub fn add_udp_service(&mut self, service: Box<dyn UdpService + Send>) {
+        self.udp_services.push(service);
+    }
+
     // This is the entry point into the MMDS network stack. The src slice should hold the contents
     // of an Ethernet frame (of that exact size, without the CRC).
     pub fn detour_frame(&mut self, src: &[u8]) -> bool {
-        // The frame cannot possibly contain an ARP request or IPv4 packet for the MMDS.
+        // The frame cannot possibly contain an ARP request, neighbor solicitation, or IP packet
+        // for the MMDS.
         if !test_speculative_tpa(src, self.ipv4_addr)
             && !test_speculative_dst_addr(src, self.ipv4_addr)
+            && !test_speculative_target_addr(src, self.ipv6_addr)
+            && !ipv6::test_speculative_dst_addr(src, self.ipv6_addr)
         {
             return false;
         }
@@ -115,6 +188,7 @@ impl MmdsNetworkStack {
             match eth.ethertype() {
                 ETHERTYPE_ARP => return self.detour_arp(eth),
                 ETHERTYPE_IPV4 => return self.detour_ipv4(eth),
+                ETHERTYPE_IPV6 => return self.detour_ipv6(eth),
                 _ => (),
             };
         } else {
@@ -140,29 +214,92 @@ impl MmdsNetworkStack {
         // context at some point!
         if let Ok(ip) = IPv4Packet::from_bytes(eth.payload(), false) {
             if ip.destination_address() == self.ipv4_addr {
-                if ip.protocol() == PROTOCOL_TCP {
-                    self.remote_mac_addr = eth.src_mac();
-                    match self.tcp_handler.receive_packet(&ip) {
-                        Ok(event) => {
-                            METRICS.mmds.rx_count.inc();
-                            match event {
-                                RecvEvent::NewConnectionSuccessful => {
-                                    METRICS.mmds.connections_created.inc()
-                                }
-                                RecvEvent::NewConnectionReplacing => {
-                                    METRICS.mmds.connections_created.inc();
-                                    METRICS.mmds.connections_destroyed.inc();
-                                }
-                                RecvEvent::EndpointDone => {
-                                    METRICS.mmds.connections_destroyed.inc();
-                                }
-                                _ => (),
-                            }
-                        }
-                        Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+                match ip.protocol() {
+                    PROTOCOL_TCP => {
+                        self.remote_mac_addr = eth.src_mac();
+                        Self::record_tcp_recv(self.tcp_handler.receive_packet(&ip));
                     }
+                    PROTOCOL_UDP if self.has_udp_service(&ip) => {
+                        self.remote_mac_addr = eth.src_mac();
+                        self.receive_udp(&ip);
+                    }
+                    // Any other IPv4 packet heading towards the MMDS (including UDP packets which
+                    // no service is interested in); we consider it unusual.
+                    _ => METRICS.mmds.rx_accepted_unusual.inc(),
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // Checks whether a UDP service listens on the destination port of the datagram carried by
+    // `ip`.
+    fn has_udp_service(&self, ip: &IPv4Packet<&[u8]>) -> bool {
+        UdpDatagram::from_bytes(ip.payload(), None)
+            .map(|datagram| {
+                self.udp_service_index(datagram.destination_port())
+                    .is_some()
+            })
+            .unwrap_or(false)
+    }
+
+    fn udp_service_index(&self, port: u16) -> Option<usize> {
+        self.udp_services
+            .iter()
+            .position(|service| service.port() == port)
+    }
+
+    // Hands over the UDP request carried by `ip` to the service listening on its destination
+    // port, and queues the reply (if any).
+    fn receive_udp(&mut self, ip: &IPv4Packet<&[u8]>) {
+        // As for TCP, we skip verifying the checksum. The unwrap()s are safe because we only get
+        // here after has_udp_service() returns true.
+        let datagram = UdpDatagram::from_bytes(ip.payload(), None).unwrap();
+        let local_port = datagram.destination_port();
+        let index = self.udp_service_index(local_port).unwrap();
+
+        METRICS.mmds.rx_udp_requests.inc();
+        // The length from the header leaves out any padding added to the frame.
+        let request = match (datagram.len() as usize).checked_sub(UDP_HEADER_SIZE) {
+            Some(len) if len <= datagram.payload().len() => &datagram.payload()[..len],
+            _ => {
+                METRICS.mmds.rx_accepted_err.inc();
+                return;
+            }
+        };
+
+        let mut payload = vec![0u8; MAX_REPLY_SIZE];
+        if let Some(len) = self.udp_services[index].handle_request(request, &mut payload) {
+            if self.pending_udp_replies.len() >= MAX_PENDING_UDP_REPLIES {
+                METRICS.mmds.rx_accepted_err.inc();
+                return;
+            }
+            payload.truncate(len);
+            self.pending_udp_replies.push_back(UdpReply {
+                remote_addr: ip.source_address(),
+                remote_port: datagram.source_port(),
+                local_port,
+                payload,
+            });
+        }
+    }
+
+    fn detour_ipv6(&mut self, eth: EthernetFrame<&[u8]>) -> bool {
+        if let Ok(ip) = IPv6Packet::from_bytes(eth.payload()) {
+            // Neighbor solicitations are usually sent to a multicast address, so we check them
+            // before looking at the destination address.
+            if ip.next_header() == PROTOCOL_ICMPV6 && self.detour_ndp(eth.src_mac(), &ip) {
+                return true;
+            }
+
+            if ip.destination_address() == self.ipv6_addr {
+                if ip.next_header() == PROTOCOL_TCP {
+                    self.remote_mac_addr = eth.src_mac();
+                    Self::record_tcp_recv(self.tcp_ipv6_handler.receive_ipv6_packet(&ip));
                 } else {
-                    // A non-TCP IPv4 packet heading towards the MMDS; we consider it unusual.
+                    // A non-TCP IPv6 packet (which is not a neighbor solicitation either) heading
+                    // towards the MMDS; we consider it unusual.
                     METRICS.mmds.rx_accepted_unusual.inc();
                 }
                 return true;
@@ -171,45 +308,114 @@ impl MmdsNetworkStack {
         false
     }
 
+    fn detour_ndp(&mut self, src_mac: MacAddr, ip: &IPv6Packet<&[u8]>) -> bool {
+        // Neighbor solicitations which may have crossed a router must be ignored. We also leave
+        // duplicate address detection probes (sent from the unspecified address) alone.
+        if ip.hop_limit() != NDP_HOP_LIMIT || ip.source_address().is_unspecified() {
+            return false;
+        }
+
+        // Unlike the TCP checksum, the ICMPv6 checksum is never offloaded, so we can verify it.
+        if let Ok(ns) = NdpMessage::solicitation_from_bytes(
+            ip.payload(),
+            Some((ip.source_address(), ip.destination_address())),
+        ) {
+            if ns.target_address() == self.ipv6_addr {
+                self.remote_mac_addr = ns.link_layer_addr().unwrap_or(src_mac);
+                self.pending_ndp_reply = Some(ip.source_address());
+                return true;
+            }
+        }
+        false
+    }
+
+    // Updates the MMDS metrics based on the outcome of handing over a packet to a TCP handler.
+    fn record_tcp_recv(result: Result<RecvEvent, RecvError>) {
+        match result {
+            Ok(event) => {
+                METRICS.mmds.rx_count.inc();
+                match event {
+                    RecvEvent::NewConnectionSuccessful => METRICS.mmds.connections_created.inc(),
+                    RecvEvent::NewConnectionReplacing => {
+                        METRICS.mmds.connections_created.inc();
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    RecvEvent::EndpointDone => {
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    _ => (),
+                }
+            }
+            Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+        }
+    }
+
     // Allows the MMDS network stack to write a frame to the specified buffer. Will return:
     // - None, if the MMDS network stack has no frame to send at this point. The buffer can be
     // used for something else by the device model.
     // - Some(len), if a frame of the given length has been written to the specified buffer.
     pub fn write_next_frame(&mut self, buf: &mut [u8]) -> Option<NonZeroUsize> {
-        // We try to send ARP replies first.
-        if let Some(spa) = self.pending_arp_reply {
-            return match self.write_arp_reply(buf, spa) {
-                Ok(something) => {
-                    METRICS.mmds.tx_count.inc();
+        // We try to send ARP replies and neighbor advertisements first.
+        let result = if let Some(spa) = self.pending_arp_reply {
+            self.write_arp_reply(buf, spa)
+                .map(|something| {
                     self.pending_arp_reply = None;
                     something
-                }
-                Err(_) => {
-                    METRICS.mmds.tx_errors.inc();
-                    None
-                }
-            };
+                })
+                .map_err(|_| ())
+        } else if let Some(dst_ipv6) = self.pending_ndp_reply {
+            self.write_neighbor_advertisement(buf, dst_ipv6)
+                .map(|something| {
+                    self.pending_ndp_reply = None;
+                    something
+                })
+                .map_err(|_| ())
+        } else if let Some(reply) = self.pending_udp_replies.pop_front() {
+            self.write_udp_reply(buf, &reply).map_err(|_| ())
+        } else if Self::should_call_write(&self.tcp_handler) {
+            self.write_packet(buf, ETHERTYPE_IPV4).map_err(|_| ())
+        } else if Self::should_call_write(&self.tcp_ipv6_handler) {
+            self.write_packet(buf, ETHERTYPE_IPV6).map_err(|_| ())
         } else {
-            let call_write = match self.tcp_handler.next_segment_status() {
-                NextSegmentStatus::Available => true,
-                NextSegmentStatus::Timeout(value) => timestamp_cycles() >= value,
-                NextSegmentStatus::Nothing => false,
-            };
+            return None;
+        };
 
-            if call_write {
-                return match self.write_packet(buf) {
-                    Ok(something) => {
-                        METRICS.mmds.tx_count.inc();
-                        something
-                    }
-                    Err(_) => {
-                        METRICS.mmds.tx_errors.inc();
-                        None
-                    }
-                };
+        match result {
+            Ok(something) => {
+                METRICS.mmds.tx_count.inc();
+                something
+            }
+            Err(()) => {
+                METRICS.mmds.tx_errors.inc();
+                None
             }
         }
-        None
+    }
+
+    /// Answers the long-poll requests whose wait is over, because the MMDS got updated or they
+    /// timed out. The responses are sent by the next calls to `write_next_frame`.
+    pub fn check_waiting_requests(&mut self) {
+        self.tcp_handler.check_waiting_requests();
+        self.tcp_ipv6_handler.check_waiting_requests();
+    }
+
+    /// Returns the closest moment into the future when the wait of a long-poll request times out.
+    pub fn next_wait_deadline(&self) -> Option<Instant> {
+        match (
+            self.tcp_handler.next_wait_deadline(),
+            self.tcp_ipv6_handler.next_wait_deadline(),
+        ) {
+            (Some(first), Some(second)) => Some(first.min(second)),
+            (first, second) => first.or(second),
+        }
+    }
+
+    fn should_call_write(tcp_handler: &TcpIPHandler) -> bool {
+        match tcp_handler.next_segment_status() {
+            NextSegmentStatus::Available => true,
+            NextSegmentStatus::Timeout(value) => timestamp_cycles() >= value,
+            NextSegmentStatus::Nothing => false,
+        }
     }
 
     fn prepare_eth_unsized<'a>(
@@ -249,14 +455,110 @@ impl MmdsNetworkStack {
         ))
     }
 
-    fn write_packet(&mut self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WritePacketError> {
+    fn write_neighbor_advertisement(
+        &self,
+        buf: &mut [u8],
+        dst_ipv6: Ipv6Addr,
+    ) -> Result<Option<NonZeroUsize>, WriteNdpFrameError> {
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ETHERTYPE_IPV6)
+            .map_err(WriteNdpFrameError::Ethernet)?;
+
+        let packet_len = {
+            let mut packet = IPv6Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_ICMPV6,
+                self.ipv6_addr,
+                dst_ipv6,
+            )
+            .map_err(WriteNdpFrameError::IPv6Packet)?;
+            packet.inner_mut().set_hop_limit(NDP_HOP_LIMIT);
+
+            let ndp_len = NdpMessage::write_advertisement(
+                packet
+                    .inner_mut()
+                    .payload_mut()
+                    .get_mut(..ETH_NEIGHBOR_ADVERTISEMENT_LEN)
+                    .ok_or(WriteNdpFrameError::Ndp(NdpMessageError::SliceExactLen))?,
+                FLAG_SOLICITED | FLAG_OVERRIDE,
+                self.ipv6_addr,
+                self.mac_addr,
+                self.ipv6_addr,
+                dst_ipv6,
+            )
+            .map_err(WriteNdpFrameError::Ndp)?
+            .len();
+
+            packet.with_payload_len_unchecked(ndp_len).len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
+    fn write_udp_reply(
+        &self,
+        buf: &mut [u8],
+        reply: &UdpReply,
+    ) -> Result<Option<NonZeroUsize>, WritePacketError> {
         let mut eth_unsized = self
             .prepare_eth_unsized(buf, ETHERTYPE_IPV4)
             .map_err(WritePacketError::Ethernet)?;
 
-        let (maybe_len, event) = self
-            .tcp_handler
-            .write_next_packet(eth_unsized.inner_mut().payload_mut())?;
+        let packet_len = {
+            let mut packet = IPv4Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_UDP,
+                self.ipv4_addr,
+                reply.remote_addr,
+            )
+            .map_err(WritePacketError::IPv4Packet)?;
+
+            let datagram_len = UdpDatagram::write_incomplete_datagram(
+                packet.inner_mut().payload_mut(),
+                &reply.payload,
+            )
+            .map_err(WritePacketError::UdpDatagram)?
+            .finalize(
+                reply.local_port,
+                reply.remote_port,
+                Some((
+                    IpAddr::from(self.ipv4_addr),
+                    IpAddr::from(reply.remote_addr),
+                )),
+            )
+            .len();
+
+            packet
+                .with_payload_len_unchecked(datagram_len as usize, true)
+                .len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
+    fn write_packet(
+        &mut self,
+        buf: &mut [u8],
+        ethertype: u16,
+    ) -> Result<Option<NonZeroUsize>, WritePacketError> {
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ethertype)
+            .map_err(WritePacketError::Ethernet)?;
+
+        let tcp_handler = if ethertype == ETHERTYPE_IPV6 {
+            &mut self.tcp_ipv6_handler
+        } else {
+            &mut self.tcp_handler
+        };
+
+        let (maybe_len, event) =
+            tcp_handler.write_next_packet(eth_unsized.inner_mut().payload_mut())?;
 
         if let WriteEvent::EndpointDone = event {
             METRICS.mmds.connections_destroyed.inc()
@@ -281,12 +583,16 @@ impl MmdsNetworkStack {
 mod tests {
     use super::*;
 
+    use pdu::ndp::TYPE_NEIGHBOR_ADVERTISEMENT;
     use pdu::tcp::{Flags as TcpFlags, TcpSegment};
     use std::str::FromStr;
+    use udp::{SntpResponder, NTP_PORT};
 
     // We use LOCALHOST here because const new() is not stable yet, so just reuse this const, since
     // all we're interested in is having some address different from the MMDS one.
     const REMOTE_ADDR: Ipv4Addr = Ipv4Addr::LOCALHOST;
+    const REMOTE_IPV6_ADDR: Ipv6Addr =
+        Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1311, 0x11ff, 0xfe22, 0x2222);
     const REMOTE_MAC_STR: &str = "11:11:11:22:22:22";
     const MMDS_PORT: u16 = 80;
     const REMOTE_PORT: u16 = 1235;
@@ -340,11 +646,16 @@ mod tests {
                     flags,
                     10000,
                     None,
+                    None,
                     0,
                     None,
                 )
                 .unwrap()
-                .finalize(REMOTE_PORT, MMDS_PORT, Some((REMOTE_ADDR, addr)))
+                .finalize(
+                    REMOTE_PORT,
+                    MMDS_PORT,
+                    Some((IpAddr::from(REMOTE_ADDR), IpAddr::from(addr))),
+                )
                 .len();
 
                 packet.with_payload_len_unchecked(segment_len, true).len()
@@ -358,12 +669,128 @@ mod tests {
             let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
             IPv4Packet::from_bytes(&buf[eth.payload_offset()..len], true).unwrap()
         }
+
+        fn write_neighbor_solicitation(
+            &self,
+            buf: &mut [u8],
+            target: Ipv6Addr,
+            hop_limit: u8,
+        ) -> usize {
+            // We write an advertisement, and then modify it into a solicitation which is sent to
+            // the solicited-node multicast address of the target.
+            let s = target.segments();
+            let dst = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00 | (s[6] & 0xff), s[7]);
+            let remote_mac = MacAddr::parse_str(REMOTE_MAC_STR).unwrap();
+
+            let len = self
+                .write_neighbor_advertisement(buf, REMOTE_IPV6_ADDR)
+                .unwrap()
+                .unwrap()
+                .get();
+
+            let mut eth = EthernetFrame::from_bytes_unchecked(&mut buf[..len]);
+            eth.set_src_mac(remote_mac);
+            let mut ip = IPv6Packet::from_bytes_unchecked(eth.payload_mut());
+            ip.set_source_address(REMOTE_IPV6_ADDR)
+                .set_destination_address(dst)
+                .set_hop_limit(hop_limit);
+
+            let mut ns = NdpMessage::from_bytes_unchecked(ip.payload_mut());
+            ns.set_message_type(135)
+                .set_flags(0)
+                .set_target_address(target)
+                .set_link_layer_addr_option(1, remote_mac)
+                .set_checksum(0);
+            let checksum = ns.compute_checksum(REMOTE_IPV6_ADDR, dst);
+            ns.set_checksum(checksum);
+            len
+        }
+
+        fn write_incoming_ipv6_tcp_segment(
+            &self,
+            buf: &mut [u8],
+            addr: Ipv6Addr,
+            flags: TcpFlags,
+        ) -> usize {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_TCP,
+                    REMOTE_IPV6_ADDR,
+                    addr,
+                )
+                .unwrap();
+
+                let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
+                    packet.inner_mut().payload_mut(),
+                    SEQ_NUMBER,
+                    1234,
+                    flags,
+                    10000,
+                    None,
+                    None,
+                    0,
+                    None,
+                )
+                .unwrap()
+                .finalize(
+                    REMOTE_PORT,
+                    MMDS_PORT,
+                    Some((IpAddr::from(REMOTE_IPV6_ADDR), IpAddr::from(addr))),
+                )
+                .len();
+
+                packet.with_payload_len_unchecked(segment_len).len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_incoming_udp_datagram(
+            &self,
+            buf: &mut [u8],
+            dst_port: u16,
+            payload: &[u8],
+        ) -> usize {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV4).unwrap();
+            let packet_len = {
+                let mut packet = IPv4Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_UDP,
+                    REMOTE_ADDR,
+                    self.ipv4_addr,
+                )
+                .unwrap();
+
+                let datagram_len = UdpDatagram::write_incomplete_datagram(
+                    packet.inner_mut().payload_mut(),
+                    payload,
+                )
+                .unwrap()
+                .finalize(REMOTE_PORT, dst_port, None)
+                .len();
+
+                packet
+                    .with_payload_len_unchecked(datagram_len as usize, true)
+                    .len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn next_frame_as_ipv6_packet<'a>(&mut self, buf: &'a mut [u8]) -> IPv6Packet<&'a [u8]> {
+            let len = self.write_next_frame(buf).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+            IPv6Packet::from_bytes(&buf[eth.payload_offset()..len]).unwrap()
+        }
     }
 
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn test_ns() {
-        let mut ns = MmdsNetworkStack::new_with_defaults();
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, None);
         assert_eq!(ns.mac_addr, MacAddr::parse_str(DEFAULT_MAC_ADDR).unwrap());
         assert_eq!(ns.ipv4_addr, Ipv4Addr::from(DEFAULT_IPV4_ADDR));
 
@@ -447,7 +874,10 @@ mod tests {
 
             let s = TcpSegment::from_bytes(
                 ip.payload(),
-                Some((ip.source_address(), ip.destination_address())),
+                Some((
+                    IpAddr::from(ip.source_address()),
+                    IpAddr::from(ip.destination_address()),
+                )),
             )
             .unwrap();
             assert_eq!(s.flags_after_ns(), TcpFlags::RST);
@@ -472,7 +902,146 @@ mod tests {
 
             let s = TcpSegment::from_bytes(
                 ip.payload(),
-                Some((ip.source_address(), ip.destination_address())),
+                Some((
+                    IpAddr::from(ip.source_address()),
+                    IpAddr::from(ip.destination_address()),
+                )),
+            )
+            .unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.source_port(), MMDS_PORT);
+            assert_eq!(s.destination_port(), REMOTE_PORT);
+            assert_eq!(s.ack_number(), SEQ_NUMBER.wrapping_add(1));
+        }
+
+        // Nothing else to send.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+    }
+
+    #[test]
+    fn test_ns_custom_ipv4_addr() {
+        let custom_addr = Ipv4Addr::new(169, 254, 170, 2);
+        let mut ns = MmdsNetworkStack::new_with_defaults(Some(custom_addr), None);
+        assert_eq!(ns.ipv4_addr, custom_addr);
+
+        let mut buf = [0u8; 2000];
+
+        // ARP requests for the configured address are answered.
+        let len = ns.write_arp_request(buf.as_mut(), true);
+        assert!(ns.detour_frame(&buf[..len]));
+        let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+        let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(
+            EthIPv4ArpFrame::from_bytes_unchecked(eth.payload()).spa(),
+            custom_addr
+        );
+
+        // Traffic heading to the default address is left for the tap.
+        let len = ns.write_incoming_tcp_segment(
+            buf.as_mut(),
+            Ipv4Addr::from(DEFAULT_IPV4_ADDR),
+            TcpFlags::SYN,
+        );
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        let len = ns.write_incoming_tcp_segment(buf.as_mut(), custom_addr, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+    }
+
+    #[test]
+    fn test_ns_ipv6() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, None);
+        assert_eq!(ns.ipv6_addr, Ipv6Addr::from(DEFAULT_IPV6_ADDR));
+
+        let mut buf = [0u8; 2000];
+        let mut bad_buf = [0u8; 60];
+
+        let remote_mac = MacAddr::parse_str(REMOTE_MAC_STR).unwrap();
+        let mmds_mac = ns.mac_addr;
+        let mmds_addr = ns.ipv6_addr;
+        let bad_mmds_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+
+        // Not asking for the MMDS MAC address.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), bad_mmds_addr, NDP_HOP_LIMIT);
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // The solicitation may have crossed a router.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), mmds_addr, 64);
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // The checksum is wrong.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), mmds_addr, NDP_HOP_LIMIT);
+            buf[len - 1] ^= 0xff;
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // Asking for the MMDS MAC address.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), mmds_addr, NDP_HOP_LIMIT);
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(ns.remote_mac_addr, remote_mac);
+        }
+
+        // There should be a neighbor advertisement to send.
+        {
+            // Buffer is too small.
+            assert!(ns.write_next_frame(bad_buf.as_mut()).is_none());
+
+            let curr_tx_count = METRICS.mmds.tx_count.count();
+            let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+            assert_eq!(curr_tx_count + 1, METRICS.mmds.tx_count.count());
+            assert_eq!(ip.next_header(), PROTOCOL_ICMPV6);
+            assert_eq!(ip.hop_limit(), NDP_HOP_LIMIT);
+            assert_eq!(ip.source_address(), mmds_addr);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+
+            let na = NdpMessage::from_bytes_unchecked(ip.payload());
+            assert_eq!(na.message_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+            assert_eq!(na.flags(), FLAG_SOLICITED | FLAG_OVERRIDE);
+            assert_eq!(na.target_address(), mmds_addr);
+            assert_eq!(na.link_layer_addr(), Some(mmds_mac));
+            assert_eq!(na.compute_checksum(mmds_addr, REMOTE_IPV6_ADDR), 0);
+        }
+
+        // Nothing to send anymore.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // A TCP segment heading to the wrong address is not for us.
+        {
+            let len =
+                ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), bad_mmds_addr, TcpFlags::SYN);
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // Let's send a TCP SYN into the ns.
+        {
+            let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), mmds_addr, TcpFlags::SYN);
+            let curr_rx_count = METRICS.mmds.rx_count.count();
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(curr_rx_count + 1, METRICS.mmds.rx_count.count());
+        }
+
+        // We should be getting a SYNACK out of the ns in response.
+        {
+            let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+            assert_eq!(ip.source_address(), mmds_addr);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+
+            let s = TcpSegment::from_bytes(
+                ip.payload(),
+                Some((
+                    IpAddr::from(ip.source_address()),
+                    IpAddr::from(ip.destination_address()),
+                )),
             )
             .unwrap();
             assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
@@ -484,4 +1053,139 @@ mod tests {
         // Nothing else to send.
         assert!(ns.write_next_frame(buf.as_mut()).is_none());
     }
+
+    #[test]
+    fn test_ns_custom_ipv6_addr() {
+        let custom_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xaa02);
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, Some(custom_addr));
+        assert_eq!(ns.ipv6_addr, custom_addr);
+
+        let mut buf = [0u8; 2000];
+
+        // Neighbor solicitations for the configured address are answered.
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), custom_addr, NDP_HOP_LIMIT);
+        assert!(ns.detour_frame(&buf[..len]));
+        let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+        assert_eq!(ip.source_address(), custom_addr);
+        assert_eq!(
+            NdpMessage::from_bytes_unchecked(ip.payload()).target_address(),
+            custom_addr
+        );
+
+        // Traffic heading to the default address is left for the tap.
+        let len = ns.write_incoming_ipv6_tcp_segment(
+            buf.as_mut(),
+            Ipv6Addr::from(DEFAULT_IPV6_ADDR),
+            TcpFlags::SYN,
+        );
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), custom_addr, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+    }
+
+    // Answers requests with their payload reversed, and ignores empty ones.
+    struct ReverseService;
+
+    impl UdpService for ReverseService {
+        fn port(&self) -> u16 {
+            7
+        }
+
+        fn handle_request(&mut self, request: &[u8], reply: &mut [u8]) -> Option<usize> {
+            if request.is_empty() {
+                return None;
+            }
+            for (dst, src) in reply.iter_mut().zip(request.iter().rev()) {
+                *dst = *src;
+            }
+            Some(request.len())
+        }
+    }
+
+    #[test]
+    fn test_ns_udp_services() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, None);
+        ns.add_udp_service(Box::new(ReverseService));
+        let mmds_addr = ns.ipv4_addr;
+        let mut buf = [0u8; 2000];
+
+        // A request for a registered service gets answered.
+        {
+            let curr_rx_udp_requests = METRICS.mmds.rx_udp_requests.count();
+            let len = ns.write_incoming_udp_datagram(buf.as_mut(), 7, b"hello");
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(
+                curr_rx_udp_requests + 1,
+                METRICS.mmds.rx_udp_requests.count()
+            );
+
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            assert_eq!(ip.protocol(), PROTOCOL_UDP);
+            assert_eq!(ip.source_address(), mmds_addr);
+            assert_eq!(ip.destination_address(), REMOTE_ADDR);
+            let datagram = UdpDatagram::from_bytes(
+                ip.payload(),
+                Some((
+                    IpAddr::from(ip.source_address()),
+                    IpAddr::from(ip.destination_address()),
+                )),
+            )
+            .unwrap();
+            assert_eq!(datagram.source_port(), 7);
+            assert_eq!(datagram.destination_port(), REMOTE_PORT);
+            assert_eq!(datagram.payload(), b"olleh");
+        }
+
+        // No other service is registered by default.
+        {
+            let mut request = [0u8; 48];
+            // SNTP version 4, client mode.
+            request[0] = 0x23;
+            let len = ns.write_incoming_udp_datagram(buf.as_mut(), NTP_PORT, &request);
+            assert!(ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+            ns.add_udp_service(Box::new(SntpResponder::new()));
+            let len = ns.write_incoming_udp_datagram(buf.as_mut(), NTP_PORT, &request);
+            assert!(ns.detour_frame(&buf[..len]));
+
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            let datagram = UdpDatagram::from_bytes(ip.payload(), None).unwrap();
+            assert_eq!(datagram.source_port(), NTP_PORT);
+            assert_eq!(datagram.payload().len(), 48);
+            // Server mode.
+            assert_eq!(datagram.payload()[0], 0x24);
+        }
+
+        // Requests which the service ignores, and requests for ports without a service, do not
+        // get a reply.
+        {
+            let len = ns.write_incoming_udp_datagram(buf.as_mut(), 7, b"");
+            assert!(ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+            let curr_rx_accepted_unusual = METRICS.mmds.rx_accepted_unusual.count();
+            let len = ns.write_incoming_udp_datagram(buf.as_mut(), 9, b"hello");
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(
+                curr_rx_accepted_unusual + 1,
+                METRICS.mmds.rx_accepted_unusual.count()
+            );
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // Replies are dropped while too many of them are waiting to be sent.
+        {
+            for _ in 0..=MAX_PENDING_UDP_REPLIES {
+                let len = ns.write_incoming_udp_datagram(buf.as_mut(), 7, b"hello");
+                assert!(ns.detour_frame(&buf[..len]));
+            }
+            for _ in 0..MAX_PENDING_UDP_REPLIES {
+                let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+                assert_eq!(ip.protocol(), PROTOCOL_UDP);
+            }
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+    }
 }
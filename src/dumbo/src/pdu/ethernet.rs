@@ -16,7 +16,7 @@ const ETHERTYPE_OFFSET: usize = 12;
 
 // We don't support 802.1Q tags.
 // TODO: support 802.1Q tags?! If so, don't forget to change the speculative_test_* functions
-// for ARP and IPv4.
+// for ARP, IPv4, and IPv6.
 /// Payload offset in an ethernet frame
 pub const PAYLOAD_OFFSET: usize = 14;
 
@@ -24,6 +24,8 @@ pub const PAYLOAD_OFFSET: usize = 14;
 pub const ETHERTYPE_ARP: u16 = 0x0806;
 /// Ethertype value for IPv4 packets.
 pub const ETHERTYPE_IPV4: u16 = 0x0800;
+/// Ethertype value for IPv6 packets.
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
 
 /// Describes the errors which may occur when handling Ethernet frames.
 #[derive(Debug, PartialEq)]
@@ -7,15 +7,18 @@
 //! protocol. Ethernet frames, IP packets, and TCP segments are all examples of protocol data
 //! units.
 
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use pdu::bytes::NetworkBytes;
 use pdu::ipv4::{PROTOCOL_TCP, PROTOCOL_UDP};
+use pdu::ipv6::PROTOCOL_ICMPV6;
 
 pub mod arp;
 pub mod bytes;
 pub mod ethernet;
 pub mod ipv4;
+pub mod ipv6;
+pub mod ndp;
 pub mod tcp;
 pub mod udp;
 
@@ -55,39 +58,49 @@ impl<T> Incomplete<T> {
 enum ChecksumProto {
     Tcp = PROTOCOL_TCP,
     Udp = PROTOCOL_UDP,
+    Icmpv6 = PROTOCOL_ICMPV6,
 }
 
-/// Computes the checksum of a TCP/UDP packet. Since both protocols use
+// Returns the sum of the 16-bit words which make up `addr`, as it appears in the pseudo-header.
+#[inline]
+fn pseudo_header_addr_sum(addr: IpAddr) -> u32 {
+    match addr {
+        IpAddr::V4(addr) => {
+            let a = u32::from(addr);
+            (a & 0xffff) + (a >> 16)
+        }
+        IpAddr::V6(addr) => addr.segments().iter().map(|&s| u32::from(s)).sum(),
+    }
+}
+
+/// Computes the checksum of a TCP/UDP packet or ICMPv6 message. Since all of these protocols use
 /// the same algorithm to compute the checksum.
 ///
 /// # Arguments
-/// * `bytes` - Raw bytes of a TCP packet or a UDP datagram
-/// * `src_addr` - IPv4 source address
-/// * `dst_addr` - IPv4 destination address
-/// * `protocol` - **must** be either `PROTOCOL_TCP` or `PROTOCOL_UDP` defined in
-/// `ipv4` module
+/// * `bytes` - Raw bytes of a TCP packet, a UDP datagram, or an ICMPv6 message
+/// * `src_addr` - IPv4 or IPv6 source address
+/// * `dst_addr` - IPv4 or IPv6 destination address (same family as `src_addr`)
+/// * `protocol` - **must** be one of `PROTOCOL_TCP` or `PROTOCOL_UDP` defined in the `ipv4`
+/// module, or `PROTOCOL_ICMPV6` defined in the `ipv6` module
 ///
-/// More details about TCP checksum computation can be found [here].
+/// More details about TCP checksum computation can be found [here]. The IPv6 pseudo-header is
+/// described in [RFC 8200].
 ///
 /// [here]: https://en.wikipedia.org/wiki/Transmission_Control_Protocol#Checksum_computation
+/// [RFC 8200]: https://tools.ietf.org/html/rfc8200#section-8.1
 #[inline]
 fn compute_checksum<T: NetworkBytes>(
     bytes: &T,
-    src_addr: Ipv4Addr,
-    dst_addr: Ipv4Addr,
+    src_addr: IpAddr,
+    dst_addr: IpAddr,
     protocol: ChecksumProto,
 ) -> u16 {
     // TODO: Is u32 enough to prevent overflow for the code in this function? I think so, but it
     // would be nice to double-check.
     let mut sum = 0u32;
 
-    let a = u32::from(src_addr);
-    sum += a & 0xffff;
-    sum += a >> 16;
-
-    let b = u32::from(dst_addr);
-    sum += b & 0xffff;
-    sum += b >> 16;
+    sum += pseudo_header_addr_sum(src_addr);
+    sum += pseudo_header_addr_sum(dst_addr);
 
     let len = bytes.len();
     sum += protocol as u32;
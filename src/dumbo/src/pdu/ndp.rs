@@ -0,0 +1,424 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains logic that helps with handling Neighbor Discovery Protocol (NDP) messages, which
+//! fulfill the role ARP plays for IPv4 when it comes to resolving IPv6 addresses over Ethernet.
+//!
+//! Only neighbor solicitations and neighbor advertisements are supported. Both are carried inside
+//! ICMPv6 messages, and a more detailed view of their format can be found in [RFC 4861].
+//!
+//! [RFC 4861]: https://tools.ietf.org/html/rfc4861#section-4.3
+
+use std::convert::From;
+use std::net::{IpAddr, Ipv6Addr};
+use std::result::Result;
+
+use super::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use super::ethernet;
+use super::ipv6;
+use super::ChecksumProto;
+use crate::mac::{MacAddr, MAC_ADDR_LEN};
+
+/// ICMPv6 message type for neighbor solicitations.
+pub const TYPE_NEIGHBOR_SOLICITATION: u8 = 135;
+
+/// ICMPv6 message type for neighbor advertisements.
+pub const TYPE_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Neighbor advertisement flag which indicates the message is a response to a solicitation.
+pub const FLAG_SOLICITED: u8 = 0x40;
+/// Neighbor advertisement flag which indicates the receiver should update its cache entry.
+pub const FLAG_OVERRIDE: u8 = 0x20;
+
+/// NDP messages must be sent with this exact hop limit, and received messages with a different
+/// value must be discarded, as this guarantees they did not cross a router.
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+/// The length of a neighbor advertisement which carries an Ethernet target link-layer address
+/// option.
+pub const ETH_NEIGHBOR_ADVERTISEMENT_LEN: usize = 32;
+
+const TYPE_OFFSET: usize = 0;
+const CODE_OFFSET: usize = 1;
+const CHECKSUM_OFFSET: usize = 2;
+const FLAGS_OFFSET: usize = 4;
+const TARGET_ADDRESS_OFFSET: usize = 8;
+const OPTIONS_OFFSET: usize = 24;
+
+const IPV6_ADDR_LEN: usize = 16;
+
+// Option type for the target link-layer address, and its length (in units of 8 octets) when the
+// link layer is Ethernet.
+const OPTION_TARGET_LINK_LAYER_ADDR: u8 = 2;
+const OPTION_ETH_LINK_LAYER_ADDR_LEN: u8 = 1;
+
+/// Represents errors which may occur while parsing or writing a message.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The ICMPv6 checksum is invalid.
+    Checksum,
+    /// Invalid ICMPv6 code.
+    Code,
+    /// Unexpected ICMPv6 message type.
+    MessageType,
+    /// The provided slice does not fit the size of a message.
+    SliceExactLen,
+    /// The provided slice is shorter than the fixed part of the message.
+    SliceTooShort,
+}
+
+/// The inner bytes will be interpreted as an NDP neighbor solicitation or advertisement.
+pub struct NdpMessage<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> NdpMessage<'a, T> {
+    /// Interprets the given bytes as an NDP message, without doing any validity checks beforehand.
+    ///
+    ///  # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        NdpMessage {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Tries to interpret a byte slice as a valid neighbor solicitation.
+    ///
+    /// The `verify_checksum` parameter must contain the source and destination addresses from the
+    /// enclosing IPv6 packet if the ICMPv6 checksum must be validated. Any options are ignored.
+    pub fn solicitation_from_bytes(
+        bytes: T,
+        verify_checksum: Option<(Ipv6Addr, Ipv6Addr)>,
+    ) -> Result<Self, Error> {
+        if bytes.len() < OPTIONS_OFFSET {
+            return Err(Error::SliceTooShort);
+        }
+
+        let maybe = NdpMessage::from_bytes_unchecked(bytes);
+
+        if maybe.message_type() != TYPE_NEIGHBOR_SOLICITATION {
+            return Err(Error::MessageType);
+        }
+
+        if maybe.code() != 0 {
+            return Err(Error::Code);
+        }
+
+        if let Some((src_addr, dst_addr)) = verify_checksum {
+            if maybe.compute_checksum(src_addr, dst_addr) != 0 {
+                return Err(Error::Checksum);
+            }
+        }
+
+        Ok(maybe)
+    }
+
+    /// Returns the ICMPv6 message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the ICMPv6 code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.bytes[CODE_OFFSET]
+    }
+
+    /// Returns the ICMPv6 checksum.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.bytes.ntohs_unchecked(CHECKSUM_OFFSET)
+    }
+
+    /// Returns the flags of the message (only meaningful for neighbor advertisements).
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.bytes[FLAGS_OFFSET]
+    }
+
+    /// Returns the target address of the message.
+    #[inline]
+    pub fn target_address(&self) -> Ipv6Addr {
+        let mut octets = [0u8; IPV6_ADDR_LEN];
+        octets.copy_from_slice(
+            &self.bytes[TARGET_ADDRESS_OFFSET..TARGET_ADDRESS_OFFSET + IPV6_ADDR_LEN],
+        );
+        Ipv6Addr::from(octets)
+    }
+
+    /// Returns the link-layer address carried by the first option of the message, if that option
+    /// describes an Ethernet address.
+    #[inline]
+    pub fn link_layer_addr(&self) -> Option<MacAddr> {
+        let option = &self.bytes[OPTIONS_OFFSET..];
+        if option.len() >= 2 + MAC_ADDR_LEN && option[1] == OPTION_ETH_LINK_LAYER_ADDR_LEN {
+            return Some(MacAddr::from_bytes_unchecked(&option[2..2 + MAC_ADDR_LEN]));
+        }
+        None
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Computes the ICMPv6 checksum of the message, based on the addresses of the enclosing IPv6
+    /// packet.
+    #[inline]
+    pub fn compute_checksum(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> u16 {
+        crate::pdu::compute_checksum(
+            &self.bytes,
+            IpAddr::from(src_addr),
+            IpAddr::from(dst_addr),
+            ChecksumProto::Icmpv6,
+        )
+    }
+}
+
+impl<'a, T: NetworkBytesMut> NdpMessage<'a, T> {
+    /// Attempts to write a neighbor advertisement to `buf`, which announces that `target_addr`
+    /// can be reached at `target_mac`. The `src_addr` and `dst_addr` parameters are the addresses
+    /// of the enclosing IPv6 packet, and are used to compute the checksum.
+    pub fn write_advertisement(
+        buf: T,
+        flags: u8,
+        target_addr: Ipv6Addr,
+        target_mac: MacAddr,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Self, Error> {
+        if buf.len() != ETH_NEIGHBOR_ADVERTISEMENT_LEN {
+            return Err(Error::SliceExactLen);
+        }
+
+        // This is ok, because we've checked the length of the slice.
+        let mut message = NdpMessage::from_bytes_unchecked(buf);
+
+        message
+            .set_message_type(TYPE_NEIGHBOR_ADVERTISEMENT)
+            .set_code(0)
+            .set_checksum(0)
+            .set_flags(flags)
+            .set_target_address(target_addr)
+            .set_link_layer_addr_option(OPTION_TARGET_LINK_LAYER_ADDR, target_mac);
+
+        let checksum = message.compute_checksum(src_addr, dst_addr);
+        message.set_checksum(checksum);
+
+        Ok(message)
+    }
+
+    /// Sets the ICMPv6 message type.
+    #[inline]
+    pub fn set_message_type(&mut self, value: u8) -> &mut Self {
+        self.bytes[TYPE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the ICMPv6 code.
+    #[inline]
+    pub fn set_code(&mut self, value: u8) -> &mut Self {
+        self.bytes[CODE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the ICMPv6 checksum.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(CHECKSUM_OFFSET, value);
+        self
+    }
+
+    /// Sets the flags of the message, and clears the reserved bits which follow them.
+    #[inline]
+    pub fn set_flags(&mut self, value: u8) -> &mut Self {
+        self.bytes
+            .htonl_unchecked(FLAGS_OFFSET, u32::from(value) << 24);
+        self
+    }
+
+    /// Sets the target address of the message.
+    #[inline]
+    pub fn set_target_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[TARGET_ADDRESS_OFFSET..TARGET_ADDRESS_OFFSET + IPV6_ADDR_LEN]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Writes an Ethernet link-layer address option of the given type right after the fixed part
+    /// of the message.
+    #[inline]
+    pub fn set_link_layer_addr_option(&mut self, option_type: u8, addr: MacAddr) -> &mut Self {
+        let option = &mut self.bytes[OPTIONS_OFFSET..];
+        option[0] = option_type;
+        option[1] = OPTION_ETH_LINK_LAYER_ADDR_LEN;
+        option[2..2 + MAC_ADDR_LEN].copy_from_slice(addr.get_bytes());
+        self
+    }
+}
+
+/// This function checks if `buf` may hold an Ethernet frame which encapsulates an IPv6 neighbor
+/// solicitation for the given address. Cannot produce false negatives.
+#[inline]
+pub fn test_speculative_target_addr(buf: &[u8], addr: Ipv6Addr) -> bool {
+    let offset = ethernet::PAYLOAD_OFFSET + ipv6::HEADER_LEN;
+    // The unchecked methods are safe because we actually check the buffer length beforehand.
+    if buf.len() >= offset + OPTIONS_OFFSET {
+        let bytes = &buf[offset..];
+        if NdpMessage::from_bytes_unchecked(bytes).target_address() == addr {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for NdpMessage<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(NDP message)")
+        }
+    }
+
+    // A neighbor solicitation for fe80::a9fe:a9fe, sent from fe80::1 (with the link-layer
+    // address 11:11:11:22:22:22) to the solicited-node address ff02::1:fffe:a9fe.
+    const SOLICITATION: [u8; 32] = [
+        0x87, 0x00, 0x39, 0x4d, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xa9, 0xfe, 0xa9, 0xfe, 0x01, 0x01, 0x11, 0x11, 0x11, 0x22,
+        0x22, 0x22,
+    ];
+
+    #[test]
+    fn test_solicitation() {
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+        // The solicited-node multicast address of the target.
+        let dst = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xfffe, 0xa9fe);
+
+        let ns =
+            NdpMessage::solicitation_from_bytes(SOLICITATION.as_ref(), Some((src, dst))).unwrap();
+        assert_eq!(ns.message_type(), TYPE_NEIGHBOR_SOLICITATION);
+        assert_eq!(ns.code(), 0);
+        assert_eq!(ns.checksum(), 0x394d);
+        assert_eq!(ns.target_address(), target);
+        assert_eq!(
+            ns.link_layer_addr(),
+            Some(MacAddr::parse_str("11:11:11:22:22:22").unwrap())
+        );
+        assert_eq!(ns.len(), SOLICITATION.len());
+
+        // Options are not mandatory.
+        let ns =
+            NdpMessage::solicitation_from_bytes(&SOLICITATION[..OPTIONS_OFFSET], None).unwrap();
+        assert_eq!(ns.link_layer_addr(), None);
+
+        // Wrong destination address for the pseudo-header.
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(SOLICITATION.as_ref(), Some((src, target)))
+                .unwrap_err(),
+            Error::Checksum
+        );
+
+        // Slice is too short.
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(&SOLICITATION[..OPTIONS_OFFSET - 1], None)
+                .unwrap_err(),
+            Error::SliceTooShort
+        );
+
+        let mut a = SOLICITATION;
+        NdpMessage::from_bytes_unchecked(a.as_mut()).set_code(1);
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(a.as_ref(), None).unwrap_err(),
+            Error::Code
+        );
+
+        NdpMessage::from_bytes_unchecked(a.as_mut())
+            .set_code(0)
+            .set_message_type(TYPE_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(a.as_ref(), None).unwrap_err(),
+            Error::MessageType
+        );
+    }
+
+    #[test]
+    fn test_advertisement() {
+        let mut a = [1u8; 100];
+        let mut bad_array = [0u8; 1];
+
+        let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let mac = MacAddr::parse_str("06:01:23:45:67:01").unwrap();
+        let flags = FLAG_SOLICITED | FLAG_OVERRIDE;
+
+        // Slice is too short.
+        assert_eq!(
+            NdpMessage::write_advertisement(bad_array.as_mut(), flags, target, mac, target, dst)
+                .unwrap_err(),
+            Error::SliceExactLen
+        );
+
+        // Slice is too long.
+        assert_eq!(
+            NdpMessage::write_advertisement(a.as_mut(), flags, target, mac, target, dst)
+                .unwrap_err(),
+            Error::SliceExactLen
+        );
+
+        let na = NdpMessage::write_advertisement(
+            &mut a[..ETH_NEIGHBOR_ADVERTISEMENT_LEN],
+            flags,
+            target,
+            mac,
+            target,
+            dst,
+        )
+        .unwrap();
+
+        assert_eq!(na.message_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(na.code(), 0);
+        assert_eq!(na.flags(), flags);
+        assert_eq!(na.target_address(), target);
+        assert_eq!(na.link_layer_addr(), Some(mac));
+        assert_eq!(na.len(), ETH_NEIGHBOR_ADVERTISEMENT_LEN);
+        assert_eq!(na.compute_checksum(target, dst), 0);
+        // The reserved bits have been cleared.
+        assert_eq!(&a[FLAGS_OFFSET + 1..TARGET_ADDRESS_OFFSET], &[0, 0, 0]);
+        assert_eq!(a[OPTIONS_OFFSET], OPTION_TARGET_LINK_LAYER_ADDR);
+    }
+
+    #[test]
+    fn test_speculative() {
+        let mut a = [0u8; 1000];
+        let addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+
+        assert!(!test_speculative_target_addr(a.as_ref(), addr));
+
+        {
+            let mac = MacAddr::from_bytes_unchecked(&[0; 6]);
+            let mut eth =
+                ::pdu::ethernet::EthernetFrame::write_incomplete(a.as_mut(), mac, mac, 0).unwrap();
+            let mut ns = NdpMessage::from_bytes_unchecked(
+                &mut eth.inner_mut().payload_mut()[ipv6::HEADER_LEN..],
+            );
+            ns.set_target_address(addr);
+        }
+
+        assert!(test_speculative_target_addr(a.as_ref(), addr));
+
+        // Let's also test for a very small buffer.
+        let small = [0u8; 1];
+        assert!(!test_speculative_target_addr(small.as_ref(), addr));
+    }
+}
@@ -8,7 +8,7 @@
 //! [Here]: https://en.wikipedia.org/wiki/Transmission_Control_Protocol#TCP_segment_structure
 
 use std::cmp::min;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::num::NonZeroU16;
 use std::result::Result;
 
@@ -33,8 +33,15 @@ const MAX_HEADER_LEN: usize = 60;
 const OPTION_KIND_EOL: u8 = 0x00;
 const OPTION_KIND_NOP: u8 = 0x01;
 const OPTION_KIND_MSS: u8 = 0x02;
+const OPTION_KIND_WSCALE: u8 = 0x03;
 
 const OPTION_LEN_MSS: usize = 0x04;
+const OPTION_LEN_WSCALE: usize = 0x03;
+
+/// The largest window scale shift count allowed by [RFC 7323].
+///
+/// [RFC 7323]: https://tools.ietf.org/html/rfc7323#section-2.3
+pub const WSCALE_MAX: u8 = 14;
 
 // An arbitrarily chosen value, used for sanity checks.
 const MSS_MIN: u16 = 100;
@@ -80,6 +87,8 @@ pub enum Error {
     MssOption,
     /// The remaining segment length cannot accommodate the MSS option.
     MssRemaining,
+    /// The header options are malformed.
+    Options,
     /// The specified slice is shorter than the header length.
     SliceTooShort,
 }
@@ -204,59 +213,91 @@ impl<'a, T: NetworkBytes> TcpSegment<'a, T> {
     /// be found [here].
     ///
     /// [here]: https://en.wikipedia.org/wiki/Transmission_Control_Protocol#Checksum_computation
-    pub fn compute_checksum(&self, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> u16 {
+    pub fn compute_checksum(&self, src_addr: IpAddr, dst_addr: IpAddr) -> u16 {
         crate::pdu::compute_checksum(&self.bytes, src_addr, dst_addr, ChecksumProto::Tcp)
     }
 
-    /// Parses TCP header options (only `MSS` is supported for now).
-    ///
-    /// If no error is encountered, returns the `MSS` value, or `None` if the option is not
-    /// present.
-    ///
-    /// # Panics
-    ///
-    /// This method may panic if the value of `header_len` is invalid.
-    pub fn parse_mss_option_unchecked(
-        &self,
-        header_len: usize,
-    ) -> Result<Option<NonZeroU16>, Error> {
+    // Looks for the first option of the given kind, and returns its data (the bytes after the
+    // kind and length fields).
+    fn find_option_unchecked(&self, header_len: usize, kind: u8) -> Result<Option<&[u8]>, Error> {
         let b = self.options_unchecked(header_len);
         let mut i = 0;
 
         // All TCP options (except EOL and NOP) are encoded using x bytes (x >= 2), where the first
-        // byte represents the option kind, the second is the option length (including these first two
-        // bytes), and finally the next x - 2 bytes represent option data. The length of the MSS option
-        // is 4, so the option data encodes an u16 in network order.
-
-        // The MSS option is 4 bytes wide, so we need at least 4 more bytes to look for it.
-        while i + 3 < b.len() {
+        // byte represents the option kind, the second is the option length (including these first
+        // two bytes), and finally the next x - 2 bytes represent option data.
+        while i < b.len() {
             match b[i] {
                 OPTION_KIND_EOL => break,
                 OPTION_KIND_NOP => {
                     i += 1;
                     continue;
                 }
-                OPTION_KIND_MSS => {
-                    // Read from option data (we skip checking if the len is valid).
-                    // TODO: To be super strict, we should make sure there aren't additional MSS
-                    // options present (which would be super wrong). Should we be super strict?
-                    let mss = b.ntohs_unchecked(i + 2);
-                    if mss < MSS_MIN {
-                        return Err(Error::MssOption);
-                    }
-                    // The unwarp() is safe because mms >= MSS_MIN at this point.
-                    return Ok(Some(NonZeroU16::new(mss).unwrap()));
-                }
                 _ => {
-                    // Some other option; just skip opt_len bytes in total.
-                    i += b[i + 1] as usize;
-                    continue;
+                    if i + 1 >= b.len() {
+                        return Err(Error::Options);
+                    }
+                    let opt_len = b[i + 1] as usize;
+                    if opt_len < 2 || i + opt_len > b.len() {
+                        return Err(Error::Options);
+                    }
+                    if b[i] == kind {
+                        return Ok(Some(&b[i + 2..i + opt_len]));
+                    }
+                    i += opt_len;
                 }
             }
         }
         Ok(None)
     }
 
+    /// Parses the `MSS` TCP header option.
+    ///
+    /// If no error is encountered, returns the `MSS` value, or `None` if the option is not
+    /// present.
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `header_len` is invalid.
+    pub fn parse_mss_option_unchecked(
+        &self,
+        header_len: usize,
+    ) -> Result<Option<NonZeroU16>, Error> {
+        // The length of the MSS option is 4, so the option data encodes an u16 in network order.
+        // TODO: To be super strict, we should make sure there aren't additional MSS options
+        // present (which would be super wrong). Should we be super strict?
+        match self.find_option_unchecked(header_len, OPTION_KIND_MSS) {
+            Ok(Some(data)) if data.len() == OPTION_LEN_MSS - 2 => {
+                let mss = data.ntohs_unchecked(0);
+                if mss < MSS_MIN {
+                    return Err(Error::MssOption);
+                }
+                // The unwarp() is safe because mms >= MSS_MIN at this point.
+                Ok(Some(NonZeroU16::new(mss).unwrap()))
+            }
+            Ok(Some(_)) | Err(_) => Err(Error::MssOption),
+            Ok(None) => Ok(None),
+        }
+    }
+
+    /// Parses the window scale TCP header option.
+    ///
+    /// If no error is encountered, returns the shift count, or `None` if the option is not
+    /// present. Shift counts larger than [`WSCALE_MAX`] are reported as `WSCALE_MAX`.
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `header_len` is invalid.
+    ///
+    /// [`WSCALE_MAX`]: constant.WSCALE_MAX.html
+    pub fn parse_wscale_option_unchecked(&self, header_len: usize) -> Result<Option<u8>, Error> {
+        match self.find_option_unchecked(header_len, OPTION_KIND_WSCALE)? {
+            Some(data) if data.len() == OPTION_LEN_WSCALE - 2 => Ok(Some(min(data[0], WSCALE_MAX))),
+            Some(_) => Err(Error::Options),
+            None => Ok(None),
+        }
+    }
+
     /// Interprets `bytes` as a TCP segment without any validity checks.
     ///
     /// # Panics
@@ -273,12 +314,9 @@ impl<'a, T: NetworkBytes> TcpSegment<'a, T> {
     /// Attempts to interpret `bytes` as a TCP segment, checking the validity of the header fields.
     ///
     /// The `verify_checksum` parameter must contain the source and destination addresses from the
-    /// enclosing IPv4 packet if the TCP checksum must be validated.
+    /// enclosing IP packet if the TCP checksum must be validated.
     #[inline]
-    pub fn from_bytes(
-        bytes: T,
-        verify_checksum: Option<(Ipv4Addr, Ipv4Addr)>,
-    ) -> Result<Self, Error> {
+    pub fn from_bytes(bytes: T, verify_checksum: Option<(IpAddr, IpAddr)>) -> Result<Self, Error> {
         if bytes.len() < OPTIONS_OFFSET {
             return Err(Error::SliceTooShort);
         }
@@ -402,13 +440,16 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
     /// * `flags_after_ns` - TCP flags to set (except `NS`, which is always set to 0).
     /// * `window_size` - Value to write in the `window size` field.
     /// * `mss_option` - When a value is specified, use it to add a TCP MSS option to the header.
+    /// * `wscale_option` - When a value is specified, use it to add a TCP window scale option to
+    ///    the header.
     /// * `mss_remaining` - Represents an upper bound on the payload length (the number of bytes
     ///    used up by things like IP options have to be subtracted from the MSS). There is some
     ///    redundancy looking at this argument and the next one, so we might end up removing
     ///    or changing something.
-    /// * `payload` - May contain a buffer which holds payload data and the maximum amount of bytes
-    ///    we should read from that buffer. When `None`, the TCP segment will carry no payload.
-    /// * `compute_checksum` - May contain the pair addresses from the enclosing IPv4 packet, which
+    /// * `payload` - May contain a buffer which holds payload data, the offset within that buffer
+    ///    where the payload begins, and the maximum amount of bytes we should read starting at
+    ///    that offset. When `None`, the TCP segment will carry no payload.
+    /// * `compute_checksum` - May contain the pair addresses from the enclosing IP packet, which
     ///    are required for TCP checksum computation. Skip the checksum altogether when `None`.
     #[allow(clippy::too_many_arguments)]
     #[inline]
@@ -421,9 +462,10 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
         flags_after_ns: Flags,
         window_size: u16,
         mss_option: Option<u16>,
+        wscale_option: Option<u8>,
         mss_remaining: u16,
-        payload: Option<(&R, usize)>,
-        compute_checksum: Option<(Ipv4Addr, Ipv4Addr)>,
+        payload: Option<(&R, usize, usize)>,
+        compute_checksum: Option<(IpAddr, IpAddr)>,
     ) -> Result<Self, Error> {
         Ok(Self::write_incomplete_segment(
             buf,
@@ -432,6 +474,7 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
             flags_after_ns,
             window_size,
             mss_option,
+            wscale_option,
             mss_remaining,
             payload,
         )?
@@ -453,12 +496,15 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
     /// * `flags_after_ns` - TCP flags to set (except `NS`, which is always set to 0).
     /// * `window_size` - Value to write in the `window size` field.
     /// * `mss_option` - When a value is specified, use it to add a TCP MSS option to the header.
+    /// * `wscale_option` - When a value is specified, use it to add a TCP window scale option to
+    ///    the header.
     /// * `mss_remaining` - Represents an upper bound on the payload length (the number of bytes
     ///    used up by things like IP options have to be subtracted from the MSS). There is some
     ///    redundancy looking at this argument and the next one, so we might end up removing
     ///    or changing something.
-    /// * `payload` - May contain a buffer which holds payload data and the maximum amount of bytes
-    ///    we should read from that buffer. When `None`, the TCP segment will carry no payload.
+    /// * `payload` - May contain a buffer which holds payload data, the offset within that buffer
+    ///    where the payload begins, and the maximum amount of bytes we should read starting at
+    ///    that offset. When `None`, the TCP segment will carry no payload.
     // Marked inline because a lot of code vanishes after constant folding when
     // we don't add TCP options, or when mss_remaining is actually a constant, etc.
     #[allow(clippy::too_many_arguments)]
@@ -470,23 +516,27 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
         flags_after_ns: Flags,
         window_size: u16,
         mss_option: Option<u16>,
+        wscale_option: Option<u8>,
         mss_remaining: u16,
-        payload: Option<(&R, usize)>,
+        payload: Option<(&R, usize, usize)>,
     ) -> Result<Incomplete<Self>, Error> {
         let mut mss_left = mss_remaining as usize;
 
         // We're going to need at least this many bytes.
         let mut segment_len = OPTIONS_OFFSET;
 
-        // The TCP options will require this much more bytes.
-        let options_len = if mss_option.is_some() {
-            mss_left = mss_left
-                .checked_sub(OPTION_LEN_MSS)
-                .ok_or(Error::MssRemaining)?;
-            OPTION_LEN_MSS
-        } else {
-            0
-        };
+        // The TCP options will require this much more bytes. The window scale option is preceded
+        // by a NOP, so the options length remains a multiple of 4.
+        let mut options_len = 0;
+        if mss_option.is_some() {
+            options_len += OPTION_LEN_MSS;
+        }
+        if wscale_option.is_some() {
+            options_len += OPTION_LEN_WSCALE + 1;
+        }
+        mss_left = mss_left
+            .checked_sub(options_len)
+            .ok_or(Error::MssRemaining)?;
 
         segment_len += options_len;
 
@@ -505,15 +555,26 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
             .set_window_size(window_size)
             .set_urgent_pointer(0);
 
-        // Let's write the MSS option if we have to.
+        // Let's write the MSS and window scale options if we have to.
+        let mut option_offset = OPTIONS_OFFSET;
         if let Some(value) = mss_option {
-            segment.bytes[OPTIONS_OFFSET] = OPTION_KIND_MSS;
-            segment.bytes[OPTIONS_OFFSET + 1] = OPTION_LEN_MSS as u8;
-            segment.bytes.htons_unchecked(OPTIONS_OFFSET + 2, value);
+            segment.bytes[option_offset] = OPTION_KIND_MSS;
+            segment.bytes[option_offset + 1] = OPTION_LEN_MSS as u8;
+            segment.bytes.htons_unchecked(option_offset + 2, value);
+            option_offset += OPTION_LEN_MSS;
+        }
+        if let Some(value) = wscale_option {
+            segment.bytes[option_offset] = OPTION_KIND_NOP;
+            segment.bytes[option_offset + 1] = OPTION_KIND_WSCALE;
+            segment.bytes[option_offset + 2] = OPTION_LEN_WSCALE as u8;
+            segment.bytes[option_offset + 3] = value;
         }
 
-        segment_len += if let Some((payload_buf, max_payload_bytes)) = payload {
-            let left_to_read = min(payload_buf.len(), max_payload_bytes);
+        segment_len += if let Some((payload_buf, payload_offset, max_payload_bytes)) = payload {
+            let left_to_read = min(
+                payload_buf.len().saturating_sub(payload_offset),
+                max_payload_bytes,
+            );
 
             // The subtraction makes sense because we previously checked that
             // buf.len() >= segment_len.
@@ -525,7 +586,7 @@ impl<'a, T: NetworkBytesMut> TcpSegment<'a, T> {
             }
 
             payload_buf.read_to_slice(
-                0,
+                payload_offset,
                 &mut segment.bytes[segment_len..segment_len + room_for_payload],
             );
             room_for_payload
@@ -549,7 +610,7 @@ impl<'a, T: NetworkBytesMut> Incomplete<TcpSegment<'a, T>> {
         mut self,
         src_port: u16,
         dst_port: u16,
-        compute_checksum: Option<(Ipv4Addr, Ipv4Addr)>,
+        compute_checksum: Option<(IpAddr, IpAddr)>,
     ) -> TcpSegment<'a, T> {
         self.inner.set_source_port(src_port);
         self.inner.set_destination_port(dst_port);
@@ -566,6 +627,7 @@ impl<'a, T: NetworkBytesMut> Incomplete<TcpSegment<'a, T>> {
 #[cfg(test)]
 mod tests {
     use std::fmt;
+    use std::net::Ipv4Addr;
 
     use super::*;
 
@@ -633,8 +695,8 @@ mod tests {
         let b = [2u8; 1000];
         let c = [3u8; 2000];
 
-        let src_addr = Ipv4Addr::new(10, 1, 2, 3);
-        let dst_addr = Ipv4Addr::new(192, 168, 44, 77);
+        let src_addr = IpAddr::from(Ipv4Addr::new(10, 1, 2, 3));
+        let dst_addr = IpAddr::from(Ipv4Addr::new(192, 168, 44, 77));
         let src_port = 1234;
         let dst_port = 5678;
         let seq_number = 11_111_222;
@@ -643,7 +705,7 @@ mod tests {
         let window_size = 19999;
         let mss_left = 1460;
         let mss_option = Some(mss_left);
-        let payload = Some((b.as_ref(), b.len()));
+        let payload = Some((b.as_ref(), 0, b.len()));
 
         let header_len = OPTIONS_OFFSET + OPTION_LEN_MSS;
 
@@ -657,6 +719,7 @@ mod tests {
                 flags_after_ns,
                 window_size,
                 mss_option,
+                None,
                 mss_left,
                 payload,
                 Some((src_addr, dst_addr)),
@@ -714,8 +777,9 @@ mod tests {
                 flags_after_ns,
                 window_size,
                 mss_option,
+                None,
                 mss_left,
-                Some((c.as_ref(), c.len())),
+                Some((c.as_ref(), 0, c.len())),
                 Some((src_addr, dst_addr)),
             )
             .unwrap()
@@ -775,6 +839,7 @@ mod tests {
                 flags_after_ns,
                 window_size,
                 mss_option,
+                None,
                 mss_left,
                 payload,
                 Some((src_addr, dst_addr)),
@@ -794,6 +859,7 @@ mod tests {
                 flags_after_ns,
                 window_size,
                 mss_option,
+                None,
                 0,
                 payload,
                 Some((src_addr, dst_addr)),
@@ -802,4 +868,72 @@ mod tests {
             Error::MssRemaining
         );
     }
+    #[test]
+    fn test_options() {
+        let mut a = [0u8; 100];
+        let mss = 1460;
+        let wscale = 7;
+
+        let header_len = {
+            let p = TcpSegment::write_segment::<[u8]>(
+                a.as_mut(),
+                1234,
+                5678,
+                11_111_222,
+                34_566_543,
+                Flags::SYN | Flags::ACK,
+                19999,
+                Some(mss),
+                Some(wscale),
+                mss,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let header_len = OPTIONS_OFFSET + OPTION_LEN_MSS + OPTION_LEN_WSCALE + 1;
+            assert_eq!(p.header_len(), header_len);
+            assert_eq!(p.len(), header_len);
+            assert_eq!(
+                p.parse_mss_option_unchecked(header_len),
+                Ok(Some(NonZeroU16::new(mss).unwrap()))
+            );
+            assert_eq!(
+                p.parse_wscale_option_unchecked(header_len),
+                Ok(Some(wscale))
+            );
+            header_len
+        };
+
+        let mut p = TcpSegment::from_bytes_unchecked(a.as_mut());
+
+        // Shift counts are capped at WSCALE_MAX.
+        p.bytes[header_len - 1] = WSCALE_MAX + 1;
+        assert_eq!(
+            p.parse_wscale_option_unchecked(header_len),
+            Ok(Some(WSCALE_MAX))
+        );
+
+        // Options are no longer found after an EOL.
+        p.bytes[OPTIONS_OFFSET + OPTION_LEN_MSS] = OPTION_KIND_EOL;
+        assert_eq!(p.parse_wscale_option_unchecked(header_len), Ok(None));
+
+        // A zero option length is an error, instead of something to loop on.
+        p.bytes[OPTIONS_OFFSET + 1] = 0;
+        assert_eq!(
+            p.parse_wscale_option_unchecked(header_len),
+            Err(Error::Options)
+        );
+        assert_eq!(
+            p.parse_mss_option_unchecked(header_len),
+            Err(Error::MssOption)
+        );
+
+        // So is an option which does not fit within the header.
+        p.bytes[OPTIONS_OFFSET + 1] = OPTION_LEN_MSS as u8 + 5;
+        assert_eq!(
+            p.parse_wscale_option_unchecked(header_len),
+            Err(Error::Options)
+        );
+    }
 }
@@ -9,7 +9,7 @@
 //! [1]: https://tools.ietf.org/html/rfc768
 //! [2]: https://tools.ietf.org/html/rfc5405
 
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use pdu::bytes::NetworkBytesMut;
 use pdu::{ChecksumProto, Incomplete};
@@ -63,10 +63,7 @@ impl<'a, T: NetworkBytes> UdpDatagram<'a, T> {
     /// Interprets `bytes` as a UDP datagram if possible or returns
     /// the reason for failing to do so.
     #[inline]
-    pub fn from_bytes(
-        bytes: T,
-        verify_checksum: Option<(Ipv4Addr, Ipv4Addr)>,
-    ) -> Result<Self, Error> {
+    pub fn from_bytes(bytes: T, verify_checksum: Option<(IpAddr, IpAddr)>) -> Result<Self, Error> {
         if bytes.len() < UDP_HEADER_SIZE {
             return Err(Error::DatagramTooShort);
         }
@@ -117,7 +114,7 @@ impl<'a, T: NetworkBytes> UdpDatagram<'a, T> {
 
     /// Computes the checksum of a UDP datagram.
     #[inline]
-    pub fn compute_checksum(&self, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> u16 {
+    pub fn compute_checksum(&self, src_addr: IpAddr, dst_addr: IpAddr) -> u16 {
         crate::pdu::compute_checksum(&self.bytes, src_addr, dst_addr, ChecksumProto::Udp)
     }
 }
@@ -190,7 +187,7 @@ impl<'a, T: NetworkBytesMut> Incomplete<UdpDatagram<'a, T>> {
         mut self,
         src_port: u16,
         dst_port: u16,
-        compute_checksum: Option<(Ipv4Addr, Ipv4Addr)>,
+        compute_checksum: Option<(IpAddr, IpAddr)>,
     ) -> UdpDatagram<'a, T> {
         self.inner.set_source_port(src_port);
         self.inner.set_destination_port(dst_port);
@@ -208,6 +205,7 @@ impl<'a, T: NetworkBytesMut> Incomplete<UdpDatagram<'a, T>> {
 #[cfg(test)]
 mod tests {
     use std::fmt;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     use pdu::udp::UdpDatagram;
 
@@ -283,8 +281,8 @@ mod tests {
         let payload: Vec<u8> = (0..32).collect();
         let src_port = 32133;
         let dst_port = 22113;
-        let src_addr = Ipv4Addr::new(10, 100, 11, 21);
-        let dst_addr = Ipv4Addr::new(192, 168, 121, 35);
+        let src_addr = IpAddr::from(Ipv4Addr::new(10, 100, 11, 21));
+        let dst_addr = IpAddr::from(Ipv4Addr::new(192, 168, 121, 35));
         let p = UdpDatagram::write_incomplete_datagram(packet.as_mut(), &payload[..]).unwrap();
         let mut p = p.finalize(src_port, dst_port, Some((src_addr, dst_addr)));
 
@@ -311,8 +309,20 @@ mod tests {
         let mut bytes = [0u8; (2 + UDP_HEADER_SIZE)]; // 2-byte payload
         let correct_checksum: u16 = 0x14de;
         let payload_bytes = b"bb";
-        let src_ip = Ipv4Addr::new(152, 1, 51, 27);
-        let dst_ip = Ipv4Addr::new(152, 14, 94, 75);
+        let src_ip = IpAddr::from(Ipv4Addr::new(152, 1, 51, 27));
+        let dst_ip = IpAddr::from(Ipv4Addr::new(152, 14, 94, 75));
+        let p = UdpDatagram::write_incomplete_datagram(bytes.as_mut(), payload_bytes).unwrap();
+        let p = p.finalize(41103, 9876, Some((src_ip, dst_ip)));
+        assert_eq!(p.checksum(), correct_checksum);
+    }
+
+    #[test]
+    fn test_checksum_ipv6() {
+        let mut bytes = [0u8; (2 + UDP_HEADER_SIZE)]; // 2-byte payload
+        let correct_checksum: u16 = 0xd94f;
+        let payload_bytes = b"bb";
+        let src_ip = IpAddr::from(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let dst_ip = IpAddr::from(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2));
         let p = UdpDatagram::write_incomplete_datagram(bytes.as_mut(), payload_bytes).unwrap();
         let p = p.finalize(41103, 9876, Some((src_ip, dst_ip)));
         assert_eq!(p.checksum(), correct_checksum);
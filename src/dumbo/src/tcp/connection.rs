@@ -6,15 +6,20 @@
 //!
 //! [`Connection`]: struct.Connection.html
 
+use std::cmp::{max, min};
 use std::num::{NonZeroU16, NonZeroU64, NonZeroUsize, Wrapping};
 
 use pdu::bytes::NetworkBytes;
-use pdu::tcp::{Error as TcpSegmentError, Flags as TcpFlags, TcpSegment};
+use pdu::tcp::{Error as TcpSegmentError, Flags as TcpFlags, TcpSegment, WSCALE_MAX};
 use pdu::Incomplete;
 use tcp::{seq_after, seq_at_or_after, NextSegmentStatus, RstConfig, MAX_WINDOW_SIZE, MSS_DEFAULT};
 use utils::rand::xor_rng_u32;
 use ByteBuffer;
 
+// How many duplicate ACKs have to arrive before the segment following the highest ACK is
+// considered lost, and gets retransmitted (RFC 5681, section 3.2).
+const DUP_ACK_THRESHOLD: u16 = 3;
+
 bitflags! {
     // We use a set of flags, instead of a state machine, to represent the connection status. Some
     // parts of the status information are reflected in other fields of the Connection struct, such
@@ -81,6 +86,8 @@ pub enum PassiveOpenError {
     InvalidSyn,
     /// The `SYN` segment carries an invalid `MSS` option.
     MssOption,
+    /// The `SYN` segment carries an invalid window scale option.
+    WindowScaleOption,
 }
 
 /// Describes errors which may occur when an existing connection receives a TCP segment.
@@ -120,8 +127,8 @@ pub enum WriteNextError {
 /// current implementation).
 ///
 /// A `Connection` object can only be created via passive open, and will not recognize/use any TCP
-/// options except `MSS` during the handshake. The associated state machine is similar to how
-/// TCP normally functions, but there are some differences:
+/// options except `MSS` and window scale during the handshake. The associated state machine is
+/// similar to how TCP normally functions, but there are some differences:
 ///
 /// * Since only passive opens are supported, a `Connection` can only be instantiated in response
 ///   to an incoming `SYN` segment. If the segment is valid, it will start directly in a state
@@ -140,10 +147,14 @@ pub enum WriteNextError {
 ///   and received a `FIN`, it marks itself as being done. There's no equivalent for the
 ///   `TIME_WAIT` TCP state.
 ///
-/// The current implementation does not do any kind of congestion control, expects segments to
-/// arrive in order, triggers a retransmission after the first duplicate `ACK`, and relies on the
-/// user to supply an opaque `u64` timestamp value when invoking send or receive functionality. The
-/// timestamps must be non-decreasing, and are mainly used for retransmission timeouts.
+/// The current implementation does not do any kind of congestion control, and expects segments to
+/// arrive in order. The retransmission timeout is computed from round-trip time measurements as
+/// described in [RFC 6298], and the segment following the highest `ACK` is retransmitted right
+/// away after three duplicate `ACK`s (fast retransmit). The user has to supply an opaque `u64`
+/// timestamp value when invoking send or receive functionality. The timestamps must be
+/// non-decreasing, and are mainly used for retransmission timeouts.
+///
+/// [RFC 6298]: https://tools.ietf.org/html/rfc6298
 ///
 /// [`close`]: #method.close
 #[cfg_attr(test, derive(Clone))]
@@ -164,8 +175,18 @@ pub struct Connection {
     // takes up sequence number space.
     rto_start: u64,
     // How much time can pass after rto_start, without making progress in the ACK space, before a
-    // retransmission is triggered.
+    // retransmission is triggered. It's computed from round-trip time measurements, doubles after
+    // each retransmission timeout, and always stays between rto_min and rto_max.
     rto_period: u64,
+    rto_min: u64,
+    rto_max: u64,
+    // The smoothed round-trip time and the round-trip time variation (SRTT and RTTVAR from
+    // RFC 6298), which are only available after the first measurement.
+    rtt_estimate: Option<(u64, u64)>,
+    // When set, we're measuring the round-trip time of the segment which ends right before the
+    // specified sequence number, and was sent at the specified moment. We only time one segment
+    // at once, and stop timing it if something gets retransmitted (Karn's algorithm).
+    rtt_sample: Option<(Wrapping<u32>, u64)>,
     // How many retransmissions triggered before receiving a valid ACK from the other endpoint.
     rto_count: u16,
     // When rto_count reaches this value, the next retransmission will actually reset the
@@ -185,9 +206,14 @@ pub struct Connection {
     // If true, send an ACK segment at the first opportunity. ACKs can piggyback data segments, so
     // we'll only send an empty ACK segment if we can't transmit any data.
     pending_ack: bool,
-    // We've got a duplicate ACK, so we'll retransmit the highest ACKed sequence number at the
-    // first opportunity. Unlike regular TCP, we retransmit after the first duplicate ACK.
+    // We've got enough duplicate ACKs, so we'll retransmit the highest ACKed sequence number at
+    // the first opportunity.
     dup_ack: bool,
+    // How many duplicate ACKs we received since the last ACK which made progress.
+    dup_ack_count: u16,
+    // When the other endpoint uses the window scale option, this holds the shift counts applied
+    // to the local and the remote receive windows, respectively.
+    wscale: Option<(u8, u8)>,
     status_flags: ConnStatusFlags,
 }
 
@@ -199,6 +225,24 @@ fn parse_mss_option<T: NetworkBytes>(segment: &TcpSegment<T>) -> Result<u16, Pas
     }
 }
 
+fn parse_wscale_option<T: NetworkBytes>(
+    segment: &TcpSegment<T>,
+) -> Result<Option<u8>, PassiveOpenError> {
+    segment
+        .parse_wscale_option_unchecked(segment.header_len())
+        .map_err(|_| PassiveOpenError::WindowScaleOption)
+}
+
+// Returns the smallest shift count which makes a window of the given size fit into the 16 bit
+// window size field of a TCP segment.
+fn wscale_for_window(window_size: u32) -> u8 {
+    let mut shift = 0;
+    while shift < WSCALE_MAX && window_size >> shift > u32::from(u16::max_value()) {
+        shift += 1;
+    }
+    shift
+}
+
 fn is_valid_syn<T: NetworkBytes>(segment: &TcpSegment<T>) -> bool {
     segment.flags_after_ns() == TcpFlags::SYN && segment.payload_len() == 0
 }
@@ -211,13 +255,18 @@ impl Connection {
     /// * `segment` - The incoming `SYN`.
     /// * `local_rwnd_size` - Initial size of the local receive window.
     /// * `rto_period` - How long the connection waits before a retransmission timeout fires for
-    ///   the first segment which has not been acknowledged yet. This uses an opaque time unit.
+    ///   the first segment which has not been acknowledged yet, until the round-trip time gets
+    ///   measured. This uses an opaque time unit.
+    /// * `rto_min` - The smallest retransmission timeout.
+    /// * `rto_max` - The largest retransmission timeout.
     /// * `rto_count_max` - How many consecutive timeout-based retransmission may occur before
     ///   the connection resets itself.
     pub fn passive_open<T: NetworkBytes>(
         segment: &TcpSegment<T>,
         local_rwnd_size: u32,
         rto_period: NonZeroU64,
+        rto_min: NonZeroU64,
+        rto_max: NonZeroU64,
         rto_count_max: NonZeroU16,
     ) -> Result<Self, PassiveOpenError> {
         // We don't accepting anything other than a SYN segment here.
@@ -225,11 +274,13 @@ impl Connection {
             return Err(PassiveOpenError::InvalidSyn);
         }
 
-        // TODO: If we ever implement window scaling, change the part that computes
-        // remote_rwnd_edge below.
-
-        // We only care about the MSS option for now.
+        // We only care about the MSS and window scale options for now.
         let mss = parse_mss_option(segment)?;
+        // Window scaling is only used if the other endpoint asks for it. The window advertised
+        // by the SYN itself is never scaled, so the initial remote_rwnd_edge is computed without
+        // taking the remote shift count into account.
+        let wscale = parse_wscale_option(segment)?
+            .map(|remote_wscale| (wscale_for_window(local_rwnd_size), remote_wscale));
 
         // This is going to get sent on the SYNACK.
         let ack_to_send = Wrapping(segment.sequence_number()) + Wrapping(1);
@@ -249,7 +300,11 @@ impl Connection {
             // the ESTABLISHED state.
             remote_rwnd_edge,
             rto_start: 0,
-            rto_period: rto_period.get(),
+            rto_period: min(max(rto_period.get(), rto_min.get()), rto_max.get()),
+            rto_min: rto_min.get(),
+            rto_max: rto_max.get(),
+            rtt_estimate: None,
+            rtt_sample: None,
             rto_count: 0,
             rto_count_max: rto_count_max.get(),
             fin_received: None,
@@ -258,6 +313,8 @@ impl Connection {
             mss,
             pending_ack: false,
             dup_ack: false,
+            dup_ack_count: 0,
+            wscale,
             status_flags: ConnStatusFlags::SYN_RECEIVED,
         })
     }
@@ -306,8 +363,10 @@ impl Connection {
             return false;
         }
 
-        match parse_mss_option(segment) {
-            Ok(mss) if mss == self.mss => true,
+        match (parse_mss_option(segment), parse_wscale_option(segment)) {
+            (Ok(mss), Ok(wscale)) => {
+                mss == self.mss && wscale == self.wscale.map(|(_, remote_wscale)| remote_wscale)
+            }
             _ => false,
         }
     }
@@ -326,6 +385,33 @@ impl Connection {
         now - self.rto_start >= self.rto_period
     }
 
+    fn set_rto(&mut self, value: u64) {
+        self.rto_period = min(max(value, self.rto_min), self.rto_max);
+    }
+
+    // Doubles the retransmission timeout after it fired, and gives up on the current round-trip
+    // time measurement, since it can no longer be told which transmission gets ACKed.
+    fn back_off_rto(&mut self) {
+        let rto = self.rto_period.saturating_mul(2);
+        self.set_rto(rto);
+        self.rtt_sample = None;
+    }
+
+    // Updates the round-trip time estimate with a new measurement, and recomputes the
+    // retransmission timeout as described in RFC 6298, section 2. The clock granularity is
+    // assumed to be a single time unit.
+    fn update_rtt_estimate(&mut self, rtt: u64) {
+        let (srtt, rttvar) = match self.rtt_estimate {
+            None => (rtt, rtt / 2),
+            Some((srtt, rttvar)) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                (srtt - srtt / 8 + rtt / 8, rttvar - rttvar / 4 + delta / 4)
+            }
+        };
+        self.rtt_estimate = Some((srtt, rttvar));
+        self.set_rto(srtt.saturating_add(max(1, rttvar.saturating_mul(4))));
+    }
+
     // We send a FIN control segment if every data byte up to the self.send_fin sequence number
     // has been ACKed by the other endpoint, and no FIN has been previously sent.
     fn can_send_first_fin(&self) -> bool {
@@ -336,10 +422,16 @@ impl Connection {
             }
     }
 
-    // Returns the window size which should be written to an outgoing segment. This is going to be
-    // even more useful when we'll support window scaling.
-    fn local_rwnd(&self) -> u16 {
-        let rwnd = (self.local_rwnd_edge - self.ack_to_send).0;
+    // Returns the window size which should be written to an outgoing segment. The window size of
+    // SYNACK segments is never scaled.
+    fn local_rwnd(&self, flags_after_ns: TcpFlags) -> u16 {
+        let mut rwnd = (self.local_rwnd_edge - self.ack_to_send).0;
+
+        if let Some((local_wscale, _)) = self.wscale {
+            if !flags_after_ns.intersects(TcpFlags::SYN) {
+                rwnd >>= local_wscale;
+            }
+        }
 
         if rwnd > u32::from(u16::max_value()) {
             u16::max_value()
@@ -348,9 +440,12 @@ impl Connection {
         }
     }
 
-    // Will actually become meaningful when/if we implement window scaling.
+    // Returns the actual size of the remote receive window advertised by a non-SYN segment.
     fn remote_window_size(&self, window_size: u16) -> u32 {
-        u32::from(window_size)
+        match self.wscale {
+            Some((_, remote_wscale)) => u32::from(window_size) << remote_wscale,
+            None => u32::from(window_size),
+        }
     }
 
     // Computes the remote rwnd edge given the ACK number and window size from an incoming segment.
@@ -461,7 +556,7 @@ impl Connection {
         self.remote_rwnd_edge
     }
 
-    /// Returns `true` if a retransmission caused by the reception of a duplicate `ACK` is pending.
+    /// Returns `true` if a retransmission caused by the reception of duplicate `ACK`s is pending.
     #[inline]
     pub fn dup_ack_pending(&self) -> bool {
         self.dup_ack
@@ -592,14 +687,29 @@ impl Connection {
                         // Just kidding, a DUPACK is not valid before the connection is ESTABLISHED.
                         return self.reset_for_segment_helper(s, RecvStatusFlags::INVALID_ACK);
                     }
-                    // Duplicate ACKs can only increase in sequence number, so there's no need
-                    // to check if this one is older than self.dup_ack.
-                    self.dup_ack = true;
-                    recv_status_flags |= RecvStatusFlags::DUP_ACK;
+                    // Segments which carry data are not duplicate ACKs, even if they don't
+                    // acknowledge anything new.
+                    if payload_len == 0 {
+                        // We only retransmit once for a given ACK number, when the duplicate ACK
+                        // count reaches the threshold.
+                        self.dup_ack_count = self.dup_ack_count.saturating_add(1);
+                        if self.dup_ack_count == DUP_ACK_THRESHOLD {
+                            self.dup_ack = true;
+                        }
+                        recv_status_flags |= RecvStatusFlags::DUP_ACK;
+                    }
                 } else {
                     // We're making progress. We should also reset rto_start in this case.
                     self.highest_ack_received = ack;
                     self.rto_start = now;
+                    self.dup_ack_count = 0;
+
+                    if let Some((sample_seq, sent_at)) = self.rtt_sample {
+                        if seq_at_or_after(ack, sample_seq) {
+                            self.rtt_sample = None;
+                            self.update_rtt_estimate(now - sent_at);
+                        }
+                    }
                     if !self.is_established() && self.synack_sent() {
                         // The connection becomes ESTABLISHED.
                         self.set_flags(ConnStatusFlags::ESTABLISHED);
@@ -733,13 +843,17 @@ impl Connection {
         seq: Wrapping<u32>,
         ack: Wrapping<u32>,
         flags_after_ns: TcpFlags,
-        payload: Option<(&R, usize)>,
+        payload: Option<(&R, usize, usize)>,
     ) -> Result<Incomplete<TcpSegment<'a, &'a mut [u8]>>, WriteNextError> {
-        // Write the MSS option on SYNACK segments.
-        let mss_option = if flags_after_ns == TcpFlags::SYN | TcpFlags::ACK {
-            Some(self.mss)
+        // Write the MSS option on SYNACK segments, together with the window scale option if the
+        // other endpoint also sent one.
+        let (mss_option, wscale_option) = if flags_after_ns == TcpFlags::SYN | TcpFlags::ACK {
+            (
+                Some(self.mss),
+                self.wscale.map(|(local_wscale, _)| local_wscale),
+            )
         } else {
-            None
+            (None, None)
         };
 
         let segment = TcpSegment::write_incomplete_segment(
@@ -747,8 +861,9 @@ impl Connection {
             seq.0,
             ack.0,
             flags_after_ns,
-            self.local_rwnd(),
+            self.local_rwnd(flags_after_ns),
             mss_option,
+            wscale_option,
             self.mss
                 .checked_sub(mss_reserved)
                 .ok_or_else(|| WriteNextError::MssRemaining)?,
@@ -861,6 +976,7 @@ impl Connection {
                     return self.write_next_segment(buf, mss_reserved, payload_src, now);
                 }
                 let segment = self.write_control_segment::<R>(buf, mss_reserved)?;
+                self.back_off_rto();
                 self.rto_start = now;
                 return Ok(Some(segment));
             }
@@ -893,6 +1009,7 @@ impl Connection {
                             // We're in the relatively unlikely situation where our FIN got lost.
                             // Simply calling write_control_segment() will retransmit it.
                             let segment = self.write_control_segment::<R>(buf, mss_reserved)?;
+                            self.back_off_rto();
                             self.rto_start = now;
                             return Ok(Some(segment));
                         }
@@ -937,6 +1054,9 @@ impl Connection {
             // delimit a valid sequence number interval.
             if seq_after(actual_end, seq_to_send) {
                 let max_payload_len = (actual_end - seq_to_send).0 as usize;
+                // The payload starts at the byte associated with seq_to_send, which may come
+                // after the beginning of read_buf.
+                let payload_offset = (seq_to_send - payload_seq).0 as usize;
 
                 // We always set the ACK flag for data segments.
                 let tcp_flags = TcpFlags::ACK;
@@ -948,7 +1068,7 @@ impl Connection {
                     seq_to_send,
                     ack_to_send,
                     tcp_flags,
-                    Some((read_buf, max_payload_len)),
+                    Some((read_buf, payload_offset, max_payload_len)),
                 )?;
 
                 // If self.dup_ack was Some(_), we've just written the retransmission segment,
@@ -975,12 +1095,24 @@ impl Connection {
                     }
                 }
 
+                if rto_triggered {
+                    self.back_off_rto();
+                }
+
                 if rto_triggered || self.first_not_sent == self.highest_ack_received {
                     // Reset the RTO "timer" after each retransmission, or after sending the first
                     // unacknowledged segment in the current window.
                     self.rto_start = now;
                 }
 
+                if seq_to_send != self.first_not_sent {
+                    // Something got retransmitted.
+                    self.rtt_sample = None;
+                } else if self.rtt_sample.is_none() {
+                    // Time the round trip of this new segment, if we're not timing another one.
+                    self.rtt_sample = Some((first_seq_after, now));
+                }
+
                 if seq_after(first_seq_after, self.first_not_sent) {
                     self.first_not_sent = first_seq_after;
                 }
@@ -1039,7 +1171,11 @@ pub(crate) mod tests {
         local_rwnd_size: u32,
         remote_isn: u32,
         pub rto_period: u64,
+        pub rto_min: u64,
+        pub rto_max: u64,
         rto_count_max: u16,
+        // The shift count advertised by the window scale option of our SYN segments.
+        pub wscale: Option<u8>,
         now: u64,
     }
 
@@ -1055,7 +1191,11 @@ pub(crate) mod tests {
                 local_rwnd_size: 10000,
                 remote_isn: 12_345_678,
                 rto_period: 100_000,
+                // The RTO stays constant unless a test changes these.
+                rto_min: 100_000,
+                rto_max: 100_000,
                 rto_count_max: 3,
+                wscale: None,
                 now: 0,
             }
         }
@@ -1068,6 +1208,8 @@ pub(crate) mod tests {
                 s,
                 self.local_rwnd_size,
                 NonZeroU64::new(self.rto_period).unwrap(),
+                NonZeroU64::new(self.rto_min).unwrap(),
+                NonZeroU64::new(self.rto_max).unwrap(),
                 NonZeroU16::new(self.rto_count_max).unwrap(),
             )
         }
@@ -1077,10 +1219,14 @@ pub(crate) mod tests {
         fn write_segment_helper<'a>(
             &self,
             buf: &'a mut [u8],
-            add_mss_option: bool,
-            payload: Option<(&[u8], usize)>,
+            add_syn_options: bool,
+            payload: Option<(&[u8], usize, usize)>,
         ) -> TcpSegment<'a, &'a mut [u8]> {
-            let mss_option = if add_mss_option { Some(self.mss) } else { None };
+            let (mss_option, wscale_option) = if add_syn_options {
+                (Some(self.mss), self.wscale)
+            } else {
+                (None, None)
+            };
             TcpSegment::write_segment(
                 buf,
                 self.src_port,
@@ -1090,6 +1236,7 @@ pub(crate) mod tests {
                 TcpFlags::empty(),
                 self.remote_window_size,
                 mss_option,
+                wscale_option,
                 self.mss.checked_sub(self.mss_reserved).unwrap(),
                 payload,
                 None,
@@ -1110,7 +1257,8 @@ pub(crate) mod tests {
             buf: &'a mut [u8],
             data_buf: &[u8],
         ) -> TcpSegment<'a, &'a mut [u8]> {
-            let segment = self.write_segment_helper(buf, false, Some((data_buf, data_buf.len())));
+            let segment =
+                self.write_segment_helper(buf, false, Some((data_buf, 0, data_buf.len())));
             assert_eq!(segment.payload_len(), data_buf.len());
             segment
         }
@@ -1257,7 +1405,7 @@ pub(crate) mod tests {
         // Buffer containing the payload of the incoming data segment.
         let data_buf = [2u8; 1000];
         // Buffer containing the data which the connection sends on outgoing segments.
-        let send_buf = [11u8; 20000];
+        let send_buf: Vec<u8> = (0..20000).map(|i| i as u8).collect();
 
         let mut t = ConnectionTester::new();
 
@@ -1319,7 +1467,7 @@ pub(crate) mod tests {
 
         // Let's restore the connection.
         c = c_clone;
-        let mut payload_src = Some((send_buf.as_ref(), c.highest_ack_received));
+        let mut payload_src = Some((send_buf.as_slice(), c.highest_ack_received));
 
         // Sending the exact same SYN again should be fine.
         syn.set_sequence_number(t.remote_isn);
@@ -1560,6 +1708,10 @@ pub(crate) mod tests {
             assert_eq!(s.ack_number(), remote_isn.wrapping_add(1));
             assert_eq!(s.flags_after_ns(), TcpFlags::ACK);
             assert_eq!(s.payload_len(), mss as usize);
+            assert_eq!(
+                s.payload(),
+                &send_buf[(i * mss) as usize..((i + 1) * mss) as usize]
+            );
         }
 
         // No more new data can be sent until the window advances, even though data_buf
@@ -1584,7 +1736,15 @@ pub(crate) mod tests {
 
         // We have to wait for the window to open again in order to send new data, but we can
         // have retransmissions. For example, receiving the previous ACK again will cause a
-        // DUPACK, which will trigger a retransmission.
+        // DUPACK, and the third one will trigger a retransmission.
+        for _ in 1..DUP_ACK_THRESHOLD {
+            assert_eq!(
+                t.receive_segment(&mut c, &ctrl).unwrap(),
+                (None, RecvStatusFlags::DUP_ACK)
+            );
+            assert!(!c.dup_ack_pending());
+            assert!(t.write_next_segment(&mut c, payload_src).unwrap().is_none());
+        }
         assert_eq!(
             t.receive_segment(&mut c, &ctrl).unwrap(),
             (None, RecvStatusFlags::DUP_ACK)
@@ -1596,9 +1756,17 @@ pub(crate) mod tests {
             let s = t.write_next_segment(&mut c, payload_src).unwrap().unwrap();
             assert_eq!(s.sequence_number(), ctrl.ack_number());
             assert_eq!(s.payload_len(), mss as usize);
+            assert_eq!(s.payload(), &send_buf[mss as usize..2 * mss as usize]);
         }
         assert!(t.write_next_segment(&mut c, payload_src).unwrap().is_none());
 
+        // Further duplicate ACKs don't trigger other retransmissions.
+        assert_eq!(
+            t.receive_segment(&mut c, &ctrl).unwrap(),
+            (None, RecvStatusFlags::DUP_ACK)
+        );
+        assert!(!c.dup_ack_pending());
+
         // Retransmissions also trigger after time-out.
         t.now += t.rto_period;
         {
@@ -1774,4 +1942,135 @@ pub(crate) mod tests {
         // and we don't wait for our FIN to be ACKed.
         assert!(c.is_done());
     }
+    #[test]
+    fn test_rto_estimation() {
+        let mut buf1 = [0u8; 100];
+        let mut buf2 = [0u8; 100];
+        let send_buf = vec![0u8; 200_000];
+
+        let mut t = ConnectionTester::new();
+        t.rto_min = 1_000;
+        t.rto_max = 100_000;
+        t.rto_period = 50_000;
+
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let mut c = t.passive_open(&syn).unwrap();
+        t.check_synack_is_next(&mut c);
+
+        let conn_isn = c.first_not_sent.0.wrapping_sub(1);
+        let payload_src = Some((send_buf.as_slice(), Wrapping(conn_isn) + Wrapping(1)));
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK)
+            .set_ack_number(conn_isn.wrapping_add(1));
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        check_established(&c);
+
+        // The initial RTO is used until the first measurement.
+        assert_eq!(c.rto_period, t.rto_period);
+
+        // Sends a data segment, and returns the sequence number right after it.
+        let mut send = |t: &mut ConnectionTester, c: &mut Connection| {
+            let s = t.write_next_segment(c, payload_src).unwrap().unwrap();
+            s.sequence_number().wrapping_add(s.payload_len() as u32)
+        };
+
+        // The first measurement initializes the estimate.
+        let seq = send(&mut t, &mut c);
+        t.now += 10_000;
+        ctrl.set_ack_number(seq);
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        assert_eq!(c.rtt_estimate, Some((10_000, 5_000)));
+        assert_eq!(c.rto_period, 30_000);
+
+        // Subsequent measurements update it.
+        let seq = send(&mut t, &mut c);
+        t.now += 2_000;
+        ctrl.set_ack_number(seq);
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        assert_eq!(c.rtt_estimate, Some((9_000, 5_750)));
+        assert_eq!(c.rto_period, 32_000);
+
+        // The RTO doubles after each timeout, but does not exceed rto_max.
+        let seq = send(&mut t, &mut c);
+        t.now += 32_000;
+        assert_eq!(send(&mut t, &mut c), seq);
+        assert_eq!(c.rto_period, 64_000);
+        t.now += 64_000;
+        assert_eq!(send(&mut t, &mut c), seq);
+        assert_eq!(c.rto_period, t.rto_max);
+
+        // The ACK of a retransmitted segment does not count as a measurement.
+        t.now += 1_000;
+        ctrl.set_ack_number(seq);
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        assert_eq!(c.rtt_estimate, Some((9_000, 5_750)));
+        assert_eq!(c.rto_period, t.rto_max);
+
+        // Only the next measurement brings the RTO back down.
+        let seq = send(&mut t, &mut c);
+        t.now += 2_000;
+        ctrl.set_ack_number(seq);
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        assert_eq!(c.rtt_estimate, Some((8_125, 6_063)));
+        assert_eq!(c.rto_period, 32_377);
+
+        // Small measurements cannot bring the RTO below rto_min.
+        for _ in 0..100 {
+            let seq = send(&mut t, &mut c);
+            ctrl.set_ack_number(seq);
+            t.receive_segment(&mut c, &ctrl).unwrap();
+        }
+        assert_eq!(c.rto_period, t.rto_min);
+    }
+
+    #[test]
+    fn test_window_scaling() {
+        let mut buf1 = [0u8; 100];
+        let mut buf2 = [0u8; 100];
+
+        let mut t = ConnectionTester::new();
+        t.wscale = Some(3);
+        t.local_rwnd_size = 200_000;
+
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let mut c = t.passive_open(&syn).unwrap();
+        // A shift count of 2 is enough to advertise the local window.
+        assert_eq!(c.wscale, Some((2, 3)));
+
+        // The SYNACK carries the window scale option, and its window size is not scaled.
+        let conn_isn = {
+            let s = t.write_next_segment(&mut c, None).unwrap().unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.parse_wscale_option_unchecked(s.header_len()), Ok(Some(2)));
+            assert_eq!(s.window_size(), u16::max_value());
+            s.sequence_number()
+        };
+
+        // Window sizes advertised by the other endpoint are scaled after the handshake.
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK)
+            .set_ack_number(conn_isn.wrapping_add(1))
+            .set_window_size(2000);
+        t.receive_segment(&mut c, &ctrl).unwrap();
+        check_established(&c);
+        assert_eq!(
+            c.remote_rwnd_edge(),
+            Wrapping(conn_isn.wrapping_add(1)) + Wrapping(16_000)
+        );
+
+        // And so are the ones we advertise.
+        c.enqueue_ack();
+        let s = t.write_next_segment(&mut c, None).unwrap().unwrap();
+        check_control_segment(&s, 0, TcpFlags::ACK);
+        assert_eq!(s.window_size(), 50_000);
+
+        // Window scaling is not used when the other endpoint does not ask for it.
+        t.wscale = None;
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let c = t.passive_open(&syn).unwrap();
+        assert_eq!(c.wscale, None);
+    }
 }
@@ -12,9 +12,10 @@
 // Endpoint in here too for the time being.
 
 use std::num::{NonZeroU16, NonZeroU64, Wrapping};
+use std::time::Instant;
 
 use logger::{Metric, METRICS};
-use mmds::parse_request;
+use mmds::{parse_request, wait_timeout};
 use pdu::bytes::NetworkBytes;
 use pdu::tcp::TcpSegment;
 use pdu::Incomplete;
@@ -25,27 +26,54 @@ use utils::time::timestamp_cycles;
 // TODO: These are currently expressed in cycles. Normally, they would be the equivalent of a
 // certain duration, depending on the frequency of the CPU, but we still have a bit to go until
 // that functionality is available, so we just use some conservative-ish values. Even on a fast
-// 4GHz CPU, the first is roughly equal to 10 seconds, and the other is ~300 ms.
+// 4GHz CPU, the first is roughly equal to 10 seconds, and the second is ~300 ms. The RTO of a
+// connection starts at that value, and then adjusts to the measured round-trip time, without
+// going below ~25 ms or above ~3 seconds.
 const EVICTION_THRESHOLD: u64 = 40_000_000_000;
 const CONNECTION_RTO_PERIOD: u64 = 1_200_000_000;
+const CONNECTION_RTO_MIN: u64 = 100_000_000;
+const CONNECTION_RTO_MAX: u64 = 12_000_000_000;
 const CONNECTION_RTO_COUNT_MAX: u16 = 15;
 
-// This is one plus the size of the largest bytestream carrying an HTTP request we are willing to
-// accept. It's limited in order to have a bound on memory usage. This value should be plenty for
-// imaginable regular MMDS requests.
+// This is the size of the largest request line plus headers we are willing to accept. It's limited
+// in order to have a bound on memory usage. This value should be plenty for imaginable regular
+// MMDS requests.
 // TODO: Maybe at some point include this in the checks we do when populating the MMDS via the API,
 // since it effectively limits the size of the keys (URIs) we're willing to use.
-const RCV_BUF_MAX_SIZE: usize = 2500;
+const MAX_HEADERS_SIZE: usize = 4096;
+
+/// The default size of the largest request body accepted by an `Endpoint`.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4096;
+
+// Requests pipelined by the remote are parsed only while the responses which have not been
+// acknowledged yet take less than this many bytes. A single response may exceed it.
+const RESPONSE_BUF_SOFT_LIMIT: usize = 65536;
+
+// Describes what was found while looking for the end of the first request in the receive buffer.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum RequestBoundary {
+    // The first that many bytes hold a complete request, including its body.
+    Complete(usize),
+    // More bytes have to arrive before the request is complete.
+    Incomplete,
+    // The headers or the body of the request exceed their maximum size.
+    TooLarge,
+}
 
-// Represents the local endpoint of a HTTP over TCP connection which carries GET requests
-// to the MMDS.
+// Represents the local endpoint of a HTTP over TCP connection which carries requests to the MMDS.
+// The remote may pipeline requests over the connection, which are answered in order.
 pub struct Endpoint {
-    // A fixed size buffer used to store bytes received via TCP. If the current request does not
-    // fit within, we reset the connection, since we see this as a hard memory bound.
-    receive_buf: [u8; RCV_BUF_MAX_SIZE],
+    // A fixed size buffer used to store bytes received via TCP. It fits the largest request we
+    // accept. If the current request does not fit within, we reset the connection, since we see
+    // this as a hard memory bound.
+    receive_buf: Vec<u8>,
     // Represents the next available position in the buffer.
     receive_buf_left: usize,
-    // This is filled with the HTTP response bytes after we parse a request and generate the reply.
+    // The size of the largest request body we accept.
+    max_body_size: usize,
+    // This is filled with the HTTP response bytes after we parse requests and generate the
+    // replies. Bytes are removed from the front as soon as the remote acknowledges them, so
+    // responses larger than the remote window get streamed.
     response_buf: Vec<u8>,
     // Represents the sequence number associated with the first byte from response_buf.
     response_seq: Wrapping<u32>,
@@ -59,6 +87,9 @@ pub struct Endpoint {
     // We ignore incoming segments when this is set, and that happens when we decide to reset
     // the connection (or it decides to reset itself).
     stop_receiving: bool,
+    // Set while holding the response to a long-poll request. Holds the length of the request,
+    // which stays at the beginning of receive_buf, and the moment when the wait times out.
+    waiting_request: Option<(usize, Instant)>,
 }
 
 // The "contract" for the Endpoint (if it implemented a trait or something) is something along
@@ -70,6 +101,9 @@ pub struct Endpoint {
 // increases a metric).
 // - After calling either of the previous functions, the user should also call is_done() to see
 // if the Endpoint is finished.
+// - Long-poll requests are held until the MMDS gets updated, or their wait times out. The user
+// should call check_waiting_request() in either case, and wait_deadline() tells when the wait
+// of the current request times out.
 // - The is_evictable() function returns true if the Endpoint can be destroyed as far as its
 // internal logic is concerned. It's going to be used by the connection handler when trying to
 // find a new slot for incoming connections if none are free (when replacing an existing connection
@@ -78,24 +112,31 @@ pub struct Endpoint {
 impl Endpoint {
     pub fn new<T: NetworkBytes>(
         segment: &TcpSegment<T>,
+        max_body_size: usize,
         eviction_threshold: NonZeroU64,
         connection_rto_period: NonZeroU64,
+        connection_rto_min: NonZeroU64,
+        connection_rto_max: NonZeroU64,
         connection_rto_count_max: NonZeroU16,
     ) -> Result<Self, PassiveOpenError> {
+        let receive_buf_size = MAX_HEADERS_SIZE + max_body_size;
         // TODO: mention this in doc comment for function
         // This simplifies things, and is a very reasonable assumption.
-        assert!(RCV_BUF_MAX_SIZE <= MAX_WINDOW_SIZE as usize);
+        assert!(receive_buf_size <= MAX_WINDOW_SIZE as usize);
 
         let connection = Connection::passive_open(
             segment,
-            RCV_BUF_MAX_SIZE as u32,
+            receive_buf_size as u32,
             connection_rto_period,
+            connection_rto_min,
+            connection_rto_max,
             connection_rto_count_max,
         )?;
 
         Ok(Endpoint {
-            receive_buf: [0u8; RCV_BUF_MAX_SIZE],
+            receive_buf: vec![0u8; receive_buf_size],
             receive_buf_left: 0,
+            max_body_size,
             response_buf: Vec::new(),
             // TODO: Using first_not_sent() makes sense here because a connection is currently
             // created via passive open only, so this points to the sequence number right after
@@ -105,17 +146,22 @@ impl Endpoint {
             last_segment_received_timestamp: timestamp_cycles(),
             eviction_threshold: eviction_threshold.get(),
             stop_receiving: false,
+            waiting_request: None,
         })
     }
 
     pub fn new_with_defaults<T: NetworkBytes>(
         segment: &TcpSegment<T>,
+        max_body_size: usize,
     ) -> Result<Self, PassiveOpenError> {
         // The unwraps are safe because the constants are greater than 0.
         Self::new(
             segment,
+            max_body_size,
             NonZeroU64::new(EVICTION_THRESHOLD).unwrap(),
             NonZeroU64::new(CONNECTION_RTO_PERIOD).unwrap(),
+            NonZeroU64::new(CONNECTION_RTO_MIN).unwrap(),
+            NonZeroU64::new(CONNECTION_RTO_MAX).unwrap(),
             NonZeroU16::new(CONNECTION_RTO_COUNT_MAX).unwrap(),
         )
     }
@@ -158,80 +204,104 @@ impl Endpoint {
             self.receive_buf_left += len.get();
         };
 
-        if !self.response_buf.is_empty()
-            && self.connection.highest_ack_received()
-                == self.response_seq + Wrapping(self.response_buf.len() as u32)
-        {
-            // If we got here, then we still have some response bytes to send (which are
-            // stored in self.response_buf).
-
-            // It seems we just recevied the last ACK we were waiting for, so the entire
-            // response has been successfully received. Set the new response_seq and clear
-            // the response_buf.
+        // Drop the response bytes which have been acknowledged, since they won't have to be
+        // retransmitted. ACKs outside the response are stale or refer to control segments.
+        let acked = (self.connection.highest_ack_received() - self.response_seq).0 as usize;
+        if acked > 0 && acked <= self.response_buf.len() {
+            self.response_buf.drain(..acked);
             self.response_seq = self.connection.highest_ack_received();
-            self.response_buf.clear();
         }
 
-        if self.response_buf.is_empty() {
-            // There's no pending response currently, so we're back to waiting for a request to be
-            // available in self.receive_buf.
-
-            // The following is some ugly but workable code that attempts to find the end of an
-            // HTTP 1.x request in receive_buf. We need to do this for now because parse_request()
-            // expects the entire request contents as parameter.
-            if self.receive_buf_left > 2 {
-                let b = self.receive_buf.as_mut();
-                for i in 0..self.receive_buf_left - 1 {
-                    // We're basically looking for a double new line, which can only appear at the
-                    // end of a valid request.
-                    if b[i] == b'\n' {
-                        let end = if b[i + 1] == b'\n' {
-                            i + 2
-                        } else if i + 3 <= self.receive_buf_left && &b[i + 1..i + 3] == b"\r\n" {
-                            i + 3
-                        } else {
-                            continue;
-                        };
-
-                        // We found a potential request, let's parse it.
-                        let response = parse_request(&b[..end]);
-                        // The unwrap is safe because a Vec will allocate more space until all the
-                        // writes succeed.
-                        response.write_all(&mut self.response_buf).unwrap();
-
-                        // Sanity check because the current logic operates under this assumption.
-                        assert!(self.response_buf.len() < u32::max_value() as usize);
-
-                        // We have to remove the bytes up to end from receive_buf, by shifting the
-                        // others to the beginning of the buffer, and updating receive_buf_left.
-                        // Also, advance the rwnd edge of the inner connection.
-                        // TODO: Maximum efficiency.
-                        for j in 0..b.len() - end {
-                            b[j] = b[j + end];
-                        }
-                        self.receive_buf_left -= end;
-                        self.connection.advance_local_rwnd_edge(end as u32);
-                        break;
-                    }
-                }
-            }
-
-            if self.receive_buf_left == self.receive_buf.len() {
-                // If we get here the buffer is full, but we still couldn't identify the end of a
-                // request, so we reset because we are over the maximum request size.
-                self.connection.reset();
-                self.stop_receiving = true;
-                return;
-            }
+        self.process_requests();
+        if self.stop_receiving {
+            return;
         }
 
         // We close the connection after receiving a FIN, and making sure there are no more
         // responses to send.
-        if self.connection.fin_received() && self.response_buf.is_empty() {
+        if self.connection.fin_received()
+            && self.response_buf.is_empty()
+            && self.waiting_request.is_none()
+        {
             self.connection.close();
         }
     }
 
+    // Answers the complete requests found in receive_buf, in the order they arrived. Parsing
+    // stops while a long-poll request is being held, and while there are plenty of response
+    // bytes waiting to be acknowledged already.
+    fn process_requests(&mut self) {
+        while self.waiting_request.is_none() && self.response_buf.len() < RESPONSE_BUF_SOFT_LIMIT {
+            let end = match find_request_end(
+                &self.receive_buf[..self.receive_buf_left],
+                self.max_body_size,
+            ) {
+                RequestBoundary::Complete(end) => end,
+                RequestBoundary::Incomplete => return,
+                RequestBoundary::TooLarge => {
+                    // The request doesn't fit within the receive buffer, so we reset because
+                    // we are over the maximum request size.
+                    self.connection.reset();
+                    self.stop_receiving = true;
+                    return;
+                }
+            };
+
+            // Long-poll requests are held until the MMDS gets updated, the others are
+            // answered right away.
+            match wait_timeout(&self.receive_buf[..end]) {
+                Some(timeout) => {
+                    METRICS.mmds.rx_long_poll_requests.inc();
+                    self.waiting_request = Some((end, Instant::now() + timeout));
+                }
+                None => self.respond(end),
+            }
+        }
+    }
+
+    // Parses the request made of the first request_len bytes of receive_buf, and appends the
+    // response to response_buf.
+    fn respond(&mut self, request_len: usize) {
+        let response = parse_request(&self.receive_buf[..request_len]);
+        // The unwrap is safe because a Vec will allocate more space until all the
+        // writes succeed.
+        response.write_all(&mut self.response_buf).unwrap();
+
+        // Sanity check because the current logic operates under this assumption.
+        assert!(self.response_buf.len() < MAX_WINDOW_SIZE as usize);
+
+        // We have to remove the bytes up to request_len from receive_buf, by shifting the
+        // others to the beginning of the buffer, and updating receive_buf_left.
+        // Also, advance the rwnd edge of the inner connection.
+        self.receive_buf
+            .copy_within(request_len..self.receive_buf_left, 0);
+        self.receive_buf_left -= request_len;
+        self.connection.advance_local_rwnd_edge(request_len as u32);
+    }
+
+    /// Answers the long-poll request being held, if the MMDS got updated since it arrived, or
+    /// its wait timed out. Returns whether a response is now available.
+    pub fn check_waiting_request(&mut self) -> bool {
+        if let Some((request_len, deadline)) = self.waiting_request {
+            if Instant::now() >= deadline
+                || wait_timeout(&self.receive_buf[..request_len]).is_none()
+            {
+                self.waiting_request = None;
+                self.respond(request_len);
+                // Requests pipelined after the long-poll one can be answered now.
+                self.process_requests();
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the moment when the wait of the long-poll request being held times out.
+    #[inline]
+    pub fn wait_deadline(&self) -> Option<Instant> {
+        self.waiting_request.map(|(_, deadline)| deadline)
+    }
+
     pub fn write_next_segment<'a>(
         &mut self,
         buf: &'a mut [u8],
@@ -264,12 +334,15 @@ impl Endpoint {
 
     #[inline]
     pub fn is_evictable(&self) -> bool {
-        timestamp_cycles().wrapping_sub(self.last_segment_received_timestamp)
-            > self.eviction_threshold
+        // Waiting for the MMDS to get updated is not a sign of inactivity.
+        self.waiting_request.is_none()
+            && timestamp_cycles().wrapping_sub(self.last_segment_received_timestamp)
+                > self.eviction_threshold
     }
 
     pub fn next_segment_status(&self) -> NextSegmentStatus {
-        let can_send_new_data = !self.response_buf.is_empty()
+        let response_end = self.response_seq + Wrapping(self.response_buf.len() as u32);
+        let can_send_new_data = seq_after(response_end, self.connection.first_not_sent())
             && seq_after(
                 self.connection.remote_rwnd_edge(),
                 self.connection.first_not_sent(),
@@ -288,13 +361,67 @@ impl Endpoint {
     }
 }
 
+// Looks for the end of the first HTTP 1.x request in buf. We need to do this for now because
+// parse_request() expects the entire request contents as parameter. The headers end with a double
+// new line, and are followed by as many body bytes as the Content-Length header specifies.
+fn find_request_end(buf: &[u8], max_body_size: usize) -> RequestBoundary {
+    let mut headers_end = None;
+    for i in 0..buf.len().saturating_sub(1) {
+        // We're basically looking for a double new line, which can only appear at the end of
+        // the headers of a valid request.
+        if buf[i] == b'\n' {
+            if buf[i + 1] == b'\n' {
+                headers_end = Some(i + 2);
+            } else if i + 3 <= buf.len() && &buf[i + 1..i + 3] == b"\r\n" {
+                headers_end = Some(i + 3);
+            } else {
+                continue;
+            }
+            break;
+        }
+    }
+
+    let headers_end = match headers_end {
+        Some(end) if end <= MAX_HEADERS_SIZE => end,
+        Some(_) => return RequestBoundary::TooLarge,
+        None if buf.len() >= MAX_HEADERS_SIZE => return RequestBoundary::TooLarge,
+        None => return RequestBoundary::Incomplete,
+    };
+
+    // An invalid Content-Length is left for parse_request() to reject.
+    let body_size = buf[..headers_end]
+        .split(|&byte| byte == b'\n')
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(|line| {
+            let mut name_value = line.splitn(2, ':');
+            match (name_value.next(), name_value.next()) {
+                (Some(name), Some(value)) if name.eq_ignore_ascii_case("content-length") => {
+                    value.trim().parse::<usize>().ok()
+                }
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(0);
+
+    if body_size > max_body_size {
+        RequestBoundary::TooLarge
+    } else if headers_end + body_size <= buf.len() {
+        RequestBoundary::Complete(headers_end + body_size)
+    } else {
+        RequestBoundary::Incomplete
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    extern crate serde_json;
     use super::*;
 
     use std::fmt;
     use std::str::from_utf8;
 
+    use mmds::MMDS;
     use pdu::tcp::Flags as TcpFlags;
     use tcp::connection::tests::ConnectionTester;
 
@@ -316,7 +443,7 @@ mod tests {
         let mut buf1 = [0u8; 500];
         let mut buf2 = [0u8; 500];
 
-        let mut write_buf = [0u8; RCV_BUF_MAX_SIZE + 100];
+        let mut write_buf = [0u8; MAX_HEADERS_SIZE + 100];
 
         let mut t = ConnectionTester::new();
 
@@ -325,14 +452,14 @@ mod tests {
         // Put another flag on the SYN so it becomes invalid.
         syn.set_flags_after_ns(TcpFlags::ACK);
         assert_eq!(
-            Endpoint::new_with_defaults(&syn).unwrap_err(),
+            Endpoint::new_with_defaults(&syn, DEFAULT_MAX_BODY_SIZE).unwrap_err(),
             PassiveOpenError::InvalidSyn
         );
 
         // Fix the SYN and create an endpoint.
         syn.set_flags_after_ns(TcpFlags::SYN);
         let remote_isn = syn.sequence_number();
-        let mut e = Endpoint::new_with_defaults(&syn).unwrap();
+        let mut e = Endpoint::new_with_defaults(&syn, DEFAULT_MAX_BODY_SIZE).unwrap();
 
         // Let's complete the three-way handshake. The next segment sent by the endpoint should
         // be a SYNACK.
@@ -491,11 +618,11 @@ mod tests {
 
         // Finally, let's fill self.receive_buf with the following request, and see if we get the
         // reset we expect on the next segment.
-        let request_to_fill = vec![0u8; RCV_BUF_MAX_SIZE - e.receive_buf_left];
+        let request_to_fill = vec![0u8; MAX_HEADERS_SIZE - e.receive_buf_left];
 
         {
             // Hack: have to artificially increase t.mss to create this segment which is 2k+.
-            t.mss = RCV_BUF_MAX_SIZE as u16;
+            t.mss = MAX_HEADERS_SIZE as u16;
             let mut data = t.write_data(write_buf.as_mut(), request_to_fill.as_ref());
 
             data.set_flags_after_ns(TcpFlags::ACK);
@@ -511,4 +638,207 @@ mod tests {
             assert_eq!(s.inner().flags_after_ns(), TcpFlags::RST);
         }
     }
+
+    #[test]
+    fn test_find_request_end() {
+        let request = b"GET /latest HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            find_request_end(request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Complete(request.len())
+        );
+        assert_eq!(
+            find_request_end(b"GET /latest HTTP/1.1\n\nGET", DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Complete(22)
+        );
+        assert_eq!(
+            find_request_end(b"GET /latest HTTP/1.1\r\n", DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Incomplete
+        );
+
+        // The body is part of the request.
+        let request = b"PUT /latest HTTP/1.1\r\ncontent-length: 5\r\n\r\nabcdeGET";
+        assert_eq!(
+            find_request_end(&request[..request.len() - 4], DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Incomplete
+        );
+        assert_eq!(
+            find_request_end(request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Complete(request.len() - 3)
+        );
+
+        // An invalid Content-Length is left for the HTTP parser to reject.
+        let request = b"PUT /latest HTTP/1.1\r\nContent-Length: five\r\n\r\n";
+        assert_eq!(
+            find_request_end(request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Complete(request.len())
+        );
+
+        // Bodies larger than the limit are rejected before they arrive.
+        let request = b"PUT /latest HTTP/1.1\r\nContent-Length: 11\r\n\r\n";
+        assert_eq!(find_request_end(request, 10), RequestBoundary::TooLarge);
+
+        // So are headers which don't end within MAX_HEADERS_SIZE bytes.
+        let mut request = vec![b'a'; MAX_HEADERS_SIZE - 1];
+        assert_eq!(
+            find_request_end(&request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::Incomplete
+        );
+        request.push(b'a');
+        assert_eq!(
+            find_request_end(&request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::TooLarge
+        );
+        request.extend_from_slice(b"\r\n\r\n");
+        assert_eq!(
+            find_request_end(&request, DEFAULT_MAX_BODY_SIZE),
+            RequestBoundary::TooLarge
+        );
+    }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let mut buf1 = [0u8; 500];
+        let mut buf2 = [0u8; 500];
+        let mut write_buf = vec![0u8; MAX_HEADERS_SIZE + DEFAULT_MAX_BODY_SIZE + 100];
+
+        let mut t = ConnectionTester::new();
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let remote_isn = syn.sequence_number();
+        let mut e = Endpoint::new_with_defaults(&syn, DEFAULT_MAX_BODY_SIZE).unwrap();
+
+        // Complete the three-way handshake.
+        let endpoint_isn = e
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap()
+            .inner()
+            .sequence_number();
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK);
+        ctrl.set_ack_number(endpoint_isn.wrapping_add(1));
+        e.receive_segment(&ctrl);
+        assert!(e.connection.is_established());
+
+        // Pipeline enough requests in a single segment for the responses to exceed the window
+        // of the remote endpoint.
+        let request_count = 150;
+        let requests =
+            b"GET http://169.254.169.255/asdfghjkl HTTP/1.1\r\n\r\n".repeat(request_count);
+        assert!(requests.len() <= e.receive_buf.len());
+        {
+            t.mss = requests.len() as u16;
+            let mut data = t.write_data(write_buf.as_mut(), requests.as_ref());
+            data.set_flags_after_ns(TcpFlags::ACK);
+            data.set_sequence_number(remote_isn.wrapping_add(1));
+            data.set_ack_number(endpoint_isn.wrapping_add(1));
+            e.receive_segment(&data);
+        }
+
+        // All the requests have been answered at once.
+        assert_eq!(e.receive_buf_left, 0);
+        let response_len = e.response_buf.len();
+        assert!(response_len > 11000);
+
+        // The responses are streamed as the remote acknowledges them.
+        let mut responses = Vec::new();
+        let mut endpoint_first_not_sent = endpoint_isn.wrapping_add(1);
+        while !e.response_buf.is_empty() {
+            while e.next_segment_status() == NextSegmentStatus::Available {
+                let s = e
+                    .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+                    .unwrap();
+                responses.extend_from_slice(s.inner().payload());
+                endpoint_first_not_sent =
+                    endpoint_first_not_sent.wrapping_add(s.inner().payload_len() as u32);
+            }
+            // No more than a window worth of bytes is in flight.
+            let acked = response_len - e.response_buf.len();
+            assert!(responses.len() - acked <= 11000);
+
+            let mut ctrl = t.write_ctrl(buf2.as_mut());
+            ctrl.set_flags_after_ns(TcpFlags::ACK);
+            ctrl.set_sequence_number(remote_isn.wrapping_add(1 + requests.len() as u32));
+            ctrl.set_ack_number(endpoint_first_not_sent);
+            e.receive_segment(&ctrl);
+
+            // Acknowledged bytes are no longer kept around.
+            assert_eq!(e.response_buf.len(), response_len - responses.len());
+        }
+
+        let responses = from_utf8(&responses).unwrap();
+        assert_eq!(responses.matches("HTTP/1.1 404").count(), request_count);
+    }
+
+    #[test]
+    fn test_long_poll() {
+        let mut buf1 = [0u8; 500];
+        let mut buf2 = [0u8; 500];
+        let mut write_buf = [0u8; MAX_HEADERS_SIZE + 100];
+
+        MMDS.lock()
+            .unwrap()
+            .put_data(serde_json::json!({"config": "first"}))
+            .unwrap();
+        let etag = MMDS.lock().unwrap().etag();
+
+        let mut t = ConnectionTester::new();
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let remote_isn = syn.sequence_number();
+        let mut e = Endpoint::new_with_defaults(&syn, DEFAULT_MAX_BODY_SIZE).unwrap();
+
+        // Complete the three-way handshake.
+        let endpoint_isn = e
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap()
+            .inner()
+            .sequence_number();
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK);
+        ctrl.set_ack_number(endpoint_isn.wrapping_add(1));
+        e.receive_segment(&ctrl);
+        assert!(e.connection.is_established());
+
+        let request = format!("GET /config?wait=true&last_etag={} HTTP/1.1\r\n\r\n", etag);
+        {
+            let mut data = t.write_data(write_buf.as_mut(), request.as_bytes());
+            data.set_flags_after_ns(TcpFlags::ACK);
+            data.set_sequence_number(remote_isn.wrapping_add(1));
+            data.set_ack_number(endpoint_isn.wrapping_add(1));
+            e.receive_segment(&data);
+        }
+
+        // The request is held, so only an ACK gets sent.
+        assert!(e.wait_deadline().is_some());
+        {
+            let s = e
+                .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+                .unwrap();
+            assert_eq!(s.inner().payload_len(), 0);
+        }
+        assert_eq!(e.next_segment_status(), NextSegmentStatus::Nothing);
+        assert!(!e.check_waiting_request());
+
+        // Holding a request does not make the endpoint evictable.
+        e.set_eviction_threshold(0);
+        assert!(!e.is_evictable());
+
+        // Updating the MMDS ends the wait.
+        MMDS.lock()
+            .unwrap()
+            .patch_data(serde_json::json!({"config": "second"}))
+            .unwrap();
+        assert!(e.check_waiting_request());
+        assert!(e.wait_deadline().is_none());
+        assert_eq!(e.next_segment_status(), NextSegmentStatus::Available);
+
+        let s = e
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap();
+        let response = from_utf8(s.inner().payload()).unwrap();
+        assert!(response.contains("200"));
+        assert!(response.contains(&format!("ETag: {}", MMDS.lock().unwrap().etag())));
+        assert!(response.ends_with("second"));
+        assert!(e.is_evictable());
+    }
 }
@@ -1,22 +1,23 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-//! Exposes simple TCP over IPv4 listener functionality via the [`TcpIPv4Handler`] structure.
+//! Exposes simple TCP over IPv4/IPv6 listener functionality via the [`TcpIPHandler`] structure.
 //!
-//! [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
+//! [`TcpIPHandler`]: struct.TcpIPHandler.html
 
 use std::collections::{HashMap, HashSet};
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::num::NonZeroUsize;
+use std::time::Instant;
 
 use pdu::bytes::NetworkBytes;
 use pdu::ipv4::{Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP};
+use pdu::ipv6::{Error as IPv6PacketError, IPv6Packet};
 use pdu::tcp::{Error as TcpSegmentError, Flags as TcpFlags, TcpSegment};
+use pdu::Incomplete;
 use tcp::endpoint::Endpoint;
 use tcp::{NextSegmentStatus, RstConfig};
 
-// TODO: This is currently IPv4 specific. Maybe change it to a more generic implementation.
-
 /// Describes events which may occur when the handler receives packets.
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum RecvEvent {
@@ -49,11 +50,12 @@ pub enum WriteEvent {
     Nothing,
 }
 
-/// Describes errors which may be encountered by the [`receive_packet`] method from
-/// [`TcpIPv4Handler`].
+/// Describes errors which may be encountered by the [`receive_packet`] and
+/// [`receive_ipv6_packet`] methods from [`TcpIPHandler`].
 ///
-/// [`receive_packet`]: struct.TcpIPv4Handler.html#method.receive_packet
-/// [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
+/// [`receive_packet`]: struct.TcpIPHandler.html#method.receive_packet
+/// [`receive_ipv6_packet`]: struct.TcpIPHandler.html#method.receive_ipv6_packet
+/// [`TcpIPHandler`]: struct.TcpIPHandler.html
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum RecvError {
     /// The packet has an invalid destination address.
@@ -65,30 +67,33 @@ pub enum RecvError {
 }
 
 /// Describes errors which may be encountered by the [`write_next_packet`] method from
-/// [`TcpIPv4Handler`].
+/// [`TcpIPHandler`].
 ///
-/// [`write_next_packet`]: struct.TcpIPv4Handler.html#method.write_next_packet
-/// [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
+/// [`write_next_packet`]: struct.TcpIPHandler.html#method.write_next_packet
+/// [`TcpIPHandler`]: struct.TcpIPHandler.html
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum WriteNextError {
     /// There was an error while writing the contents of the IPv4 packet.
     IPv4Packet(IPv4PacketError),
+    /// There was an error while writing the contents of the IPv6 packet.
+    IPv6Packet(IPv6PacketError),
     /// There was an error while writing the contents of the inner TCP segment.
     TcpSegment(TcpSegmentError),
 }
 
-// Generally speaking, a TCP/IPv4 connection is identified using the four-tuple (src_addr, src_port,
-// dst_addr, dst_port). However, the IPv4 address and TCP port of the MMDS endpoint are fixed, so
+// Generally speaking, a TCP/IP connection is identified using the four-tuple (src_addr, src_port,
+// dst_addr, dst_port). However, the IP address and TCP port of the MMDS endpoint are fixed, so
 // we can get away with uniquely identifying connections using just the remote address and port.
+// The remote address always belongs to the same family as the local address of the handler.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 #[cfg_attr(test, derive(Debug))]
 struct ConnectionTuple {
-    remote_addr: Ipv4Addr,
+    remote_addr: IpAddr,
     remote_port: u16,
 }
 
 impl ConnectionTuple {
-    fn new(remote_addr: Ipv4Addr, remote_port: u16) -> Self {
+    fn new(remote_addr: IpAddr, remote_port: u16) -> Self {
         ConnectionTuple {
             remote_addr,
             remote_port,
@@ -96,20 +101,67 @@ impl ConnectionTuple {
     }
 }
 
-/// Implements a minimalist TCP over IPv4 listener.
+// The IP packet which is currently being written by the handler. Allows the same logic to be used
+// regardless of the address family of the handler.
+enum IncompletePacket<'a> {
+    V4(Incomplete<IPv4Packet<'a, &'a mut [u8]>>),
+    V6(Incomplete<IPv6Packet<'a, &'a mut [u8]>>),
+}
+
+impl<'a> IncompletePacket<'a> {
+    // Writes the header of a packet sent from src_addr. We use src_addr for the destination
+    // address as well, just as a placeholder value until the actual one is known.
+    fn write_header(buf: &'a mut [u8], src_addr: IpAddr) -> Result<Self, WriteNextError> {
+        match src_addr {
+            IpAddr::V4(addr) => IPv4Packet::write_header(buf, PROTOCOL_TCP, addr, addr)
+                .map(IncompletePacket::V4)
+                .map_err(WriteNextError::IPv4Packet),
+            IpAddr::V6(addr) => IPv6Packet::write_header(buf, PROTOCOL_TCP, addr, addr)
+                .map(IncompletePacket::V6)
+                .map_err(WriteNextError::IPv6Packet),
+        }
+    }
+
+    fn payload_mut(&mut self) -> &mut [u8] {
+        match self {
+            IncompletePacket::V4(packet) => packet.inner_mut().payload_mut(),
+            IncompletePacket::V6(packet) => packet.inner_mut().payload_mut(),
+        }
+    }
+
+    // Sets the destination address and the payload length, returning the length of the packet.
+    fn complete(self, dst_addr: IpAddr, payload_len: usize) -> usize {
+        match (self, dst_addr) {
+            (IncompletePacket::V4(mut packet), IpAddr::V4(addr)) => {
+                packet.inner_mut().set_destination_address(addr);
+                packet.with_payload_len_unchecked(payload_len, true).len()
+            }
+            (IncompletePacket::V6(mut packet), IpAddr::V6(addr)) => {
+                packet.inner_mut().set_destination_address(addr);
+                packet.with_payload_len_unchecked(payload_len).len()
+            }
+            // The handler only accepts connections from addresses which belong to the same
+            // family as the local address.
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Implements a minimalist TCP over IPv4/IPv6 listener.
 ///
 /// Forwards incoming TCP segments to the appropriate connection object, based on the associated
 /// tuple, or attempts to establish new connections (when receiving `SYN` segments). Aside from
 /// constructors, the handler operation is based on three methods:
 ///
-/// * [`receive_packet`] examines an incoming IPv4 packet. It checks whether the destination
-///   address is correct, the attempts examine the inner TCP segment, making sure the destination
-///   port number is also correct. Then, it steers valid segments towards exiting connections,
-///   creates new connections for incoming `SYN` segments, and enqueues `RST` replies in response
-///   to any segments which cannot be associated with a connection (except other `RST` segments).
+/// * [`receive_packet`] (or [`receive_ipv6_packet`]) examines an incoming IPv4 (or IPv6) packet.
+///   It checks whether the destination address is correct, the attempts examine the inner TCP
+///   segment, making sure the destination port number is also correct. Then, it steers valid
+///   segments towards exiting connections, creates new connections for incoming `SYN` segments,
+///   and enqueues `RST` replies in response to any segments which cannot be associated with a
+///   connection (except other `RST` segments).
 ///   On success, also describes any internal status changes triggered by the reception of the
 ///   packet.
-/// * [`write_next_packet`] writes the next IPv4 packet (if available) that would be sent by the
+/// * [`write_next_packet`] writes the next IP packet (if available) that would be sent by the
 ///   handler itself (right now it can only mean an enqueued `RST`), or one of the existing
 ///   connections. On success, also describes any internal status changes triggered as the packet
 ///   gets transmitted.
@@ -118,11 +170,15 @@ impl ConnectionTuple {
 ///   to send for the moment. This is used to determine whether it's appropriate to call
 ///   [`write_next_packet`].
 ///
-/// [`receive_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_packet
-/// [`write_next_packet`]: ../handler/struct.TcpIPv4Handler.html#method.write_next_packet
-/// [`next_segment_status`]: ../handler/struct.TcpIPv4Handler.html#method.next_segment_status
-pub struct TcpIPv4Handler {
-    local_addr: Ipv4Addr,
+/// A handler is bound to a single local address, so it speaks either IPv4 or IPv6, depending on
+/// the family of that address.
+///
+/// [`receive_packet`]: ../handler/struct.TcpIPHandler.html#method.receive_packet
+/// [`receive_ipv6_packet`]: ../handler/struct.TcpIPHandler.html#method.receive_ipv6_packet
+/// [`write_next_packet`]: ../handler/struct.TcpIPHandler.html#method.write_next_packet
+/// [`next_segment_status`]: ../handler/struct.TcpIPHandler.html#method.next_segment_status
+pub struct TcpIPHandler {
+    local_addr: IpAddr,
     local_port: u16,
     // This map holds the currently active endpoints, identified by their connection tuple.
     connections: HashMap<ConnectionTuple, Endpoint>,
@@ -137,6 +193,8 @@ pub struct TcpIPv4Handler {
     rst_queue: Vec<(ConnectionTuple, RstConfig)>,
     // Maximum size of the RST queue.
     max_pending_resets: usize,
+    // Size of the largest request body accepted over each connection.
+    max_body_size: usize,
 }
 
 // Only used locally, in the receive_packet method, to differentiate between different outcomes
@@ -148,22 +206,24 @@ enum RecvSegmentOutcome {
     UnexpectedSegment(bool),
 }
 
-impl TcpIPv4Handler {
-    /// Creates a new `TcpIPv4Handler`.
+impl TcpIPHandler {
+    /// Creates a new `TcpIPHandler`.
     ///
     /// The handler acts as if bound to `local_addr`:`local_port`, and will accept at most
     /// `max_connections` concurrent connections. `RST` segments generated by unexpected incoming
-    /// segments are placed in a queue which is at most `max_pending_resets` long.
+    /// segments are placed in a queue which is at most `max_pending_resets` long. Connections
+    /// carrying requests with bodies larger than `max_body_size` bytes are reset.
     #[inline]
     pub fn new(
-        local_addr: Ipv4Addr,
+        local_addr: IpAddr,
         local_port: u16,
         max_connections: NonZeroUsize,
         max_pending_resets: NonZeroUsize,
+        max_body_size: usize,
     ) -> Self {
         let max_connections = max_connections.get();
         let max_pending_resets = max_pending_resets.get();
-        TcpIPv4Handler {
+        TcpIPHandler {
             local_addr,
             local_port,
             connections: HashMap::with_capacity(max_connections),
@@ -172,31 +232,61 @@ impl TcpIPv4Handler {
             next_timeout: None,
             rst_queue: Vec::with_capacity(max_pending_resets),
             max_pending_resets,
+            max_body_size,
         }
     }
 
-    /// Contains logic for handling incoming segments.
+    /// Contains logic for handling incoming segments carried by IPv4 packets.
     ///
     /// Any changes to the state if the handler are communicated through an `Ok(RecvEvent)`.
+    #[inline]
     pub fn receive_packet<T: NetworkBytes>(
         &mut self,
         packet: &IPv4Packet<T>,
     ) -> Result<RecvEvent, RecvError> {
-        if packet.destination_address() != self.local_addr {
+        self.receive_segment(
+            IpAddr::from(packet.source_address()),
+            IpAddr::from(packet.destination_address()),
+            packet.payload(),
+        )
+    }
+
+    /// Contains logic for handling incoming segments carried by IPv6 packets.
+    ///
+    /// Any changes to the state if the handler are communicated through an `Ok(RecvEvent)`.
+    #[inline]
+    pub fn receive_ipv6_packet<T: NetworkBytes>(
+        &mut self,
+        packet: &IPv6Packet<T>,
+    ) -> Result<RecvEvent, RecvError> {
+        self.receive_segment(
+            IpAddr::from(packet.source_address()),
+            IpAddr::from(packet.destination_address()),
+            packet.payload(),
+        )
+    }
+
+    fn receive_segment(
+        &mut self,
+        src_addr: IpAddr,
+        dst_addr: IpAddr,
+        payload: &[u8],
+    ) -> Result<RecvEvent, RecvError> {
+        // This also rejects packets from the other address family.
+        if dst_addr != self.local_addr {
             return Err(RecvError::InvalidAddress);
         }
 
         // TODO: We skip verifying the checksum, just in case the device model relies on offloading
         // checksum computation from the guest to some other entity. Clear this up at some point!
         // (Issue #520)
-        let segment =
-            TcpSegment::from_bytes(packet.payload(), None).map_err(RecvError::TcpSegment)?;
+        let segment = TcpSegment::from_bytes(payload, None).map_err(RecvError::TcpSegment)?;
 
         if segment.destination_port() != self.local_port {
             return Err(RecvError::InvalidPort);
         }
 
-        let tuple = ConnectionTuple::new(packet.source_address(), segment.source_port());
+        let tuple = ConnectionTuple::new(src_addr, segment.source_port());
 
         let outcome = if let Some(endpoint) = self.connections.get_mut(&tuple) {
             endpoint.receive_segment(&segment);
@@ -228,7 +318,7 @@ impl TcpIPv4Handler {
                 Ok(RecvEvent::Nothing)
             }
             RecvSegmentOutcome::NewConnection => {
-                let endpoint = match Endpoint::new_with_defaults(&segment) {
+                let endpoint = match Endpoint::new_with_defaults(&segment, self.max_body_size) {
                     Ok(endpoint) => endpoint,
                     Err(_) => return Ok(RecvEvent::FailedNewConnection),
                 };
@@ -362,12 +452,9 @@ impl TcpIPv4Handler {
         let mut writer_status = None;
         let mut event = WriteEvent::Nothing;
 
-        // We use self.local_addr for the dst_addr parameter also just as a placeholder value. The
-        // actual destination address is written below, after deciding which endpoint is allowed
-        // to send the next packet.
-        let mut packet =
-            IPv4Packet::write_header(buf, PROTOCOL_TCP, self.local_addr, self.local_addr)
-                .map_err(WriteNextError::IPv4Packet)?;
+        // The actual destination address is written below, after deciding which endpoint is
+        // allowed to send the next packet.
+        let mut packet = IncompletePacket::write_header(buf, self.local_addr)?;
 
         // We set mss_used to 0, because we don't add any IP options.
         // TODO: Maybe get this nicely from packet at some point.
@@ -379,12 +466,13 @@ impl TcpIPv4Handler {
         if let Some((tuple, rst_cfg)) = self.rst_queue.pop() {
             let (seq, ack, flags_after_ns) = rst_cfg.seq_ack_tcp_flags();
             let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
-                packet.inner_mut().payload_mut(),
+                packet.payload_mut(),
                 seq,
                 ack,
                 flags_after_ns,
                 10000,
                 None,
+                None,
                 0,
                 None,
             )
@@ -396,11 +484,7 @@ impl TcpIPv4Handler {
             )
             .len();
 
-            packet
-                .inner_mut()
-                .set_destination_address(tuple.remote_addr);
-
-            let packet_len = packet.with_payload_len_unchecked(segment_len, true).len();
+            let packet_len = packet.complete(tuple.remote_addr, segment_len);
             // The unwrap() is safe because packet_len > 0.
             return Ok((
                 Some(NonZeroUsize::new(packet_len).unwrap()),
@@ -417,10 +501,9 @@ impl TcpIPv4Handler {
             // in self.connections.
             let endpoint = self.connections.get_mut(tuple).unwrap();
             // We need this block to clearly delimit the lifetime of the mutable borrow started by
-            // the following packet.inner_mut().
+            // the following packet.payload_mut().
             let segment_len = {
-                let maybe_segment =
-                    endpoint.write_next_segment(packet.inner_mut().payload_mut(), mss_reserved);
+                let maybe_segment = endpoint.write_next_segment(packet.payload_mut(), mss_reserved);
 
                 match maybe_segment {
                     Some(segment) => segment
@@ -434,11 +517,7 @@ impl TcpIPv4Handler {
                 }
             };
 
-            packet
-                .inner_mut()
-                .set_destination_address(tuple.remote_addr);
-
-            let ip_len = packet.with_payload_len_unchecked(segment_len, true).len();
+            let ip_len = packet.complete(tuple.remote_addr, segment_len);
 
             // The unwrap is safe because ip_len > 0.
             len = Some(NonZeroUsize::new(ip_len).unwrap());
@@ -465,6 +544,30 @@ impl TcpIPv4Handler {
         Ok((len, event))
     }
 
+    /// Answers the long-poll requests whose wait is over, either because the MMDS got updated,
+    /// or because they timed out. Their endpoints become able to send the responses right away.
+    pub fn check_waiting_requests(&mut self) {
+        let mut ready = Vec::new();
+        for (tuple, endpoint) in self.connections.iter_mut() {
+            if endpoint.check_waiting_request() {
+                ready.push(*tuple);
+            }
+        }
+
+        for tuple in ready {
+            let status = self.connections[&tuple].next_segment_status();
+            self.check_next_segment_status(tuple, status);
+        }
+    }
+
+    /// Returns the closest moment into the future when the wait of a long-poll request times out.
+    pub fn next_wait_deadline(&self) -> Option<Instant> {
+        self.connections
+            .values()
+            .filter_map(Endpoint::wait_deadline)
+            .min()
+    }
+
     /// Describes the status of the next segment to be sent by the handler.
     #[inline]
     pub fn next_segment_status(&self) -> NextSegmentStatus {
@@ -482,7 +585,10 @@ impl TcpIPv4Handler {
 
 #[cfg(test)]
 mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
     use pdu::bytes::NetworkBytesMut;
+    use tcp::DEFAULT_MAX_BODY_SIZE;
 
     use super::*;
 
@@ -494,7 +600,7 @@ mod tests {
 
     #[allow(clippy::type_complexity)]
     fn write_next<'a>(
-        h: &mut TcpIPv4Handler,
+        h: &mut TcpIPHandler,
         buf: &'a mut [u8],
     ) -> Result<(Option<IPv4Packet<'a, &'a mut [u8]>>, WriteEvent), WriteNextError> {
         h.write_next_packet(buf).map(|(o, e)| {
@@ -509,7 +615,7 @@ mod tests {
     }
 
     fn next_written_segment<'a>(
-        h: &mut TcpIPv4Handler,
+        h: &mut TcpIPHandler,
         buf: &'a mut [u8],
         expected_event: WriteEvent,
     ) -> TcpSegment<'a, &'a mut [u8]> {
@@ -526,17 +632,14 @@ mod tests {
     // Calls write_next_packet until either an error occurs, or there's nothing left to send.
     // When successful, returns how many packets were written. The remote_addr argument is used
     // to check the packets are sent to the appropriate destination.
-    fn drain_packets(
-        h: &mut TcpIPv4Handler,
-        remote_addr: Ipv4Addr,
-    ) -> Result<usize, WriteNextError> {
+    fn drain_packets(h: &mut TcpIPHandler, remote_addr: Ipv4Addr) -> Result<usize, WriteNextError> {
         let mut buf = [0u8; 2000];
         let mut count: usize = 0;
         loop {
             let (o, _) = write_next(h, buf.as_mut())?;
             if let Some(packet) = o {
                 count += 1;
-                assert_eq!(packet.source_address(), h.local_addr);
+                assert_eq!(IpAddr::from(packet.source_address()), h.local_addr);
                 assert_eq!(packet.destination_address(), remote_addr);
             } else {
                 break;
@@ -559,11 +662,12 @@ mod tests {
         let max_connections = 2;
         let max_pending_resets = 2;
 
-        let mut h = TcpIPv4Handler::new(
-            local_addr,
+        let mut h = TcpIPHandler::new(
+            IpAddr::from(local_addr),
             local_port,
             NonZeroUsize::new(max_connections).unwrap(),
             NonZeroUsize::new(max_pending_resets).unwrap(),
+            DEFAULT_MAX_BODY_SIZE,
         );
 
         // We start with a wrong destination address and destination port to check those error
@@ -586,6 +690,7 @@ mod tests {
                 TcpFlags::empty(),
                 10000,
                 None,
+                None,
                 100,
                 None,
                 None,
@@ -662,8 +767,8 @@ mod tests {
         assert_eq!(h.next_segment_status(), NextSegmentStatus::Available);
         assert_eq!(drain_packets(&mut h, remote_addr), Ok(1));
 
-        let remote_tuple = ConnectionTuple::new(remote_addr, remote_port);
-        let remote_tuple2 = ConnectionTuple::new(remote_addr, remote_port + 1);
+        let remote_tuple = ConnectionTuple::new(IpAddr::from(remote_addr), remote_port);
+        let remote_tuple2 = ConnectionTuple::new(IpAddr::from(remote_addr), remote_port + 1);
 
         // Also, there should be a retransmission timer associated with the previous SYNACK now.
         assert_eq!(h.active_connections.len(), 0);
@@ -721,7 +826,10 @@ mod tests {
         // The timeout associated with the SYNACK of the second connection should be next.
         assert_eq!(h.active_connections.len(), 0);
         if let Some((_, tuple)) = h.next_timeout {
-            assert_ne!(tuple, ConnectionTuple::new(remote_addr, remote_port));
+            assert_ne!(
+                tuple,
+                ConnectionTuple::new(IpAddr::from(remote_addr), remote_port)
+            );
         } else {
             panic!("missing third expected timeout");
         }
@@ -766,4 +874,93 @@ mod tests {
         assert_eq!(h.connections.len(), 1);
         assert_eq!(h.active_connections.len(), 0);
     }
+
+    #[test]
+    fn test_handler_ipv6() {
+        let mut buf = [0u8; 100];
+        let mut buf2 = [0u8; 2000];
+
+        let local_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+        let local_port = 80;
+        let remote_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let remote_port = 1012;
+        let seq_number = 123;
+
+        let mut h = TcpIPHandler::new(
+            IpAddr::from(local_addr),
+            local_port,
+            NonZeroUsize::new(2).unwrap(),
+            NonZeroUsize::new(2).unwrap(),
+            DEFAULT_MAX_BODY_SIZE,
+        );
+
+        let mut p =
+            IPv6Packet::write_header(buf.as_mut(), PROTOCOL_TCP, remote_addr, local_addr).unwrap();
+        let s_len = TcpSegment::write_segment::<[u8]>(
+            p.inner_mut().payload_mut(),
+            remote_port,
+            local_port,
+            seq_number,
+            456,
+            TcpFlags::SYN,
+            10000,
+            None,
+            None,
+            100,
+            None,
+            Some((IpAddr::from(remote_addr), IpAddr::from(local_addr))),
+        )
+        .unwrap()
+        .len();
+        let p = p.with_payload_len_unchecked(s_len);
+
+        // An IPv4 packet can never reach an IPv6 handler.
+        {
+            let mut buf4 = [0u8; 100];
+            let v4_addr = Ipv4Addr::new(169, 254, 169, 254);
+            let p4 = IPv4Packet::write_header(buf4.as_mut(), PROTOCOL_TCP, v4_addr, v4_addr)
+                .unwrap()
+                .with_payload_len_unchecked(s_len, false);
+            assert_eq!(
+                h.receive_packet(&p4).unwrap_err(),
+                RecvError::InvalidAddress
+            );
+        }
+
+        assert_eq!(
+            h.receive_ipv6_packet(&p),
+            Ok(RecvEvent::NewConnectionSuccessful)
+        );
+        assert_eq!(h.next_segment_status(), NextSegmentStatus::Available);
+
+        let len = h.write_next_packet(buf2.as_mut()).unwrap().0.unwrap().get();
+        let p = IPv6Packet::from_bytes(&buf2[..len]).unwrap();
+        assert_eq!(p.next_header(), PROTOCOL_TCP);
+        assert_eq!(p.source_address(), local_addr);
+        assert_eq!(p.destination_address(), remote_addr);
+
+        // The checksum must be valid with respect to the IPv6 pseudo-header.
+        let s = TcpSegment::from_bytes(
+            p.payload(),
+            Some((IpAddr::from(local_addr), IpAddr::from(remote_addr))),
+        )
+        .unwrap();
+        assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(s.source_port(), local_port);
+        assert_eq!(s.destination_port(), remote_port);
+        assert_eq!(s.ack_number(), seq_number.wrapping_add(1));
+
+        // Nothing else to send.
+        assert!(h.write_next_packet(buf.as_mut()).unwrap().0.is_none());
+
+        // Buffer is too small to hold an IPv6 header.
+        h.enqueue_rst(
+            ConnectionTuple::new(IpAddr::from(remote_addr), remote_port),
+            &s,
+        );
+        assert_eq!(
+            h.write_next_packet(&mut buf[..10]).unwrap_err(),
+            WriteNextError::IPv6Packet(IPv6PacketError::SliceTooShort)
+        );
+    }
 }
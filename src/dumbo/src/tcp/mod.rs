@@ -7,6 +7,8 @@ pub mod connection;
 mod endpoint;
 pub mod handler;
 
+pub use self::endpoint::DEFAULT_MAX_BODY_SIZE;
+
 use pdu::bytes::NetworkBytes;
 use pdu::tcp::{Flags as TcpFlags, TcpSegment};
 
@@ -109,6 +111,7 @@ mod tests {
             TcpFlags::empty(),
             0,
             None,
+            None,
             100,
             None,
             None,
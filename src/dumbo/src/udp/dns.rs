@@ -0,0 +1,399 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains a tiny DNS responder, which answers `A` and `AAAA` queries for the names defined
+//! under the `/dns` key of the MMDS data store.
+//!
+//! Each name maps to a single address, for example `{"dns": {"db.internal": "10.0.0.2"}}`. Names
+//! are looked up in lowercase. Lookups are authorized like the HTTP requests of the guest, so
+//! when the MMDS requires session tokens, which DNS queries can't carry, they are refused. The
+//! message format is described in [1].
+//!
+//! [1]: https://tools.ietf.org/html/rfc1035#section-4
+
+use std::net::IpAddr;
+
+use mmds::data_store::Error as MmdsError;
+use mmds::{read_for_guest, GuestReadError};
+
+use super::UdpService;
+
+/// The UDP port the DNS responder listens on.
+pub const DNS_PORT: u16 = 53;
+
+const ID_OFFSET: usize = 0;
+const FLAGS_OFFSET: usize = 2;
+const QDCOUNT_OFFSET: usize = 4;
+const ANCOUNT_OFFSET: usize = 6;
+const HEADER_LEN: usize = 12;
+
+const FLAG_QR: u16 = 1 << 15;
+const FLAG_AA: u16 = 1 << 10;
+const FLAG_RD: u16 = 1 << 8;
+const OPCODE_MASK: u16 = 0xf << 11;
+
+const RCODE_FORMERR: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+const RCODE_NOTIMP: u16 = 4;
+const RCODE_REFUSED: u16 = 5;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+// The longest encoded name allowed by RFC 1035.
+const MAX_NAME_LEN: usize = 255;
+// Answers refer to the name of the question, which always starts right after the header.
+const QUESTION_NAME_POINTER: u16 = 0xc000 | HEADER_LEN as u16;
+// The time, in seconds, guests may cache our answers for.
+const ANSWER_TTL: u32 = 60;
+
+// The MMDS key which holds the name to address mappings.
+const MMDS_DNS_KEY: &str = "/dns";
+
+// Returns the addresses `name` maps to, or the response code to answer with when there are
+// none to give.
+type Resolver = fn(&str) -> Result<Vec<IpAddr>, u16>;
+
+fn resolve_from_mmds(name: &str) -> Result<Vec<IpAddr>, u16> {
+    // The root name would otherwise resolve to the MMDS key itself.
+    if name.is_empty() {
+        return Err(RCODE_NXDOMAIN);
+    }
+    match read_for_guest(&format!("{}/{}", MMDS_DNS_KEY, name), None) {
+        Ok(values) => Ok(values
+            .iter()
+            .filter_map(|value| value.parse().ok())
+            .collect()),
+        // The name is defined, but it does not map to an address.
+        Err(GuestReadError::DataStore(MmdsError::UnsupportedValueType)) => Ok(Vec::new()),
+        Err(GuestReadError::DataStore(_)) => Err(RCODE_NXDOMAIN),
+        Err(GuestReadError::Unauthorized(_)) => Err(RCODE_REFUSED),
+    }
+}
+
+/// Answers DNS queries for the names defined in the MMDS data store.
+pub struct DnsResponder {
+    resolve: Resolver,
+}
+
+impl DnsResponder {
+    /// Creates a responder which looks names up in the MMDS data store.
+    pub fn new() -> Self {
+        DnsResponder {
+            resolve: resolve_from_mmds,
+        }
+    }
+
+    #[cfg(test)]
+    fn with_resolver(resolve: Resolver) -> Self {
+        DnsResponder { resolve }
+    }
+}
+
+impl Default for DnsResponder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+// Parses the name which starts at `offset`, returning it in lowercase, along with the offset of
+// the first byte after it. Compressed names and labels which cannot appear in MMDS keys are
+// rejected.
+fn parse_name(buf: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let start = offset;
+    let mut name = String::new();
+    loop {
+        let label_len = *buf.get(offset)? as usize;
+        offset += 1;
+        if label_len == 0 {
+            break;
+        }
+        // The two most significant bits are set for pointers, and reserved otherwise.
+        if label_len & 0xc0 != 0 || offset + label_len - start > MAX_NAME_LEN {
+            return None;
+        }
+        let label = buf.get(offset..offset + label_len)?;
+        if !label
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        {
+            return None;
+        }
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.extend(label.iter().map(|&c| char::from(c.to_ascii_lowercase())));
+        offset += label_len;
+    }
+    Some((name, offset))
+}
+
+// Writes a reply which carries only the header of the request and the given response code.
+fn write_error(request: &[u8], reply: &mut [u8], flags: u16, rcode: u16) -> usize {
+    reply[..HEADER_LEN].iter_mut().for_each(|b| *b = 0);
+    write_u16(reply, ID_OFFSET, read_u16(request, ID_OFFSET));
+    write_u16(reply, FLAGS_OFFSET, flags | rcode);
+    HEADER_LEN
+}
+
+impl UdpService for DnsResponder {
+    fn port(&self) -> u16 {
+        DNS_PORT
+    }
+
+    fn handle_request(&mut self, request: &[u8], reply: &mut [u8]) -> Option<usize> {
+        if request.len() < HEADER_LEN || reply.len() < HEADER_LEN {
+            return None;
+        }
+
+        let request_flags = read_u16(request, FLAGS_OFFSET);
+        // We never answer responses.
+        if request_flags & FLAG_QR != 0 {
+            return None;
+        }
+
+        let mut flags = FLAG_QR | FLAG_AA | (request_flags & (OPCODE_MASK | FLAG_RD));
+        if request_flags & OPCODE_MASK != 0 {
+            return Some(write_error(request, reply, flags, RCODE_NOTIMP));
+        }
+        if read_u16(request, QDCOUNT_OFFSET) != 1 {
+            return Some(write_error(request, reply, flags, RCODE_FORMERR));
+        }
+
+        let (name, name_end) = match parse_name(request, HEADER_LEN) {
+            Some(value) => value,
+            None => return Some(write_error(request, reply, flags, RCODE_FORMERR)),
+        };
+        let question_end = name_end + 4;
+        if request.len() < question_end || reply.len() < question_end {
+            return Some(write_error(request, reply, flags, RCODE_FORMERR));
+        }
+        let qtype = read_u16(request, name_end);
+        let qclass = read_u16(request, name_end + 2);
+        if qclass != CLASS_IN {
+            return Some(write_error(request, reply, flags, RCODE_NOTIMP));
+        }
+
+        let addresses = match (self.resolve)(&name) {
+            Ok(addresses) => addresses,
+            Err(rcode) => {
+                flags |= rcode;
+                Vec::new()
+            }
+        };
+
+        // The reply echoes the header and the question of the request, with the counts of the
+        // other sections reset. Additional records (such as EDNS options) are dropped.
+        reply[..question_end].copy_from_slice(&request[..question_end]);
+        reply[ANCOUNT_OFFSET..HEADER_LEN]
+            .iter_mut()
+            .for_each(|b| *b = 0);
+        write_u16(reply, FLAGS_OFFSET, flags);
+
+        let mut len = question_end;
+        let mut answer_count = 0u16;
+        for address in addresses {
+            let (rtype, rdata) = match address {
+                IpAddr::V4(addr) if qtype == TYPE_A => (TYPE_A, addr.octets().to_vec()),
+                IpAddr::V6(addr) if qtype == TYPE_AAAA => (TYPE_AAAA, addr.octets().to_vec()),
+                _ => continue,
+            };
+            let answer_len = 12 + rdata.len();
+            // Answers which do not fit are left out.
+            if len + answer_len > reply.len() {
+                break;
+            }
+            write_u16(reply, len, QUESTION_NAME_POINTER);
+            write_u16(reply, len + 2, rtype);
+            write_u16(reply, len + 4, CLASS_IN);
+            reply[len + 6..len + 10].copy_from_slice(&ANSWER_TTL.to_be_bytes());
+            write_u16(reply, len + 10, rdata.len() as u16);
+            reply[len + 12..len + answer_len].copy_from_slice(&rdata);
+            len += answer_len;
+            answer_count += 1;
+        }
+        write_u16(reply, ANCOUNT_OFFSET, answer_count);
+
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn resolve(name: &str) -> Result<Vec<IpAddr>, u16> {
+        match name {
+            "db.internal" => Ok(vec![
+                IpAddr::from(Ipv4Addr::new(10, 0, 0, 2)),
+                IpAddr::from(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2)),
+            ]),
+            "empty.internal" => Ok(Vec::new()),
+            "private.internal" => Err(RCODE_REFUSED),
+            _ => Err(RCODE_NXDOMAIN),
+        }
+    }
+
+    fn query(id: u16, flags: u16, name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_LEN];
+        write_u16(&mut buf, ID_OFFSET, id);
+        write_u16(&mut buf, FLAGS_OFFSET, flags);
+        write_u16(&mut buf, QDCOUNT_OFFSET, 1);
+        for label in name.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0);
+        buf.extend_from_slice(&qtype.to_be_bytes());
+        buf.extend_from_slice(&qclass.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_parse_name() {
+        let q = query(0, 0, "Db.Internal", TYPE_A, CLASS_IN);
+        assert_eq!(
+            parse_name(&q, HEADER_LEN),
+            Some((String::from("db.internal"), q.len() - 4))
+        );
+
+        // Truncated names, pointers and labels which could escape the MMDS key are rejected.
+        assert_eq!(parse_name(&q[..q.len() - 6], HEADER_LEN), None);
+        assert_eq!(parse_name(&[0xc0, 0x0c], 0), None);
+        assert_eq!(parse_name(b"\x03a/b\x00", 0), None);
+        assert_eq!(parse_name(b"\x03a~b\x00", 0), None);
+
+        let mut long_name = Vec::new();
+        for _ in 0..5 {
+            long_name.push(63);
+            long_name.extend_from_slice(&[b'a'; 63]);
+        }
+        long_name.push(0);
+        assert_eq!(parse_name(&long_name, 0), None);
+    }
+
+    #[test]
+    fn test_dns_responder() {
+        let mut responder = DnsResponder::with_resolver(resolve);
+        let mut reply = [0u8; 512];
+        assert_eq!(responder.port(), DNS_PORT);
+
+        // An A query.
+        let q = query(0x1234, FLAG_RD, "DB.internal", TYPE_A, CLASS_IN);
+        let len = responder.handle_request(&q, &mut reply).unwrap();
+        assert_eq!(len, q.len() + 16);
+        assert_eq!(read_u16(&reply, ID_OFFSET), 0x1234);
+        assert_eq!(read_u16(&reply, FLAGS_OFFSET), FLAG_QR | FLAG_AA | FLAG_RD);
+        assert_eq!(read_u16(&reply, QDCOUNT_OFFSET), 1);
+        assert_eq!(read_u16(&reply, ANCOUNT_OFFSET), 1);
+        assert_eq!(reply[HEADER_LEN..q.len()], q[HEADER_LEN..]);
+        let answer = &reply[q.len()..len];
+        assert_eq!(read_u16(answer, 0), QUESTION_NAME_POINTER);
+        assert_eq!(read_u16(answer, 2), TYPE_A);
+        assert_eq!(read_u16(answer, 4), CLASS_IN);
+        assert_eq!(answer[6..10], ANSWER_TTL.to_be_bytes());
+        assert_eq!(read_u16(answer, 10), 4);
+        assert_eq!(answer[12..], [10, 0, 0, 2]);
+
+        // An AAAA query.
+        let q = query(1, 0, "db.internal", TYPE_AAAA, CLASS_IN);
+        let len = responder.handle_request(&q, &mut reply).unwrap();
+        assert_eq!(len, q.len() + 28);
+        assert_eq!(read_u16(&reply, FLAGS_OFFSET), FLAG_QR | FLAG_AA);
+        assert_eq!(read_u16(&reply, ANCOUNT_OFFSET), 1);
+        assert_eq!(
+            reply[q.len() + 12..len],
+            Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2).octets()
+        );
+
+        // Names without addresses of the requested type get an empty answer.
+        for q in [
+            query(1, 0, "db.internal", 16, CLASS_IN),
+            query(1, 0, "empty.internal", TYPE_A, CLASS_IN),
+        ]
+        .iter()
+        {
+            assert_eq!(responder.handle_request(&q, &mut reply), Some(q.len()));
+            assert_eq!(read_u16(&reply, FLAGS_OFFSET), FLAG_QR | FLAG_AA);
+            assert_eq!(read_u16(&reply, ANCOUNT_OFFSET), 0);
+        }
+
+        // Unknown names.
+        let q = query(1, 0, "web.internal", TYPE_A, CLASS_IN);
+        assert_eq!(responder.handle_request(&q, &mut reply), Some(q.len()));
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | RCODE_NXDOMAIN
+        );
+
+        // Names the guest is not allowed to look up.
+        let q = query(1, 0, "private.internal", TYPE_A, CLASS_IN);
+        assert_eq!(responder.handle_request(&q, &mut reply), Some(q.len()));
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | RCODE_REFUSED
+        );
+        assert_eq!(read_u16(&reply, ANCOUNT_OFFSET), 0);
+
+        // Unsupported opcodes and classes.
+        let q = query(1, 1 << 11, "db.internal", TYPE_A, CLASS_IN);
+        assert_eq!(responder.handle_request(&q, &mut reply), Some(HEADER_LEN));
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | (1 << 11) | RCODE_NOTIMP
+        );
+        assert_eq!(read_u16(&reply, QDCOUNT_OFFSET), 0);
+        let q = query(1, 0, "db.internal", TYPE_A, 3);
+        assert_eq!(responder.handle_request(&q, &mut reply), Some(HEADER_LEN));
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | RCODE_NOTIMP
+        );
+
+        // Malformed queries.
+        let mut q = query(1, 0, "db.internal", TYPE_A, CLASS_IN);
+        write_u16(&mut q, QDCOUNT_OFFSET, 2);
+        assert_eq!(responder.handle_request(&q, &mut reply), Some(HEADER_LEN));
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | RCODE_FORMERR
+        );
+        let q = query(1, 0, "db.internal", TYPE_A, CLASS_IN);
+        assert_eq!(
+            responder.handle_request(&q[..q.len() - 1], &mut reply),
+            Some(HEADER_LEN)
+        );
+        assert_eq!(
+            read_u16(&reply, FLAGS_OFFSET),
+            FLAG_QR | FLAG_AA | RCODE_FORMERR
+        );
+
+        // Responses and messages shorter than the header are ignored.
+        let q = query(1, FLAG_QR, "db.internal", TYPE_A, CLASS_IN);
+        assert_eq!(responder.handle_request(&q, &mut reply), None);
+        assert_eq!(
+            responder.handle_request(&q[..HEADER_LEN - 1], &mut reply),
+            None
+        );
+
+        // Answers which do not fit in the reply buffer are left out.
+        let q = query(1, 0, "db.internal", TYPE_A, CLASS_IN);
+        let mut short_reply = [0u8; 40];
+        assert_eq!(
+            responder.handle_request(&q, &mut short_reply),
+            Some(q.len())
+        );
+        assert_eq!(read_u16(&short_reply, ANCOUNT_OFFSET), 0);
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains the services which answer small UDP requests sent to the MMDS address, together with
+//! the trait used to plug them into the MMDS network stack.
+
+mod dns;
+mod sntp;
+
+pub use self::dns::{DnsResponder, DNS_PORT};
+pub use self::sntp::{SntpResponder, NTP_PORT};
+
+/// The size of the largest reply payload a `UdpService` may produce. This is the classic DNS
+/// message size limit, which comfortably fits in a single Ethernet frame.
+pub const MAX_REPLY_SIZE: usize = 512;
+
+/// A service which answers UDP requests sent to a given port at the MMDS address. Each request
+/// gets at most one reply, which is sent back to the source address and port of the request.
+pub trait UdpService {
+    /// Returns the local UDP port the service listens on.
+    fn port(&self) -> u16;
+
+    /// Handles the `request` payload, writing the reply payload to the beginning of `reply`.
+    /// Returns the length of the reply, or `None` if the request should go unanswered.
+    fn handle_request(&mut self, request: &[u8], reply: &mut [u8]) -> Option<usize>;
+}
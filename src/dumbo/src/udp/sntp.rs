@@ -0,0 +1,186 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains an SNTP responder, which serves the time of the host to the guest.
+//!
+//! The packet format is described in [1]. Only unicast client requests are answered.
+//!
+//! [1]: https://tools.ietf.org/html/rfc4330#section-4
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::UdpService;
+
+/// The UDP port the SNTP responder listens on.
+pub const NTP_PORT: u16 = 123;
+
+const PACKET_LEN: usize = 48;
+
+const STRATUM_OFFSET: usize = 1;
+const POLL_OFFSET: usize = 2;
+const PRECISION_OFFSET: usize = 3;
+const REFERENCE_ID_OFFSET: usize = 12;
+const REFERENCE_TIMESTAMP_OFFSET: usize = 16;
+const ORIGINATE_TIMESTAMP_OFFSET: usize = 24;
+const RECEIVE_TIMESTAMP_OFFSET: usize = 32;
+const TRANSMIT_TIMESTAMP_OFFSET: usize = 40;
+
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const MIN_VERSION: u8 = 1;
+const MAX_VERSION: u8 = 4;
+
+// The host clock is the only reference we know about, so we present it as a primary one.
+const STRATUM: u8 = 1;
+const REFERENCE_ID: &[u8; 4] = b"LOCL";
+// The precision of the host clock, as a power of two in seconds (about one microsecond).
+const PRECISION: i8 = -20;
+
+// The number of seconds between the NTP epoch (1900) and the Unix epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+// Converts `time` to the 64 bit NTP timestamp format: seconds since the NTP epoch in the upper
+// half, and the fraction of a second in the lower half.
+fn ntp_timestamp(time: SystemTime) -> u64 {
+    // Times before the Unix epoch are not representable by a well configured host.
+    let since_unix_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_unix_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(since_unix_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    // The seconds wrap around in 2036, as the NTP era changes.
+    ((seconds & 0xffff_ffff) << 32) | fraction
+}
+
+/// Answers SNTP requests with the time of the host.
+pub struct SntpResponder {
+    now: fn() -> SystemTime,
+}
+
+impl SntpResponder {
+    /// Creates a responder which serves the host wall clock time.
+    pub fn new() -> Self {
+        SntpResponder {
+            now: SystemTime::now,
+        }
+    }
+
+    #[cfg(test)]
+    fn with_clock(now: fn() -> SystemTime) -> Self {
+        SntpResponder { now }
+    }
+}
+
+impl Default for SntpResponder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UdpService for SntpResponder {
+    fn port(&self) -> u16 {
+        NTP_PORT
+    }
+
+    fn handle_request(&mut self, request: &[u8], reply: &mut [u8]) -> Option<usize> {
+        if request.len() < PACKET_LEN || reply.len() < PACKET_LEN {
+            return None;
+        }
+
+        let version = (request[0] >> 3) & 0x7;
+        let mode = request[0] & 0x7;
+        if mode != MODE_CLIENT || version < MIN_VERSION || version > MAX_VERSION {
+            return None;
+        }
+
+        let timestamp = ntp_timestamp((self.now)()).to_be_bytes();
+        let reply = &mut reply[..PACKET_LEN];
+        reply.iter_mut().for_each(|b| *b = 0);
+
+        // The leap indicator stays 0 (no warning), and the version of the request is echoed.
+        reply[0] = (version << 3) | MODE_SERVER;
+        reply[STRATUM_OFFSET] = STRATUM;
+        reply[POLL_OFFSET] = request[POLL_OFFSET];
+        reply[PRECISION_OFFSET] = PRECISION as u8;
+        // The root delay and the root dispersion are both 0.
+        reply[REFERENCE_ID_OFFSET..REFERENCE_TIMESTAMP_OFFSET].copy_from_slice(REFERENCE_ID);
+        reply[REFERENCE_TIMESTAMP_OFFSET..ORIGINATE_TIMESTAMP_OFFSET].copy_from_slice(&timestamp);
+        // The originate timestamp is the transmit timestamp of the request.
+        reply[ORIGINATE_TIMESTAMP_OFFSET..RECEIVE_TIMESTAMP_OFFSET]
+            .copy_from_slice(&request[TRANSMIT_TIMESTAMP_OFFSET..TRANSMIT_TIMESTAMP_OFFSET + 8]);
+        reply[RECEIVE_TIMESTAMP_OFFSET..TRANSMIT_TIMESTAMP_OFFSET].copy_from_slice(&timestamp);
+        reply[TRANSMIT_TIMESTAMP_OFFSET..].copy_from_slice(&timestamp);
+
+        Some(PACKET_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    // 2020-01-01 00:00:00.5 UTC.
+    fn clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_577_836_800_500)
+    }
+
+    #[test]
+    fn test_ntp_timestamp() {
+        assert_eq!(ntp_timestamp(UNIX_EPOCH), NTP_UNIX_OFFSET << 32);
+        assert_eq!(ntp_timestamp(clock()), (3_786_825_600 << 32) | (1 << 31));
+    }
+
+    #[test]
+    fn test_sntp_responder() {
+        let mut responder = SntpResponder::with_clock(clock);
+        let mut reply = [0xffu8; 64];
+        assert_eq!(responder.port(), NTP_PORT);
+
+        let mut request = [0u8; PACKET_LEN];
+        // Version 4, client mode.
+        request[0] = (4 << 3) | MODE_CLIENT;
+        request[POLL_OFFSET] = 6;
+        request[TRANSMIT_TIMESTAMP_OFFSET..].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(
+            responder.handle_request(&request, &mut reply),
+            Some(PACKET_LEN)
+        );
+        let timestamp = ntp_timestamp(clock()).to_be_bytes();
+        assert_eq!(reply[0], (4 << 3) | MODE_SERVER);
+        assert_eq!(reply[STRATUM_OFFSET], STRATUM);
+        assert_eq!(reply[POLL_OFFSET], 6);
+        assert_eq!(reply[PRECISION_OFFSET] as i8, PRECISION);
+        assert_eq!(reply[4..REFERENCE_ID_OFFSET], [0u8; 8]);
+        assert_eq!(
+            reply[REFERENCE_ID_OFFSET..REFERENCE_TIMESTAMP_OFFSET],
+            *b"LOCL"
+        );
+        assert_eq!(
+            reply[REFERENCE_TIMESTAMP_OFFSET..ORIGINATE_TIMESTAMP_OFFSET],
+            timestamp
+        );
+        assert_eq!(
+            reply[ORIGINATE_TIMESTAMP_OFFSET..RECEIVE_TIMESTAMP_OFFSET],
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            reply[RECEIVE_TIMESTAMP_OFFSET..TRANSMIT_TIMESTAMP_OFFSET],
+            timestamp
+        );
+        assert_eq!(reply[TRANSMIT_TIMESTAMP_OFFSET..PACKET_LEN], timestamp);
+
+        // Requests which are too short, do not come from clients, or carry an unknown version
+        // are ignored.
+        assert_eq!(
+            responder.handle_request(&request[..PACKET_LEN - 1], &mut reply),
+            None
+        );
+        request[0] = (4 << 3) | MODE_SERVER;
+        assert_eq!(responder.handle_request(&request, &mut reply), None);
+        request[0] = MODE_CLIENT;
+        assert_eq!(responder.handle_request(&request, &mut reply), None);
+        request[0] = (5 << 3) | MODE_CLIENT;
+        assert_eq!(responder.handle_request(&request, &mut reply), None);
+    }
+}
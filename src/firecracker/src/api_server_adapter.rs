@@ -2,12 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 
-use api_server::{ApiRequest, ApiResponse, ApiServer};
+use api_server::{AccessPolicy, ApiRequest, ApiResponse, ApiServer, ApiSocket};
 use mmds::MMDS;
 use polly::event_manager::{EventManager, Subscriber};
 use seccomp::BpfProgram;
@@ -54,14 +53,14 @@ impl ApiServerAdapter {
 }
 impl Subscriber for ApiServerAdapter {
     /// Handle a read event (EPOLLIN).
-    fn process(&mut self, event: &EpollEvent, _: &mut EventManager) {
+    fn process(&mut self, event: &EpollEvent, event_manager: &mut EventManager) {
         let source = event.fd();
         let event_set = event.event_set();
 
         if source == self.api_event_fd.as_raw_fd() && event_set == EventSet::IN {
             match self.from_api.try_recv() {
                 Ok(api_request) => {
-                    let response = self.controller.handle_request(*api_request);
+                    let response = self.controller.handle_request(*api_request, event_manager);
                     // Send back the result.
                     self.to_api
                         .send(Box::new(response))
@@ -92,8 +91,9 @@ impl Subscriber for ApiServerAdapter {
 pub fn run_with_api(
     seccomp_filter: BpfProgram,
     config_json: Option<String>,
-    bind_path: PathBuf,
-    instance_info: InstanceInfo,
+    api_socket: ApiSocket,
+    access_policy: Option<AccessPolicy>,
+    instance_info: Arc<RwLock<InstanceInfo>>,
     start_time_us: Option<u64>,
     start_time_cpu_us: Option<u64>,
 ) {
@@ -107,8 +107,7 @@ pub fn run_with_api(
 
     // MMDS only supported with API.
     let mmds_info = MMDS.clone();
-    let api_shared_info = Arc::new(RwLock::new(instance_info));
-    let vmm_shared_info = api_shared_info.clone();
+    let vmm_shared_info = instance_info.clone();
     let to_vmm_event_fd = api_event_fd.try_clone().unwrap();
 
     let api_seccomp_filter = seccomp_filter.clone();
@@ -116,16 +115,19 @@ pub fn run_with_api(
     thread::Builder::new()
         .name("fc_api".to_owned())
         .spawn(move || {
-            match ApiServer::new(
+            let mut api_server = ApiServer::new(
                 mmds_info,
                 vmm_shared_info,
                 to_vmm,
                 from_vmm,
                 to_vmm_event_fd,
             )
-            .expect("Cannot create API server")
-            .bind_and_run(
-                bind_path,
+            .expect("Cannot create API server");
+            if let Some(access_policy) = access_policy {
+                api_server.set_access_policy(access_policy);
+            }
+            match api_server.bind_and_run(
+                api_socket,
                 start_time_us,
                 start_time_cpu_us,
                 api_seccomp_filter,
@@ -158,11 +160,14 @@ pub fn run_with_api(
 
     // Configure, build and start the microVM.
     let (vm_resources, vmm) = match config_json {
-        Some(json) => super::build_microvm_from_json(seccomp_filter, &mut event_manager, json),
+        Some(json) => {
+            super::build_microvm_from_json(seccomp_filter, &mut event_manager, json, instance_info)
+        }
         None => PrebootApiController::build_microvm_from_requests(
             seccomp_filter,
             &mut event_manager,
             FIRECRACKER_VERSION.to_string(),
+            instance_info,
             || {
                 let req = from_api
                     .recv()
@@ -188,9 +193,6 @@ pub fn run_with_api(
         .expect("Metrics lock poisoned.")
         .start(super::metrics::WRITE_METRICS_PERIOD_MS);
 
-    // Update the api shared instance info.
-    api_shared_info.write().unwrap().started = true;
-
     ApiServerAdapter::run_microvm(
         api_event_fd,
         from_api,
@@ -16,6 +16,7 @@ extern crate vmm;
 mod api_server_adapter;
 mod metrics;
 
+use api_server::{AccessPolicy, ApiSocket};
 use backtrace::Backtrace;
 
 use std::fs;
@@ -23,9 +24,9 @@ use std::io;
 use std::panic;
 use std::path::PathBuf;
 use std::process;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
-use logger::{Metric, LOGGER, METRICS};
+use logger::{ExitReason, Metric, EXIT_STATUS, LOGGER, METRICS};
 use polly::event_manager::EventManager;
 use seccomp::{BpfProgram, SeccompLevel};
 use utils::arg_parser::{ArgParser, Argument};
@@ -76,10 +77,9 @@ fn main() {
         let bt = Backtrace::new();
         error!("{:?}", bt);
 
-        // Write the metrics before aborting.
-        if let Err(e) = METRICS.write() {
-            error!("Failed to write metrics while panicking: {}", e);
-        }
+        // Write the metrics and the exit status before aborting. The process is killed by
+        // `SIGABRT`, which shells report as this exit code.
+        vmm::report_exit(128 + libc::SIGABRT, ExitReason::Panic);
     }));
 
     let mut arg_parser = ArgParser::new()
@@ -89,6 +89,21 @@ fn main() {
                 .default_value(DEFAULT_API_SOCK_PATH)
                 .help("Path to unix domain socket used by the API."),
         )
+        .arg(
+            Argument::new("api-tcp-port")
+                .takes_value(true)
+                .help("Optional TCP port of the loopback interface on which the API listens, instead of the unix domain socket. Requires an access policy."),
+        )
+        .arg(
+            Argument::new("api-vsock-port")
+                .takes_value(true)
+                .help("Optional vsock port of the host on which the API listens, instead of the unix domain socket. Requires an access policy."),
+        )
+        .arg(
+            Argument::new("api-access-policy")
+                .takes_value(true)
+                .help("Path to a file that contains the access policy of the API in JSON format."),
+        )
         .arg(
             Argument::new("id")
                 .takes_value(true)
@@ -121,6 +136,11 @@ fn main() {
                 .takes_value(true)
                 .help("Path to a file that contains the microVM configuration in JSON format."),
         )
+        .arg(
+            Argument::new("validate-config")
+                .takes_value(true)
+                .help("Path to a file that contains a microVM configuration in JSON format. The configuration is validated and all the errors found are reported, without starting the microVM.")
+        )
         .arg(
             Argument::new("no-api")
                 .takes_value(false)
@@ -150,6 +170,16 @@ fn main() {
                 .takes_value(false)
                 .requires("log-path")
                 .help("Whether or not to include the file path and line number of the log's origin.")
+        )
+        .arg(
+            Argument::new("exit-status-file")
+                .takes_value(true)
+                .help("Path to a file where the exit code of the process and the reason for the exit are written in JSON format, along with the last metrics, when the microVM stops.")
+        )
+        .arg(
+            Argument::new("mmds-size-limit")
+                .takes_value(true)
+                .help("Maximum size, in bytes, of the serialized MMDS data store.")
         );
 
     let arguments = match arg_parser.parse_from_cmdline() {
@@ -205,6 +235,16 @@ fn main() {
         init_logger(logger_config, FIRECRACKER_VERSION).expect("Could not initialize logger.");
     }
 
+    if let Some(config_path) = arguments.value_as_string("validate-config") {
+        validate_config(config_path);
+    }
+
+    if let Some(path) = arguments.value_as_string("exit-status-file") {
+        EXIT_STATUS
+            .init(&PathBuf::from(path))
+            .expect("Could not initialize the exit status file.");
+    }
+
     // It's safe to unwrap here because the field's been provided with a default value.
     let seccomp_level = arguments.value_as_string("seccomp-level").unwrap();
     let seccomp_filter = get_seccomp_filter(
@@ -216,18 +256,64 @@ fn main() {
         panic!("Could not create seccomp filter: {}", err);
     });
 
+    if let Some(limit) = arguments.value_as_string("mmds-size-limit") {
+        let limit = limit
+            .parse::<usize>()
+            .expect("'mmds-size-limit' parameter expected to be of 'usize' type.");
+        mmds::MMDS
+            .lock()
+            .expect("Failed to acquire lock on MMDS")
+            .set_data_store_limit(limit);
+    }
+
     let vmm_config_json = arguments
         .value_as_string("config-file")
         .map(fs::read_to_string)
         .map(|x| x.expect("Unable to open or read from the configuration file"));
 
     let api_enabled = !arguments.value_as_bool("no-api").unwrap_or(false);
+    let instance_info = Arc::new(RwLock::new(InstanceInfo::new(
+        instance_id,
+        FIRECRACKER_VERSION.to_string(),
+        "Firecracker".to_string(),
+    )));
 
     if api_enabled {
-        let bind_path = arguments
-            .value_as_string("api-sock")
-            .map(PathBuf::from)
-            .expect("Missing argument: api-sock");
+        let api_socket = match (
+            arguments.value_as_string("api-tcp-port"),
+            arguments.value_as_string("api-vsock-port"),
+        ) {
+            (Some(_), Some(_)) => {
+                panic!("'api-tcp-port' and 'api-vsock-port' cannot be used together.")
+            }
+            (Some(port), None) => ApiSocket::Tcp(
+                port.parse::<u16>()
+                    .expect("'api-tcp-port' parameter expected to be of 'u16' type."),
+            ),
+            (None, Some(port)) => ApiSocket::Vsock(
+                port.parse::<u32>()
+                    .expect("'api-vsock-port' parameter expected to be of 'u32' type."),
+            ),
+            (None, None) => ApiSocket::Unix(
+                arguments
+                    .value_as_string("api-sock")
+                    .map(PathBuf::from)
+                    .expect("Missing argument: api-sock"),
+            ),
+        };
+
+        let access_policy = arguments.value_as_string("api-access-policy").map(|path| {
+            let json = fs::read_to_string(path)
+                .expect("Unable to open or read from the access policy file");
+            AccessPolicy::from_json(&json)
+                .unwrap_or_else(|err| panic!("Invalid access policy: {}", err))
+        });
+        // Any process of the host, or any guest for vsock, can connect to these ports, so the
+        // clients have to be selected by a policy.
+        match (&api_socket, &access_policy) {
+            (ApiSocket::Unix(_), _) | (_, Some(_)) => (),
+            _ => panic!("'api-tcp-port' and 'api-vsock-port' require 'api-access-policy'."),
+        }
 
         let start_time_us = arguments.value_as_string("start-time-us").map(|s| {
             s.parse::<u64>()
@@ -238,22 +324,38 @@ fn main() {
             s.parse::<u64>()
                 .expect("'start-time-cpu_us' parameter expected to be of 'u64' type.")
         });
-        let instance_info = InstanceInfo {
-            id: instance_id,
-            started: false,
-            vmm_version: FIRECRACKER_VERSION.to_string(),
-            app_name: "Firecracker".to_string(),
-        };
         api_server_adapter::run_with_api(
             seccomp_filter,
             vmm_config_json,
-            bind_path,
+            api_socket,
+            access_policy,
             instance_info,
             start_time_us,
             start_time_cpu_us,
         );
     } else {
-        run_without_api(seccomp_filter, vmm_config_json);
+        run_without_api(seccomp_filter, vmm_config_json, instance_info);
+    }
+}
+
+// Validate the microVM configuration found at `config_path` and exit.
+fn validate_config(config_path: String) -> ! {
+    let config_json = fs::read_to_string(&config_path).unwrap_or_else(|err| {
+        eprintln!("Unable to open or read from {}: {}", config_path, err);
+        process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
+    });
+
+    match VmResources::validate_json(&config_json) {
+        Ok(()) => {
+            println!("{}: the configuration is valid.", config_path);
+            process::exit(i32::from(vmm::FC_EXIT_CODE_OK));
+        }
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", config_path, error);
+            }
+            process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
+        }
     }
 }
 
@@ -262,6 +364,7 @@ fn build_microvm_from_json(
     seccomp_filter: BpfProgram,
     event_manager: &mut EventManager,
     config_json: String,
+    instance_info: Arc<RwLock<InstanceInfo>>,
 ) -> (VmResources, Arc<Mutex<vmm::Vmm>>) {
     let vm_resources =
         VmResources::from_json(&config_json, FIRECRACKER_VERSION).unwrap_or_else(|err| {
@@ -271,20 +374,25 @@ fn build_microvm_from_json(
             );
             process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
         });
-    let vmm = vmm::builder::build_microvm(&vm_resources, event_manager, &seccomp_filter)
-        .unwrap_or_else(|err| {
-            error!(
-                "Building VMM configured from cmdline json failed: {:?}",
-                err
-            );
-            process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
-        });
+    let vmm =
+        vmm::builder::build_microvm(&vm_resources, event_manager, &seccomp_filter, instance_info)
+            .unwrap_or_else(|err| {
+                error!(
+                    "Building VMM configured from cmdline json failed: {:?}",
+                    err
+                );
+                process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
+            });
     info!("Successfully started microvm that was configured from one single json");
 
     (vm_resources, vmm)
 }
 
-fn run_without_api(seccomp_filter: BpfProgram, config_json: Option<String>) {
+fn run_without_api(
+    seccomp_filter: BpfProgram,
+    config_json: Option<String>,
+    instance_info: Arc<RwLock<InstanceInfo>>,
+) {
     let mut event_manager = EventManager::new().expect("Unable to create EventManager");
 
     // Create the firecracker metrics object responsible for periodically printing metrics.
@@ -301,6 +409,7 @@ fn run_without_api(seccomp_filter: BpfProgram, config_json: Option<String>) {
         &mut event_manager,
         // Safe to unwrap since '--no-api' requires this to be set.
         config_json.unwrap(),
+        instance_info,
     );
 
     // Start the metrics.
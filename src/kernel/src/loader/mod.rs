@@ -63,21 +63,14 @@ impl fmt::Display for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Loads a kernel from a vmlinux elf image to a slice
-///
-/// # Arguments
-///
-/// * `guest_mem` - The guest memory region the kernel is written to.
-/// * `kernel_image` - Input vmlinux image.
-/// * `start_address` - For x86_64, this is the start of the high memory. Kernel should reside above it.
+/// Reads and sanity checks the ELF header and the program headers of a vmlinux elf image.
 ///
-/// Returns the entry address of the kernel.
+/// Returns the entry address of the kernel and its program headers.
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-pub fn load_kernel<F>(
-    guest_mem: &GuestMemoryMmap,
+fn read_elf_headers<F>(
     kernel_image: &mut F,
     start_address: u64,
-) -> Result<GuestAddress>
+) -> Result<(u64, Vec<elf::Elf64_Phdr>)>
 where
     F: Read + Seek,
 {
@@ -122,12 +115,57 @@ where
             .map_err(|_| Error::ReadKernelDataStruct("Failed to read ELF program header"))?
     };
 
-    // Read in each section pointed to by the program headers.
-    for phdr in &phdrs {
-        if (phdr.p_type & elf::PT_LOAD) == 0 || phdr.p_filesz == 0 {
-            continue;
-        }
+    Ok((ehdr.e_entry, phdrs))
+}
+
+/// Returns the program headers of the sections that have to be loaded in the guest memory.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn loadable_sections(phdrs: &[elf::Elf64_Phdr]) -> impl Iterator<Item = &elf::Elf64_Phdr> {
+    phdrs
+        .iter()
+        .filter(|phdr| (phdr.p_type & elf::PT_LOAD) != 0 && phdr.p_filesz != 0)
+}
+
+/// Checks that a vmlinux elf image can be loaded, without writing it to the guest memory.
+///
+/// # Arguments
+///
+/// * `kernel_image` - Input vmlinux image.
+/// * `start_address` - For x86_64, this is the start of the high memory. Kernel should reside above it.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn check_kernel<F>(kernel_image: &mut F, start_address: u64) -> Result<()>
+where
+    F: Read + Seek,
+{
+    let (_, phdrs) = read_elf_headers(kernel_image, start_address)?;
+    if loadable_sections(&phdrs).any(|phdr| phdr.p_paddr < start_address) {
+        return Err(Error::InvalidProgramHeaderAddress);
+    }
+    Ok(())
+}
+
+/// Loads a kernel from a vmlinux elf image to a slice
+///
+/// # Arguments
+///
+/// * `guest_mem` - The guest memory region the kernel is written to.
+/// * `kernel_image` - Input vmlinux image.
+/// * `start_address` - For x86_64, this is the start of the high memory. Kernel should reside above it.
+///
+/// Returns the entry address of the kernel.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn load_kernel<F>(
+    guest_mem: &GuestMemoryMmap,
+    kernel_image: &mut F,
+    start_address: u64,
+) -> Result<GuestAddress>
+where
+    F: Read + Seek,
+{
+    let (entry_addr, phdrs) = read_elf_headers(kernel_image, start_address)?;
 
+    // Read in each section pointed to by the program headers.
+    for phdr in loadable_sections(&phdrs) {
         kernel_image
             .seek(SeekFrom::Start(phdr.p_offset))
             .map_err(|_| Error::SeekKernelStart)?;
@@ -142,15 +180,14 @@ where
             .map_err(|_| Error::ReadKernelImage)?;
     }
 
-    Ok(GuestAddress(ehdr.e_entry))
+    Ok(GuestAddress(entry_addr))
 }
 
+/// Reads and sanity checks the header of an aarch64 kernel image.
+///
+/// Returns the offset, relative to the start of the RAM, at which the image has to be loaded.
 #[cfg(target_arch = "aarch64")]
-pub fn load_kernel<F>(
-    guest_mem: &GuestMemoryMmap,
-    kernel_image: &mut F,
-    start_address: u64,
-) -> Result<GuestAddress>
+fn read_image_header<F>(kernel_image: &mut F) -> Result<u64>
 where
     F: Read + Seek,
 {
@@ -205,6 +242,35 @@ where
     if u64::from_le(hdrvals[1]) != 0 {
         kernel_load_offset = u64::from_le(hdrvals[0]);
     }
+
+    Ok(kernel_load_offset)
+}
+
+/// Checks that an aarch64 kernel image can be loaded, without writing it to the guest memory.
+///
+/// # Arguments
+///
+/// * `kernel_image` - Input kernel image.
+/// * `_start_address` - Unused on aarch64, the image is loaded relative to the start of the RAM.
+#[cfg(target_arch = "aarch64")]
+pub fn check_kernel<F>(kernel_image: &mut F, _start_address: u64) -> Result<()>
+where
+    F: Read + Seek,
+{
+    read_image_header(kernel_image).map(|_| ())
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn load_kernel<F>(
+    guest_mem: &GuestMemoryMmap,
+    kernel_image: &mut F,
+    start_address: u64,
+) -> Result<GuestAddress>
+where
+    F: Read + Seek,
+{
+    let mut kernel_load_offset = read_image_header(kernel_image)?;
+
     /* Get the total size of kernel image. */
     let kernel_size = kernel_image
         .seek(SeekFrom::End(0))
@@ -297,6 +363,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_check_kernel() {
+        let mut image = make_test_bin();
+        assert!(check_kernel(&mut Cursor::new(&image), 0).is_ok());
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        let offset = 0x1;
+        #[cfg(target_arch = "aarch64")]
+        let offset = 0x38;
+        image[offset] = 0x33;
+        assert_eq!(
+            Err(Error::InvalidElfMagicNumber),
+            check_kernel(&mut Cursor::new(&image), 0)
+        );
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_check_kernel_invalid_address() {
+        let image = make_test_bin();
+        // The entry address of the test image is 0x10_0000.
+        assert_eq!(
+            Err(Error::InvalidEntryAddress),
+            check_kernel(&mut Cursor::new(&image), 0x20_0000)
+        );
+    }
+
     #[test]
     fn test_load_kernel_no_memory() {
         let gm = GuestMemoryMmap::from_ranges(&[(GuestAddress(0x0), 79)]).unwrap();
@@ -0,0 +1,252 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the lifecycle events of the VMM.
+//!
+//! The components of the VMM record events, like a vCPU exit or a device error, through the
+//! `EVENTS` static. Events are only recorded once a consumer subscribed to them, which is then
+//! notified through an `EventFd` and takes them with `consume`.
+//!
+//! # Event format
+//! Each event is serialized to a JSON object holding the time it was recorded at, the name of
+//! the event and its fields:
+//! ```bash
+//! {
+//!   "utc_timestamp_ms": 1589197254000,
+//!   "event": "vcpu_exit",
+//!   "vcpu": 0,
+//!   "exit_code": 0
+//! }
+//! ```
+//!
+//! # Limitations
+//! At most `MAX_PENDING_EVENTS` events wait for the consumer, after which the oldest ones
+//! are dropped.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use utils::eventfd::EventFd;
+use utils::time::{get_time, ClockType};
+
+/// The maximum number of events waiting for the consumer.
+pub const MAX_PENDING_EVENTS: usize = 256;
+
+lazy_static! {
+    /// Static instance used for recording events.
+    pub static ref EVENTS: Events = Events::new();
+}
+
+/// A lifecycle event of the VMM.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The VMM is stopping, after which the process exits with `exit_code`.
+    VmmStop {
+        /// The exit code of the process.
+        exit_code: i32,
+    },
+    /// A vCPU exited.
+    VcpuExit {
+        /// The index of the vCPU.
+        vcpu: usize,
+        /// The exit code reported by the vCPU.
+        exit_code: u8,
+    },
+    /// A graceful shutdown of the guest, requested through the API, completed.
+    Shutdown {
+        /// How the shutdown completed.
+        path: ShutdownPath,
+        /// The number of milliseconds since the shutdown was requested.
+        elapsed_ms: u64,
+    },
+    /// The guest rebooted and was booted again, without stopping the VMM.
+    Reboot,
+    /// A block device failed to handle an event or a request.
+    BlockDeviceError {
+        /// The ID of the drive.
+        drive_id: String,
+        /// The description of the error.
+        error: String,
+    },
+    /// A network device failed to handle an event or a frame.
+    NetDeviceError {
+        /// The ID of the network interface.
+        iface_id: String,
+        /// The description of the error.
+        error: String,
+    },
+    /// A rate limiter started or stopped throttling a device.
+    RateLimiterThrottle {
+        /// The ID of the drive or network interface.
+        device_id: String,
+        /// The queue the rate limiter applies to, for devices with more than one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        queue: Option<String>,
+        /// `true` when the device starts being throttled, `false` when it stops.
+        throttled: bool,
+    },
+}
+
+/// How a graceful shutdown of the guest completed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownPath {
+    /// The guest shut down or rebooted before the timeout expired.
+    Guest,
+    /// The timeout expired and the VMM was stopped.
+    Timeout,
+}
+
+/// An event, along with the time it was recorded at.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EventRecord {
+    /// The number of milliseconds since the Unix epoch.
+    pub utc_timestamp_ms: u64,
+    /// The event.
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+struct PendingEvents {
+    records: VecDeque<EventRecord>,
+    // Written whenever an event is recorded. Events are only recorded when set.
+    notifier: Option<EventFd>,
+}
+
+/// Events system.
+pub struct Events {
+    pending: Mutex<PendingEvents>,
+}
+
+impl Events {
+    fn new() -> Events {
+        Events {
+            pending: Mutex::new(PendingEvents {
+                records: VecDeque::new(),
+                notifier: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<PendingEvents> {
+        match self.pending.lock() {
+            Ok(guard) => guard,
+            // The events are still consistent if a thread panicked while holding the lock.
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Starts recording events. `notifier` is written whenever an event is recorded,
+    /// after which the events should be taken with `consume`.
+    pub fn subscribe(&self, notifier: EventFd) {
+        self.lock().notifier = Some(notifier);
+    }
+
+    /// Records `event`, if there is a subscriber.
+    pub fn emit(&self, event: Event) {
+        let mut pending = self.lock();
+        if pending.notifier.is_none() {
+            return;
+        }
+        if pending.records.len() == MAX_PENDING_EVENTS {
+            pending.records.pop_front();
+        }
+        pending.records.push_back(EventRecord {
+            utc_timestamp_ms: get_time(ClockType::Real) / 1_000_000,
+            event,
+        });
+        if let Some(notifier) = pending.notifier.as_ref() {
+            // This only fails when the counter overflows, in which case the subscriber
+            // has yet to be woken up anyway.
+            let _ = notifier.write(1);
+        }
+    }
+
+    /// Passes the recorded events to `handler`, in the order they were recorded in.
+    pub fn consume<F: FnMut(&EventRecord)>(&self, mut handler: F) {
+        let mut pending = self.lock();
+        if let Some(notifier) = pending.notifier.as_ref() {
+            // The notifier only wakes up the subscriber, so its count does not matter.
+            let _ = notifier.read();
+        }
+        // The lock is held until the events are handled, so that `flush` returns once
+        // they are.
+        while let Some(record) = pending.records.pop_front() {
+            handler(&record);
+        }
+    }
+
+    /// Waits for the subscriber to consume the recorded events, for at most `timeout`.
+    /// Returns `true` if they were consumed. This busy waits, so it is only meant for
+    /// short timeouts, like before the process exits.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.lock().records.is_empty() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::hint::spin_loop();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let events = Events::new();
+        // Without a subscriber, events are not recorded.
+        events.emit(Event::VmmStop { exit_code: 0 });
+        assert!(events.flush(Duration::from_millis(0)));
+
+        let notifier = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        events.subscribe(notifier.try_clone().unwrap());
+        events.emit(Event::VcpuExit {
+            vcpu: 1,
+            exit_code: 2,
+        });
+        events.emit(Event::RateLimiterThrottle {
+            device_id: "rootfs".to_string(),
+            queue: None,
+            throttled: true,
+        });
+        events.emit(Event::Reboot);
+        assert!(!events.flush(Duration::from_millis(1)));
+
+        // The subscriber is notified, then gets the events in order.
+        assert_eq!(notifier.read().unwrap(), 3);
+        let mut records = vec![];
+        events.consume(|record| records.push(serde_json::to_value(record).unwrap()));
+        assert_eq!(records.len(), 3);
+        assert!(records[0]["utc_timestamp_ms"].as_u64().unwrap() > 0);
+        assert_eq!(records[0]["event"], "vcpu_exit");
+        assert_eq!(records[0]["vcpu"], 1);
+        assert_eq!(records[0]["exit_code"], 2);
+        assert_eq!(records[1]["event"], "rate_limiter_throttle");
+        assert_eq!(records[1]["device_id"], "rootfs");
+        assert!(records[1].get("queue").is_none());
+        assert_eq!(records[1]["throttled"], true);
+        assert_eq!(records[2]["event"], "reboot");
+        assert!(events.flush(Duration::from_millis(0)));
+
+        // The oldest events are dropped when the subscriber falls behind.
+        for exit_code in 0..=MAX_PENDING_EVENTS {
+            events.emit(Event::VmmStop {
+                exit_code: exit_code as i32,
+            });
+        }
+        let mut exit_codes = vec![];
+        events.consume(|record| {
+            if let Event::VmmStop { exit_code } = record.event {
+                exit_codes.push(exit_code);
+            }
+        });
+        assert_eq!(exit_codes.len(), MAX_PENDING_EVENTS);
+        assert_eq!(exit_codes[0], 1);
+    }
+}
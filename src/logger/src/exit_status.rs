@@ -0,0 +1,342 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reports why the VMM exited.
+//!
+//! Once a file was provided through `EXIT_STATUS.init`, the VMM describes why it stopped in that
+//! file right before the process exits, so that its supervisor can tell a guest shutdown from a
+//! crash without parsing the logs.
+//!
+//! # Exit status format
+//! The exit status is a JSON object holding the exit code of the process, the reason for the
+//! exit, when Firecracker started and exited and the last metrics written:
+//! ```bash
+//! {
+//!   "exit_code": 0,
+//!   "reason": "guest_shutdown",
+//!   "start_utc_timestamp_ms": 1589197250000,
+//!   "exit_utc_timestamp_ms": 1589197254000,
+//!   "metrics": { "utc_timestamp_ms": 1589197254000, ... }
+//! }
+//! ```
+//!
+//! # Limitations
+//! The file is created on initialization, so that only writing to it is needed when exiting.
+//! Firecracker failing before the VMM is started, like on bad configuration, is only reported
+//! through the exit code. The exit status written from a signal handler doesn't hold the metrics,
+//! because serializing them allocates memory.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+
+use utils::time::{get_time, ClockType};
+
+lazy_static! {
+    /// Static instance used for reporting the exit status.
+    pub static ref EXIT_STATUS: ExitStatus = ExitStatus::new();
+}
+
+/// Why the VMM exited.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    /// The guest shut down or rebooted on its own.
+    GuestShutdown,
+    /// The guest was shut down through the API, or stopped for not shutting down in time.
+    ApiRequest,
+    /// A vCPU failed to run the guest.
+    VcpuError,
+    /// The VMM failed to handle an event of the guest, like booting it again after a reboot.
+    InternalError,
+    /// Firecracker intercepted a restricted system call.
+    SeccompViolation,
+    /// Firecracker intercepted `SIGBUS` or `SIGSEGV`.
+    FatalSignal,
+    /// Firecracker panicked, after which it aborts.
+    Panic,
+}
+
+impl ExitReason {
+    // The name of the reason in the exit status, which doesn't need serde.
+    fn as_str(self) -> &'static str {
+        match self {
+            ExitReason::GuestShutdown => "guest_shutdown",
+            ExitReason::ApiRequest => "api_request",
+            ExitReason::VcpuError => "vcpu_error",
+            ExitReason::InternalError => "internal_error",
+            ExitReason::SeccompViolation => "seccomp_violation",
+            ExitReason::FatalSignal => "fatal_signal",
+            ExitReason::Panic => "panic",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExitStatusRecord {
+    exit_code: i32,
+    reason: ExitReason,
+    start_utc_timestamp_ms: u64,
+    exit_utc_timestamp_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metrics: Option<serde_json::Value>,
+}
+
+/// Describes the errors which may occur while reporting the exit status.
+#[derive(Debug)]
+pub enum ExitStatusError {
+    /// The exit status file was already provided.
+    AlreadyInitialized,
+    /// Creating the exit status file failed.
+    CreateFile(io::Error),
+    /// Error in the serialization of the exit status.
+    Serde(String),
+    /// Writing the exit status file failed.
+    Write(io::Error),
+}
+
+impl fmt::Display for ExitStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            ExitStatusError::AlreadyInitialized => {
+                "Reinitialization of the exit status file not allowed.".to_string()
+            }
+            ExitStatusError::CreateFile(ref e) => {
+                format!("Failed to create the exit status file. Error: {}", e)
+            }
+            ExitStatusError::Serde(ref e) => e.to_string(),
+            ExitStatusError::Write(ref e) => {
+                format!("Failed to write the exit status. Error: {}", e)
+            }
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// Exit status reporting system.
+pub struct ExitStatus {
+    // The descriptor of the exit status file, or -1 when none was provided. The file stays open
+    // until the process exits, so that signal handlers only have to write to it.
+    fd: AtomicI32,
+    start_utc_timestamp_ms: AtomicU64,
+    // Set when the exit status is written, so that it is only written once.
+    written: AtomicBool,
+}
+
+impl ExitStatus {
+    fn new() -> ExitStatus {
+        ExitStatus {
+            fd: AtomicI32::new(-1),
+            start_utc_timestamp_ms: AtomicU64::new(0),
+            written: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether a file was provided for the exit status.
+    pub fn is_initialized(&self) -> bool {
+        self.fd.load(Ordering::SeqCst) >= 0
+    }
+
+    /// Creates the file at `path`, which the exit status is written to. Firecracker is
+    /// considered started at this time.
+    pub fn init(&self, path: &Path) -> Result<(), ExitStatusError> {
+        if self.is_initialized() {
+            return Err(ExitStatusError::AlreadyInitialized);
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(ExitStatusError::CreateFile)?;
+        self.start_utc_timestamp_ms
+            .store(get_time(ClockType::Real) / 1_000_000, Ordering::SeqCst);
+        let fd = file.into_raw_fd();
+        if self
+            .fd
+            .compare_exchange(-1, fd, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            // Another thread provided a file first. Safe because we own `fd`.
+            drop(unsafe { File::from_raw_fd(fd) });
+            return Err(ExitStatusError::AlreadyInitialized);
+        }
+        Ok(())
+    }
+
+    // Returns the descriptor of the exit status file if the exit status is still to be written,
+    // marking it as written.
+    fn take_fd(&self) -> Option<RawFd> {
+        let fd = self.fd.load(Ordering::SeqCst);
+        if fd < 0 || self.written.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Some(fd)
+    }
+
+    /// Writes the exit status, if a file was provided. `metrics` are the last metrics written,
+    /// in JSON format. Only the first exit status is written, later ones are ignored.
+    pub fn write(
+        &self,
+        exit_code: i32,
+        reason: ExitReason,
+        metrics: Option<&str>,
+    ) -> Result<(), ExitStatusError> {
+        let fd = match self.take_fd() {
+            Some(fd) => fd,
+            None => return Ok(()),
+        };
+        let record = ExitStatusRecord {
+            exit_code,
+            reason,
+            start_utc_timestamp_ms: self.start_utc_timestamp_ms.load(Ordering::SeqCst),
+            exit_utc_timestamp_ms: get_time(ClockType::Real) / 1_000_000,
+            metrics: match metrics {
+                Some(metrics) => Some(
+                    serde_json::from_str(metrics)
+                        .map_err(|e| ExitStatusError::Serde(e.to_string()))?,
+                ),
+                None => None,
+            },
+        };
+        let msg =
+            serde_json::to_string(&record).map_err(|e| ExitStatusError::Serde(e.to_string()))?;
+        // Safe because the descriptor stays open until the process exits, and the file is not
+        // closed when dropped.
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        file.write_all(format!("{}\n", msg).as_bytes())
+            .map_err(ExitStatusError::Write)
+    }
+
+    /// Writes the exit status without the metrics, if a file was provided. Unlike `write`, it
+    /// neither allocates nor locks, so it can be called from a signal handler: the exit status
+    /// is formatted on the stack and written with a single `write` call.
+    pub fn write_from_signal_handler(&self, exit_code: i32, reason: ExitReason) {
+        let fd = match self.take_fd() {
+            Some(fd) => fd,
+            None => return,
+        };
+        let mut buf = [0u8; 256];
+        let unused_len = {
+            let mut cursor = &mut buf[..];
+            if writeln!(
+                cursor,
+                "{{\"exit_code\":{},\"reason\":\"{}\",\"start_utc_timestamp_ms\":{},\
+                 \"exit_utc_timestamp_ms\":{}}}",
+                exit_code,
+                reason.as_str(),
+                self.start_utc_timestamp_ms.load(Ordering::SeqCst),
+                get_time(ClockType::Real) / 1_000_000
+            )
+            .is_err()
+            {
+                return;
+            }
+            cursor.len()
+        };
+        let len = buf.len() - unused_len;
+        // Safe because `buf` holds at least `len` bytes. There is nothing left to do if the
+        // write fails.
+        unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use utils::tempfile::TempFile;
+
+    #[test]
+    fn test_write() {
+        let exit_status = ExitStatus::new();
+        // Nothing is written until a file is provided.
+        assert!(exit_status
+            .write(0, ExitReason::GuestShutdown, None)
+            .is_ok());
+
+        let f = TempFile::new().unwrap();
+        assert!(exit_status.init(f.as_path()).is_ok());
+        match exit_status.init(f.as_path()) {
+            Err(ExitStatusError::AlreadyInitialized) => (),
+            _ => panic!("The exit status file should only be provided once."),
+        }
+
+        assert!(exit_status
+            .write(148, ExitReason::SeccompViolation, Some(r#"{"seccomp":1}"#))
+            .is_ok());
+        // Only the first exit status is written.
+        assert!(exit_status.write(0, ExitReason::ApiRequest, None).is_ok());
+
+        let contents = fs::read_to_string(f.as_path()).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        let record: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(record["exit_code"], 148);
+        assert_eq!(record["reason"], "seccomp_violation");
+        assert_eq!(record["metrics"]["seccomp"], 1);
+        assert!(
+            record["start_utc_timestamp_ms"].as_u64().unwrap()
+                <= record["exit_utc_timestamp_ms"].as_u64().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_from_signal_handler() {
+        let exit_status = ExitStatus::new();
+        exit_status.write_from_signal_handler(148, ExitReason::SeccompViolation);
+
+        let f = TempFile::new().unwrap();
+        exit_status.init(f.as_path()).unwrap();
+        exit_status.write_from_signal_handler(148, ExitReason::SeccompViolation);
+        // Only the first exit status is written.
+        exit_status.write_from_signal_handler(135, ExitReason::FatalSignal);
+        assert!(exit_status
+            .write(0, ExitReason::GuestShutdown, Some("{}"))
+            .is_ok());
+
+        let contents = fs::read_to_string(f.as_path()).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        let record: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(record["exit_code"], 148);
+        assert_eq!(record["reason"], "seccomp_violation");
+        assert!(record.get("metrics").is_none());
+        assert!(
+            record["start_utc_timestamp_ms"].as_u64().unwrap()
+                <= record["exit_utc_timestamp_ms"].as_u64().unwrap()
+        );
+
+        // The names of the reasons match their serialization.
+        for reason in &[
+            ExitReason::GuestShutdown,
+            ExitReason::ApiRequest,
+            ExitReason::VcpuError,
+            ExitReason::InternalError,
+            ExitReason::SeccompViolation,
+            ExitReason::FatalSignal,
+            ExitReason::Panic,
+        ] {
+            assert_eq!(
+                serde_json::to_value(reason).unwrap(),
+                serde_json::Value::from(reason.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(
+            ExitStatusError::AlreadyInitialized.to_string(),
+            "Reinitialization of the exit status file not allowed."
+        );
+        assert_eq!(
+            ExitStatusError::Serde("bad json".to_string()).to_string(),
+            "bad json"
+        );
+    }
+}
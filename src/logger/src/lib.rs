@@ -13,9 +13,13 @@ extern crate serde_derive;
 extern crate serde_json;
 extern crate utils;
 
+mod events;
+mod exit_status;
 mod logger;
 mod metrics;
 
+pub use events::{Event, EventRecord, Events, ShutdownPath, EVENTS, MAX_PENDING_EVENTS};
+pub use exit_status::{ExitReason, ExitStatus, ExitStatusError, EXIT_STATUS};
 pub use log::Level::*;
 pub use log::*;
 pub use logger::{LoggerError, LOGGER};
@@ -119,31 +119,35 @@ impl<T: Serialize> Metrics<T> {
     /// were successfully written to disk) or `False` (if metrics system was not yet initialized).
     pub fn write(&self) -> Result<bool, MetricsError> {
         if self.is_initialized.load(Ordering::Relaxed) {
-            match serde_json::to_string(&self.app_metrics) {
-                Ok(msg) => {
-                    if let Some(guard) = buf_guard(&self.metrics_buf).as_mut() {
-                        // No need to explicitly call flush because the underlying LineWriter flushes
-                        // automatically whenever a newline is detected (and we always end with a
-                        // newline the current write).
-                        return guard
-                            .write(&(format!("{}\n", msg)).as_bytes())
-                            .map_err(MetricsError::Write)
-                            .map(|_| true);
-                    } else {
-                        // We have not incremented `missed_metrics_count` as there is no way to push metrics
-                        // if destination lock got poisoned.
-                        panic!("Failed to write to the provided metrics destination due to poisoned lock");
-                    }
-                }
-                Err(e) => {
-                    return Err(MetricsError::Serde(e.to_string()));
-                }
-            }
+            return self.write_snapshot().map(|_| true);
         }
         // If the metrics are not initialized, no error is thrown but we do let the user know that
         // metrics were not written.
         Ok(false)
     }
+
+    /// Serializes the metrics to JSON and, if the metrics system is initialized, writes them to
+    /// the destination provided upon initialization. The serialized metrics are returned even
+    /// when the metrics system is not initialized, in which case they are reset all the same.
+    pub fn write_snapshot(&self) -> Result<String, MetricsError> {
+        let msg = serde_json::to_string(&self.app_metrics)
+            .map_err(|e| MetricsError::Serde(e.to_string()))?;
+        if self.is_initialized.load(Ordering::Relaxed) {
+            if let Some(guard) = buf_guard(&self.metrics_buf).as_mut() {
+                // No need to explicitly call flush because the underlying LineWriter flushes
+                // automatically whenever a newline is detected (and we always end with a
+                // newline the current write).
+                guard
+                    .write(&(format!("{}\n", msg)).as_bytes())
+                    .map_err(MetricsError::Write)?;
+            } else {
+                // We have not incremented `missed_metrics_count` as there is no way to push metrics
+                // if destination lock got poisoned.
+                panic!("Failed to write to the provided metrics destination due to poisoned lock");
+            }
+        }
+        Ok(msg)
+    }
 }
 
 impl<T: Serialize> Deref for Metrics<T> {
@@ -239,6 +243,34 @@ impl Serialize for SharedMetric {
     }
 }
 
+/// Representation of a metric that holds the last value stored in it, such as the size of a
+/// buffer, instead of counting events. Unlike `SharedMetric`, flushing does not reset it.
+#[derive(Default)]
+pub struct StoreMetric(AtomicUsize);
+
+impl StoreMetric {
+    /// Replaces the current value of the metric with `value`.
+    pub fn store(&self, value: usize) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+impl Metric for StoreMetric {
+    fn add(&self, value: usize) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Serialize for StoreMetric {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0.load(Ordering::Relaxed) as u64)
+    }
+}
+
 // The following structs are used to define a certain organization for the set of metrics we
 // are interested in. Whenever the name of a field differs from its ideal textual representation
 // in the serialized form, we can use the #[serde(rename = "name")] attribute to, well, rename it.
@@ -254,19 +286,39 @@ pub struct ApiServerMetrics {
     pub sync_response_fails: SharedMetric,
     /// Number of timeouts during communication with the VMM.
     pub sync_vmm_send_timeout_count: SharedMetric,
+    /// Number of requests denied by the access policy.
+    pub access_denied_count: SharedMetric,
 }
 
 /// Metrics specific to GET API Requests for counting user triggered actions and/or failures.
 #[derive(Default, Serialize)]
 pub struct GetRequestsMetrics {
+    /// Number of GETs for getting the boot source configuration.
+    pub boot_source_count: SharedMetric,
+    /// Number of GETs for getting the configuration and state of block devices.
+    pub drive_count: SharedMetric,
+    /// Number of GETs for subscribing to the events of the VMM.
+    pub events_count: SharedMetric,
     /// Number of GETs for getting information on the instance.
     pub instance_info_count: SharedMetric,
     /// Number of failures when obtaining information on the current instance.
     pub instance_info_fails: SharedMetric,
+    /// Number of GETs for getting the logger configuration.
+    pub logger_count: SharedMetric,
     /// Number of GETs for getting status on attaching machine configuration.
     pub machine_cfg_count: SharedMetric,
     /// Number of failures during GETs for getting information on the instance.
     pub machine_cfg_fails: SharedMetric,
+    /// Number of GETs for getting the configuration and state of network interfaces.
+    pub network_count: SharedMetric,
+    /// Number of GETs for getting the schema of the API.
+    pub openapi_count: SharedMetric,
+    /// Number of GETs for getting the full configuration of the microVM.
+    pub vm_cfg_count: SharedMetric,
+    /// Number of failures when serializing the full configuration of the microVM.
+    pub vm_cfg_fails: SharedMetric,
+    /// Number of GETs for getting the configuration and state of the vsock device.
+    pub vsock_count: SharedMetric,
 }
 
 /// Metrics specific to PUT API Requests for counting user triggered actions and/or failures.
@@ -300,6 +352,10 @@ pub struct PutRequestsMetrics {
     pub network_count: SharedMetric,
     /// Number of failures in creating a new network interface.
     pub network_fails: SharedMetric,
+    /// Number of PUTs for configuring the whole microVM at once.
+    pub vm_count: SharedMetric,
+    /// Number of failures in configuring the whole microVM at once.
+    pub vm_fails: SharedMetric,
 }
 
 /// Metrics specific to PATCH API Requests for counting user triggered actions and/or failures.
@@ -319,6 +375,21 @@ pub struct PatchRequestsMetrics {
     pub machine_cfg_fails: SharedMetric,
 }
 
+/// Metrics specific to DELETE API Requests for counting user triggered actions and/or failures.
+#[derive(Default, Serialize)]
+pub struct DeleteRequestsMetrics {
+    /// Number of tries to DELETE a block device.
+    pub drive_count: SharedMetric,
+    /// Number of failures in DELETEing a block device.
+    pub drive_fails: SharedMetric,
+    /// Number of tries to DELETE a net device.
+    pub network_count: SharedMetric,
+    /// Number of failures in DELETEing a net device.
+    pub network_fails: SharedMetric,
+    /// Number of tries to DELETE the vsock device.
+    pub vsock_count: SharedMetric,
+}
+
 /// Block Device associated metrics.
 #[derive(Default, Serialize)]
 pub struct BlockDeviceMetrics {
@@ -395,6 +466,10 @@ pub struct MmdsMetrics {
     pub rx_bad_eth: SharedMetric,
     /// The total number of successful receive operations by the MMDS.
     pub rx_count: SharedMetric,
+    /// The number of long-poll requests held until the MMDS data store gets updated.
+    pub rx_long_poll_requests: SharedMetric,
+    /// The number of UDP requests handed over to the services of the MMDS.
+    pub rx_udp_requests: SharedMetric,
     /// The total number of bytes sent by the MMDS.
     pub tx_bytes: SharedMetric,
     /// The total number of successful send operations by the MMDS.
@@ -407,6 +482,10 @@ pub struct MmdsMetrics {
     pub connections_created: SharedMetric,
     /// The number of connections cleaned up by the MMDS TCP handler.
     pub connections_destroyed: SharedMetric,
+    /// The size, in bytes, of the serialized MMDS data store.
+    pub data_store_size: StoreMetric,
+    /// The number of data store updates rejected because the result exceeded the size limit.
+    pub data_store_rejected_updates: SharedMetric,
 }
 
 /// Network-related metrics.
@@ -424,6 +503,8 @@ pub struct NetDeviceMetrics {
     pub rx_event_rate_limiter_count: SharedMetric,
     /// Number of events received on the associated tap.
     pub rx_tap_event_count: SharedMetric,
+    /// Number of MMDS data store updates and long-poll timeouts handled by a network device.
+    pub mmds_event_count: SharedMetric,
     /// Number of bytes received.
     pub rx_bytes_count: SharedMetric,
     /// Number of packets received.
@@ -446,6 +527,15 @@ pub struct NetDeviceMetrics {
     pub tx_rate_limiter_event_count: SharedMetric,
     /// Number of packets with a spoofed mac, sent by the guest.
     pub tx_spoofed_mac_count: SharedMetric,
+    /// Number of packets sent by the guest and dropped because the link was down.
+    pub tx_link_down_drop_count: SharedMetric,
+}
+
+/// Metrics specific to the GPIO device.
+#[derive(Default, Serialize)]
+pub struct GpioDeviceMetrics {
+    /// Errors triggered while using the GPIO device.
+    pub error_count: SharedMetric,
 }
 
 /// Metrics specific to the i8042 device.
@@ -538,8 +628,12 @@ pub struct FirecrackerMetrics {
     pub api_server: ApiServerMetrics,
     /// A block device's related metrics.
     pub block: BlockDeviceMetrics,
+    /// Metrics related to API DELETE requests.
+    pub delete_api_requests: DeleteRequestsMetrics,
     /// Metrics related to API GET requests.
     pub get_api_requests: GetRequestsMetrics,
+    /// Metrics related to the GPIO device.
+    pub gpio: GpioDeviceMetrics,
     /// Metrics related to the i8042 device.
     pub i8042: I8042DeviceMetrics,
     /// Logging related metrics.
@@ -595,6 +689,20 @@ mod tests {
         assert!(m.init(Box::new(f.into_file()),).is_err());
     }
 
+    #[test]
+    fn test_write_snapshot() {
+        let m = Metrics::new(FirecrackerMetrics::default());
+        m.seccomp.num_faults.inc();
+
+        // The metrics are serialized even when the metrics system is not initialized.
+        let snapshot: serde_json::Value =
+            serde_json::from_str(&m.write_snapshot().unwrap()).unwrap();
+        assert_eq!(snapshot["seccomp"]["num_faults"], 1);
+        let snapshot: serde_json::Value =
+            serde_json::from_str(&m.write_snapshot().unwrap()).unwrap();
+        assert_eq!(snapshot["seccomp"]["num_faults"], 0);
+    }
+
     #[test]
     fn test_metric() {
         // Test SharedMetric.
@@ -631,6 +739,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_store_metric() {
+        let m = StoreMetric::default();
+        m.store(42);
+        assert_eq!(m.count(), 42);
+        m.add(8);
+        assert_eq!(m.count(), 50);
+
+        // Flushing keeps the value.
+        assert_eq!(serde_json::to_string(&m).unwrap(), "50");
+        assert_eq!(serde_json::to_string(&m).unwrap(), "50");
+
+        m.store(7);
+        assert_eq!(m.count(), 7);
+    }
+
     #[test]
     fn test_serialize() {
         let s = serde_json::to_string(&FirecrackerMetrics::default());
@@ -1,6 +1,7 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::result::Result;
 
 use RequestError;
@@ -8,10 +9,16 @@ use RequestError;
 /// Wrapper over an HTTP Header type.
 #[derive(Debug, Eq, Hash, PartialEq)]
 pub enum Header {
+    /// Header `Accept`.
+    Accept,
+    /// Header `Connection`.
+    Connection,
     /// Header `Content-Length`.
     ContentLength,
     /// Header `Content-Type`.
     ContentType,
+    /// Header `ETag`.
+    ETag,
     /// Header `Expect`.
     Expect,
     /// Header `Transfer-Encoding`.
@@ -24,8 +31,11 @@ impl Header {
     /// Returns a byte slice representation of the object.
     pub fn raw(&self) -> &'static [u8] {
         match self {
+            Self::Accept => b"Accept",
+            Self::Connection => b"Connection",
             Self::ContentLength => b"Content-Length",
             Self::ContentType => b"Content-Type",
+            Self::ETag => b"ETag",
             Self::Expect => b"Expect",
             Self::TransferEncoding => b"Transfer-Encoding",
             Self::Server => b"Server",
@@ -42,8 +52,11 @@ impl Header {
         if let Ok(mut utf8_string) = String::from_utf8(string.to_vec()) {
             utf8_string.make_ascii_lowercase();
             match utf8_string.trim() {
+                "accept" => Ok(Self::Accept),
+                "connection" => Ok(Self::Connection),
                 "content-length" => Ok(Self::ContentLength),
                 "content-type" => Ok(Self::ContentType),
+                "etag" => Ok(Self::ETag),
                 "expect" => Ok(Self::Expect),
                 "transfer-encoding" => Ok(Self::TransferEncoding),
                 "server" => Ok(Self::Server),
@@ -62,8 +75,9 @@ impl Header {
 /// in plain text actually and don't influence our parsing process.
 ///
 /// All the other possible header fields are not necessary in order to serve this connection
-/// and, thus, are not of interest to us. However, we still look for header fields that might
-/// invalidate our request as we don't support the full set of HTTP/1.1 specification.
+/// and are stored as they are, for the users of the crate to interpret. However, we still
+/// look for header fields that might invalidate our request as we don't support the full set
+/// of HTTP/1.1 specification.
 /// Such header entries are "Transfer-Encoding: identity; q=0", which means a compression
 /// algorithm is applied to the body of the request, or "Expect: 103-checkpoint".
 #[derive(Debug)]
@@ -80,6 +94,17 @@ pub struct Headers {
     /// server must support it. It is useful only when receiving the body of the request and should
     /// be known immediately after parsing the headers.
     chunked: bool,
+    /// The `Accept` header field holds the media type the client prefers for the response
+    /// body, out of the ones we support. It defaults to `text/plain` when the client accepts
+    /// any media type, or none of the supported ones.
+    accept: MediaType,
+    /// The `Connection` header field tells us whether the client wants the connection closed
+    /// (`close`) or kept open (`keep-alive`) after the response. It is `None` when the client
+    /// did not state either, in which case the default of its HTTP version applies.
+    keep_alive: Option<bool>,
+    /// The header fields this implementation does not interpret, keyed by their name as it
+    /// appeared in the request.
+    custom_entries: HashMap<String, String>,
 }
 
 impl Default for Headers {
@@ -89,6 +114,9 @@ impl Default for Headers {
             content_length: Default::default(),
             expect: Default::default(),
             chunked: Default::default(),
+            accept: MediaType::PlainText,
+            keep_alive: None,
+            custom_entries: HashMap::new(),
         }
     }
 }
@@ -97,9 +125,11 @@ impl Headers {
     /// Expects one header line and parses it, updating the header structure or returning an
     /// error if the header is invalid.
     ///
+    /// Header fields that are not recognized are stored as custom entries.
+    ///
     /// # Errors
-    /// `UnsupportedHeader` is returned when the parsed header line is not of interest
-    /// to us or when it is unrecognizable.
+    /// `UnsupportedHeader` is returned when a recognized header field holds a value that
+    /// is not of interest to us.
     /// `InvalidHeader` is returned when the parsed header is formatted incorrectly or suggests
     /// that the client is using HTTP features that we do not support in this implementation,
     /// which invalidates the request.
@@ -124,6 +154,32 @@ impl Headers {
                 }
                 if let Ok(head) = Header::try_from(entry[0].as_bytes()) {
                     match head {
+                        Header::Accept => {
+                            // The media ranges are listed in order of preference, so pick the
+                            // first supported one, ignoring its parameters (e.g. `q=0.9`).
+                            if let Some(media_type) = entry[1]
+                                .split(',')
+                                .filter_map(|range| range.split(';').next())
+                                .find_map(|range| MediaType::try_from(range.as_bytes()).ok())
+                            {
+                                self.accept = media_type;
+                            }
+                            Ok(())
+                        }
+                        Header::Connection => {
+                            // The field holds a list of options, out of which `close` takes
+                            // precedence.
+                            for option in entry[1].split(',').map(str::trim) {
+                                if option.eq_ignore_ascii_case("close") {
+                                    self.keep_alive = Some(false);
+                                } else if option.eq_ignore_ascii_case("keep-alive")
+                                    && self.keep_alive.is_none()
+                                {
+                                    self.keep_alive = Some(true);
+                                }
+                            }
+                            Ok(())
+                        }
                         Header::ContentLength => match entry[1].trim().parse::<i32>() {
                             Ok(content_length) => {
                                 self.content_length = content_length;
@@ -152,10 +208,12 @@ impl Headers {
                             }
                             _ => Err(RequestError::InvalidHeader),
                         },
-                        Header::Server => Ok(()),
+                        Header::ETag | Header::Server => Ok(()),
                     }
                 } else {
-                    Err(RequestError::UnsupportedHeader)
+                    self.custom_entries
+                        .insert(entry[0].to_string(), entry[1].trim().to_string());
+                    Ok(())
                 }
             }
             _ => Err(RequestError::InvalidHeader),
@@ -179,6 +237,26 @@ impl Headers {
         self.expect
     }
 
+    /// Returns the media type the client prefers for the response body.
+    pub fn accept(&self) -> MediaType {
+        self.accept
+    }
+
+    /// Returns `Some(false)` if the client asked for the connection to be closed after the
+    /// response, `Some(true)` if it asked for it to be kept open, and `None` otherwise.
+    pub fn keep_alive(&self) -> Option<bool> {
+        self.keep_alive
+    }
+
+    /// Returns the value of the custom header field `name`. The lookup is case insensitive,
+    /// as header field names are.
+    pub fn custom_entry(&self, name: &str) -> Option<&str> {
+        self.custom_entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
     /// Parses a byte slice into a Headers structure for a HTTP request.
     ///
     /// The byte slice is expected to have the following format: </br>
@@ -228,6 +306,8 @@ pub enum MediaType {
     PlainText,
     /// Media Type: "application/json".
     ApplicationJson,
+    /// Media Type: "text/event-stream".
+    EventStream,
 }
 
 impl Default for MediaType {
@@ -263,6 +343,7 @@ impl MediaType {
         match utf8_slice.as_str().trim() {
             "text/plain" => Ok(Self::PlainText),
             "application/json" => Ok(Self::ApplicationJson),
+            "text/event-stream" => Ok(Self::EventStream),
             _ => Err(RequestError::InvalidRequest),
         }
     }
@@ -282,6 +363,7 @@ impl MediaType {
         match self {
             Self::PlainText => "text/plain",
             Self::ApplicationJson => "application/json",
+            Self::EventStream => "text/event-stream",
         }
     }
 }
@@ -296,6 +378,9 @@ mod tests {
                 content_length,
                 expect,
                 chunked,
+                accept: MediaType::PlainText,
+                keep_alive: None,
+                custom_entries: HashMap::new(),
             }
         }
     }
@@ -306,6 +391,8 @@ mod tests {
         assert_eq!(headers.content_length(), 0);
         assert_eq!(headers.chunked(), false);
         assert_eq!(headers.expect(), false);
+        assert_eq!(headers.accept(), MediaType::PlainText);
+        assert_eq!(headers.keep_alive(), None);
     }
 
     #[test]
@@ -320,6 +407,11 @@ mod tests {
             MediaType::PlainText
         );
 
+        assert_eq!(
+            MediaType::try_from(b"text/event-stream").unwrap(),
+            MediaType::EventStream
+        );
+
         assert_eq!(
             MediaType::try_from(b"").unwrap_err(),
             RequestError::InvalidRequest
@@ -338,6 +430,9 @@ mod tests {
 
         let media_type = MediaType::PlainText;
         assert_eq!(media_type.as_str(), "text/plain");
+
+        let media_type = MediaType::EventStream;
+        assert_eq!(media_type.as_str(), "text/event-stream");
     }
 
     #[test]
@@ -416,6 +511,33 @@ mod tests {
         assert!(header
             .parse_header_line(b"Content-Type: application/json")
             .is_ok());
+
+        // Test accept.
+        assert!(header.parse_header_line(b"Accept: */*").is_ok());
+        assert_eq!(header.accept(), MediaType::PlainText);
+        assert!(header
+            .parse_header_line(b"Accept: application/json")
+            .is_ok());
+        assert_eq!(header.accept(), MediaType::ApplicationJson);
+        assert!(header
+            .parse_header_line(b"Accept: text/html, text/plain;q=0.9, application/json;q=0.8")
+            .is_ok());
+        assert_eq!(header.accept(), MediaType::PlainText);
+
+        // Test connection options.
+        assert!(header.parse_header_line(b"Connection: Keep-Alive").is_ok());
+        assert_eq!(header.keep_alive(), Some(true));
+        assert!(header.parse_header_line(b"Connection: TE, close").is_ok());
+        assert_eq!(header.keep_alive(), Some(false));
+        assert!(header.parse_header_line(b"Connection: keep-alive").is_ok());
+        assert_eq!(header.keep_alive(), Some(false));
+
+        // Test custom header fields.
+        assert!(header
+            .parse_header_line(b"X-Custom-Header: some value ")
+            .is_ok());
+        assert_eq!(header.custom_entry("x-custom-header"), Some("some value"));
+        assert_eq!(header.custom_entry("X-Other-Header"), None);
     }
 
     #[test]
@@ -18,6 +18,9 @@ pub enum ConnectionState {
     WaitingForRequestLine,
     WaitingForHeaders,
     WaitingForBody,
+    WaitingForChunkSize,
+    WaitingForChunkData,
+    WaitingForTrailers,
     RequestReady,
 }
 
@@ -39,8 +42,8 @@ pub struct HttpConnection<T> {
     /// Contains all bytes pertaining to the body of the request that
     /// is currently being processed.
     body_vec: Vec<u8>,
-    /// Represents how many bytes from the body of the request are still
-    /// to be read.
+    /// Represents how many bytes from the body of the request, or from the
+    /// current chunk of a chunked body, are still to be read.
     body_bytes_to_be_read: i32,
     /// A queue of all requests that have been fully received and parsed.
     parsed_requests: VecDeque<Request>,
@@ -49,6 +52,9 @@ pub struct HttpConnection<T> {
     /// A buffer containing the bytes of a response that is currently
     /// being sent.
     response_buffer: Option<Vec<u8>>,
+    /// Bytes of a stream that are waiting to be sent, after all the
+    /// responses in `response_queue`.
+    stream_data: Vec<u8>,
 }
 
 impl<T: Read + Write> HttpConnection<T> {
@@ -65,6 +71,7 @@ impl<T: Read + Write> HttpConnection<T> {
             parsed_requests: VecDeque::new(),
             response_queue: VecDeque::new(),
             response_buffer: None,
+            stream_data: vec![],
         }
     }
 
@@ -100,6 +107,21 @@ impl<T: Read + Write> HttpConnection<T> {
                         return Ok(());
                     }
                 }
+                ConnectionState::WaitingForChunkSize => {
+                    if !self.parse_chunk_size(&mut line_start_index, end_cursor)? {
+                        return Ok(());
+                    }
+                }
+                ConnectionState::WaitingForChunkData => {
+                    if !self.parse_chunk_data(&mut line_start_index, end_cursor)? {
+                        return Ok(());
+                    }
+                }
+                ConnectionState::WaitingForTrailers => {
+                    if !self.parse_trailers(&mut line_start_index, end_cursor)? {
+                        return Ok(());
+                    }
+                }
                 ConnectionState::RequestReady => {
                     // This request is ready to be passed for handling.
                     // Update the state machine to expect a new request and push this request into
@@ -198,7 +220,16 @@ impl<T: Read + Write> HttpConnection<T> {
                 // If our current state is `WaitingForHeaders`, it means that we already have
                 // a valid request formed from a request line, so it's safe to unwrap.
                 let request = self.pending_request.as_mut().unwrap();
-                if request.headers.content_length() == 0 {
+                if request.headers.chunked() {
+                    // A chunked transfer coding takes precedence over `Content-Length`, as
+                    // mandated by RFC 7230, section 3.3.3.
+                    if request.headers.expect() {
+                        let expect_response =
+                            Response::new(request.http_version(), StatusCode::Continue);
+                        self.response_queue.push_back(expect_response);
+                    }
+                    self.state = ConnectionState::WaitingForChunkSize;
+                } else if request.headers.content_length() == 0 {
                     self.state = ConnectionState::RequestReady;
                 } else {
                     if request.headers.expect() {
@@ -308,6 +339,134 @@ impl<T: Read + Write> HttpConnection<T> {
         Ok(true)
     }
 
+    /// Parses bytes in `buffer` for the size line of the next chunk of a chunked body.
+    /// Chunk extensions are ignored. Returns `false` if there are no more bytes to be
+    /// parsed in the buffer.
+    ///
+    /// # Errors
+    /// `ParseError` is returned if the chunk size is invalid, if the line is longer than
+    /// BUFFER_SIZE or if the body would grow larger than `i32::MAX` bytes.
+    fn parse_chunk_size(
+        &mut self,
+        line_start_index: &mut usize,
+        end_cursor: usize,
+    ) -> Result<bool, ConnectionError> {
+        match find(&self.buffer[*line_start_index..end_cursor], &[CR, LF]) {
+            Some(relative_line_end_index) => {
+                let line_end_index = *line_start_index + relative_line_end_index;
+                let line = &self.buffer[*line_start_index..line_end_index];
+                // Chunk extensions start with a `;`.
+                let size = line.split(|byte| *byte == b';').next().unwrap_or(&[]);
+                let chunk_size = std::str::from_utf8(size)
+                    .ok()
+                    .map(str::trim)
+                    .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|size| i32::from_str_radix(size, 16).ok())
+                    .ok_or(ConnectionError::ParseError(RequestError::InvalidRequest))?;
+
+                *line_start_index = line_end_index + CRLF_LEN;
+                if chunk_size == 0 {
+                    // The last chunk is followed by the (optional) trailer fields.
+                    self.state = ConnectionState::WaitingForTrailers;
+                    return Ok(true);
+                }
+
+                // The chunk data is followed by a CR LF sequence, which we read along with it.
+                if (self.body_vec.len() as i32)
+                    .checked_add(chunk_size)
+                    .and_then(|body_len| body_len.checked_add(CRLF_LEN as i32))
+                    .is_none()
+                {
+                    return Err(ConnectionError::ParseError(RequestError::InvalidRequest));
+                }
+                self.body_bytes_to_be_read = chunk_size + CRLF_LEN as i32;
+                self.state = ConnectionState::WaitingForChunkData;
+                Ok(true)
+            }
+            None => {
+                // The chunk size line is longer than BUFFER_SIZE bytes, so the request is
+                // invalid.
+                if *line_start_index == 0 && end_cursor == BUFFER_SIZE {
+                    return Err(ConnectionError::ParseError(RequestError::InvalidRequest));
+                }
+                self.shift_buffer_left(*line_start_index, end_cursor);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Parses bytes in `buffer` to be put into the body of the request, as the data of the
+    /// current chunk. Returns `false` if there are no more bytes to be parsed in the buffer.
+    ///
+    /// # Errors
+    /// `ParseError` is returned when the chunk data is not followed by a CR LF sequence.
+    fn parse_chunk_data(
+        &mut self,
+        line_start_index: &mut usize,
+        end_cursor: usize,
+    ) -> Result<bool, ConnectionError> {
+        let bytes_to_read = std::cmp::min(
+            end_cursor - *line_start_index,
+            self.body_bytes_to_be_read as usize,
+        );
+        self.body_vec
+            .extend_from_slice(&self.buffer[*line_start_index..*line_start_index + bytes_to_read]);
+        *line_start_index += bytes_to_read;
+        self.body_bytes_to_be_read -= bytes_to_read as i32;
+
+        if self.body_bytes_to_be_read > 0 {
+            // We have consumed everything in the buffer, so the next read can start from
+            // its beginning.
+            self.read_cursor = 0;
+            return Ok(false);
+        }
+
+        if !self.body_vec.ends_with(&[CR, LF]) {
+            return Err(ConnectionError::ParseError(RequestError::InvalidRequest));
+        }
+        let body_len = self.body_vec.len() - CRLF_LEN;
+        self.body_vec.truncate(body_len);
+        self.state = ConnectionState::WaitingForChunkSize;
+        Ok(true)
+    }
+
+    /// Parses bytes in `buffer` for the trailer fields which end a chunked body. Trailer
+    /// fields are not interpreted. Returns `false` if there are no more bytes to be parsed
+    /// in the buffer.
+    ///
+    /// # Errors
+    /// `ParseError` is returned if a trailer field line is longer than BUFFER_SIZE.
+    fn parse_trailers(
+        &mut self,
+        line_start_index: &mut usize,
+        end_cursor: usize,
+    ) -> Result<bool, ConnectionError> {
+        match find(&self.buffer[*line_start_index..end_cursor], &[CR, LF]) {
+            // An empty line ends the chunked body.
+            Some(0) => {
+                *line_start_index += CRLF_LEN;
+                let body = std::mem::replace(&mut self.body_vec, vec![]);
+                let request = self.pending_request.as_mut().unwrap();
+                if !body.is_empty() {
+                    request.body = Some(Body::new(body));
+                }
+                self.state = ConnectionState::RequestReady;
+                Ok(true)
+            }
+            Some(relative_line_end_index) => {
+                *line_start_index += relative_line_end_index + CRLF_LEN;
+                Ok(true)
+            }
+            None => {
+                if *line_start_index == 0 && end_cursor == BUFFER_SIZE {
+                    return Err(ConnectionError::ParseError(RequestError::InvalidHeader));
+                }
+                self.shift_buffer_left(*line_start_index, end_cursor);
+                Ok(false)
+            }
+        }
+    }
+
     /// Tries to write the first available response to the provided stream.
     /// Meant to be used only with non-blocking streams and an `EPOLL` structure.
     /// Should be called whenever an `EPOLLOUT` event is signaled. If no bytes
@@ -328,6 +487,8 @@ impl<T: Read + Write> HttpConnection<T> {
                     .write_all(&mut response_buffer_vec)
                     .map_err(ConnectionError::StreamError)?;
                 self.response_buffer = Some(response_buffer_vec);
+            } else if !self.stream_data.is_empty() {
+                self.response_buffer = Some(std::mem::replace(&mut self.stream_data, vec![]));
             } else {
                 return Err(ConnectionError::InvalidWrite);
             }
@@ -365,6 +526,7 @@ impl<T: Read + Write> HttpConnection<T> {
     fn clear_write_buffer(&mut self) {
         self.response_queue.clear();
         self.response_buffer.take();
+        self.stream_data.clear();
     }
 
     /// Send a response back to the source of a request.
@@ -372,6 +534,12 @@ impl<T: Read + Write> HttpConnection<T> {
         self.response_queue.push_back(response);
     }
 
+    /// Send bytes which are not framed as a response, like the content of a stream.
+    /// They are written after the responses already enqueued.
+    pub fn enqueue_stream_data(&mut self, data: &[u8]) {
+        self.stream_data.extend_from_slice(data);
+    }
+
     fn shift_buffer_left(&mut self, line_start_index: usize, end_cursor: usize) {
         // We don't want to shift something that is already at the beginning.
         if line_start_index != 0 {
@@ -398,7 +566,9 @@ impl<T: Read + Write> HttpConnection<T> {
 
     /// Returns `true` if there are bytes waiting to be written into the stream.
     pub fn pending_write(&self) -> bool {
-        self.response_buffer.is_some() || !self.response_queue.is_empty()
+        self.response_buffer.is_some()
+            || !self.response_queue.is_empty()
+            || !self.stream_data.is_empty()
     }
 }
 
@@ -418,8 +588,7 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Content-Length: 26\r\n\
-                                 Transfer-Encoding: chunked\r\n\r\n",
+                                 Content-Length: 26\r\n\r\n",
             )
             .unwrap();
         assert!(conn.try_read().is_ok());
@@ -430,7 +599,7 @@ mod tests {
 
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
 
@@ -446,8 +615,7 @@ mod tests {
         sender
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
-                                 Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n",
+                                 Expect: 100-continue\r\n",
             )
             .unwrap();
 
@@ -466,7 +634,7 @@ mod tests {
 
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
         assert_eq!(request, expected_request);
@@ -482,7 +650,7 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n",
+                                 Custom-Header-Testing: 100\r\n",
             )
             .unwrap();
 
@@ -499,7 +667,7 @@ mod tests {
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
         assert_eq!(request, expected_request);
@@ -545,7 +713,6 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n\
                                  Content-Length: 1400\r\n\r\n",
             )
             .unwrap();
@@ -560,7 +727,7 @@ mod tests {
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(1400, true, true),
+            headers: Headers::new(1400, true, false),
             body: Some(Body::new(request_body)),
         };
         assert_eq!(request, expected_request);
@@ -617,15 +784,14 @@ mod tests {
         sender
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
-                                 Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n\r\n",
+                                 Expect: 100-continue\r\n\r\n",
             )
             .unwrap();
         conn.try_read().unwrap();
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(0, true, true),
+            headers: Headers::new(0, true, false),
             body: None,
         };
         assert_eq!(request, expected_request);
@@ -707,7 +873,6 @@ mod tests {
         sender
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
-                                 Transfer-Encoding: chunked\r\n\
                                  Content-Length: 26\r\n\r\nthis is not\n\r\na json \nbody",
             )
             .unwrap();
@@ -719,7 +884,7 @@ mod tests {
 
         let expected_request_first = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, false, true),
+            headers: Headers::new(26, false, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
 
@@ -736,6 +901,104 @@ mod tests {
         assert_eq!(request_second, expected_request_second);
     }
 
+    #[test]
+    fn test_try_read_chunked_body() {
+        // Chunked body, split across reads, with chunk extensions and trailer fields.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                                 Expect: 100-continue\r\n\
+                                 Content-Length: 3\r\n\
+                                 Transfer-Encoding: chunked\r\n\r\n\
+                                 B\r\nthis is not\r\n7;ext=",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_none());
+        // The client is told to continue sending the body.
+        assert!(conn.pending_write());
+
+        sender.write_all(b"1\r\n a js").unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_none());
+
+        sender
+            .write_all(b"on\r\n09\r\n \r\nbody\r\n\r\n0\r\nSome-Trailer: value\r\n\r\n")
+            .unwrap();
+        sender
+            .write_all(b"GET http://localhost/home HTTP/1.1\r\n\r\n")
+            .unwrap();
+        conn.try_read().unwrap();
+
+        let request = conn.pop_parsed_request().unwrap();
+        let expected_request = Request {
+            request_line: RequestLine::new(Method::Put, "http://localhost/home", Version::Http11),
+            headers: Headers::new(3, true, true),
+            body: None,
+        };
+        assert_eq!(request, expected_request);
+        assert_eq!(
+            request.body.unwrap().body,
+            b"this is not a json \r\nbody\r\n".to_vec()
+        );
+
+        let request = conn.pop_parsed_request().unwrap();
+        assert_eq!(request.method(), Method::Get);
+        assert!(request.body.is_none());
+
+        // A chunked request without data has no body.
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                                 Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().unwrap().body.is_none());
+    }
+
+    #[test]
+    fn test_try_read_invalid_chunk() {
+        let chunked_request = b"PUT http://localhost/home HTTP/1.1\r\n\
+                                Transfer-Encoding: chunked\r\n\r\n";
+
+        // Invalid chunk sizes.
+        for chunk in [
+            &b"x\r\n"[..],
+            &b"\r\n"[..],
+            &b"-1\r\n"[..],
+            &b"+1\r\n"[..],
+            &b"80000000\r\n"[..],
+            &b"7ffffffe\r\n"[..],
+        ]
+        .iter()
+        {
+            let (mut sender, receiver) = UnixStream::pair().unwrap();
+            receiver.set_nonblocking(true).expect("Can't modify socket");
+            let mut conn = HttpConnection::new(receiver);
+            sender.write_all(chunked_request).unwrap();
+            sender.write_all(chunk).unwrap();
+            assert_eq!(
+                conn.try_read().unwrap_err(),
+                ConnectionError::ParseError(RequestError::InvalidRequest)
+            );
+        }
+
+        // Chunk data which is longer than the chunk size.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender.write_all(chunked_request).unwrap();
+        sender.write_all(b"2\r\nabc\r\n0\r\n\r\n").unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::InvalidRequest)
+        );
+    }
+
     #[test]
     fn test_try_read_connection_closed() {
         // Connection abruptly closed.
@@ -796,4 +1059,30 @@ mod tests {
         receiver.read_exact(&mut response_buffer).unwrap();
         assert_eq!(response_buffer, expected_response);
     }
+
+    #[test]
+    fn test_enqueue_stream_data() {
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(sender);
+
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_streaming();
+        let mut expected_data: Vec<u8> = vec![];
+        response.write_all(&mut expected_data).unwrap();
+        expected_data.extend_from_slice(b"data: 1\n\ndata: 2\n\n");
+
+        // The stream data is sent after the response which started the stream.
+        conn.enqueue_response(response);
+        conn.enqueue_stream_data(b"data: 1\n\n");
+        conn.enqueue_stream_data(b"data: 2\n\n");
+        while conn.pending_write() {
+            conn.try_write().unwrap();
+        }
+        assert_eq!(conn.try_write().unwrap_err(), ConnectionError::InvalidWrite);
+
+        let mut data = vec![0u8; expected_data.len()];
+        receiver.read_exact(&mut data).unwrap();
+        assert_eq!(data, expected_data);
+    }
 }
@@ -7,16 +7,34 @@
 //! HTTP/1.1 has a mandatory header **Host**, but as this crate is only used
 //! for parsing API requests, this header (if present) is ignored.
 //!
-//! This HTTP implementation is stateless thus it does not support chunking or
-//! compression.
+//! Request bodies can be sent with a `Content-Length` or with the `chunked`
+//! transfer coding. Compression is not supported.
+//!
+//! The **HttpServer** listens on a Unix domain socket, a TCP socket or a vsock
+//! socket, and hands each request to its user along with the identity of the
+//! client which sent it.
+//!
+//! Connections are persistent: the **HttpServer** accepts pipelined requests and
+//! answers them in order, until the client asks for the connection to be closed
+//! through the **Connection** header, or the connection stays idle for longer than
+//! the idle timeout of the server.
+//!
+//! A **Response** can also start a stream, like a stream of server-sent events, which
+//! keeps its connection open and carries the data the **HttpServer** is later asked
+//! to send, until the client closes it.
 //!
 //! ## Supported Headers
 //! The **micro_http** crate has support for parsing the following **Request**
 //! headers:
+//! - Accept
+//! - Connection
 //! - Content-Length
 //! - Expect
 //! - Transfer-Encoding
 //!
+//! Any other request header is stored verbatim and can be looked up by the user
+//! of the crate.
+//!
 //! The **Response** does not have a public interface for adding headers, but whenever
 //! a write to the **Body** is made, the headers **ContentLength** and **MediaType**
 //! are automatically updated.
@@ -25,6 +43,7 @@
 //! The supported media types are:
 //! - text/plain
 //! - application/json
+//! - text/event-stream
 //!
 //! ## Supported Methods
 //! The supported HTTP Methods are:
@@ -39,7 +58,12 @@
 //! - OK - 200
 //! - No Content - 204
 //! - Bad Request - 400
+//! - Unauthorized - 401
+//! - Forbidden - 403
 //! - Not Found - 404
+//! - Method Not Allowed - 405
+//! - Not Acceptable - 406
+//! - Payload Too Large - 413
 //! - Internal Server Error - 500
 //! - Not Implemented - 501
 //!
@@ -114,6 +138,7 @@ extern crate utils;
 
 mod common;
 mod connection;
+mod listener;
 mod request;
 mod response;
 mod server;
@@ -121,9 +146,10 @@ use common::ascii;
 use common::headers;
 
 pub use connection::{ConnectionError, HttpConnection};
+pub use listener::{Listener, Peer, PeerCredentials, VsockListener, VsockStream};
 pub use request::{Request, RequestError};
 pub use response::{Response, StatusCode};
-pub use server::{HttpServer, ServerError, ServerRequest, ServerResponse};
+pub use server::{HttpServer, ServerError, ServerRequest, ServerResponse, DEFAULT_IDLE_TIMEOUT};
 
 pub use common::headers::{Headers, MediaType};
 pub use common::{Body, Method, Version};
@@ -0,0 +1,259 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+use std::io::{Read, Result, Write};
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+// The length of the queue of pending connections on a vsock listener.
+const VSOCK_BACKLOG: libc::c_int = 128;
+
+/// The credentials of a process connected through a Unix domain socket, as reported by the
+/// kernel when the connection was established.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerCredentials {
+    /// Process ID.
+    pub pid: i32,
+    /// User ID.
+    pub uid: u32,
+    /// Group ID.
+    pub gid: u32,
+}
+
+/// Identifies the other end of a connection accepted by the `HttpServer`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Peer {
+    /// A process on the same host, connected through a Unix domain socket.
+    Unix(PeerCredentials),
+    /// A TCP client.
+    Tcp(SocketAddr),
+    /// A vsock client.
+    Vsock {
+        /// The context ID of the client.
+        cid: u32,
+        /// The port of the client.
+        port: u32,
+    },
+}
+
+/// A socket on which the `HttpServer` listens for new connections.
+pub trait Listener: AsRawFd {
+    /// The stream of an accepted connection.
+    type Stream: Read + Write + AsRawFd;
+
+    /// Accepts a new connection, returning its stream in non-blocking mode, along with
+    /// the identity of the peer.
+    fn accept(&self) -> Result<(Self::Stream, Peer)>;
+}
+
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn accept(&self) -> Result<(UnixStream, Peer)> {
+        let (stream, _) = UnixListener::accept(self)?;
+        stream.set_nonblocking(true)?;
+        let credentials = peer_credentials(&stream)?;
+        Ok((stream, Peer::Unix(credentials)))
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept(&self) -> Result<(TcpStream, Peer)> {
+        let (stream, address) = TcpListener::accept(self)?;
+        stream.set_nonblocking(true)?;
+        Ok((stream, Peer::Tcp(address)))
+    }
+}
+
+/// Reads the credentials of the process at the other end of `stream`.
+fn peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
+    // This is safe because `ucred` is a plain C structure, which is valid when zeroed.
+    let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // This is safe because we pass a valid file descriptor, and the kernel writes at most
+    // `len` bytes into `credentials`.
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        pid: credentials.pid,
+        uid: credentials.uid,
+        gid: credentials.gid,
+    })
+}
+
+/// A listening `AF_VSOCK` socket.
+pub struct VsockListener {
+    socket: File,
+}
+
+impl VsockListener {
+    /// Creates a vsock listener bound to `port`, on any context ID of the host.
+    ///
+    /// # Errors
+    /// Returns an error when creating, binding or listening on the socket fails.
+    pub fn bind(port: u32) -> Result<Self> {
+        // This is safe because we check the returned file descriptor.
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // This is safe because we own the newly created file descriptor, which is then
+        // closed when `socket` is dropped.
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        let address = vsock_address(libc::VMADDR_CID_ANY, port);
+        // This is safe because we pass a valid file descriptor and a valid address.
+        let ret = unsafe {
+            libc::bind(
+                fd,
+                &address as *const libc::sockaddr_vm as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // This is safe because we pass a valid file descriptor.
+        if unsafe { libc::listen(fd, VSOCK_BACKLOG) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(VsockListener { socket })
+    }
+}
+
+impl AsRawFd for VsockListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl Listener for VsockListener {
+    type Stream = VsockStream;
+
+    fn accept(&self) -> Result<(VsockStream, Peer)> {
+        let mut address = vsock_address(0, 0);
+        let mut len = mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        // This is safe because we pass a valid file descriptor, and the kernel writes at most
+        // `len` bytes into `address`.
+        let fd = unsafe {
+            libc::accept4(
+                self.socket.as_raw_fd(),
+                &mut address as *mut libc::sockaddr_vm as *mut libc::sockaddr,
+                &mut len,
+                libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // This is safe because we own the newly accepted file descriptor.
+        let stream = VsockStream {
+            socket: unsafe { File::from_raw_fd(fd) },
+        };
+        Ok((
+            stream,
+            Peer::Vsock {
+                cid: address.svm_cid,
+                port: address.svm_port,
+            },
+        ))
+    }
+}
+
+/// A connection accepted by a `VsockListener`.
+pub struct VsockStream {
+    socket: File,
+}
+
+impl Read for VsockStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl Write for VsockStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for VsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+fn vsock_address(cid: u32, port: u32) -> libc::sockaddr_vm {
+    // This is safe because `sockaddr_vm` is a plain C structure, which is valid when zeroed.
+    let mut address: libc::sockaddr_vm = unsafe { mem::zeroed() };
+    address.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    address.svm_cid = cid;
+    address.svm_port = port;
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_listener() {
+        let path_to_socket = "/tmp/test_listener_unix.sock";
+        std::fs::remove_file(path_to_socket).unwrap_or_default();
+        let listener = UnixListener::bind(path_to_socket).unwrap();
+
+        let _client = UnixStream::connect(path_to_socket).unwrap();
+        let (mut stream, peer) = Listener::accept(&listener).unwrap();
+        // The process on the other end is this one.
+        // This is safe because these calls have no side effects.
+        let expected_peer = unsafe {
+            Peer::Unix(PeerCredentials {
+                pid: libc::getpid(),
+                uid: libc::getuid(),
+                gid: libc::getgid(),
+            })
+        };
+        assert_eq!(peer, expected_peer);
+        // The stream is non-blocking.
+        assert_eq!(
+            stream.read(&mut [0u8; 1]).unwrap_err().kind(),
+            std::io::ErrorKind::WouldBlock
+        );
+        std::fs::remove_file(path_to_socket).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let (mut stream, peer) = Listener::accept(&listener).unwrap();
+        assert_eq!(peer, Peer::Tcp(client.local_addr().unwrap()));
+        assert_eq!(
+            stream.read(&mut [0u8; 1]).unwrap_err().kind(),
+            std::io::ErrorKind::WouldBlock
+        );
+    }
+}
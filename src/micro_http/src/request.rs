@@ -49,15 +49,16 @@ impl Uri {
     /// "http:" "//" host [ ":" port ] [ abs_path ]
     /// The relative URIs can be one of net_path | abs_path | rel_path.
     /// This method only handles absolute URIs and relative URIs specified by abs_path.
-    /// The abs_path is expected to start with '/'.
+    /// The abs_path is expected to start with '/'. The query, if any, is not part of it.
     ///
     /// # Errors
     /// Returns an empty byte array when the host or the path are empty/invalid.
     pub fn get_abs_path(&self) -> &str {
         const HTTP_SCHEME_PREFIX: &str = "http://";
 
-        if self.string.starts_with(HTTP_SCHEME_PREFIX) {
-            let without_scheme = &self.string[HTTP_SCHEME_PREFIX.len()..];
+        let without_query = self.without_query();
+        if without_query.starts_with(HTTP_SCHEME_PREFIX) {
+            let without_scheme = &without_query[HTTP_SCHEME_PREFIX.len()..];
             if without_scheme.is_empty() {
                 return "";
             }
@@ -68,13 +69,27 @@ impl Uri {
                 None => "",
             }
         } else {
-            if self.string.starts_with('/') {
-                return self.string.as_str();
+            if without_query.starts_with('/') {
+                return without_query;
             }
 
             ""
         }
     }
+
+    /// Returns the query of the `Uri`, which follows the first '?', or `None` if there is none.
+    pub fn get_query(&self) -> Option<&str> {
+        self.string
+            .find('?')
+            .map(|position| &self.string[position + 1..])
+    }
+
+    fn without_query(&self) -> &str {
+        match self.string.find('?') {
+            Some(position) => &self.string[..position],
+            None => self.string.as_str(),
+        }
+    }
 }
 
 /// Wrapper over an HTTP Request Line.
@@ -251,6 +266,15 @@ impl Request {
     pub fn method(&self) -> Method {
         self.request_line.method
     }
+
+    /// Returns `true` if the connection should stay open after the response to this
+    /// `Request` is sent. Unless the client says otherwise through the `Connection` header,
+    /// HTTP/1.1 connections are persistent and HTTP/1.0 ones are not.
+    pub fn keep_alive(&self) -> bool {
+        self.headers
+            .keep_alive()
+            .unwrap_or(self.http_version() == Version::Http11)
+    }
 }
 
 #[cfg(test)]
@@ -287,11 +311,26 @@ mod tests {
             ("home", ""),
             ("http://", ""),
             ("http://192.168.0.0", ""),
+            ("http://localhost/home?wait=true", "/home"),
+            ("/home/sub?a=b&c=d", "/home/sub"),
+            ("http://localhost?a=b", ""),
         ] {
             assert_eq!(Uri::new(tc.0).get_abs_path(), tc.1);
         }
     }
 
+    #[test]
+    fn test_uri_query() {
+        for tc in &vec![
+            ("http://localhost/home", None),
+            ("http://localhost/home?wait=true", Some("wait=true")),
+            ("/home/sub?a=b&c=d", Some("a=b&c=d")),
+            ("/home?", Some("")),
+        ] {
+            assert_eq!(Uri::new(tc.0).get_query(), tc.1);
+        }
+    }
+
     #[test]
     fn test_find() {
         let bytes: &[u8; 13] = b"abcacrgbabsjl";
@@ -462,4 +501,27 @@ mod tests {
         assert_eq!(request.headers.content_length(), 0);
         assert!(request.body.is_none());
     }
+
+    #[test]
+    fn test_keep_alive() {
+        // HTTP/1.1 connections are persistent by default.
+        let request = Request::try_from(b"GET http://localhost/ HTTP/1.1\r\n\r\n").unwrap();
+        assert!(request.keep_alive());
+        let request = Request::try_from(
+            b"GET http://localhost/ HTTP/1.1\r\n\
+                                     Connection: close\r\n\r\n",
+        )
+        .unwrap();
+        assert!(!request.keep_alive());
+
+        // HTTP/1.0 connections are not, unless the client asks for it.
+        let request = Request::try_from(b"GET http://localhost/ HTTP/1.0\r\n\r\n").unwrap();
+        assert!(!request.keep_alive());
+        let request = Request::try_from(
+            b"GET http://localhost/ HTTP/1.0\r\n\
+                                     Connection: keep-alive\r\n\r\n",
+        )
+        .unwrap();
+        assert!(request.keep_alive());
+    }
 }
@@ -22,8 +22,18 @@ pub enum StatusCode {
     NoContent,
     /// 400, Bad Request
     BadRequest,
+    /// 401, Unauthorized
+    Unauthorized,
+    /// 403, Forbidden
+    Forbidden,
     /// 404, Not Found
     NotFound,
+    /// 405, Method Not Allowed
+    MethodNotAllowed,
+    /// 406, Not Acceptable
+    NotAcceptable,
+    /// 413, Payload Too Large
+    PayloadTooLarge,
     /// 500, Internal Server Error
     InternalServerError,
     /// 501, Not Implemented
@@ -38,7 +48,12 @@ impl StatusCode {
             Self::OK => b"200",
             Self::NoContent => b"204",
             Self::BadRequest => b"400",
+            Self::Unauthorized => b"401",
+            Self::Forbidden => b"403",
             Self::NotFound => b"404",
+            Self::MethodNotAllowed => b"405",
+            Self::NotAcceptable => b"406",
+            Self::PayloadTooLarge => b"413",
             Self::InternalServerError => b"500",
             Self::NotImplemented => b"501",
         }
@@ -75,6 +90,9 @@ pub struct ResponseHeaders {
     content_length: i32,
     content_type: MediaType,
     server: String,
+    etag: Option<String>,
+    keep_alive: bool,
+    streaming: bool,
 }
 
 impl Default for ResponseHeaders {
@@ -83,6 +101,9 @@ impl Default for ResponseHeaders {
             content_length: Default::default(),
             content_type: Default::default(),
             server: String::from("Firecracker API"),
+            etag: None,
+            keep_alive: true,
+            streaming: false,
         }
     }
 }
@@ -95,10 +116,29 @@ impl ResponseHeaders {
         buf.write_all(self.server.as_bytes())?;
 
         buf.write_all(&[CR, LF])?;
-        buf.write_all(b"Connection: keep-alive")?;
+        buf.write_all(Header::Connection.raw())?;
+        buf.write_all(&[COLON, SP])?;
+        if self.keep_alive {
+            buf.write_all(b"keep-alive")?;
+        } else {
+            buf.write_all(b"close")?;
+        }
         buf.write_all(&[CR, LF])?;
 
-        if self.content_length != 0 {
+        if let Some(ref etag) = self.etag {
+            buf.write_all(Header::ETag.raw())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(etag.as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        }
+
+        if self.streaming {
+            // The body of a stream ends when the connection is closed, so it has no length.
+            buf.write_all(Header::ContentType.raw())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(self.content_type.as_str().as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        } else if self.content_length != 0 {
             buf.write_all(Header::ContentType.raw())?;
             buf.write_all(&[COLON, SP])?;
             buf.write_all(self.content_type.as_str().as_bytes())?;
@@ -128,14 +168,25 @@ impl ResponseHeaders {
     pub fn set_content_type(&mut self, content_type: MediaType) {
         self.content_type = content_type;
     }
+
+    /// Sets the HTTP response header ETag.
+    pub fn set_etag(&mut self, etag: &str) {
+        self.etag = Some(String::from(etag));
+    }
+
+    /// Sets whether the connection stays open after the HTTP response is sent.
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive = keep_alive;
+    }
 }
 
 /// Wrapper over an HTTP Response.
 ///
 /// The Response is created using a `Version` and a `StatusCode`. When creating a Response object,
 /// the body is initialized to `None` and the header is initialized with the `default` value. The body
-/// can be updated with a call to `set_body`. The header can be updated with `set_content_type` and
-/// `set_server`.
+/// can be updated with a call to `set_body`. The header can be updated with `set_content_type`,
+/// `set_server`, `set_etag` and `set_keep_alive`. A response made with `set_streaming` has no
+/// body of its own, but starts a stream of data sent by the `HttpServer` later on.
 pub struct Response {
     status_line: StatusLine,
     headers: ResponseHeaders,
@@ -171,6 +222,27 @@ impl Response {
         self.headers.set_server(server);
     }
 
+    /// Sets the entity tag of the `Response`, identifying the version of the resource it holds.
+    pub fn set_etag(&mut self, etag: &str) {
+        self.headers.set_etag(etag);
+    }
+
+    /// Sets whether the connection stays open after the `Response` is sent. This is
+    /// announced to the client through the `Connection` header.
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.headers.set_keep_alive(keep_alive);
+    }
+
+    /// Turns the `Response` into the start of a stream. It is sent without a body and
+    /// without a `Content-Length`, after which the connection stays open and carries the
+    /// data passed to `HttpServer::send_to_streams`, until either end closes it.
+    pub fn set_streaming(&mut self) {
+        self.headers.streaming = true;
+        self.headers.set_keep_alive(false);
+        self.headers.set_content_length(0);
+        self.body = None;
+    }
+
     fn write_body<T: Write>(&self, mut buf: T) -> Result<(), WriteError> {
         if let Some(ref body) = self.body {
             buf.write_all(body.raw())?;
@@ -211,6 +283,21 @@ impl Response {
         self.headers.content_type
     }
 
+    /// Returns the ETag of the response, if set.
+    pub fn etag(&self) -> Option<&str> {
+        self.headers.etag.as_ref().map(String::as_str)
+    }
+
+    /// Returns `true` if the connection stays open after the response is sent.
+    pub fn keep_alive(&self) -> bool {
+        self.headers.keep_alive
+    }
+
+    /// Returns `true` if the response starts a stream.
+    pub fn is_streaming(&self) -> bool {
+        self.headers.streaming
+    }
+
     /// Returns the HTTP Version of the response.
     pub fn http_version(&self) -> Version {
         self.status_line.http_version
@@ -280,13 +367,74 @@ mod tests {
         assert!(response_buf.as_ref() == expected_response.as_bytes());
     }
 
+    #[test]
+    fn test_set_etag() {
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_body(Body::new("test"));
+        response.set_etag("\"1\"");
+
+        let expected_response = "HTTP/1.1 200 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: keep-alive\r\n\
+            ETag: \"1\"\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: 4\r\n\r\n\
+            test";
+
+        let mut response_buf = Vec::new();
+        assert!(response.write_all(&mut response_buf).is_ok());
+        assert_eq!(response_buf, expected_response.as_bytes());
+    }
+
+    #[test]
+    fn test_set_keep_alive() {
+        let mut response = Response::new(Version::Http11, StatusCode::NoContent);
+        assert!(response.keep_alive());
+        response.set_keep_alive(false);
+        assert!(!response.keep_alive());
+
+        let expected_response = "HTTP/1.1 204 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: close\r\n\r\n";
+
+        let mut response_buf = Vec::new();
+        assert!(response.write_all(&mut response_buf).is_ok());
+        assert_eq!(response_buf, expected_response.as_bytes());
+    }
+
+    #[test]
+    fn test_set_streaming() {
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_body(Body::new("test"));
+        response.set_content_type(MediaType::EventStream);
+        assert!(!response.is_streaming());
+        response.set_streaming();
+        assert!(response.is_streaming());
+        assert!(!response.keep_alive());
+        assert!(response.body().is_none());
+
+        let expected_response = "HTTP/1.1 200 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: close\r\n\
+            Content-Type: text/event-stream\r\n\r\n";
+
+        let mut response_buf = Vec::new();
+        assert!(response.write_all(&mut response_buf).is_ok());
+        assert_eq!(response_buf, expected_response.as_bytes());
+    }
+
     #[test]
     fn test_status_code() {
         assert_eq!(StatusCode::Continue.raw(), b"100");
         assert_eq!(StatusCode::OK.raw(), b"200");
         assert_eq!(StatusCode::NoContent.raw(), b"204");
         assert_eq!(StatusCode::BadRequest.raw(), b"400");
+        assert_eq!(StatusCode::Unauthorized.raw(), b"401");
+        assert_eq!(StatusCode::Forbidden.raw(), b"403");
         assert_eq!(StatusCode::NotFound.raw(), b"404");
+        assert_eq!(StatusCode::MethodNotAllowed.raw(), b"405");
+        assert_eq!(StatusCode::NotAcceptable.raw(), b"406");
+        assert_eq!(StatusCode::PayloadTooLarge.raw(), b"413");
         assert_eq!(StatusCode::InternalServerError.raw(), b"500");
         assert_eq!(StatusCode::NotImplemented.raw(), b"501");
     }
@@ -1,15 +1,18 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
 use std::os::unix::io::RawFd;
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::net::UnixListener;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use common::{Body, Version};
 pub use common::{ConnectionError, RequestError, ServerError};
 use connection::HttpConnection;
+use listener::{Listener, Peer};
 use request::Request;
 use response::{Response, StatusCode};
 use std::collections::HashMap;
@@ -21,6 +24,8 @@ static SERVER_FULL_ERROR_MESSAGE: &[u8] = b"HTTP/1.1 503\r\n\
                                             Connection: close\r\n\
                                             Content-Length: 40\r\n\r\n{ \"error\": \"Too many open connections\" }";
 const MAX_CONNECTIONS: usize = 10;
+/// The time after which a connection with no requests in progress is closed by the server.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
 
 type Result<T> = std::result::Result<T, ServerError>;
 
@@ -30,13 +35,23 @@ pub struct ServerRequest {
     pub request: Request,
     /// Identification token.
     id: u64,
+    /// The position of the request among the ones received on its connection.
+    sequence: u64,
+    /// The client which sent the request.
+    peer: Peer,
 }
 
 impl ServerRequest {
     /// Creates a new `ServerRequest` object from an existing `Request`,
-    /// adding an identification token.
-    pub fn new(request: Request, id: u64) -> Self {
-        Self { request, id }
+    /// adding an identification token, its position on the connection and
+    /// the client which sent it.
+    pub fn new(request: Request, id: u64, sequence: u64, peer: Peer) -> Self {
+        Self {
+            request,
+            id,
+            sequence,
+            peer,
+        }
     }
 
     /// Returns a reference to the inner request.
@@ -44,6 +59,11 @@ impl ServerRequest {
         &self.request
     }
 
+    /// Returns the client which sent the request.
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
     /// Calls the function provided on the inner request to obtain the response.
     /// The response is then wrapped in a `ServerResponse`.
     ///
@@ -53,7 +73,7 @@ impl ServerRequest {
         F: Fn(&Request) -> Response,
     {
         let http_response = callable(self.inner());
-        ServerResponse::new(http_response, self.id)
+        ServerResponse::new(http_response, self.id, self.sequence)
     }
 }
 
@@ -63,11 +83,17 @@ pub struct ServerResponse {
     response: Response,
     /// Identification token.
     id: u64,
+    /// The position of the request this is a response to.
+    sequence: u64,
 }
 
 impl ServerResponse {
-    fn new(response: Response, id: u64) -> Self {
-        Self { response, id }
+    fn new(response: Response, id: u64, sequence: u64) -> Self {
+        Self {
+            response,
+            id,
+            sequence,
+        }
     }
 }
 
@@ -91,21 +117,56 @@ struct ClientConnection<T> {
     /// absorbed responses.
     /// This has to be `0` if we want to drop the connection.
     in_flight_response_count: u32,
+    /// The sequence number of the next request received on this connection.
+    next_request_sequence: u64,
+    /// The sequence number of the next response to be written, as responses
+    /// have to be sent in the order the requests came in.
+    next_response_sequence: u64,
+    /// Responses which have to wait for the ones to earlier requests.
+    pending_responses: BTreeMap<u64, Response>,
+    /// Set when no more requests are accepted on this connection, which is
+    /// then closed once all the responses are written.
+    closing: bool,
+    /// The last time data was exchanged on this connection.
+    last_activity: Instant,
+    /// The client at the other end of the connection.
+    peer: Peer,
+    /// Set once a response starting a stream was handed to the connection, after
+    /// which it carries the data sent through `HttpServer::send_to_streams`.
+    streaming: bool,
 }
 
 impl<T: Read + Write> ClientConnection<T> {
-    fn new(connection: HttpConnection<T>) -> Self {
+    fn new(connection: HttpConnection<T>, peer: Peer) -> Self {
         Self {
+            peer,
             connection,
             state: ClientConnectionState::AwaitingIncoming,
             in_flight_response_count: 0,
+            next_request_sequence: 0,
+            next_response_sequence: 0,
+            pending_responses: BTreeMap::new(),
+            closing: false,
+            last_activity: Instant::now(),
+            streaming: false,
         }
     }
 
-    fn read(&mut self) -> Result<Vec<Request>> {
+    fn read(&mut self, id: u64) -> Result<Vec<ServerRequest>> {
         // Data came into the connection.
+        self.last_activity = Instant::now();
         let mut parsed_requests = vec![];
-        match self.connection.try_read() {
+        let read_result = self.connection.try_read();
+        if self.closing {
+            // The client asked for the connection to be closed, so whatever it sent
+            // afterwards is discarded.
+            while let Some(_discarded_request) = self.connection.pop_parsed_request() {}
+            if let Err(ConnectionError::ConnectionClosed) = read_result {
+                self.state = ClientConnectionState::Closed;
+            }
+            return Ok(vec![]);
+        }
+        match read_result {
             Err(ConnectionError::ConnectionClosed) => {
                 // Connection timeout.
                 self.state = ClientConnectionState::Closed;
@@ -120,7 +181,7 @@ impl<T: Read + Write> ClientConnection<T> {
                 let mut internal_error_response =
                     Response::new(Version::Http11, StatusCode::InternalServerError);
                 internal_error_response.set_body(Body::new(inner.to_string()));
-                self.connection.enqueue_response(internal_error_response);
+                self.enqueue_error_response(internal_error_response);
             }
             Err(ConnectionError::ParseError(inner)) => {
                 // An error occurred while parsing the read bytes.
@@ -136,7 +197,8 @@ impl<T: Read + Write> ClientConnection<T> {
                     )
                     .to_string(),
                 ));
-                self.connection.enqueue_response(error_response);
+                // We cannot tell where the next request starts, so the connection is closed.
+                self.enqueue_error_response(error_response);
             }
             Err(ConnectionError::InvalidWrite) => {
                 // This is unreachable because `HttpConnection::try_read()` cannot return this error variant.
@@ -144,8 +206,19 @@ impl<T: Read + Write> ClientConnection<T> {
             }
             Ok(()) => {
                 while let Some(request) = self.connection.pop_parsed_request() {
-                    // Add all valid requests to `parsed_requests`.
-                    parsed_requests.push(request);
+                    // Add all valid requests to `parsed_requests`, up to the first one
+                    // after which the client wants the connection closed.
+                    self.closing = !request.keep_alive();
+                    parsed_requests.push(ServerRequest::new(
+                        request,
+                        id,
+                        self.next_request_sequence,
+                        self.peer,
+                    ));
+                    self.next_request_sequence += 1;
+                    if self.closing {
+                        while let Some(_discarded_request) = self.connection.pop_parsed_request() {}
+                    }
                 }
             }
         }
@@ -161,6 +234,7 @@ impl<T: Read + Write> ClientConnection<T> {
 
     fn write(&mut self) -> Result<()> {
         // The stream is available for writing.
+        self.last_activity = Instant::now();
         match self.connection.try_write() {
             Err(ConnectionError::ConnectionClosed) | Err(ConnectionError::StreamError(_)) => {
                 // Writing to the stream failed so it will be removed.
@@ -174,20 +248,77 @@ impl<T: Read + Write> ClientConnection<T> {
             _ => {
                 // Check if we still have bytes to write for this connection.
                 if !self.connection.pending_write() {
-                    self.state = ClientConnectionState::AwaitingIncoming;
+                    if self.closing
+                        && self.next_response_sequence == self.next_request_sequence
+                        && !self.streaming
+                    {
+                        // The last response was written, so the connection can be dropped.
+                        self.state = ClientConnectionState::Closed;
+                    } else {
+                        self.state = ClientConnectionState::AwaitingIncoming;
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    fn enqueue_response(&mut self, response: Response) {
+    fn enqueue_response(&mut self, sequence: u64, response: Response) {
         if self.state != ClientConnectionState::Closed {
-            self.connection.enqueue_response(response);
+            self.pending_responses.insert(sequence, response);
+            self.flush_responses();
         }
         self.in_flight_response_count -= 1;
     }
 
+    // Answers the client with an error generated by the server, after which the
+    // connection is closed.
+    fn enqueue_error_response(&mut self, response: Response) {
+        self.closing = true;
+        self.pending_responses
+            .insert(self.next_request_sequence, response);
+        self.next_request_sequence += 1;
+        self.flush_responses();
+    }
+
+    // Moves the responses which are next in line to the outgoing queue of the connection.
+    fn flush_responses(&mut self) {
+        while let Some(mut response) = self.pending_responses.remove(&self.next_response_sequence) {
+            self.next_response_sequence += 1;
+            if response.is_streaming() {
+                // Nothing but the stream is sent on this connection from now on.
+                self.streaming = true;
+            }
+            if !response.keep_alive() {
+                self.closing = true;
+            }
+            if self.closing && self.next_response_sequence == self.next_request_sequence {
+                // Let the client know this is the last response on the connection.
+                response.set_keep_alive(false);
+            }
+            self.connection.enqueue_response(response);
+        }
+    }
+
+    // Appends `data` to the stream carried by the connection.
+    fn enqueue_stream_data(&mut self, data: &[u8]) {
+        if self.streaming && self.state != ClientConnectionState::Closed {
+            self.last_activity = Instant::now();
+            self.connection.enqueue_stream_data(data);
+        }
+    }
+
+    // Returns `true` if no request is in progress on the connection and it has been
+    // inactive for at least `timeout`. Streams are never idle, as they only end when
+    // the client closes them.
+    fn is_idle(&self, now: Instant, timeout: Duration) -> bool {
+        !self.streaming
+            && self.state == ClientConnectionState::AwaitingIncoming
+            && self.in_flight_response_count == 0
+            && !self.connection.pending_write()
+            && now.duration_since(self.last_activity) >= timeout
+    }
+
     // Returns `true` if the connection is closed and safe to drop.
     fn is_done(&self) -> bool {
         self.state == ClientConnectionState::Closed
@@ -196,8 +327,10 @@ impl<T: Read + Write> ClientConnection<T> {
     }
 }
 
-/// HTTP Server implementation using Unix Domain Sockets and `EPOLL` to
-/// handle multiple connections on the same thread.
+/// HTTP Server implementation using `EPOLL` to handle multiple connections
+/// on the same thread. The server listens on a Unix domain socket by default,
+/// and on any other `Listener`, like a TCP or a vsock one, when created with
+/// `with_listener`.
 ///
 /// The function that handles incoming connections, parses incoming
 /// requests and sends responses for awaiting requests is `requests`.
@@ -239,9 +372,9 @@ impl<T: Read + Write> ClientConnection<T> {
 ///     break;
 /// }
 /// ```
-pub struct HttpServer {
+pub struct HttpServer<L: Listener = UnixListener> {
     /// Socket on which we listen for new connections.
-    socket: UnixListener,
+    socket: L,
     /// Server's epoll instance.
     epoll: epoll::Epoll,
     /// Holds the token-connection pairs of the server.
@@ -249,26 +382,46 @@ pub struct HttpServer {
     /// the file descriptor of the underlying stream.
     /// We use the file descriptor of the stream as the key for mapping
     /// connections because the 1-to-1 relation is guaranteed by the OS.
-    connections: HashMap<RawFd, ClientConnection<UnixStream>>,
+    connections: HashMap<RawFd, ClientConnection<L::Stream>>,
+    /// The time after which connections with no requests in progress are closed.
+    idle_timeout: Duration,
 }
 
 impl HttpServer {
     /// Constructor for `HttpServer`.
     ///
-    /// Returns the newly formed `HttpServer`.
+    /// Returns the newly formed `HttpServer`, listening on a Unix domain socket
+    /// bound to `path_to_socket`.
     ///
     /// # Errors
     /// Returns an `IOError` when binding or `epoll::create` fails.
     pub fn new<P: AsRef<Path>>(path_to_socket: P) -> Result<Self> {
         let socket = UnixListener::bind(path_to_socket).map_err(ServerError::IOError)?;
+        Self::with_listener(socket)
+    }
+}
+
+impl<L: Listener> HttpServer<L> {
+    /// Creates an `HttpServer` which accepts connections on `socket`.
+    ///
+    /// # Errors
+    /// Returns an `IOError` when `epoll::create` fails.
+    pub fn with_listener(socket: L) -> Result<Self> {
         let epoll = epoll::Epoll::new().map_err(ServerError::IOError)?;
         Ok(Self {
             socket,
             epoll,
             connections: HashMap::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
         })
     }
 
+    /// Sets the time after which connections with no requests in progress are closed.
+    /// Defaults to `DEFAULT_IDLE_TIMEOUT`.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
     /// Starts the HTTP Server.
     pub fn start_server(&mut self) -> Result<()> {
         // Add the socket on which we listen for new connections to the
@@ -282,7 +435,7 @@ impl HttpServer {
     /// on this thread and we can afford to call it in a loop.
     ///
     /// Note that this function will block the current thread if there are no notifications
-    /// to be handled by the server.
+    /// to be handled by the server, at most until the next idle connection times out.
     ///
     /// Returns a collection of complete and valid requests to be processed by the user
     /// of the server. Once processed, responses should be sent using `enqueue_responses()`.
@@ -297,10 +450,12 @@ impl HttpServer {
         let mut parsed_requests: Vec<ServerRequest> = vec![];
         let mut events = vec![epoll::EpollEvent::default(); MAX_CONNECTIONS];
         // This is a wrapper over the syscall `epoll_wait` and it will block the
-        // current thread until at least one event is received.
+        // current thread until at least one event is received, or until the
+        // first idle connection times out.
         // The received notifications will then populate the `events` array with
-        // `event_count` elements, where 1 <= event_count <= MAX_CONNECTIONS.
-        let event_count = match self.epoll.wait(MAX_CONNECTIONS, -1, &mut events[..]) {
+        // `event_count` elements, where 0 <= event_count <= MAX_CONNECTIONS.
+        let timeout = self.epoll_timeout();
+        let event_count = match self.epoll.wait(MAX_CONNECTIONS, timeout, &mut events[..]) {
             Ok(event_count) => event_count,
             Err(e) if e.raw_os_error() == Some(libc::EINTR) => 0,
             Err(e) => return Err(ServerError::IOError(e)),
@@ -324,7 +479,7 @@ impl HttpServer {
                         self.socket
                             .accept()
                             .map_err(ServerError::IOError)
-                            .and_then(move |(mut stream, _)| {
+                            .and_then(move |(mut stream, _peer)| {
                                 stream
                                     .write(SERVER_FULL_ERROR_MESSAGE)
                                     .map_err(ServerError::IOError)
@@ -342,13 +497,7 @@ impl HttpServer {
                     // We have bytes to read from this connection.
                     // If our `read` yields `Request` objects, we wrap them with an ID before
                     // handing them to the user.
-                    parsed_requests.append(
-                        &mut client_connection
-                            .read()?
-                            .into_iter()
-                            .map(|request| ServerRequest::new(request, e.data()))
-                            .collect(),
-                    );
+                    parsed_requests.append(&mut client_connection.read(e.data())?);
                     // If the connection was incoming before we read and we now have to write
                     // either an error message or an `expect` response, we change its `epoll`
                     // event set to notify us when the stream is ready for writing.
@@ -368,6 +517,15 @@ impl HttpServer {
             }
         }
 
+        // Close the connections which have been idle for too long.
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout;
+        for client_connection in self.connections.values_mut() {
+            if client_connection.is_idle(now, idle_timeout) {
+                client_connection.state = ClientConnectionState::Closed;
+            }
+        }
+
         // Remove dead connections.
         self.connections
             .retain(|_, client_connection| !client_connection.is_done());
@@ -455,17 +613,65 @@ impl HttpServer {
     /// `IOError` is returned when an `epoll::ctl` operation fails.
     pub fn respond(&mut self, response: ServerResponse) -> Result<()> {
         if let Some(client_connection) = self.connections.get_mut(&(response.id as i32)) {
+            client_connection.enqueue_response(response.sequence, response.response);
             // If the connection was incoming before we enqueue the response, we change its
-            // `epoll` event set to notify us when the stream is ready for writing.
-            if let ClientConnectionState::AwaitingIncoming = client_connection.state {
+            // `epoll` event set to notify us when the stream is ready for writing. Responses
+            // to pipelined requests may have to wait for the ones to earlier requests.
+            if client_connection.state == ClientConnectionState::AwaitingIncoming
+                && client_connection.connection.pending_write()
+            {
                 client_connection.state = ClientConnectionState::AwaitingOutgoing;
                 Self::epoll_mod(&self.epoll, response.id as RawFd, epoll::EventSet::OUT)?;
             }
-            client_connection.enqueue_response(response.response);
         }
         Ok(())
     }
 
+    /// Sends `data` on all the streams started by the responses of the server, which
+    /// stay open until their clients close them.
+    ///
+    /// # Errors
+    /// `IOError` is returned when an `epoll::ctl` operation fails.
+    pub fn send_to_streams(&mut self, data: &[u8]) -> Result<()> {
+        for (fd, client_connection) in self.connections.iter_mut() {
+            client_connection.enqueue_stream_data(data);
+            if client_connection.state == ClientConnectionState::AwaitingIncoming
+                && client_connection.connection.pending_write()
+            {
+                // Try to send the data right away, so it reaches the client even if the
+                // process is about to exit.
+                client_connection.write()?;
+                if client_connection.state == ClientConnectionState::AwaitingIncoming
+                    && client_connection.connection.pending_write()
+                {
+                    client_connection.state = ClientConnectionState::AwaitingOutgoing;
+                    Self::epoll_mod(&self.epoll, *fd, epoll::EventSet::OUT)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the timeout, in milliseconds, of the `epoll_wait` call in `requests`, which
+    /// is the time until the first idle connection times out, or `-1` if there is none.
+    /// A server polled through another `epoll` structure should wait for at most as long,
+    /// then call `requests` for idle connections to be closed.
+    pub fn epoll_timeout(&self) -> i32 {
+        let now = Instant::now();
+        self.connections
+            .values()
+            .filter(|client_connection| client_connection.is_idle(now, Duration::from_secs(0)))
+            .map(|client_connection| {
+                let idle_time = now.duration_since(client_connection.last_activity);
+                let remaining = self.idle_timeout.checked_sub(idle_time).unwrap_or_default();
+                // Round up, so we do not wake up right before the deadline.
+                let millis = (remaining.as_micros() + 999) / 1000;
+                std::cmp::min(millis, i32::max_value() as u128) as i32
+            })
+            .min()
+            .unwrap_or(-1)
+    }
+
     /// Accepts a new incoming connection and adds it to the `epoll` notification structure.
     ///
     /// # Errors
@@ -478,23 +684,18 @@ impl HttpServer {
             return Err(ServerError::ServerFull);
         }
 
+        // `HttpConnection` is supposed to work with non-blocking streams, which is what
+        // listeners hand out.
         self.socket
             .accept()
             .map_err(ServerError::IOError)
-            .and_then(|(stream, _)| {
-                // `HttpConnection` is supposed to work with non-blocking streams.
-                stream
-                    .set_nonblocking(true)
-                    .map(|_| stream)
-                    .map_err(ServerError::IOError)
-            })
-            .and_then(|stream| {
+            .and_then(|(stream, peer)| {
                 // Add the stream to the `epoll` structure and listen for bytes to be read.
                 Self::epoll_add(&self.epoll, stream.as_raw_fd())?;
                 // Then add it to our open connections.
                 self.connections.insert(
                     stream.as_raw_fd(),
-                    ClientConnection::new(HttpConnection::new(stream)),
+                    ClientConnection::new(HttpConnection::new(stream), peer),
                 );
                 Ok(())
             })
@@ -534,6 +735,7 @@ mod tests {
     use std::io::{Read, Write};
     use std::os::unix::net::UnixStream;
 
+    use common::headers::MediaType;
     use common::Body;
 
     #[test]
@@ -558,6 +760,11 @@ mod tests {
 
         let mut req_vec = server.requests().unwrap();
         let server_request = req_vec.remove(0);
+        match server_request.peer() {
+            // This is safe because `getuid` has no side effects.
+            Peer::Unix(credentials) => assert_eq!(credentials.uid, unsafe { libc::getuid() }),
+            peer => panic!("Unexpected peer: {:?}", peer),
+        }
 
         server
             .respond(server_request.process(|_request| {
@@ -745,11 +952,11 @@ mod tests {
 
         assert!(server.requests().unwrap().is_empty());
         assert!(server.requests().unwrap().is_empty());
-        let mut buf: [u8; 198] = [0; 198];
+        let mut buf: [u8; 193] = [0; 193];
         assert!(socket.read(&mut buf[..]).unwrap() > 0);
         let error_message = b"HTTP/1.1 400 \r\n\
                               Server: Firecracker API\r\n\
-                              Connection: keep-alive\r\n\
+                              Connection: close\r\n\
                               Content-Type: application/json\r\n\
                               Content-Length: 80\r\n\r\n{ \"error\": \"Invalid header.\n\
                               All previous unanswered requests will be dropped.\" }";
@@ -839,4 +1046,243 @@ mod tests {
         assert!(server.requests().is_ok());
         fs::remove_file(path_to_socket).unwrap();
     }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let path_to_socket = "/tmp/test_socket_http_server7.sock";
+        fs::remove_file(path_to_socket).unwrap_or_default();
+
+        let mut server = HttpServer::new(path_to_socket.to_string()).unwrap();
+        server.start_server().unwrap();
+
+        let mut socket = UnixStream::connect(path_to_socket).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        // Three requests in a single write, the second one with a chunked body.
+        socket
+            .write_all(
+                b"GET /first HTTP/1.1\r\n\r\n\
+                  PUT /second HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n\
+                  4\r\nbody\r\n0\r\n\r\n\
+                  GET /third HTTP/1.1\r\n\r\n",
+            )
+            .unwrap();
+        let mut req_vec = server.requests().unwrap();
+        assert_eq!(req_vec.len(), 3);
+        assert_eq!(req_vec[0].request.uri().get_abs_path(), "/first");
+        assert_eq!(req_vec[1].request.uri().get_abs_path(), "/second");
+        assert_eq!(req_vec[1].request.body.as_ref().unwrap().raw(), b"body");
+        assert_eq!(req_vec[2].request.uri().get_abs_path(), "/third");
+
+        // Answer the requests out of order.
+        let respond_with_path = |request: &Request| {
+            let mut response = Response::new(Version::Http11, StatusCode::OK);
+            response.set_body(Body::new(request.uri().get_abs_path()));
+            response
+        };
+        let third = req_vec.pop().unwrap();
+        let second = req_vec.pop().unwrap();
+        let first = req_vec.pop().unwrap();
+        server.respond(third.process(respond_with_path)).unwrap();
+        server.respond(second.process(respond_with_path)).unwrap();
+        // Nothing can be sent before the first response is available.
+        assert!(!server
+            .connections
+            .values()
+            .next()
+            .unwrap()
+            .connection
+            .pending_write());
+        server.respond(first.process(respond_with_path)).unwrap();
+        // Each `EPOLLOUT` notification is used to write one response.
+        for _ in 0..3 {
+            assert!(server.requests().unwrap().is_empty());
+        }
+
+        let mut expected_responses = vec![];
+        for path in ["/first", "/second", "/third"].iter() {
+            let mut response = Response::new(Version::Http11, StatusCode::OK);
+            response.set_body(Body::new(*path));
+            response.write_all(&mut expected_responses).unwrap();
+        }
+        let mut buf = vec![0u8; expected_responses.len()];
+        socket.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected_responses);
+        fs::remove_file(path_to_socket).unwrap();
+    }
+
+    #[test]
+    fn test_connection_close() {
+        let path_to_socket = "/tmp/test_socket_http_server8.sock";
+        fs::remove_file(path_to_socket).unwrap_or_default();
+
+        let mut server = HttpServer::new(path_to_socket.to_string()).unwrap();
+        server.start_server().unwrap();
+
+        let mut socket = UnixStream::connect(path_to_socket).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        // The request after the one asking for the connection to be closed is ignored.
+        socket
+            .write_all(
+                b"GET /machine-config HTTP/1.1\r\n\
+                  Connection: close\r\n\r\n\
+                  GET /machine-config HTTP/1.1\r\n\r\n",
+            )
+            .unwrap();
+        let mut req_vec = server.requests().unwrap();
+        assert_eq!(req_vec.len(), 1);
+        server
+            .respond(
+                req_vec
+                    .remove(0)
+                    .process(|_request| Response::new(Version::Http11, StatusCode::NoContent)),
+            )
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        assert!(server.connections.is_empty());
+
+        let mut response = Response::new(Version::Http11, StatusCode::NoContent);
+        response.set_keep_alive(false);
+        let mut expected_response = vec![];
+        response.write_all(&mut expected_response).unwrap();
+        let mut buf = vec![];
+        // The server closes the connection after the response.
+        socket.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, expected_response);
+        fs::remove_file(path_to_socket).unwrap();
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let path_to_socket = "/tmp/test_socket_http_server9.sock";
+        fs::remove_file(path_to_socket).unwrap_or_default();
+
+        let mut server = HttpServer::new(path_to_socket.to_string()).unwrap();
+        server.set_idle_timeout(Duration::from_millis(100));
+        server.start_server().unwrap();
+
+        let mut socket = UnixStream::connect(path_to_socket).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        assert_eq!(server.connections.len(), 1);
+
+        // A connection waiting for a response is not idle.
+        socket
+            .write_all(b"GET /machine-config HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut req_vec = server.requests().unwrap();
+        assert_eq!(server.epoll_timeout(), -1);
+        std::thread::sleep(Duration::from_millis(150));
+        server
+            .respond(
+                req_vec
+                    .remove(0)
+                    .process(|_request| Response::new(Version::Http11, StatusCode::NoContent)),
+            )
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        assert_eq!(server.connections.len(), 1);
+        let mut buf = [0u8; 1024];
+        assert!(socket.read(&mut buf).unwrap() > 0);
+
+        // Once the response is written, the server waits for the next request at most
+        // for the idle timeout, after which it closes the connection.
+        let start = Instant::now();
+        assert!(server.requests().unwrap().is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(server.connections.is_empty());
+        assert_eq!(socket.read(&mut buf).unwrap(), 0);
+        fs::remove_file(path_to_socket).unwrap();
+    }
+
+    #[test]
+    fn test_send_to_streams() {
+        let path_to_socket = "/tmp/test_socket_http_server10.sock";
+        fs::remove_file(path_to_socket).unwrap_or_default();
+
+        let mut server = HttpServer::new(path_to_socket.to_string()).unwrap();
+        server.set_idle_timeout(Duration::from_millis(100));
+        server.start_server().unwrap();
+
+        // One client subscribes to the stream, while the other one only sends a request.
+        let mut subscriber = UnixStream::connect(path_to_socket).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        let mut other_client = UnixStream::connect(path_to_socket).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        subscriber
+            .write_all(b"GET /events HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut req_vec = server.requests().unwrap();
+        server
+            .respond(req_vec.remove(0).process(|_request| {
+                let mut response = Response::new(Version::Http11, StatusCode::OK);
+                response.set_content_type(MediaType::EventStream);
+                response.set_streaming();
+                response
+            }))
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        let mut buf: [u8; 1024] = [0; 1024];
+        let expected_response = b"HTTP/1.1 200 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: close\r\n\
+            Content-Type: text/event-stream\r\n\r\n";
+        assert_eq!(
+            subscriber.read(&mut buf[..]).unwrap(),
+            expected_response.len()
+        );
+        assert_eq!(&buf[..expected_response.len()], &expected_response[..]);
+
+        // The data is written right away, and only to the stream.
+        server.send_to_streams(b"data: {}\n\n").unwrap();
+        assert_eq!(subscriber.read(&mut buf[..]).unwrap(), 10);
+        assert_eq!(&buf[..10], b"data: {}\n\n");
+        other_client.set_nonblocking(true).unwrap();
+        assert!(other_client.read(&mut buf[..]).is_err());
+
+        // Streams do not time out, unlike the other idle connections.
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(server.requests().unwrap().is_empty());
+        assert_eq!(server.connections.len(), 1);
+
+        // The stream ends when its client goes away.
+        drop(subscriber);
+        assert!(server.requests().unwrap().is_empty());
+        assert!(server.connections.is_empty());
+        fs::remove_file(path_to_socket).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_listener() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut server = HttpServer::with_listener(listener).unwrap();
+        server.start_server().unwrap();
+
+        let mut socket = std::net::TcpStream::connect(server.socket.local_addr().unwrap()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        socket
+            .write_all(b"GET /machine-config HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut req_vec = server.requests().unwrap();
+        let server_request = req_vec.remove(0);
+        // The handler can tell who sent the request.
+        assert_eq!(
+            server_request.peer(),
+            &Peer::Tcp(socket.local_addr().unwrap())
+        );
+
+        server
+            .respond(
+                server_request
+                    .process(|_request| Response::new(Version::Http11, StatusCode::NoContent)),
+            )
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        let mut buf: [u8; 1024] = [0; 1024];
+        assert!(socket.read(&mut buf[..]).unwrap() > 0);
+    }
 }
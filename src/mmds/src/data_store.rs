@@ -2,18 +2,59 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::fmt;
+use std::sync::Weak;
 
 use serde_json::Value;
 
+use logger::{Metric, METRICS};
+use token::{Error as TokenError, TokenAuthority};
+use utils::eventfd::EventFd;
+
+/// The default maximum size, in bytes, of the serialized data store.
+pub const DEFAULT_DATA_STORE_LIMIT: usize = 51200;
+
 /// The Mmds is the Microvm Metadata Service represented as an untyped json.
 #[derive(Clone)]
 pub struct Mmds {
     data_store: Value,
+    data_store_limit: usize,
+    // Counts the updates of the data store. The ETag of the data store is derived from it.
+    generation: u64,
+    // Signaled after every update of the data store. Listeners which are gone are dropped.
+    update_listeners: Vec<Weak<EventFd>>,
     is_initialized: bool,
+    version: MmdsVersion,
+    token_authority: TokenAuthority,
+}
+
+/// The MMDS versions, which differ in how guest requests are authorized.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum MmdsVersion {
+    /// Any GET request is served.
+    V1,
+    /// GET requests must carry a session token, obtained with a PUT request to
+    /// `/latest/api/token`.
+    V2,
+}
+
+impl Default for MmdsVersion {
+    fn default() -> Self {
+        MmdsVersion::V1
+    }
+}
+
+impl fmt::Display for MmdsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MmdsVersion::V1 => write!(f, "V1"),
+            MmdsVersion::V2 => write!(f, "V2"),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
+    DataStoreLimitExceeded,
     NotFound,
     UnsupportedValueType,
 }
@@ -21,10 +62,15 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::NotFound => write!(f, "The MMDS resource does not exist."),
-            Error::UnsupportedValueType => {
-                write!(f, "Cannot add non-strings values to the MMDS data-store.")
+            Error::DataStoreLimitExceeded => {
+                write!(f, "The MMDS data store size exceeds the configured limit.")
             }
+            Error::NotFound => write!(f, "The MMDS resource does not exist."),
+            Error::UnsupportedValueType => write!(
+                f,
+                "The MMDS resource cannot be represented in the IMDS format. Request it as \
+                 application/json instead."
+            ),
         }
     }
 }
@@ -33,7 +79,12 @@ impl Default for Mmds {
     fn default() -> Self {
         Mmds {
             data_store: Value::default(),
+            data_store_limit: DEFAULT_DATA_STORE_LIMIT,
+            generation: 0,
+            update_listeners: Vec::new(),
             is_initialized: false,
+            version: MmdsVersion::default(),
+            token_authority: TokenAuthority::default(),
         }
     }
 }
@@ -50,36 +101,85 @@ impl Mmds {
         }
     }
 
-    /// This method validates the data from a PATCH or PUT request and returns
-    /// an UnsupportedValueType error if the data contain any value type other than
-    /// Strings, arrays and dictionaries.
-    pub fn check_data_valid(data: &Value) -> Result<(), Error> {
-        if data.is_string() {
-            Ok(())
-        } else if let Some(map) = data.as_object() {
-            map.values()
-                .try_for_each(|value| Mmds::check_data_valid(value))
-        } else if let Some(array) = data.as_array() {
-            array
-                .iter()
-                .try_for_each(|value| Mmds::check_data_valid(value))
-        } else {
-            Err(Error::UnsupportedValueType)
-        }
+    /// Returns the version of the MMDS.
+    pub fn version(&self) -> MmdsVersion {
+        self.version
     }
 
-    pub fn put_data(&mut self, data: Value) -> Result<(), Error> {
-        Mmds::check_data_valid(&data)?;
+    /// Sets the version of the MMDS.
+    pub fn set_version(&mut self, version: MmdsVersion) {
+        self.version = version;
+    }
+
+    /// Generates a session token valid for `ttl_seconds` seconds.
+    pub fn generate_token(&mut self, ttl_seconds: u32) -> Result<String, TokenError> {
+        self.token_authority.generate_token(ttl_seconds)
+    }
+
+    /// Checks whether `token` is a session token that has not expired yet.
+    pub fn is_valid_token(&self, token: &str) -> bool {
+        self.token_authority.is_valid(token)
+    }
+
+    /// Returns the maximum size, in bytes, of the serialized data store.
+    pub fn data_store_limit(&self) -> usize {
+        self.data_store_limit
+    }
+
+    /// Sets the maximum size, in bytes, of the serialized data store.
+    pub fn set_data_store_limit(&mut self, data_store_limit: usize) {
+        self.data_store_limit = data_store_limit;
+    }
+
+    // Replaces the data store with `data` if its serialized form fits within the limit.
+    fn commit_data(&mut self, data: Value) -> Result<(), Error> {
+        let size = data.to_string().len();
+        if size > self.data_store_limit {
+            METRICS.mmds.data_store_rejected_updates.inc();
+            return Err(Error::DataStoreLimitExceeded);
+        }
+
         self.data_store = data;
         self.is_initialized = true;
+        self.generation = self.generation.wrapping_add(1);
+        METRICS.mmds.data_store_size.store(size);
+        self.notify_update_listeners();
         Ok(())
     }
 
+    fn notify_update_listeners(&mut self) {
+        self.update_listeners
+            .retain(|listener| match listener.upgrade() {
+                Some(evt) => {
+                    // The only possible error is an overflow of the eventfd counter, in which case
+                    // the listener has not consumed previous notifications yet anyway.
+                    let _ = evt.write(1);
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Returns the entity tag of the data store, which changes with every update.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.generation)
+    }
+
+    /// Registers `listener` to be signaled after every update of the data store.
+    pub fn add_update_listener(&mut self, listener: Weak<EventFd>) {
+        self.update_listeners.push(listener);
+    }
+
+    pub fn put_data(&mut self, data: Value) -> Result<(), Error> {
+        self.commit_data(data)
+    }
+
     pub fn patch_data(&mut self, patch_data: Value) -> Result<(), Error> {
-        Mmds::check_data_valid(&patch_data)?;
         self.check_data_store_initialized()?;
-        super::json_patch(&mut self.data_store, &patch_data);
-        Ok(())
+        // The patch is applied on a copy, so that a rejected update leaves the store intact.
+        let mut data = self.data_store.clone();
+        super::json_patch(&mut data, &patch_data);
+        self.commit_data(data)
     }
 
     pub fn get_data_str(&self) -> String {
@@ -89,24 +189,27 @@ impl Mmds {
         self.data_store.to_string()
     }
 
+    // Returns the value found at `path` in the data store.
+    fn get_pointer(&self, path: &str) -> Option<&Value> {
+        // The pointer function splits the input by "/". With a trailing "/", pointer does not
+        // know how to get the object.
+        if path.ends_with('/') {
+            self.data_store.pointer(&path[..(path.len() - 1)])
+        } else {
+            self.data_store.pointer(path)
+        }
+    }
+
     /// This function replicates the behavior of the Instance Metadata Service
     /// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/ec2-instance-metadata.html
     /// 1. For a (key, value) pair where the value is a dictionary, it will return all the keys
     /// in the dictionary.
-    /// 2. For a (key, value) pair where the value is a simple type (bool, string, number),
-    /// it will return the value.
+    /// 2. For a (key, value) pair where the value is a string, it will return the value.
     ///
-    /// When the path is not found, a NotFound error is returned.
+    /// When the path is not found, a NotFound error is returned. Other values cannot be
+    /// represented in this format, and an UnsupportedValueType error is returned instead.
     pub fn get_value(&self, path: String) -> Result<Vec<String>, Error> {
-        // The pointer function splits the input by "/". With a trailing "/", pointer does not
-        // know how to get the object.
-        let value = if path.ends_with('/') {
-            self.data_store.pointer(&path.as_str()[..(path.len() - 1)])
-        } else {
-            self.data_store.pointer(path.as_str())
-        };
-
-        match value {
+        match self.get_pointer(&path) {
             Some(val) => {
                 let mut ret = Vec::new();
                 // If the `dict` is Value::Null, Error::NotFound is thrown.
@@ -143,12 +246,22 @@ impl Mmds {
             None => Err(Error::NotFound),
         }
     }
+
+    /// Returns the JSON representation of the value found at `path`, which can be of any
+    /// type. When the path is not found, a NotFound error is returned.
+    pub fn get_json_value(&self, path: &str) -> Result<String, Error> {
+        match self.get_pointer(path) {
+            Some(Value::Null) | None => Err(Error::NotFound),
+            Some(value) => Ok(value.to_string()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json;
+    use std::sync::Arc;
 
     #[test]
     fn test_mmds() {
@@ -175,6 +288,20 @@ mod tests {
         assert_eq!(mmds.get_data_str(), mmds_json);
     }
 
+    #[test]
+    fn test_mmds_version() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.version(), MmdsVersion::V1);
+
+        mmds.set_version(MmdsVersion::V2);
+        assert_eq!(mmds.version(), MmdsVersion::V2);
+        assert_eq!(mmds.version().to_string(), "V2");
+
+        let token = mmds.generate_token(60).unwrap();
+        assert!(mmds.is_valid_token(&token));
+        assert!(!mmds.is_valid_token("invalid_token"));
+    }
+
     #[test]
     fn test_get_value() {
         let mut mmds = Mmds::default();
@@ -280,6 +407,7 @@ mod tests {
         let data_store: Value = serde_json::from_str(data).unwrap();
         assert!(mmds.patch_data(data_store).is_ok());
 
+        // Values of any type can be stored.
         let data = r#"{
             "name": {
                 "first": "John",
@@ -288,10 +416,7 @@ mod tests {
             "age": 43
         }"#;
         let data_store: Value = serde_json::from_str(data).unwrap();
-        assert_eq!(
-            mmds.put_data(data_store).unwrap_err().to_string(),
-            "Cannot add non-strings values to the MMDS data-store.".to_string()
-        );
+        assert!(mmds.put_data(data_store).is_ok());
 
         let data = r#"{
             "name": {
@@ -301,8 +426,108 @@ mod tests {
             "age": "43"
         }"#;
         let data_store: Value = serde_json::from_str(data).unwrap();
+        assert!(mmds.patch_data(data_store).is_ok());
+    }
+
+    #[test]
+    fn test_data_store_limit() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.data_store_limit(), DEFAULT_DATA_STORE_LIMIT);
+        mmds.set_data_store_limit(20);
+
+        let data: Value = serde_json::from_str(r#"{"name": "John"}"#).unwrap();
+        assert_eq!(data.to_string().len(), 15);
+        assert!(mmds.put_data(data).is_ok());
+
+        let rejected_updates = METRICS.mmds.data_store_rejected_updates.count();
+        let data: Value = serde_json::from_str(r#"{"name": "John Doe Junior"}"#).unwrap();
+        assert_eq!(mmds.put_data(data), Err(Error::DataStoreLimitExceeded));
+        assert!(METRICS.mmds.data_store_rejected_updates.count() > rejected_updates);
+
+        // A rejected patch leaves the data store unchanged.
+        let patch: Value = serde_json::from_str(r#"{"age": "43"}"#).unwrap();
+        assert_eq!(mmds.patch_data(patch), Err(Error::DataStoreLimitExceeded));
+        assert_eq!(mmds.get_data_str(), r#"{"name":"John"}"#);
+
+        // Removing keys can bring the data store back within the limit.
+        let patch: Value = serde_json::from_str(r#"{"name": null, "age": "43"}"#).unwrap();
+        assert!(mmds.patch_data(patch).is_ok());
+        assert_eq!(mmds.get_data_str(), r#"{"age":"43"}"#);
+
+        assert_eq!(
+            Error::DataStoreLimitExceeded.to_string(),
+            "The MMDS data store size exceeds the configured limit."
+        );
+    }
+
+    #[test]
+    fn test_update_listeners() {
+        let mut mmds = Mmds::default();
+        let first_etag = mmds.etag();
+
+        let listener = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        mmds.add_update_listener(Arc::downgrade(&listener));
+        // This one is gone by the time the data store is updated.
+        let dropped_listener = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        mmds.add_update_listener(Arc::downgrade(&dropped_listener));
+        drop(dropped_listener);
+
+        let data: Value = serde_json::from_str(r#"{"name": "John"}"#).unwrap();
+        assert!(mmds.put_data(data).is_ok());
+        assert_eq!(listener.read().unwrap(), 1);
+        assert_eq!(mmds.update_listeners.len(), 1);
+
+        let second_etag = mmds.etag();
+        assert_ne!(first_etag, second_etag);
+
+        // Rejected updates change neither the ETag, nor notify the listeners.
+        let patch: Value = serde_json::from_str(r#"{"age": "43"}"#).unwrap();
+        mmds.set_data_store_limit(0);
+        assert!(mmds.patch_data(patch.clone()).is_err());
+        assert_eq!(mmds.etag(), second_etag);
+        assert!(listener.read().is_err());
+
+        mmds.set_data_store_limit(DEFAULT_DATA_STORE_LIMIT);
+        assert!(mmds.patch_data(patch).is_ok());
+        assert_eq!(listener.read().unwrap(), 1);
+        assert_ne!(mmds.etag(), second_etag);
+    }
+
+    #[test]
+    fn test_get_json_value() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.get_json_value("/"), Err(Error::NotFound));
+
+        let data = r#"{
+            "name": {
+                "first": "John",
+                "second": "Doe"
+            },
+            "age": 43,
+            "married": false,
+            "phones": ["+40 1234567", "+44 1234567"]
+        }"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+
+        assert_eq!(
+            mmds.get_json_value("/name/"),
+            Ok(r#"{"first":"John","second":"Doe"}"#.to_string())
+        );
+        assert_eq!(mmds.get_json_value("/age"), Ok("43".to_string()));
+        assert_eq!(mmds.get_json_value("/married"), Ok("false".to_string()));
+        assert_eq!(
+            mmds.get_json_value("/phones"),
+            Ok(r#"["+40 1234567","+44 1234567"]"#.to_string())
+        );
+        assert_eq!(
+            mmds.get_json_value("/phones/1"),
+            Ok(r#""+44 1234567""#.to_string())
+        );
+        assert_eq!(mmds.get_json_value("/invalid"), Err(Error::NotFound));
+
+        // Non-string leaves cannot be represented in the IMDS format.
         assert_eq!(
-            mmds.patch_data(data_store),
+            mmds.get_value("/age".to_string()),
             Err(Error::UnsupportedValueType)
         );
     }
@@ -3,17 +3,39 @@
 
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 
+extern crate logger;
 extern crate micro_http;
+extern crate utils;
 
 pub mod data_store;
+pub mod token;
 
 use serde_json::{Map, Value};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use data_store::{Error as MmdsError, Mmds, MmdsVersion};
+use micro_http::{Body, MediaType, Method, Request, RequestError, Response, StatusCode, Version};
+use token::Error as TokenError;
+
+/// The path the guest requests session tokens from.
+const TOKEN_PATH: &str = "/latest/api/token";
+/// The header holding the session token of a GET request.
+const X_METADATA_TOKEN_HEADER: &str = "X-metadata-token";
+/// The header holding the time to live, in seconds, of a requested session token.
+const X_METADATA_TOKEN_TTL_SECONDS_HEADER: &str = "X-metadata-token-ttl-seconds";
+/// How long the response to a long-poll request is held for, at most.
+pub const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(60);
 
-use data_store::{Error as MmdsError, Mmds};
-use micro_http::{Body, Request, RequestError, Response, StatusCode, Version};
+/// Requests relayed by a proxy carry this header. Token requests holding it are rejected, so
+/// that guest applications tricked into forwarding requests cannot obtain tokens.
+const X_FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
 
 lazy_static! {
     // A static reference to a global Mmds instance. We currently use this for ease of access during
@@ -52,6 +74,7 @@ pub fn json_patch(target: &mut Value, patch: &Value) {
 
 fn build_response(http_version: Version, status_code: StatusCode, body: Body) -> Response {
     let mut response = Response::new(http_version, status_code);
+    response.set_content_type(MediaType::PlainText);
     response.set_body(body);
     response
 }
@@ -60,8 +83,7 @@ pub fn parse_request(request_bytes: &[u8]) -> Response {
     let request = Request::try_from(request_bytes);
     match request {
         Ok(request) => {
-            let uri = request.uri().get_abs_path();
-            if uri.is_empty() {
+            if request.uri().get_abs_path().is_empty() {
                 return build_response(
                     request.http_version(),
                     StatusCode::BadRequest,
@@ -69,44 +91,14 @@ pub fn parse_request(request_bytes: &[u8]) -> Response {
                 );
             }
 
-            // The lock can be held by one thread only, so it is safe to unwrap.
-            // If another thread poisoned the lock, we abort the execution.
-            let response = MMDS
-                .lock()
-                .expect("Failed to build MMDS response due to poisoned lock")
-                .get_value(uri.to_string());
-            match response {
-                Ok(response) => {
-                    let response_body = response.join("\n");
-                    build_response(
-                        request.http_version(),
-                        StatusCode::OK,
-                        Body::new(response_body),
-                    )
-                }
-                Err(e) => {
-                    match e {
-                        MmdsError::NotFound => {
-                            // NotFound
-                            let error_msg = format!("Resource not found: {}.", uri);
-                            build_response(
-                                request.http_version(),
-                                StatusCode::NotFound,
-                                Body::new(error_msg),
-                            )
-                        }
-                        MmdsError::UnsupportedValueType => {
-                            // InternalServerError
-                            let error_msg =
-                                format!("The resource {} has an invalid format.", uri.to_string());
-                            build_response(
-                                request.http_version(),
-                                StatusCode::InternalServerError,
-                                Body::new(error_msg),
-                            )
-                        }
-                    }
-                }
+            match request.method() {
+                Method::Get => respond_to_get_request(&request),
+                Method::Put => respond_to_put_request(&request),
+                _ => build_response(
+                    request.http_version(),
+                    StatusCode::MethodNotAllowed,
+                    Body::new("Not allowed HTTP method.".to_string()),
+                ),
             }
         }
         Err(e) => match e {
@@ -137,11 +129,282 @@ pub fn parse_request(request_bytes: &[u8]) -> Response {
     }
 }
 
+/// Returns how long the response to `request_bytes` should be held for, or `None` if the
+/// request should be answered right away, by calling `parse_request`.
+///
+/// A GET request asks to be held until the data store changes with the `wait=true` and
+/// `last_etag=<etag>` query parameters. It is held only while `<etag>` matches the current ETag
+/// of the data store, so the caller has to check again whenever the data store is updated.
+pub fn wait_timeout(request_bytes: &[u8]) -> Option<Duration> {
+    let request = Request::try_from(request_bytes).ok()?;
+    if request.method() != Method::Get {
+        return None;
+    }
+
+    let mut wait = false;
+    let mut last_etag = None;
+    for param in request.uri().get_query()?.split('&') {
+        let mut key_value = param.splitn(2, '=');
+        match (key_value.next(), key_value.next()) {
+            (Some("wait"), Some("true")) => wait = true,
+            (Some("last_etag"), Some(etag)) => last_etag = Some(etag),
+            _ => (),
+        }
+    }
+    let last_etag = last_etag.filter(|_| wait)?;
+
+    let mmds = MMDS
+        .lock()
+        .expect("Failed to build MMDS response due to poisoned lock");
+    // Unauthorized requests are answered right away.
+    if check_token(&request, &mmds).is_err() || unquote(last_etag) != unquote(&mmds.etag()) {
+        return None;
+    }
+
+    Some(LONG_POLL_TIMEOUT)
+}
+
+// ETags are quoted strings, which guests may also pass without the quotes, or percent-encoded,
+// in the query string.
+fn unquote(etag: &str) -> &str {
+    etag.trim_start_matches("%22")
+        .trim_end_matches("%22")
+        .trim_matches('"')
+}
+
+// Returns why the guest is not allowed to read the MMDS with `token`, if it isn't. V2 requires
+// a valid session token.
+fn token_error(mmds: &Mmds, token: Option<&str>) -> Option<String> {
+    if mmds.version() == MmdsVersion::V1 {
+        return None;
+    }
+
+    match token {
+        None => Some(format!(
+            "No MMDS token provided. Use `{}` header to specify the session token.",
+            X_METADATA_TOKEN_HEADER
+        )),
+        Some(token) if !mmds.is_valid_token(token) => Some("MMDS token not valid.".to_string()),
+        Some(_) => None,
+    }
+}
+
+// Returns the response to send when a V2 request does not carry a valid session token.
+fn check_token(request: &Request, mmds: &Mmds) -> Result<(), Response> {
+    match token_error(mmds, request.headers.custom_entry(X_METADATA_TOKEN_HEADER)) {
+        None => Ok(()),
+        Some(error_msg) => Err(build_response(
+            request.http_version(),
+            StatusCode::Unauthorized,
+            Body::new(error_msg),
+        )),
+    }
+}
+
+/// Errors of the reads the guest makes over other protocols than HTTP.
+#[derive(Debug, PartialEq)]
+pub enum GuestReadError {
+    /// The guest is not allowed to read the MMDS, for the given reason.
+    Unauthorized(String),
+    /// The data store cannot serve the value.
+    DataStore(MmdsError),
+}
+
+/// Reads the value at `path` on behalf of the guest, authorized like GET requests are. `token`
+/// is the session token which came with the read, if the protocol can carry one.
+pub fn read_for_guest(path: &str, token: Option<&str>) -> Result<Vec<String>, GuestReadError> {
+    let mmds = MMDS
+        .lock()
+        .expect("Failed to read the MMDS due to poisoned lock");
+    if let Some(error_msg) = token_error(&mmds, token) {
+        return Err(GuestReadError::Unauthorized(error_msg));
+    }
+    mmds.get_value(path.to_string())
+        .map_err(GuestReadError::DataStore)
+}
+
+fn respond_to_get_request(request: &Request) -> Response {
+    let uri = request.uri().get_abs_path();
+
+    // The lock can be held by one thread only, so it is safe to unwrap.
+    // If another thread poisoned the lock, we abort the execution.
+    let mmds = MMDS
+        .lock()
+        .expect("Failed to build MMDS response due to poisoned lock");
+
+    if let Err(response) = check_token(request, &mmds) {
+        return response;
+    }
+
+    let content_type = request.headers.accept();
+    let value = match content_type {
+        MediaType::ApplicationJson => mmds.get_json_value(uri),
+        MediaType::PlainText => mmds
+            .get_value(uri.to_string())
+            .map(|value| value.join("\n")),
+        // The metadata is not served as a stream.
+        MediaType::EventStream => {
+            return build_response(
+                request.http_version(),
+                StatusCode::NotAcceptable,
+                Body::new(format!(
+                    "Cannot serve the metadata as {}.",
+                    content_type.as_str()
+                )),
+            )
+        }
+    };
+    match value {
+        Ok(response_body) => {
+            let mut response = build_response(
+                request.http_version(),
+                StatusCode::OK,
+                Body::new(response_body),
+            );
+            response.set_content_type(content_type);
+            response.set_etag(&mmds.etag());
+            response
+        }
+        Err(e) => {
+            match e {
+                MmdsError::NotFound => {
+                    // NotFound
+                    let error_msg = format!("Resource not found: {}.", uri);
+                    build_response(
+                        request.http_version(),
+                        StatusCode::NotFound,
+                        Body::new(error_msg),
+                    )
+                }
+                MmdsError::UnsupportedValueType => {
+                    // NotAcceptable
+                    build_response(
+                        request.http_version(),
+                        StatusCode::NotAcceptable,
+                        Body::new(e.to_string()),
+                    )
+                }
+                // Reading the data store never changes its size.
+                MmdsError::DataStoreLimitExceeded => build_response(
+                    request.http_version(),
+                    StatusCode::InternalServerError,
+                    Body::new(e.to_string()),
+                ),
+            }
+        }
+    }
+}
+
+fn respond_to_put_request(request: &Request) -> Response {
+    let uri = request.uri().get_abs_path();
+    if uri != TOKEN_PATH {
+        return build_response(
+            request.http_version(),
+            StatusCode::NotFound,
+            Body::new(format!("Resource not found: {}.", uri)),
+        );
+    }
+
+    if request
+        .headers
+        .custom_entry(X_FORWARDED_FOR_HEADER)
+        .is_some()
+    {
+        return build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(format!(
+                "Invalid header. Reason: Unsupported header name. Key: {}",
+                X_FORWARDED_FOR_HEADER
+            )),
+        );
+    }
+
+    let ttl_seconds = match request
+        .headers
+        .custom_entry(X_METADATA_TOKEN_TTL_SECONDS_HEADER)
+        .map(str::parse::<u32>)
+    {
+        Some(Ok(ttl_seconds)) => ttl_seconds,
+        Some(Err(_)) => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(format!(
+                    "Invalid header. Reason: Invalid value. Key: {}",
+                    X_METADATA_TOKEN_TTL_SECONDS_HEADER
+                )),
+            )
+        }
+        None => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(format!(
+                    "Token time to live value not found. Use `{}` header to specify the \
+                     token's lifetime.",
+                    X_METADATA_TOKEN_TTL_SECONDS_HEADER
+                )),
+            )
+        }
+    };
+
+    // The lock can be held by one thread only, so it is safe to unwrap.
+    // If another thread poisoned the lock, we abort the execution.
+    let token = MMDS
+        .lock()
+        .expect("Failed to build MMDS response due to poisoned lock")
+        .generate_token(ttl_seconds);
+    match token {
+        Ok(token) => build_response(request.http_version(), StatusCode::OK, Body::new(token)),
+        Err(e @ TokenError::InvalidTtlValue(_)) => build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(e.to_string()),
+        ),
+        Err(e @ TokenError::EntropyPool(_)) => build_response(
+            request.http_version(),
+            StatusCode::InternalServerError,
+            Body::new(e.to_string()),
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate serde_json;
     use super::*;
 
+    use std::sync::{MutexGuard, PoisonError};
+
+    lazy_static! {
+        static ref MMDS_TEST_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    // Gives a test exclusive use of the global MMDS, holding `data` in V1. V1 is restored when
+    // the test ends, even if it fails.
+    struct MmdsGuard(MutexGuard<'static, ()>);
+
+    impl MmdsGuard {
+        fn new(data: &str) -> Self {
+            let guard = MMDS_TEST_LOCK
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let mut mmds = MMDS.lock().unwrap_or_else(PoisonError::into_inner);
+            mmds.set_version(MmdsVersion::V1);
+            mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+            MmdsGuard(guard)
+        }
+    }
+
+    impl Drop for MmdsGuard {
+        fn drop(&mut self) {
+            MMDS.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_version(MmdsVersion::V1);
+        }
+    }
+
     #[test]
     fn test_parse_request() {
         let data = r#"{
@@ -158,10 +421,7 @@ mod tests {
                 "mobile": "+44 2345678"
             }
         }"#;
-        MMDS.lock()
-            .unwrap()
-            .put_data(serde_json::from_str(data).unwrap())
-            .unwrap();
+        let _mmds = MmdsGuard::new(data);
 
         // Test invalid request.
         let request = b"HTTP/1.1";
@@ -246,11 +506,204 @@ mod tests {
             },
             "age": 43
         }"#;
+        MMDS.lock()
+            .unwrap()
+            .put_data(serde_json::from_str(data).unwrap())
+            .unwrap();
+
+        // Test resource that cannot be represented in the IMDS format.
+        let request = b"GET http://169.254.169.254/age HTTP/1.0\r\n\r\n";
+        let actual_response = parse_request(request);
+        assert!(actual_response.status() == StatusCode::NotAcceptable);
+        assert_eq!(actual_response.content_type(), MediaType::PlainText);
+
+        // The metadata cannot be streamed.
+        let request = b"GET http://169.254.169.254/age HTTP/1.0\r\n\
+                        Accept: text/event-stream\r\n\r\n";
+        let actual_response = parse_request(request);
+        assert!(actual_response.status() == StatusCode::NotAcceptable);
+
+        // Test JSON responses.
+        let request = b"GET http://169.254.169.254/age HTTP/1.0\r\n\
+                        Accept: application/json\r\n\r\n";
+        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
+        expected_response.set_body(Body::new("43".to_string()));
+        let actual_response = parse_request(request);
+        assert!(expected_response.status() == actual_response.status());
+        assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
+        assert_eq!(actual_response.content_type(), MediaType::ApplicationJson);
+
+        let request = b"GET http://169.254.169.254/name HTTP/1.0\r\n\
+                        Accept: application/json\r\n\r\n";
+        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
+        expected_response.set_body(Body::new(r#"{"first":"John","second":"Doe"}"#.to_string()));
+        let actual_response = parse_request(request);
+        assert!(expected_response.status() == actual_response.status());
+        assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
+
+        let request = b"GET http://169.254.169.254/invalid HTTP/1.0\r\n\
+                        Accept: application/json\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::NotFound);
+
+        // Test not allowed HTTP method.
+        let request = b"PATCH http://169.254.169.254/ HTTP/1.0\r\n\r\n";
+        let actual_response = parse_request(request);
+        assert!(actual_response.status() == StatusCode::MethodNotAllowed);
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let _mmds = MmdsGuard::new(r#"{"name": {"first": "John"}, "age": 43}"#);
+        let etag = MMDS.lock().unwrap().etag();
+
+        // Successful GET responses carry the ETag of the data store.
+        let request = b"GET http://169.254.169.254/name/first?wait=true HTTP/1.0\r\n\r\n";
+        let actual_response = parse_request(request);
+        assert!(actual_response.status() == StatusCode::OK);
+        assert_eq!(actual_response.etag(), Some(etag.as_str()));
+        assert!(parse_request(b"GET /invalid HTTP/1.0\r\n\r\n")
+            .etag()
+            .is_none());
+
+        // Requests which do not ask to wait, or already miss the latest update, are not held.
+        for request in &[
+            "GET /name/first HTTP/1.0\r\n\r\n".to_string(),
+            "GET /name/first?wait=true HTTP/1.0\r\n\r\n".to_string(),
+            format!("GET /name/first?last_etag={} HTTP/1.0\r\n\r\n", etag),
+            format!(
+                "GET /name/first?wait=false&last_etag={} HTTP/1.0\r\n\r\n",
+                etag
+            ),
+            "GET /name/first?wait=true&last_etag=\"0\" HTTP/1.0\r\n\r\n".to_string(),
+            format!(
+                "PUT /name/first?wait=true&last_etag={} HTTP/1.0\r\n\r\n",
+                etag
+            ),
+            "GET /name/first?wait=true&last_etag=".to_string(),
+        ] {
+            assert_eq!(wait_timeout(request.as_bytes()), None);
+        }
+
+        // The ETag may be passed with or without its quotes.
+        let unquoted_etag = etag.trim_matches('"');
+        for last_etag in &[
+            etag.clone(),
+            unquoted_etag.to_string(),
+            format!("%22{}%22", unquoted_etag),
+        ] {
+            let request = format!(
+                "GET /name/first?wait=true&last_etag={} HTTP/1.0\r\n\r\n",
+                last_etag
+            );
+            assert_eq!(wait_timeout(request.as_bytes()), Some(LONG_POLL_TIMEOUT));
+        }
+
+        // Unauthorized requests are answered right away.
+        let request = format!(
+            "GET /name/first?wait=true&last_etag={} HTTP/1.0\r\n\r\n",
+            etag
+        );
+        MMDS.lock().unwrap().set_version(MmdsVersion::V2);
+        assert_eq!(wait_timeout(request.as_bytes()), None);
+        MMDS.lock().unwrap().set_version(MmdsVersion::V1);
+
+        // An update of the data store ends the wait.
+        MMDS.lock()
+            .unwrap()
+            .patch_data(serde_json::json!({"age": 44}))
+            .unwrap();
+        assert_eq!(wait_timeout(request.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_parse_request_v2() {
+        let _mmds = MmdsGuard::new(r#"{"name": {"first": "John"}, "age": 43}"#);
+
+        // Test PUT request on a path other than the token one.
+        let request = b"PUT http://169.254.169.254/age HTTP/1.0\r\n\
+                        X-metadata-token-ttl-seconds: 60\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::NotFound);
+
+        // Test token request without the time to live header.
+        let request = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::BadRequest);
+
+        // Test token request with invalid time to live values.
+        let request = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                        X-metadata-token-ttl-seconds: -1\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::BadRequest);
+        let request = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                        X-metadata-token-ttl-seconds: 21601\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::BadRequest);
+
+        // Test token request relayed by a proxy.
+        let request = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                        X-Forwarded-For: 203.0.113.195\r\n\
+                        X-metadata-token-ttl-seconds: 60\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::BadRequest);
+
+        let request = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                        x-metadata-token-ttl-seconds: 60\r\n\r\n";
+        let actual_response = parse_request(request);
+        assert!(actual_response.status() == StatusCode::OK);
+        let token = String::from_utf8(actual_response.body().unwrap().body).unwrap();
+
+        MMDS.lock().unwrap().set_version(MmdsVersion::V2);
+
+        // Test GET request without a token.
+        let request = b"GET http://169.254.169.254/age HTTP/1.0\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::Unauthorized);
+
+        // Test GET request with an invalid token.
+        let request = b"GET http://169.254.169.254/age HTTP/1.0\r\n\
+                        X-metadata-token: invalid_token\r\n\r\n";
+        assert!(parse_request(request).status() == StatusCode::Unauthorized);
+
+        // Test GET request with a valid token.
+        let request = format!(
+            "GET http://169.254.169.254/name/first HTTP/1.0\r\nX-metadata-token: {}\r\n\r\n",
+            token
+        );
+        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
+        expected_response.set_body(Body::new("John".to_string()));
+        let actual_response = parse_request(request.as_bytes());
+        assert!(expected_response.status() == actual_response.status());
+        assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
+    }
+
+    #[test]
+    fn test_read_for_guest() {
+        let _mmds = MmdsGuard::new(r#"{"name": {"first": "John"}, "age": 43}"#);
+
         assert_eq!(
-            MMDS.lock()
-                .unwrap()
-                .put_data(serde_json::from_str(data).unwrap()),
-            Err(MmdsError::UnsupportedValueType)
+            read_for_guest("/name/first", None),
+            Ok(vec!["John".to_string()])
+        );
+        assert_eq!(
+            read_for_guest("/age", None),
+            Err(GuestReadError::DataStore(MmdsError::UnsupportedValueType))
+        );
+        assert_eq!(
+            read_for_guest("/name/last", None),
+            Err(GuestReadError::DataStore(MmdsError::NotFound))
+        );
+
+        // V2 requires a valid token, like for GET requests.
+        let token = MMDS.lock().unwrap().generate_token(60).unwrap();
+        MMDS.lock().unwrap().set_version(MmdsVersion::V2);
+        match read_for_guest("/name/first", None) {
+            Err(GuestReadError::Unauthorized(msg)) => assert!(msg.starts_with("No MMDS token")),
+            _ => panic!("Reads without a token should be refused."),
+        }
+        assert_eq!(
+            read_for_guest("/name/first", Some("invalid_token")),
+            Err(GuestReadError::Unauthorized(
+                "MMDS token not valid.".to_string()
+            ))
+        );
+        assert_eq!(
+            read_for_guest("/name/first", Some(&token)),
+            Ok(vec!["John".to_string()])
         );
     }
 
@@ -0,0 +1,170 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// The minimum number of seconds a session token can be valid for.
+pub const MIN_TOKEN_TTL_SECONDS: u32 = 1;
+/// The maximum number of seconds a session token can be valid for (6 hours).
+pub const MAX_TOKEN_TTL_SECONDS: u32 = 21600;
+/// The number of random bytes a session token is made of.
+const TOKEN_LEN: usize = 32;
+/// The maximum number of tokens kept at any time. Once reached, the token closest to expiring
+/// is evicted to make room for a new one, so the guest cannot exhaust the host memory.
+const MAX_TOKENS: usize = 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The host entropy pool could not be read.
+    EntropyPool(io::Error),
+    /// The requested time to live is outside the accepted range.
+    InvalidTtlValue(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::EntropyPool(ref e) => write!(f, "Cannot generate the MMDS token: {}", e),
+            Error::InvalidTtlValue(ttl) => write!(
+                f,
+                "Invalid time to live value provided for token: {}. Please provide a value \
+                 between {} and {}.",
+                ttl, MIN_TOKEN_TTL_SECONDS, MAX_TOKEN_TTL_SECONDS
+            ),
+        }
+    }
+}
+
+/// Issues the session tokens the guest has to present when the MMDS runs in V2 mode, and
+/// keeps track of their expiry.
+#[derive(Clone, Default)]
+pub struct TokenAuthority {
+    tokens: HashMap<String, Instant>,
+}
+
+impl TokenAuthority {
+    /// Generates a token valid for `ttl_seconds` seconds.
+    pub fn generate_token(&mut self, ttl_seconds: u32) -> Result<String, Error> {
+        if ttl_seconds < MIN_TOKEN_TTL_SECONDS || ttl_seconds > MAX_TOKEN_TTL_SECONDS {
+            return Err(Error::InvalidTtlValue(ttl_seconds));
+        }
+
+        let mut bytes = [0u8; TOKEN_LEN];
+        fill_random(&mut bytes).map_err(Error::EntropyPool)?;
+        let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let now = Instant::now();
+        if self.tokens.len() >= MAX_TOKENS {
+            self.tokens.retain(|_, expiry| *expiry > now);
+        }
+        if self.tokens.len() >= MAX_TOKENS {
+            if let Some(oldest) = self
+                .tokens
+                .iter()
+                .min_by_key(|(_, expiry)| **expiry)
+                .map(|(token, _)| token.clone())
+            {
+                self.tokens.remove(&oldest);
+            }
+        }
+
+        self.tokens.insert(
+            token.clone(),
+            now + Duration::from_secs(u64::from(ttl_seconds)),
+        );
+        Ok(token)
+    }
+
+    /// Checks whether `token` was issued by this authority and has not expired yet.
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.tokens
+            .get(token)
+            .map_or(false, |expiry| *expiry > Instant::now())
+    }
+}
+
+// Fills `buf` with bytes from the host entropy pool, without going through the file system,
+// which is not reachable from the seccomp-filtered threads serving the MMDS.
+fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        // Safe because the kernel only writes within the bounds of the remaining buffer, and we
+        // check the return value.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_getrandom,
+                buf[filled..].as_mut_ptr(),
+                buf.len() - filled,
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += ret as usize;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token() {
+        let mut authority = TokenAuthority::default();
+
+        assert!(authority.generate_token(0).is_err());
+        assert_eq!(
+            authority
+                .generate_token(MAX_TOKEN_TTL_SECONDS + 1)
+                .unwrap_err()
+                .to_string(),
+            "Invalid time to live value provided for token: 21601. Please provide a value \
+             between 1 and 21600."
+        );
+
+        let token = authority.generate_token(MIN_TOKEN_TTL_SECONDS).unwrap();
+        assert_eq!(token.len(), 2 * TOKEN_LEN);
+        assert!(authority.is_valid(&token));
+        assert!(!authority.is_valid("invalid_token"));
+
+        let other_token = authority.generate_token(MAX_TOKEN_TTL_SECONDS).unwrap();
+        assert_ne!(token, other_token);
+        assert!(authority.is_valid(&other_token));
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let mut authority = TokenAuthority::default();
+        let token = authority.generate_token(MIN_TOKEN_TTL_SECONDS).unwrap();
+        // Backdate the token instead of sleeping through its time to live.
+        authority
+            .tokens
+            .insert(token.clone(), Instant::now() - Duration::from_millis(1));
+        assert!(!authority.is_valid(&token));
+    }
+
+    #[test]
+    fn test_token_eviction() {
+        let mut authority = TokenAuthority::default();
+        let first_token = authority.generate_token(MIN_TOKEN_TTL_SECONDS).unwrap();
+        for _ in 1..MAX_TOKENS {
+            authority.generate_token(MAX_TOKEN_TTL_SECONDS).unwrap();
+        }
+        assert!(authority.is_valid(&first_token));
+
+        // The token closest to expiring makes room for the new one.
+        let token = authority.generate_token(MAX_TOKEN_TTL_SECONDS).unwrap();
+        assert_eq!(authority.tokens.len(), MAX_TOKENS);
+        assert!(authority.is_valid(&token));
+        assert!(!authority.is_valid(&first_token));
+    }
+}
@@ -118,6 +118,28 @@ impl EventManager {
         Ok(())
     }
 
+    /// Unregister all the file descriptors that `subscriber` is registered for. Returns the
+    /// number of unregistered file descriptors.
+    pub fn remove_subscriber(&mut self, subscriber: &Arc<Mutex<dyn Subscriber>>) -> Result<usize> {
+        // Compare the data pointers only, the vtable part of a fat pointer is not guaranteed
+        // to be unique.
+        let subscriber_ptr = &**subscriber as *const Mutex<dyn Subscriber> as *const u8;
+        let pollables: Vec<Pollable> = self
+            .subscribers
+            .iter()
+            .filter(|(_, registered)| {
+                &***registered as *const Mutex<dyn Subscriber> as *const u8 == subscriber_ptr
+            })
+            .map(|(pollable, _)| *pollable)
+            .collect();
+
+        for pollable in pollables.iter() {
+            self.unregister(*pollable)?;
+        }
+
+        Ok(pollables.len())
+    }
+
     /// Register a new `pollable` file descriptor with the corresponding `epoll_event`
     /// for `subscriber`.
     pub fn register(
@@ -409,6 +431,36 @@ mod tests {
         assert_eq!(dummy_subscriber.lock().unwrap().processed_ev1_out(), false);
     }
 
+    #[test]
+    fn test_remove_subscriber() {
+        let mut event_manager = EventManager::new().unwrap();
+        let dummy_subscriber = Arc::new(Mutex::new(DummySubscriber::new()));
+        let other_subscriber = Arc::new(Mutex::new(DummySubscriber::new()));
+
+        event_manager
+            .add_subscriber(dummy_subscriber.clone())
+            .unwrap();
+        event_manager
+            .add_subscriber(other_subscriber.clone())
+            .unwrap();
+
+        // Register ev2 as well, so that the subscriber owns two file descriptors.
+        dummy_subscriber.lock().unwrap().register_ev2();
+        event_manager.run().unwrap();
+
+        let subscriber: Arc<Mutex<dyn Subscriber>> = dummy_subscriber.clone();
+        assert_eq!(event_manager.remove_subscriber(&subscriber).unwrap(), 2);
+        assert_eq!(event_manager.remove_subscriber(&subscriber).unwrap(), 0);
+
+        // Only the other subscriber should still receive events.
+        dummy_subscriber.lock().unwrap().reset_state();
+        other_subscriber.lock().unwrap().reset_state();
+        event_manager.run_with_timeout(100).unwrap();
+        assert_eq!(dummy_subscriber.lock().unwrap().processed_ev1_out(), false);
+        assert_eq!(dummy_subscriber.lock().unwrap().processed_ev2_out(), false);
+        assert_eq!(other_subscriber.lock().unwrap().processed_ev1_out(), true);
+    }
+
     #[test]
     fn test_modify() {
         let mut event_manager = EventManager::new().unwrap();
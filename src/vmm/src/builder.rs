@@ -8,18 +8,21 @@ use std::fmt::{Display, Formatter};
 use std::fs::OpenOptions;
 use std::io::{self, Read, Seek, SeekFrom};
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
-use super::{Error, Vmm};
+use super::{Error, RestartConfig, Vmm};
 
 use arch::InitrdConfig;
 #[cfg(target_arch = "x86_64")]
 use device_manager::legacy::PortIODeviceManager;
-use device_manager::mmio::MMIODeviceManager;
+use device_manager::mmio::{MMIODeviceInfo, MMIODeviceManager};
 use devices::legacy::Serial;
 use devices::virtio::MmioTransport;
+use dumbo::ns::MmdsNetworkStack;
+use dumbo::{DnsResponder, SntpResponder};
 use polly::event_manager::{Error as EventManagerError, EventManager};
 use seccomp::BpfProgramRef;
+use timerfd::{ClockId, TimerFd};
 use utils::eventfd::EventFd;
 use utils::terminal::Terminal;
 use utils::time::TimestampUs;
@@ -27,7 +30,10 @@ use vm_memory::{Bytes, GuestAddress, GuestMemoryMmap};
 use vmm_config;
 use vmm_config::boot_source::BootConfig;
 use vmm_config::drive::BlockDeviceConfigs;
-use vmm_config::net::NetworkInterfaceConfigs;
+use vmm_config::instance_info::{InstanceInfo, InstanceState};
+use vmm_config::machine_config::RebootPolicy;
+use vmm_config::mmds::MmdsConfig;
+use vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceConfigs};
 use vmm_config::vsock::VsockDeviceConfig;
 use vstate::{KvmContext, Vcpu, VcpuConfig, Vm};
 use {device_manager, VmmEventsObserver};
@@ -230,12 +236,19 @@ impl VmmEventsObserver for SerialStdin {
 /// independent functions in this module instead of calling this recipe.
 ///
 /// An `Arc` reference of the built `Vmm` is also plugged in the `EventManager`, while another
-/// is returned.
+/// is returned. The state of the microVM is kept up to date in `instance_info`.
 pub fn build_microvm(
     vm_resources: &super::resources::VmResources,
     event_manager: &mut EventManager,
     seccomp_filter: BpfProgramRef,
+    instance_info: Arc<RwLock<InstanceInfo>>,
 ) -> std::result::Result<Arc<Mutex<Vmm>>, StartMicrovmError> {
+    instance_info
+        .write()
+        .expect("Poisoned instance info lock")
+        .set_state(InstanceState::Starting)
+        .map_err(|e| StartMicrovmError::Internal(Error::InstanceState(e)))?;
+
     let boot_config = vm_resources
         .boot_source()
         .ok_or(StartMicrovmError::MissingKernelConfig)?;
@@ -274,6 +287,9 @@ pub fn build_microvm(
     let exit_evt = EventFd::new(libc::EFD_NONBLOCK)
         .map_err(Error::EventFd)
         .map_err(StartMicrovmError::Internal)?;
+    let shutdown_timer = TimerFd::new_custom(ClockId::Monotonic, true, true)
+        .map_err(Error::TimerFd)
+        .map_err(StartMicrovmError::Internal)?;
 
     #[cfg(target_arch = "x86_64")]
     // Safe to unwrap 'serial_device' as it's always 'Some' on x86_64.
@@ -297,7 +313,7 @@ pub fn build_microvm(
         (arch::IRQ_BASE, arch::IRQ_MAX),
     );
 
-    let vcpus;
+    let mut vcpus;
     // For x86_64 we need to create the interrupt controller before calling `KVM_CREATE_VCPUS`
     // while on aarch64 we need to do it the other way around.
     #[cfg(target_arch = "x86_64")]
@@ -349,16 +365,29 @@ pub fn build_microvm(
         vcpus_handles: Vec::new(),
         exit_evt,
         vm,
+        shutdown_timer,
+        shutdown_requested: None,
+        restart_config: None,
+        instance_info,
         mmio_device_manager,
         #[cfg(target_arch = "x86_64")]
         pio_device_manager,
     };
 
     attach_block_devices(&mut vmm, &vm_resources.block, event_manager)?;
-    attach_net_devices(&mut vmm, &vm_resources.network_interface, event_manager)?;
+    attach_net_devices(
+        &mut vmm,
+        &vm_resources.network_interface,
+        vm_resources.mmds_config(),
+        event_manager,
+    )?;
     if let Some(vsock) = vm_resources.vsock.as_ref() {
         attach_vsock_device(&mut vmm, vsock, event_manager)?;
     }
+    reserve_hotplug_slots(
+        &mut vmm,
+        vm_resources.vm_config().hotplug_slots.unwrap_or(0),
+    )?;
 
     // Write the kernel command line to guest memory. This is x86_64 specific, since on
     // aarch64 the command line will be specified through the FDT.
@@ -367,6 +396,18 @@ pub fn build_microvm(
 
     vmm.configure_system(vcpus.as_slice(), &initrd)
         .map_err(StartMicrovmError::Internal)?;
+    if vm_resources.vm_config().reboot_policy == Some(RebootPolicy::Restart) {
+        vmm.restart_config = Some(RestartConfig {
+            boot_config: boot_config
+                .try_clone()
+                .map_err(Error::BootSource)
+                .map_err(StartMicrovmError::Internal)?,
+            vcpus: Vmm::boot_vcpus(vcpus.as_slice()),
+        });
+        for vcpu in vcpus.iter_mut() {
+            vcpu.set_restart_on_stop(true);
+        }
+    }
     // Firecracker uses the same seccomp filter for all threads.
     vmm.start_vcpus(vcpus, seccomp_filter.to_vec(), seccomp_filter)
         .map_err(StartMicrovmError::Internal)?;
@@ -375,6 +416,10 @@ pub fn build_microvm(
     event_manager
         .add_subscriber(vmm.clone())
         .map_err(StartMicrovmError::RegisterEvent)?;
+    vmm.lock()
+        .expect("Poisoned Vmm lock")
+        .set_instance_state(InstanceState::Running)
+        .map_err(StartMicrovmError::Internal)?;
 
     Ok(vmm)
 }
@@ -390,7 +435,7 @@ pub fn create_guest_memory(
         .map_err(StartMicrovmError::GuestMemoryMmap)?)
 }
 
-fn load_kernel(
+pub(crate) fn load_kernel(
     boot_config: &BootConfig,
     guest_memory: &GuestMemoryMmap,
 ) -> std::result::Result<GuestAddress, StartMicrovmError> {
@@ -406,7 +451,7 @@ fn load_kernel(
     Ok(entry_addr)
 }
 
-fn load_initrd_from_config(
+pub(crate) fn load_initrd_from_config(
     boot_cfg: &BootConfig,
     vm_memory: &GuestMemoryMmap,
 ) -> std::result::Result<Option<InitrdConfig>, StartMicrovmError> {
@@ -466,7 +511,7 @@ where
 }
 
 #[cfg(target_arch = "x86_64")]
-fn load_cmdline(vmm: &Vmm) -> std::result::Result<(), StartMicrovmError> {
+pub(crate) fn load_cmdline(vmm: &Vmm) -> std::result::Result<(), StartMicrovmError> {
     kernel::loader::load_cmdline(
         vmm.guest_memory(),
         GuestAddress(arch::x86_64::layout::CMDLINE_START),
@@ -581,6 +626,11 @@ fn attach_legacy_devices(
         .map_err(Error::RegisterMMIODevice)
         .map_err(StartMicrovmError::Internal)?;
 
+    mmio_device_manager
+        .register_mmio_gpio(vm.fd())
+        .map_err(Error::RegisterMMIODevice)
+        .map_err(StartMicrovmError::Internal)?;
+
     Ok(())
 }
 
@@ -708,6 +758,7 @@ fn attach_block_devices(
 
         let block_device = Arc::new(Mutex::new(
             devices::virtio::Block::new(
+                drive_config.drive_id.clone(),
                 vmm.guest_memory.clone(),
                 block_file,
                 drive_config.is_read_only,
@@ -732,40 +783,67 @@ fn attach_block_devices(
     Ok(())
 }
 
+// Creates the net device described by `cfg`, without registering its events. Devices that
+// allow MMDS requests get their own MMDS network stack, listening on `mmds_config`'s address,
+// with the UDP services it enables.
+fn create_net_device(
+    cfg: &NetworkInterfaceConfig,
+    mmds_config: &MmdsConfig,
+    guest_memory: &GuestMemoryMmap,
+) -> std::result::Result<Arc<Mutex<devices::virtio::net::Net>>, StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let mmds_ns = if cfg.allow_mmds_requests() {
+        let mut mmds_ns =
+            MmdsNetworkStack::new_with_defaults(mmds_config.ipv4_address, mmds_config.ipv6_address);
+        if mmds_config.dns_enabled {
+            mmds_ns.add_udp_service(Box::new(DnsResponder::new()));
+        }
+        if mmds_config.sntp_enabled {
+            mmds_ns.add_udp_service(Box::new(SntpResponder::new()));
+        }
+        Some(mmds_ns)
+    } else {
+        None
+    };
+
+    let rx_rate_limiter = cfg
+        .rx_rate_limiter
+        .map(vmm_config::RateLimiterConfig::try_into)
+        .transpose()
+        .map_err(CreateRateLimiter)?;
+
+    let tx_rate_limiter = cfg
+        .tx_rate_limiter
+        .map(vmm_config::RateLimiterConfig::try_into)
+        .transpose()
+        .map_err(CreateRateLimiter)?;
+
+    let tap = cfg.open_tap().map_err(|_| NetDeviceNotConfigured)?;
+    Ok(Arc::new(Mutex::new(
+        devices::virtio::net::Net::new_with_tap(
+            cfg.iface_id.clone(),
+            tap,
+            cfg.guest_mac(),
+            guest_memory.clone(),
+            rx_rate_limiter.unwrap_or_default(),
+            tx_rate_limiter.unwrap_or_default(),
+            mmds_ns,
+        )
+        .map_err(CreateNetDevice)?,
+    )))
+}
+
 fn attach_net_devices(
     vmm: &mut Vmm,
     network_ifaces: &NetworkInterfaceConfigs,
+    mmds_config: &MmdsConfig,
     event_manager: &mut EventManager,
 ) -> std::result::Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
     for cfg in network_ifaces.iter() {
-        let allow_mmds_requests = cfg.allow_mmds_requests();
-
-        let rx_rate_limiter = cfg
-            .rx_rate_limiter
-            .map(vmm_config::RateLimiterConfig::try_into)
-            .transpose()
-            .map_err(CreateRateLimiter)?;
-
-        let tx_rate_limiter = cfg
-            .tx_rate_limiter
-            .map(vmm_config::RateLimiterConfig::try_into)
-            .transpose()
-            .map_err(CreateRateLimiter)?;
-
-        let tap = cfg.open_tap().map_err(|_| NetDeviceNotConfigured)?;
-        let net_device = Arc::new(Mutex::new(
-            devices::virtio::net::Net::new_with_tap(
-                tap,
-                cfg.guest_mac(),
-                vmm.guest_memory().clone(),
-                rx_rate_limiter.unwrap_or_default(),
-                tx_rate_limiter.unwrap_or_default(),
-                allow_mmds_requests,
-            )
-            .map_err(CreateNetDevice)?,
-        ));
+        let net_device = create_net_device(cfg, mmds_config, vmm.guest_memory())?;
         event_manager
             .add_subscriber(net_device.clone())
             .map_err(StartMicrovmError::RegisterEvent)?;
@@ -783,6 +861,51 @@ fn attach_net_devices(
     Ok(())
 }
 
+// Reserves `count` slots for the devices hot-plugged after boot. They come after the other
+// devices, for these to keep their location.
+fn reserve_hotplug_slots(vmm: &mut Vmm, count: u8) -> std::result::Result<(), StartMicrovmError> {
+    for _ in 0..count {
+        vmm.mmio_device_manager
+            .reserve_hotplug_slot(&mut vmm.kernel_cmdline)
+            .map_err(Error::RegisterMMIODevice)
+            .map_err(StartMicrovmError::Internal)?;
+    }
+    Ok(())
+}
+
+/// Plugs the net device described by `cfg` into the running `vmm`. Returns the location of the
+/// new device, which the guest needs in order to discover it.
+pub fn hotplug_net_device(
+    vmm: &mut Vmm,
+    cfg: &NetworkInterfaceConfig,
+    mmds_config: &MmdsConfig,
+    event_manager: &mut EventManager,
+) -> std::result::Result<MMIODeviceInfo, StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let net_device = create_net_device(cfg, mmds_config, vmm.guest_memory())?;
+    let dev_info = vmm
+        .hotplug_mmio_device(
+            MmioTransport::new(vmm.guest_memory().clone(), net_device.clone()).map_err(|e| {
+                RegisterNetDevice(super::device_manager::mmio::Error::CreateMmioDevice(e))
+            })?,
+            &cfg.iface_id,
+        )
+        .map_err(|e| match e {
+            Error::RegisterMMIODevice(e) => RegisterNetDevice(e),
+            e => Internal(e),
+        })?;
+
+    if let Err(e) = event_manager.add_subscriber(net_device) {
+        // Don't leave behind a device that would never be serviced.
+        vmm.unplug_mmio_device(devices::virtio::TYPE_NET, &cfg.iface_id)
+            .map_err(Internal)?;
+        return Err(RegisterEvent(e));
+    }
+
+    Ok(dev_info)
+}
+
 fn attach_vsock_device(
     vmm: &mut Vmm,
     vsock: &VsockDeviceConfig,
@@ -824,12 +947,15 @@ fn attach_vsock_device(
 pub mod tests {
     use std::fs::{remove_file, File};
     use std::io::Cursor;
+    use std::time::Duration;
 
     use super::*;
     use arch::DeviceType;
-    use devices::virtio::{TYPE_BLOCK, TYPE_VSOCK};
+    use default_syscalls::default_filter;
+    use devices::virtio::{TYPE_BLOCK, TYPE_NET, TYPE_VSOCK};
     use kernel::cmdline::Cmdline;
     use polly::event_manager::EventManager;
+    use seccomp::SeccompFilter;
     use utils::tempfile::TempFile;
     use vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
     use vmm_config::drive::BlockDeviceConfig;
@@ -916,6 +1042,14 @@ pub mod tests {
             vcpus_handles: Vec::new(),
             exit_evt,
             vm,
+            shutdown_timer: TimerFd::new_custom(ClockId::Monotonic, true, true).unwrap(),
+            shutdown_requested: None,
+            restart_config: None,
+            instance_info: Arc::new(RwLock::new(InstanceInfo::new(
+                "test_vmm".to_string(),
+                "0.1".to_string(),
+                "app".to_string(),
+            ))),
             mmio_device_manager,
             #[cfg(target_arch = "x86_64")]
             pio_device_manager,
@@ -1114,14 +1248,84 @@ pub mod tests {
         let mut network_interface_configs = NetworkInterfaceConfigs::new();
         network_interface_configs.insert(network_interface).unwrap();
 
-        assert!(
-            attach_net_devices(&mut vmm, &network_interface_configs, &mut event_manager).is_ok()
-        );
+        assert!(attach_net_devices(
+            &mut vmm,
+            &network_interface_configs,
+            &MmdsConfig::default(),
+            &mut event_manager
+        )
+        .is_ok());
 
         // We can not attach it once more.
-        assert!(
-            attach_net_devices(&mut vmm, &network_interface_configs, &mut event_manager).is_err()
-        );
+        assert!(attach_net_devices(
+            &mut vmm,
+            &network_interface_configs,
+            &MmdsConfig::default(),
+            &mut event_manager
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_hotplug_net_device() {
+        // Hot-plugging happens on the VMM thread after the default seccomp filter is installed.
+        // The filter is installed in a child process, which exits right after hot-plugging, so
+        // that it affects neither the other tests nor the exit of the test thread. KVM only
+        // accepts requests from the process which created the VM, so it is created there too.
+        match unsafe { libc::fork() } {
+            0 => {
+                let plugged = std::panic::catch_unwind(|| {
+                    let mut event_manager =
+                        EventManager::new().expect("Unable to create EventManager");
+                    let mut vmm = default_vmm();
+
+                    #[cfg(target_arch = "x86_64")]
+                    setup_interrupt_controller(&mut vmm.vm).unwrap();
+
+                    #[cfg(target_arch = "aarch64")]
+                    setup_interrupt_controller(&mut vmm.vm, 1).unwrap();
+
+                    reserve_hotplug_slots(&mut vmm, 1).unwrap();
+                    let network_interface = NetworkInterfaceConfig {
+                        iface_id: String::from("netif"),
+                        host_dev_name: String::from("hotplug"),
+                        guest_mac: None,
+                        rx_rate_limiter: None,
+                        tx_rate_limiter: None,
+                        allow_mmds_requests: false,
+                    };
+
+                    // glibc grows the heap of the test thread through `mprotect`, while the VMM
+                    // thread allocates from the main heap. Once the heap is full, allocating
+                    // through `mmap` instead keeps to the system calls the VMM makes.
+                    #[cfg(target_env = "gnu")]
+                    unsafe {
+                        libc::mallopt(libc::M_MMAP_THRESHOLD, 0)
+                    };
+                    SeccompFilter::apply(default_filter().unwrap().try_into().unwrap()).unwrap();
+                    hotplug_net_device(
+                        &mut vmm,
+                        &network_interface,
+                        &MmdsConfig::default(),
+                        &mut event_manager,
+                    )
+                    .is_ok()
+                        && vmm
+                            .mmio_device_manager
+                            .get_device(DeviceType::Virtio(TYPE_NET), "netif")
+                            .is_some()
+                })
+                .unwrap_or(false);
+                // Exiting right away skips returning to the test harness, and the thread exit.
+                unsafe { libc::_exit(if plugged { 0 } else { 1 }) };
+            }
+            child => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+                // The child exits with 0, unless it was killed by `SIGSYS` on a seccomp violation.
+                assert_eq!(status, 0);
+            }
+        }
     }
 
     #[test]
@@ -1297,6 +1501,36 @@ pub mod tests {
             .is_some());
     }
 
+    #[test]
+    fn test_shutdown_gracefully() {
+        let mut vmm = default_vmm();
+        vmm.set_instance_state(InstanceState::Starting).unwrap();
+        vmm.set_instance_state(InstanceState::Running).unwrap();
+
+        assert!(vmm.shutdown_gracefully(Duration::from_millis(1)).is_ok());
+        assert!(vmm.shutdown_requested.is_some());
+        assert_eq!(vmm.instance_state(), InstanceState::ShuttingDown);
+        // Only one graceful shutdown can be pending.
+        match vmm.shutdown_gracefully(Duration::from_millis(1)) {
+            Err(Error::ShutdownInProgress) => (),
+            _ => panic!("A second shutdown request should be rejected."),
+        }
+
+        // The timer expires, for the VMM to be stopped.
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(vmm.shutdown_timer.read(), 1);
+    }
+
+    #[test]
+    fn test_discard_guest_memory() {
+        let vmm = default_vmm();
+        let addr = GuestAddress(arch::get_kernel_start());
+
+        vmm.guest_memory().write_obj(0xdead_beef_u32, addr).unwrap();
+        assert!(vmm.discard_guest_memory().is_ok());
+        assert_eq!(vmm.guest_memory().read_obj::<u32>(addr).unwrap(), 0);
+    }
+
     #[test]
     fn test_error_messages() {
         use builder::StartMicrovmError::*;
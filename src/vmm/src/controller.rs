@@ -3,20 +3,31 @@
 
 use std::fs::{File, OpenOptions};
 use std::io::{Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::result;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use arch::DeviceType;
+use builder;
 use device_manager::mmio::MMIO_CFG_SPACE_OFF;
-use devices::virtio::{Block, MmioTransport, Net, TYPE_BLOCK, TYPE_NET};
+use devices::virtio::{Block, MmioTransport, Net, VirtioDevice, TYPE_BLOCK, TYPE_NET, TYPE_VSOCK};
 use logger::METRICS;
-use resources::VmResources;
+use polly::event_manager::EventManager;
+use resources::{VmResources, VmmConfig};
 use rpc_interface::VmmActionError;
+use utils::net::Tap;
 use vmm_config;
-use vmm_config::drive::DriveError;
+use vmm_config::drive::{BlockDeviceStatus, DriveError};
+use vmm_config::instance_info::InstanceState;
 use vmm_config::machine_config::VmConfig;
-use vmm_config::net::{NetworkInterfaceError, NetworkInterfaceUpdateConfig};
+use vmm_config::net::{
+    NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceStatus,
+    NetworkInterfaceUpdateConfig,
+};
+use vmm_config::vsock::{VsockDeviceStatus, VsockError};
+use vmm_config::RateLimiterBudget;
 use Vmm;
 
 /// Shorthand result type for external VMM commands.
@@ -34,6 +45,125 @@ impl VmmController {
         self.vm_resources.vm_config()
     }
 
+    /// Returns the full configuration of the microVM.
+    pub fn vmm_config(&self) -> VmmConfig {
+        self.vm_resources.vmm_config()
+    }
+
+    /// Returns a reference to the resources the microVM was configured with.
+    pub fn vm_resources(&self) -> &VmResources {
+        &self.vm_resources
+    }
+
+    /// Returns the current state of the microVM.
+    pub fn instance_state(&self) -> InstanceState {
+        self.vmm.lock().unwrap().instance_state()
+    }
+
+    // Passes the emulated device of type `device_type` attached with id `device_id` to `f`,
+    // if there is one.
+    fn inspect_virtio_device<F>(&self, device_type: u32, device_id: &str, f: F)
+    where
+        F: FnOnce(&dyn VirtioDevice),
+    {
+        if let Some(busdev) = self
+            .vmm
+            .lock()
+            .unwrap()
+            .get_bus_device(DeviceType::Virtio(device_type), device_id)
+        {
+            let virtio_device = busdev
+                .lock()
+                .expect("Poisoned device lock")
+                .as_any()
+                .downcast_ref::<MmioTransport>()
+                // Only MmioTransport implements BusDevice at this point.
+                .expect("Unexpected BusDevice type")
+                .device();
+            let locked_device = virtio_device.lock().expect("Poisoned device lock");
+            f(&*locked_device);
+        }
+    }
+
+    /// Returns the configuration and state of the block device with id `drive_id`.
+    pub fn block_device_status(
+        &self,
+        drive_id: &str,
+    ) -> result::Result<BlockDeviceStatus, VmmActionError> {
+        let config = self
+            .vm_resources
+            .block
+            .get(drive_id)
+            .ok_or(VmmActionError::DriveConfig(
+                DriveError::InvalidBlockDeviceID,
+            ))?;
+        let mut status = BlockDeviceStatus::from(config.clone());
+        self.inspect_virtio_device(TYPE_BLOCK, drive_id, |device| {
+            let block = device
+                .as_any()
+                .downcast_ref::<Block>()
+                .expect("Unexpected Block type");
+            status.activated = block.is_activated();
+            status.rate_limiter_budget = Some(RateLimiterBudget::from(block.rate_limiter()));
+        });
+        Ok(status)
+    }
+
+    /// Returns the configuration and state of every block device.
+    pub fn block_devices_status(&self) -> Vec<BlockDeviceStatus> {
+        self.vm_resources
+            .block
+            .config_list
+            .iter()
+            .filter_map(|config| self.block_device_status(&config.drive_id).ok())
+            .collect()
+    }
+
+    /// Returns the configuration and state of the network interface with id `iface_id`.
+    pub fn net_device_status(
+        &self,
+        iface_id: &str,
+    ) -> result::Result<NetworkInterfaceStatus, VmmActionError> {
+        let config = self.vm_resources.network_interface.get(iface_id).ok_or(
+            VmmActionError::NetworkConfig(NetworkInterfaceError::DeviceIdNotFound),
+        )?;
+        let mut status = NetworkInterfaceStatus::from(config.clone());
+        self.inspect_virtio_device(TYPE_NET, iface_id, |device| {
+            let net = device
+                .as_any()
+                .downcast_ref::<Net>()
+                .expect("Unexpected Net type");
+            status.activated = net.is_activated();
+            status.link_up = Some(net.is_link_up());
+            status.rx_rate_limiter_budget = Some(RateLimiterBudget::from(net.rx_rate_limiter()));
+            status.tx_rate_limiter_budget = Some(RateLimiterBudget::from(net.tx_rate_limiter()));
+        });
+        Ok(status)
+    }
+
+    /// Returns the configuration and state of every network interface.
+    pub fn net_devices_status(&self) -> Vec<NetworkInterfaceStatus> {
+        self.vm_resources
+            .network_interface
+            .iter()
+            .filter_map(|config| self.net_device_status(&config.iface_id).ok())
+            .collect()
+    }
+
+    /// Returns the configuration and state of the vsock device.
+    pub fn vsock_device_status(&self) -> result::Result<VsockDeviceStatus, VmmActionError> {
+        let config = self
+            .vm_resources
+            .vsock
+            .as_ref()
+            .ok_or(VmmActionError::VsockConfig(VsockError::DeviceNotFound))?;
+        let mut status = VsockDeviceStatus::from(config.clone());
+        self.inspect_virtio_device(TYPE_VSOCK, &config.vsock_id, |device| {
+            status.activated = device.is_activated();
+        });
+        Ok(status)
+    }
+
     /// Write the metrics on user demand (flush). We use the word `flush` here to highlight the fact
     /// that the metrics will be written immediately.
     /// Defer to inner Vmm. We'll move to a variant where the Vmm simply exposes functionality like
@@ -57,6 +187,15 @@ impl VmmController {
             .map_err(VmmActionError::InternalVmm)
     }
 
+    /// Asks the guest of the inner Vmm to shut down, stopping the Vmm after `timeout`.
+    pub fn shutdown_gracefully(&mut self, timeout: Duration) -> ActionResult {
+        self.vmm
+            .lock()
+            .unwrap()
+            .shutdown_gracefully(timeout)
+            .map_err(VmmActionError::InternalVmm)
+    }
+
     /// Creates a new `VmmController`.
     pub fn new(vm_resources: VmResources, vmm: Arc<Mutex<Vmm>>) -> Self {
         VmmController { vm_resources, vmm }
@@ -176,11 +315,145 @@ impl VmmController {
         Ok(())
     }
 
+    /// Plugs a new net device, described by `netif_config`, into the running microVM. Returns
+    /// the `<size>@<baseaddr>:<irq>` location of the device, which the guest uses to probe it.
+    pub fn insert_net_device(
+        &mut self,
+        netif_config: NetworkInterfaceConfig,
+        event_manager: &mut EventManager,
+    ) -> std::result::Result<String, VmmActionError> {
+        let iface_id = netif_config.iface_id.clone();
+        if self
+            .vm_resources
+            .network_interface
+            .iter()
+            .any(|netif| netif.iface_id == iface_id)
+        {
+            return Err(VmmActionError::NetworkConfig(
+                NetworkInterfaceError::DeviceIdInUse(iface_id),
+            ));
+        }
+
+        // Validates the config against the attached interfaces and checks the tap.
+        self.vm_resources
+            .network_interface
+            .insert(netif_config)
+            .map_err(VmmActionError::NetworkConfig)?;
+
+        let cfg = self
+            .vm_resources
+            .network_interface
+            .iter()
+            .find(|netif| netif.iface_id == iface_id)
+            .expect("Missing the just inserted network interface");
+        match builder::hotplug_net_device(
+            &mut self.vmm.lock().unwrap(),
+            cfg,
+            self.vm_resources.mmds_config(),
+            event_manager,
+        ) {
+            Ok(dev_info) => Ok(dev_info.device_descriptor()),
+            Err(e) => {
+                let _ = self.vm_resources.network_interface.remove(&iface_id);
+                Err(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::HotplugFailed(e),
+                ))
+            }
+        }
+    }
+
+    /// Unplugs the net device with id `iface_id` from the running microVM. The guest has to
+    /// release the device beforehand, which resets it.
+    pub fn remove_net_device(
+        &mut self,
+        iface_id: &str,
+        event_manager: &mut EventManager,
+    ) -> ActionResult {
+        if !self
+            .vm_resources
+            .network_interface
+            .iter()
+            .any(|netif| netif.iface_id == iface_id)
+        {
+            return Err(VmmActionError::NetworkConfig(
+                NetworkInterfaceError::DeviceIdNotFound,
+            ));
+        }
+
+        let busdev = {
+            let mut vmm = self.vmm.lock().unwrap();
+            let released = vmm
+                .get_bus_device(DeviceType::Virtio(TYPE_NET), iface_id)
+                .map_or(true, |busdev| {
+                    busdev
+                        .lock()
+                        .expect("Poisoned device lock")
+                        .as_any()
+                        .downcast_ref::<MmioTransport>()
+                        // Only MmioTransport implements BusDevice at this point.
+                        .expect("Unexpected BusDevice type")
+                        .is_reset()
+                });
+            if !released {
+                return Err(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::DeviceNotReleased(iface_id.to_string()),
+                ));
+            }
+            vmm.unplug_mmio_device(TYPE_NET, iface_id)
+                .map_err(VmmActionError::InternalVmm)?
+        };
+        let virtio_device = busdev
+            .lock()
+            .expect("Poisoned device lock")
+            .as_any()
+            .downcast_ref::<MmioTransport>()
+            // Only MmioTransport implements BusDevice at this point.
+            .expect("Unexpected BusDevice type")
+            .device();
+        let tap_fd = virtio_device
+            .lock()
+            .expect("Poisoned device lock")
+            .as_any()
+            .downcast_ref::<Net>()
+            .expect("Unexpected Net type")
+            .tap()
+            .as_raw_fd();
+        // The event manager holds the last reference to the device, which closes its tap
+        // once it stops listening for events.
+        event_manager
+            .subscriber(tap_fd)
+            .and_then(|net_device| event_manager.remove_subscriber(&net_device))
+            .map_err(super::Error::EventManager)
+            .map_err(VmmActionError::InternalVmm)?;
+
+        self.vm_resources
+            .remove_net_device(iface_id)
+            .map_err(VmmActionError::NetworkConfig)
+    }
+
     /// Updates configuration for an emulated net device as described in `new_cfg`.
-    pub fn update_net_rate_limiters(
+    ///
+    /// Swapping the backing tap device requires moving its event registration, hence the
+    /// `event_manager` parameter.
+    pub fn update_net_device(
         &mut self,
         new_cfg: NetworkInterfaceUpdateConfig,
+        event_manager: &mut EventManager,
     ) -> ActionResult {
+        self.vm_resources
+            .network_interface
+            .validate_partial_update(&new_cfg)
+            .map_err(VmmActionError::NetworkConfig)?;
+
+        // Open the new tap before touching the device, so that a bad name leaves it unchanged.
+        let new_tap = new_cfg
+            .host_dev_name
+            .as_ref()
+            .map(|host_dev_name| Tap::open_named(host_dev_name))
+            .transpose()
+            .map_err(NetworkInterfaceError::OpenTap)
+            .map_err(VmmActionError::NetworkConfig)?;
+
         if let Some(busdev) = self
             .vmm
             .lock()
@@ -205,24 +478,58 @@ impl VmmController {
                 }};
             }
 
-            virtio_device
-                .lock()
-                .expect("Poisoned device lock")
+            let mut locked_device = virtio_device.lock().expect("Poisoned device lock");
+            let net_device = locked_device
                 .as_mut_any()
                 .downcast_mut::<Net>()
-                .unwrap()
-                .patch_rate_limiters(
-                    get_handler_arg!(rx_rate_limiter, bandwidth),
-                    get_handler_arg!(rx_rate_limiter, ops),
-                    get_handler_arg!(tx_rate_limiter, bandwidth),
-                    get_handler_arg!(tx_rate_limiter, ops),
-                );
+                .expect("Unexpected Net type");
+
+            if new_cfg.guest_mac.is_some() && !net_device.exposes_guest_mac() {
+                return Err(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::GuestMacNotSupported,
+                ));
+            }
+
+            // Swapping the tap is the only change which can fail, so it goes first.
+            if let Some(tap) = new_tap {
+                net_device
+                    .swap_tap(tap, event_manager)
+                    .map_err(NetworkInterfaceError::TapSwapFailed)
+                    .map_err(VmmActionError::NetworkConfig)?;
+            }
+
+            net_device.patch_rate_limiters(
+                get_handler_arg!(rx_rate_limiter, bandwidth),
+                get_handler_arg!(rx_rate_limiter, ops),
+                get_handler_arg!(tx_rate_limiter, bandwidth),
+                get_handler_arg!(tx_rate_limiter, ops),
+            );
+            if let Some(guest_mac) = new_cfg.guest_mac {
+                net_device.set_guest_mac(guest_mac);
+            }
+            if let Some(link_up) = new_cfg.link_up {
+                net_device.set_link_up(link_up);
+            }
+            let config_changed = new_cfg.guest_mac.is_some() || new_cfg.link_up.is_some();
+
+            // Keep the stored configuration in sync with the device.
+            self.vm_resources
+                .network_interface
+                .apply_partial_update(new_cfg)
+                .map_err(VmmActionError::NetworkConfig)?;
+
+            // The update is applied by now, the guest driver is merely told about it.
+            if config_changed {
+                net_device
+                    .signal_config_change()
+                    .map_err(NetworkInterfaceError::DeviceUpdateFailed)
+                    .map_err(VmmActionError::NetworkConfig)?;
+            }
+            Ok(())
         } else {
-            return Err(VmmActionError::NetworkConfig(
+            Err(VmmActionError::NetworkConfig(
                 NetworkInterfaceError::DeviceIdNotFound,
-            ));
+            ))
         }
-
-        Ok(())
     }
 }
@@ -28,14 +28,21 @@ pub fn default_filter() -> Result<SeccompFilter, Error> {
             allow_syscall(libc::SYS_epoll_pwait),
             #[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
             allow_syscall(libc::SYS_epoll_wait),
+            // Used for the eventfds of the devices hot-plugged after boot.
+            allow_syscall(libc::SYS_eventfd2),
             allow_syscall(libc::SYS_exit),
             allow_syscall(libc::SYS_exit_group),
             allow_syscall_if(
                 libc::SYS_fcntl,
-                or![and![
-                    Cond::new(1, ArgLen::DWORD, Eq, super::FCNTL_F_SETFD)?,
-                    Cond::new(2, ArgLen::QWORD, Eq, super::FCNTL_FD_CLOEXEC)?,
-                ]],
+                or![
+                    and![
+                        Cond::new(1, ArgLen::DWORD, Eq, super::FCNTL_F_SETFD)?,
+                        Cond::new(2, ArgLen::QWORD, Eq, super::FCNTL_FD_CLOEXEC)?,
+                    ],
+                    // Debug builds check that a file descriptor is open before closing it, like
+                    // the ones of the sockets used to set up a hot-plugged tap.
+                    and![Cond::new(1, ArgLen::DWORD, Eq, super::FCNTL_F_GETFD)?],
+                ],
             ),
             allow_syscall(libc::SYS_fstat),
             allow_syscall_if(
@@ -59,9 +66,17 @@ pub fn default_filter() -> Result<SeccompFilter, Error> {
                 ],
             ),
             allow_syscall(libc::SYS_getrandom),
+            // The API server reads the credentials of the processes connecting to its socket.
+            allow_syscall_if(
+                libc::SYS_getsockopt,
+                or![and![
+                    Cond::new(1, ArgLen::DWORD, Eq, libc::SOL_SOCKET as u64)?,
+                    Cond::new(2, ArgLen::DWORD, Eq, libc::SO_PEERCRED as u64)?,
+                ]],
+            ),
             allow_syscall_if(libc::SYS_ioctl, super::create_ioctl_seccomp_rule()?),
             allow_syscall(libc::SYS_lseek),
-            #[cfg(target_env = "musl")]
+            // Guest memory is discarded when the guest restarts.
             allow_syscall_if(
                 libc::SYS_madvise,
                 or![and![Cond::new(
@@ -15,6 +15,7 @@ pub use self::filters::get_seccomp_filter;
 
 // See include/uapi/asm-generic/fcntl.h in the kernel code.
 const FCNTL_FD_CLOEXEC: u64 = 1;
+const FCNTL_F_GETFD: u64 = 1;
 const FCNTL_F_SETFD: u64 = 2;
 
 // See include/uapi/linux/futex.h in the kernel code.
@@ -46,19 +47,28 @@ const KVM_CREATE_VCPU: u64 = 0xae41;
 const KVM_SET_TSS_ADDR: u64 = 0xae47;
 const KVM_CREATE_IRQCHIP: u64 = 0xae60;
 const KVM_RUN: u64 = 0xae80;
+const KVM_SET_MP_STATE: u64 = 0x4004_ae99;
 const KVM_SET_MSRS: u64 = 0x4008_ae89;
 const KVM_SET_CPUID2: u64 = 0x4008_ae90;
 const KVM_SET_USER_MEMORY_REGION: u64 = 0x4020_ae46;
 const KVM_IRQFD: u64 = 0x4020_ae76;
 const KVM_CREATE_PIT2: u64 = 0x4040_ae77;
 const KVM_IOEVENTFD: u64 = 0x4040_ae79;
+const KVM_SET_PIT2: u64 = 0x4070_aea0;
 const KVM_SET_REGS: u64 = 0x4090_ae82;
 const KVM_SET_SREGS: u64 = 0x4138_ae84;
 const KVM_SET_FPU: u64 = 0x41a0_ae8d;
 const KVM_SET_LAPIC: u64 = 0x4400_ae8f;
+const KVM_GET_PIT2: u64 = 0x8070_ae9f;
 const KVM_GET_SREGS: u64 = 0x8138_ae83;
+const KVM_SET_IRQCHIP: u64 = 0x8208_ae63;
 const KVM_GET_LAPIC: u64 = 0x8400_ae8e;
 const KVM_GET_SUPPORTED_CPUID: u64 = 0xc008_ae05;
+const KVM_GET_IRQCHIP: u64 = 0xc208_ae62;
+#[cfg(target_arch = "aarch64")]
+const KVM_SET_ONE_REG: u64 = 0x4010_aeac;
+#[cfg(target_arch = "aarch64")]
+const KVM_ARM_VCPU_INIT: u64 = 0x4020_aeae;
 
 // See include/uapi/linux/if_tun.h in the kernel code.
 const TUNSETIFF: u64 = 0x4004_54ca;
@@ -93,9 +103,20 @@ fn create_ioctl_seccomp_rule() -> Result<Vec<SeccompRule>, Error> {
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_CPUID2)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_FPU)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_LAPIC)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_MP_STATE)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_MSRS)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_REGS)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_SREGS)?],
+        // The state of the PIT and of the interrupt controllers is saved when they are created,
+        // and restored when the guest restarts.
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_GET_IRQCHIP)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_GET_PIT2)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_IRQCHIP)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_PIT2)?],
+        #[cfg(target_arch = "aarch64")]
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_ARM_VCPU_INIT)?],
+        #[cfg(target_arch = "aarch64")]
+        and![Cond::new(1, ArgLen::DWORD, Eq, KVM_SET_ONE_REG)?],
     ])
 }
 
@@ -6,19 +6,25 @@
 // found in the THIRD-PARTY file.
 
 use std::collections::HashMap;
+use std::os::raw::c_ulong;
+use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
-use std::{fmt, io};
+use std::{fmt, io, mem};
 
 #[cfg(target_arch = "aarch64")]
 use arch::aarch64::DeviceInfoForFDT;
 use arch::DeviceType;
 use devices;
 
+use devices::virtio::MmioTransport;
 use devices::BusDevice;
 use kernel::cmdline as kernel_cmdline;
+use kvm_bindings::{
+    kvm_ioeventfd, kvm_ioeventfd_flag_nr_datamatch, kvm_ioeventfd_flag_nr_deassign, KVMIO,
+};
 use kvm_ioctls::{IoEventAddress, VmFd};
-#[cfg(target_arch = "aarch64")]
 use utils::eventfd::EventFd;
+use utils::ioctl::ioctl_with_ref;
 
 /// Errors for MMIO device manager.
 #[derive(Debug)]
@@ -37,8 +43,14 @@ pub enum Error {
     RegisterIoEvent(kvm_ioctls::Error),
     /// Registering an IRQ FD failed.
     RegisterIrqFd(kvm_ioctls::Error),
+    /// Unregistering an IO Event failed.
+    UnregisterIoEvent(kvm_ioctls::Error),
+    /// Unregistering an IRQ FD failed.
+    UnregisterIrqFd(kvm_ioctls::Error),
     /// The device couldn't be found
     DeviceNotFound,
+    /// No slot reserved for hot-plugged devices is free.
+    HotplugSlotsExhausted,
     /// Failed to update the mmio device.
     UpdateFailed,
 }
@@ -55,7 +67,12 @@ impl fmt::Display for Error {
             Error::IrqsExhausted => write!(f, "no more IRQs are available"),
             Error::RegisterIoEvent(ref e) => write!(f, "failed to register IO event: {}", e),
             Error::RegisterIrqFd(ref e) => write!(f, "failed to register irqfd: {}", e),
+            Error::UnregisterIoEvent(ref e) => write!(f, "failed to unregister IO event: {}", e),
+            Error::UnregisterIrqFd(ref e) => write!(f, "failed to unregister irqfd: {}", e),
             Error::DeviceNotFound => write!(f, "the device couldn't be found"),
+            Error::HotplugSlotsExhausted => {
+                write!(f, "no slot reserved for hot-plugged devices is free")
+            }
             Error::UpdateFailed => write!(f, "failed to update the mmio device"),
         }
     }
@@ -67,19 +84,87 @@ type Result<T> = ::std::result::Result<T, Error>;
 /// It has to be larger than 0x100 (the offset where the configuration space starts from
 /// the beginning of the memory mapped device registers) + the size of the configuration space
 /// Currently hardcoded to 4K.
-const MMIO_LEN: u64 = 0x1000;
+pub const MMIO_LEN: u64 = 0x1000;
 
 /// This represents the offset at which the device should call BusDevice::write in order to write
 /// to its configuration space.
 pub const MMIO_CFG_SPACE_OFF: u64 = 0x100;
 
+// _IOW(KVMIO, 0x79, struct kvm_ioeventfd)
+fn kvm_ioeventfd_ioctl() -> c_ulong {
+    (1 << 30)
+        | ((mem::size_of::<kvm_ioeventfd>() as c_ulong) << 16)
+        | (c_ulong::from(KVMIO) << 8)
+        | 0x79
+}
+
+// `VmFd::unregister_ioevent` can't remove events registered with a datamatch value, which is
+// how the queue notifications are registered, so the ioctl is issued directly.
+fn unregister_queue_ioevent(vm: &VmFd, queue_evt: &EventFd, addr: u64, queue: u32) -> Result<()> {
+    let ioeventfd = kvm_ioeventfd {
+        datamatch: u64::from(queue),
+        addr,
+        len: mem::size_of::<u32>() as u32,
+        fd: queue_evt.as_raw_fd(),
+        flags: (1 << kvm_ioeventfd_flag_nr_datamatch) | (1 << kvm_ioeventfd_flag_nr_deassign),
+        ..Default::default()
+    };
+    // Safe because we know that our file is a VM fd, we know the kernel will only read the
+    // correct amount of memory from our pointer, and we verify the return result.
+    let ret = unsafe { ioctl_with_ref(vm, kvm_ioeventfd_ioctl(), &ioeventfd) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Error::UnregisterIoEvent(utils::errno::Error::last()))
+    }
+}
+
+// Registers the queue notifications of a device. On error, the ones already registered are
+// unregistered again.
+fn register_queue_ioevents(vm: &VmFd, queue_evts: &[EventFd], notify_addr: u64) -> Result<()> {
+    for (i, queue_evt) in queue_evts.iter().enumerate() {
+        if let Err(e) = vm.register_ioevent(queue_evt, &IoEventAddress::Mmio(notify_addr), i as u32)
+        {
+            unregister_queue_ioevents(vm, &queue_evts[..i], notify_addr);
+            return Err(Error::RegisterIoEvent(e));
+        }
+    }
+    Ok(())
+}
+
+// Unregisters the queue notifications of a device which could not be plugged in. This is best
+// effort, as the device is being dropped anyway.
+fn unregister_queue_ioevents(vm: &VmFd, queue_evts: &[EventFd], notify_addr: u64) {
+    for (i, queue_evt) in queue_evts.iter().enumerate() {
+        let _ = unregister_queue_ioevent(vm, queue_evt, notify_addr, i as u32);
+    }
+}
+
+// Stands in for a device at an MMIO range reserved for hot-plugged devices. The guest reads no
+// virtio magic value there, so it leaves the range alone until it probes it again, once a device
+// was plugged in.
+struct EmptySlot;
+
+impl BusDevice for EmptySlot {
+    fn read(&mut self, _offset: u64, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            *byte = 0;
+        }
+    }
+}
+
 /// Manages the complexities of registering a MMIO device.
 pub struct MMIODeviceManager {
     pub bus: devices::Bus,
     mmio_base: u64,
     irq: u32,
     last_irq: u32,
+    // MMIO ranges and IRQs reserved at boot for hot-plugged devices, which no device uses.
+    hotplug_slots: Vec<(u64, u32)>,
     id_to_dev_info: HashMap<(DeviceType, String), MMIODeviceInfo>,
+    /// The GPIO controller holding the power key, once registered.
+    #[cfg(target_arch = "aarch64")]
+    pub gpio: Option<Arc<Mutex<devices::legacy::Gpio>>>,
 }
 
 impl MMIODeviceManager {
@@ -93,70 +178,185 @@ impl MMIODeviceManager {
             irq: irq_interval.0,
             last_irq: irq_interval.1,
             bus: devices::Bus::new(),
+            hotplug_slots: Vec::new(),
             id_to_dev_info: HashMap::new(),
+            #[cfg(target_arch = "aarch64")]
+            gpio: None,
+        }
+    }
+
+    // Returns the MMIO base address and IRQ for a new virtio device.
+    fn allocate_slot(&mut self) -> Result<(u64, u32)> {
+        if self.irq > self.last_irq {
+            return Err(Error::IrqsExhausted);
         }
+        let slot = (self.mmio_base, self.irq);
+        self.mmio_base += MMIO_LEN;
+        self.irq += 1;
+        Ok(slot)
+    }
+
+    // Wires the device to the slot at `addr` and `irq`: queue notifications, interrupts and bus.
+    // The device is registered under `type_id` and `device_id`.
+    fn plug_mmio_device(
+        &mut self,
+        vm: &VmFd,
+        mmio_device: MmioTransport,
+        addr: u64,
+        irq: u32,
+        type_id: u32,
+        device_id: &str,
+    ) -> Result<MMIODeviceInfo> {
+        let notify_addr = addr + u64::from(devices::virtio::NOTIFY_REG_OFFSET);
+        let mmio_device = Arc::new(Mutex::new(mmio_device));
+        {
+            let locked_mmio_device = mmio_device.lock().expect("Poisoned device lock");
+            let locked_device = locked_mmio_device.locked_device();
+            let queue_evts = locked_device.queue_events();
+
+            register_queue_ioevents(vm, queue_evts, notify_addr)?;
+            if let Err(e) = vm.register_irqfd(locked_device.interrupt_evt(), irq) {
+                unregister_queue_ioevents(vm, queue_evts, notify_addr);
+                return Err(Error::RegisterIrqFd(e));
+            }
+        }
+
+        if let Err(e) = self.bus.insert(mmio_device.clone(), addr, MMIO_LEN) {
+            let locked_mmio_device = mmio_device.lock().expect("Poisoned device lock");
+            let locked_device = locked_mmio_device.locked_device();
+            unregister_queue_ioevents(vm, locked_device.queue_events(), notify_addr);
+            let _ = vm.unregister_irqfd(locked_device.interrupt_evt(), irq);
+            return Err(Error::BusError(e));
+        }
+
+        let dev_info = MMIODeviceInfo {
+            addr,
+            len: MMIO_LEN,
+            irq,
+        };
+        self.id_to_dev_info.insert(
+            (DeviceType::Virtio(type_id), device_id.to_string()),
+            dev_info.clone(),
+        );
+        Ok(dev_info)
     }
 
     /// Register an already created MMIO device to be used via MMIO transport.
     pub fn register_mmio_device(
         &mut self,
         vm: &VmFd,
-        mmio_device: devices::virtio::MmioTransport,
+        mmio_device: MmioTransport,
         cmdline: &mut kernel_cmdline::Cmdline,
         type_id: u32,
         device_id: &str,
     ) -> Result<u64> {
-        if self.irq > self.last_irq {
-            return Err(Error::IrqsExhausted);
-        }
+        let (addr, irq) = self.allocate_slot()?;
+        let dev_info = self.plug_mmio_device(vm, mmio_device, addr, irq, type_id, device_id)?;
 
-        for (i, queue_evt) in mmio_device
-            .locked_device()
-            .queue_events()
-            .iter()
-            .enumerate()
-        {
-            let io_addr = IoEventAddress::Mmio(
-                self.mmio_base + u64::from(devices::virtio::NOTIFY_REG_OFFSET),
-            );
-
-            vm.register_ioevent(queue_evt, &io_addr, i as u32)
-                .map_err(Error::RegisterIoEvent)?;
-        }
+        // as per doc, [virtio_mmio.]device=<size>@<baseaddr>:<irq> needs to be appended
+        // to kernel commandline for virtio mmio devices to get recognized
+        #[cfg(target_arch = "x86_64")]
+        cmdline
+            .insert("virtio_mmio.device", &dev_info.device_descriptor())
+            .map_err(Error::Cmdline)?;
 
-        vm.register_irqfd(mmio_device.locked_device().interrupt_evt(), self.irq)
-            .map_err(Error::RegisterIrqFd)?;
+        Ok(dev_info.addr)
+    }
 
+    /// Reserves an MMIO range and an IRQ for a device hot-plugged after boot. The guest is told
+    /// about the slot like about the other devices, but finds no device there until one is
+    /// plugged in and the guest probes the slot again.
+    pub fn reserve_hotplug_slot(&mut self, cmdline: &mut kernel_cmdline::Cmdline) -> Result<()> {
+        let (addr, irq) = self.allocate_slot()?;
         self.bus
-            .insert(Arc::new(Mutex::new(mmio_device)), self.mmio_base, MMIO_LEN)
+            .insert(Arc::new(Mutex::new(EmptySlot)), addr, MMIO_LEN)
             .map_err(Error::BusError)?;
 
-        // as per doc, [virtio_mmio.]device=<size>@<baseaddr>:<irq> needs to be appended
-        // to kernel commandline for virtio mmio devices to get recognized
-        // the size parameter has to be transformed to KiB, so dividing hexadecimal value in
-        // bytes to 1024; further, the '{}' formatting rust construct will automatically
-        // transform it to decimal
-
         #[cfg(target_arch = "x86_64")]
         cmdline
             .insert(
                 "virtio_mmio.device",
-                &format!("{}K@0x{:08x}:{}", MMIO_LEN / 1024, self.mmio_base, self.irq),
+                &MMIODeviceInfo::new(addr, irq).device_descriptor(),
             )
             .map_err(Error::Cmdline)?;
-        let ret = self.mmio_base;
-        self.id_to_dev_info.insert(
-            (DeviceType::Virtio(type_id), device_id.to_string()),
-            MMIODeviceInfo {
-                addr: ret,
-                len: MMIO_LEN,
-                irq: self.irq,
-            },
-        );
-        self.mmio_base += MMIO_LEN;
-        self.irq += 1;
 
-        Ok(ret)
+        self.hotplug_slots.push((addr, irq));
+        Ok(())
+    }
+
+    /// Plugs an already created MMIO device into one of the slots reserved at boot. Returns the
+    /// location of the device.
+    pub fn hotplug_mmio_device(
+        &mut self,
+        vm: &VmFd,
+        mmio_device: MmioTransport,
+        type_id: u32,
+        device_id: &str,
+    ) -> Result<MMIODeviceInfo> {
+        let (addr, irq) = self
+            .hotplug_slots
+            .pop()
+            .ok_or(Error::HotplugSlotsExhausted)?;
+        let empty_slot = self
+            .bus
+            .remove(addr)
+            .expect("Missing the empty hot-plug slot");
+        match self.plug_mmio_device(vm, mmio_device, addr, irq, type_id, device_id) {
+            Ok(dev_info) => Ok(dev_info),
+            Err(e) => {
+                // The range was just freed, so it can't overlap with another device.
+                let _ = self.bus.insert(empty_slot, addr, MMIO_LEN);
+                self.hotplug_slots.push((addr, irq));
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes a hot-plugged virtio device from the device manager and returns it. The slot of
+    /// the device is reused by the next hot-plugged device.
+    pub fn remove_mmio_device(
+        &mut self,
+        vm: &VmFd,
+        type_id: u32,
+        device_id: &str,
+    ) -> Result<Arc<Mutex<dyn BusDevice>>> {
+        let key = (DeviceType::Virtio(type_id), device_id.to_string());
+        let dev_info = self.id_to_dev_info.get(&key).ok_or(Error::DeviceNotFound)?;
+        let (addr, irq) = (dev_info.addr, dev_info.irq);
+        let notify_addr = addr + u64::from(devices::virtio::NOTIFY_REG_OFFSET);
+
+        // The device stays on the bus until nothing in KVM refers to it anymore.
+        {
+            let (_, device) = self.bus.get_device(addr).ok_or(Error::DeviceNotFound)?;
+            let locked_device = device.lock().expect("Poisoned device lock");
+            let mmio_device = locked_device
+                .as_any()
+                .downcast_ref::<MmioTransport>()
+                // Only MmioTransport implements BusDevice for virtio devices.
+                .expect("Unexpected BusDevice type");
+            let virtio_device = mmio_device.locked_device();
+            let queue_evts = virtio_device.queue_events();
+
+            for (i, queue_evt) in queue_evts.iter().enumerate() {
+                if let Err(e) = unregister_queue_ioevent(vm, queue_evt, notify_addr, i as u32) {
+                    let _ = register_queue_ioevents(vm, &queue_evts[..i], notify_addr);
+                    return Err(e);
+                }
+            }
+            if let Err(e) = vm.unregister_irqfd(virtio_device.interrupt_evt(), irq) {
+                let _ = register_queue_ioevents(vm, queue_evts, notify_addr);
+                return Err(Error::UnregisterIrqFd(e));
+            }
+        }
+
+        let device = self.bus.remove(addr).ok_or(Error::DeviceNotFound)?;
+        self.id_to_dev_info.remove(&key);
+        // The range was just freed, so it can't overlap with another device.
+        let _ = self
+            .bus
+            .insert(Arc::new(Mutex::new(EmptySlot)), addr, MMIO_LEN);
+        self.hotplug_slots.push((addr, irq));
+        Ok(device)
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -232,9 +432,69 @@ impl MMIODeviceManager {
     }
 
     #[cfg(target_arch = "aarch64")]
-    /// Gets the information of the devices registered up to some point in time.
-    pub fn get_device_info(&self) -> &HashMap<(DeviceType, String), MMIODeviceInfo> {
-        &self.id_to_dev_info
+    /// Register a MMIO GPIO controller, whose power key is used to shut down the guest.
+    pub fn register_mmio_gpio(&mut self, vm: &VmFd) -> Result<()> {
+        if self.irq > self.last_irq {
+            return Err(Error::IrqsExhausted);
+        }
+
+        // Attaching the GPIO device.
+        let gpio_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+        let device = Arc::new(Mutex::new(devices::legacy::Gpio::new(
+            gpio_evt.try_clone().map_err(Error::EventFd)?,
+        )));
+        vm.register_irqfd(&gpio_evt, self.irq)
+            .map_err(Error::RegisterIrqFd)?;
+
+        self.bus
+            .insert(device.clone(), self.mmio_base, MMIO_LEN)
+            .map_err(|err| Error::BusError(err))?;
+
+        let ret = self.mmio_base;
+        self.id_to_dev_info.insert(
+            (DeviceType::Gpio, "gpio".to_string()),
+            MMIODeviceInfo {
+                addr: ret,
+                len: MMIO_LEN,
+                irq: self.irq,
+            },
+        );
+        self.gpio = Some(device);
+
+        self.mmio_base += MMIO_LEN;
+        self.irq += 1;
+
+        Ok(())
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    /// Gets the information of the devices registered up to some point in time, along with the
+    /// free slots reserved for hot-plugged devices, which the guest has to know about at boot.
+    pub fn get_device_info(&self) -> HashMap<(DeviceType, String), MMIODeviceInfo> {
+        let mut device_info = self.id_to_dev_info.clone();
+        for &(addr, irq) in self.hotplug_slots.iter() {
+            device_info.insert(
+                (DeviceType::Virtio(0), format!("hotplug@{:x}", addr)),
+                MMIODeviceInfo::new(addr, irq),
+            );
+        }
+        device_info
+    }
+
+    /// Restarts every virtio device, for a guest that boots again. The devices stay activated and
+    /// keep their backends, like the tap or the disk image.
+    pub fn restart_virtio_devices(&self) {
+        for dev_info in self.id_to_dev_info.values() {
+            if let Some((_, device)) = self.bus.get_device(dev_info.addr) {
+                let mut locked_device = device.lock().expect("Poisoned device lock");
+                // Only the virtio devices are behind an MmioTransport.
+                if let Some(mmio_device) =
+                    locked_device.as_mut_any().downcast_mut::<MmioTransport>()
+                {
+                    mmio_device.restart();
+                }
+            }
+        }
     }
 
     /// Gets the the specified device.
@@ -263,6 +523,24 @@ pub struct MMIODeviceInfo {
     len: u64,
 }
 
+impl MMIODeviceInfo {
+    /// Creates the information of a device registered at `addr` and using `irq`.
+    pub fn new(addr: u64, irq: u32) -> Self {
+        MMIODeviceInfo {
+            addr,
+            irq,
+            len: MMIO_LEN,
+        }
+    }
+
+    /// Returns the location of the device in the `<size>@<baseaddr>:<irq>` format of the
+    /// `virtio_mmio.device` guest kernel parameter.
+    pub fn device_descriptor(&self) -> String {
+        // The size has to be given in KiB.
+        format!("{}K@0x{:08x}:{}", self.len / 1024, self.addr, self.irq)
+    }
+}
+
 #[cfg(target_arch = "aarch64")]
 impl DeviceInfoForFDT for MMIODeviceInfo {
     fn addr(&self) -> u64 {
@@ -524,6 +802,10 @@ mod tests {
             format!("{}", Error::IrqsExhausted),
             "no more IRQs are available"
         );
+        assert_eq!(
+            format!("{}", Error::HotplugSlotsExhausted),
+            "no slot reserved for hot-plugged devices is free"
+        );
         assert_eq!(
             format!("{}", Error::RegisterIoEvent(errno::Error::new(0))),
             format!("failed to register IO event: {}", errno::Error::new(0))
@@ -532,6 +814,190 @@ mod tests {
             format!("{}", Error::RegisterIrqFd(errno::Error::new(0))),
             format!("failed to register irqfd: {}", errno::Error::new(0))
         );
+        assert_eq!(
+            format!("{}", Error::UnregisterIoEvent(errno::Error::new(0))),
+            format!("failed to unregister IO event: {}", errno::Error::new(0))
+        );
+        assert_eq!(
+            format!("{}", Error::UnregisterIrqFd(errno::Error::new(0))),
+            format!("failed to unregister irqfd: {}", errno::Error::new(0))
+        );
+    }
+
+    #[test]
+    fn test_hotplug_remove_device() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(&mut 0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+
+        let type_id = 0;
+        let new_device = || {
+            let dummy = Arc::new(Mutex::new(DummyDevice::new()));
+            devices::virtio::MmioTransport::new(guest_mem.clone(), dummy).unwrap()
+        };
+
+        // Devices are only hot-plugged into the slots reserved at boot.
+        match device_manager.hotplug_mmio_device(vm.fd(), new_device(), type_id, "foo") {
+            Err(Error::HotplugSlotsExhausted) => (),
+            _ => panic!("No slot was reserved for the device."),
+        }
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        device_manager.reserve_hotplug_slot(&mut cmdline).unwrap();
+        device_manager.reserve_hotplug_slot(&mut cmdline).unwrap();
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline
+            .as_str()
+            .contains(&MMIODeviceInfo::new(0xd000_0000, arch::IRQ_BASE).device_descriptor()));
+
+        // The guest finds no device in a free slot.
+        let mut data = [0xff; 4];
+        assert!(device_manager.bus.read(0xd000_0000, &mut data));
+        assert_eq!(data, [0; 4]);
+
+        let foo_info = device_manager
+            .hotplug_mmio_device(vm.fd(), new_device(), type_id, "foo")
+            .unwrap();
+        let bar_info = device_manager
+            .hotplug_mmio_device(vm.fd(), new_device(), type_id, "bar")
+            .unwrap();
+        assert_eq!(bar_info.irq, arch::IRQ_BASE);
+        assert_eq!(foo_info.addr, bar_info.addr + MMIO_LEN);
+        assert_eq!(
+            foo_info.device_descriptor(),
+            format!("4K@0x{:08x}:{}", foo_info.addr, foo_info.irq)
+        );
+        match device_manager.hotplug_mmio_device(vm.fd(), new_device(), type_id, "baz") {
+            Err(Error::HotplugSlotsExhausted) => (),
+            _ => panic!("The reserved slots are all in use."),
+        }
+
+        assert!(device_manager
+            .remove_mmio_device(vm.fd(), type_id, "foo")
+            .is_ok());
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(type_id), "foo")
+            .is_none());
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(type_id), "bar")
+            .is_some());
+        let mut data = [0xff; 4];
+        assert!(device_manager.bus.read(foo_info.addr, &mut data));
+        assert_eq!(data, [0; 4]);
+        match device_manager.remove_mmio_device(vm.fd(), type_id, "foo") {
+            Err(Error::DeviceNotFound) => (),
+            _ => panic!("Removed a device that is no longer registered."),
+        }
+
+        // Restarting the devices keeps them registered.
+        device_manager.restart_virtio_devices();
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(type_id), "bar")
+            .is_some());
+
+        // The freed slot is reused, with the same queue notification address.
+        let baz_info = device_manager
+            .hotplug_mmio_device(vm.fd(), new_device(), type_id, "baz")
+            .unwrap();
+        assert_eq!(baz_info.addr, foo_info.addr);
+        assert_eq!(baz_info.irq, foo_info.irq);
+    }
+
+    #[test]
+    fn test_hotplug_device_rollback() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(&mut 0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        device_manager.reserve_hotplug_slot(&mut cmdline).unwrap();
+
+        let type_id = 0;
+        let new_device = || {
+            let dummy = Arc::new(Mutex::new(DummyDevice::new()));
+            devices::virtio::MmioTransport::new(guest_mem.clone(), dummy).unwrap()
+        };
+
+        // Without an interrupt controller, the queues are registered but the interrupt is not.
+        match device_manager.hotplug_mmio_device(vm.fd(), new_device(), type_id, "foo") {
+            Err(Error::RegisterIrqFd(_)) => (),
+            _ => panic!("The interrupt of the device should not be registered."),
+        }
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(type_id), "foo")
+            .is_none());
+        let mut data = [0xff; 4];
+        assert!(device_manager.bus.read(0xd000_0000, &mut data));
+        assert_eq!(data, [0; 4]);
+
+        // The slot is reused, which fails if the queue notifications of the first device were
+        // left behind.
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+        let foo_info = device_manager
+            .hotplug_mmio_device(vm.fd(), new_device(), type_id, "foo")
+            .unwrap();
+        assert_eq!(foo_info.irq, arch::IRQ_BASE);
+    }
+
+    #[test]
+    fn test_remove_device_rollback() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(&mut 0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        device_manager.reserve_hotplug_slot(&mut cmdline).unwrap();
+
+        let type_id = 0;
+        let dummy = Arc::new(Mutex::new(DummyDevice::new()));
+        let foo_info = device_manager
+            .hotplug_mmio_device(
+                vm.fd(),
+                devices::virtio::MmioTransport::new(guest_mem, dummy.clone()).unwrap(),
+                type_id,
+                "foo",
+            )
+            .unwrap();
+
+        // Unregistering the queue notification fails once it is already gone.
+        let notify_addr = foo_info.addr + u64::from(devices::virtio::NOTIFY_REG_OFFSET);
+        unregister_queue_ioevent(
+            vm.fd(),
+            &dummy.lock().unwrap().queue_evts[0],
+            notify_addr,
+            0,
+        )
+        .unwrap();
+        match device_manager.remove_mmio_device(vm.fd(), type_id, "foo") {
+            Err(Error::UnregisterIoEvent(_)) => (),
+            _ => panic!("The queue notification should not be unregistered."),
+        }
+
+        // The device is still plugged in, and its slot is not handed out again.
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(type_id), "foo")
+            .is_some());
+        assert!(device_manager.hotplug_slots.is_empty());
     }
 
     #[test]
@@ -18,6 +18,7 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate timerfd;
 
 extern crate arch;
 #[cfg(target_arch = "x86_64")]
@@ -27,6 +28,7 @@ extern crate kernel;
 #[macro_use]
 extern crate logger;
 extern crate dumbo;
+extern crate mmds;
 extern crate rate_limiter;
 extern crate seccomp;
 extern crate utils;
@@ -54,25 +56,30 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::os::unix::io::AsRawFd;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use arch::DeviceType;
 use arch::InitrdConfig;
+use builder::StartMicrovmError;
 #[cfg(target_arch = "x86_64")]
 use device_manager::legacy::PortIODeviceManager;
-#[cfg(target_arch = "aarch64")]
-use device_manager::mmio::MMIODeviceInfo;
-use device_manager::mmio::MMIODeviceManager;
+use device_manager::mmio::{MMIODeviceInfo, MMIODeviceManager};
+use devices::virtio::MmioTransport;
 use devices::BusDevice;
 use kernel::cmdline::Cmdline as KernelCmdline;
-use logger::{LoggerError, MetricsError, METRICS};
+use logger::{
+    Event, ExitReason, LoggerError, MetricsError, ShutdownPath, EVENTS, EXIT_STATUS, METRICS,
+};
 use polly::event_manager::{self, EventManager, Subscriber};
 use seccomp::{BpfProgram, BpfProgramRef, SeccompFilter};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
 use utils::epoll::{EpollEvent, EventSet};
 use utils::eventfd::EventFd;
 use utils::time::TimestampUs;
-use vm_memory::GuestMemoryMmap;
+use vm_memory::{GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
+use vmm_config::boot_source::BootConfig;
+use vmm_config::instance_info::{InstanceInfo, InstanceState, InstanceStateError};
 use vstate::{Vcpu, VcpuEvent, VcpuHandle, VcpuResponse, Vm};
 
 /// Success exit code.
@@ -92,23 +99,57 @@ pub const FC_EXIT_CODE_BAD_CONFIGURATION: u8 = 152;
 /// Command line arguments parsing error.
 pub const FC_EXIT_CODE_ARG_PARSING: u8 = 153;
 
+/// How long the VMM waits for its events to be delivered when stopping.
+const EVENTS_FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Writes the metrics and the exit status, if destinations were provided for them, right before
+/// the process exits with `exit_code`. Signal handlers use
+/// `ExitStatus::write_from_signal_handler` instead.
+pub fn report_exit(exit_code: i32, reason: ExitReason) {
+    // The metrics are only serialized for the exit status when there is one to write.
+    if !EXIT_STATUS.is_initialized() {
+        if let Err(e) = METRICS.write() {
+            error!("Failed to write metrics while stopping: {}", e);
+        }
+        return;
+    }
+    let metrics = match METRICS.write_snapshot() {
+        Ok(metrics) => Some(metrics),
+        Err(e) => {
+            error!("Failed to write metrics while stopping: {}", e);
+            None
+        }
+    };
+    if let Err(e) = EXIT_STATUS.write(exit_code, reason, metrics.as_ref().map(String::as_str)) {
+        error!("Failed to write the exit status: {}", e);
+    }
+}
+
 /// Errors associated with the VMM internal logic. These errors cannot be generated by direct user
 /// input, but can result from bad configuration of the host (for example if Firecracker doesn't
 /// have permissions to open the KVM fd).
 #[derive(Debug)]
 pub enum Error {
+    /// Cannot keep the boot source files open for restarting the guest.
+    BootSource(io::Error),
     /// This error is thrown by the minimal boot loader implementation.
     ConfigureSystem(arch::Error),
     /// Legacy devices work with Event file descriptors and the creation can fail because
     /// of resource exhaustion.
     #[cfg(target_arch = "x86_64")]
     CreateLegacyDevice(device_manager::legacy::Error),
+    /// Cannot discard the contents of the guest memory.
+    DiscardMemory(io::Error),
     /// Cannot read from an Event file descriptor.
     EventFd(io::Error),
     /// Polly error wrapper.
     EventManager(event_manager::Error),
+    /// GPIO Error.
+    GpioError(devices::legacy::GpioDeviceError),
     /// I8042 Error.
     I8042Error(devices::legacy::I8042DeviceError),
+    /// The microVM can't move to the requested state.
+    InstanceState(InstanceStateError),
     /// Cannot access kernel file.
     KernelFile(io::Error),
     /// Cannot open /dev/kvm. Either the host does not have KVM or Firecracker does not have
@@ -125,10 +166,14 @@ pub enum Error {
     Metrics(MetricsError),
     /// Cannot add a device to the MMIO Bus.
     RegisterMMIODevice(device_manager::mmio::Error),
+    /// Cannot remove a device from the MMIO Bus.
+    RemoveMMIODevice(device_manager::mmio::Error),
     /// Cannot build seccomp filters.
     SeccompFilters(seccomp::Error),
     /// Write to the serial console failed.
     Serial(io::Error),
+    /// A graceful shutdown of the guest was already requested.
+    ShutdownInProgress,
     /// Cannot create Timer file descriptor.
     TimerFd(io::Error),
     /// Vcpu error.
@@ -137,6 +182,12 @@ pub enum Error {
     VcpuEvent(vstate::Error),
     /// Cannot create a vCPU handle.
     VcpuHandle(vstate::Error),
+    /// vCPU MMIO bus update failed.
+    VcpuMmioBusUpdate,
+    /// vCPU pause failed.
+    VcpuPause,
+    /// vCPU reset failed.
+    VcpuReset,
     /// vCPU resume failed.
     VcpuResume,
     /// Cannot spawn a new Vcpu thread.
@@ -154,12 +205,16 @@ impl Display for Error {
         use self::Error::*;
 
         match self {
+            BootSource(e) => write!(f, "Cannot keep the boot source files open: {}", e),
             ConfigureSystem(e) => write!(f, "System configuration error: {:?}", e),
             #[cfg(target_arch = "x86_64")]
             CreateLegacyDevice(e) => write!(f, "Error creating legacy device: {:?}", e),
+            DiscardMemory(e) => write!(f, "Cannot discard the guest memory: {}", e),
             EventFd(e) => write!(f, "Event fd error: {}", e),
             EventManager(e) => write!(f, "Event manager error: {:?}", e),
+            GpioError(e) => write!(f, "GPIO error: {}", e),
             I8042Error(e) => write!(f, "I8042 error: {}", e),
+            InstanceState(e) => write!(f, "{}", e),
             KernelFile(e) => write!(f, "Cannot access kernel file: {}", e),
             KvmContext(e) => write!(f, "Failed to validate KVM support: {:?}", e),
             #[cfg(target_arch = "x86_64")]
@@ -168,12 +223,17 @@ impl Display for Error {
             Logger(e) => write!(f, "Logger error: {}", e),
             Metrics(e) => write!(f, "Metrics error: {}", e),
             RegisterMMIODevice(e) => write!(f, "Cannot add a device to the MMIO Bus. {}", e),
+            RemoveMMIODevice(e) => write!(f, "Cannot remove a device from the MMIO Bus. {}", e),
             SeccompFilters(e) => write!(f, "Cannot build seccomp filters: {}", e),
             Serial(e) => write!(f, "Error writing to the serial console: {:?}", e),
+            ShutdownInProgress => write!(f, "A graceful shutdown is already in progress."),
             TimerFd(e) => write!(f, "Error creating timer fd: {}", e),
             Vcpu(e) => write!(f, "Vcpu error: {}", e),
             VcpuEvent(e) => write!(f, "Cannot send event to vCPU. {:?}", e),
             VcpuHandle(e) => write!(f, "Cannot create a vCPU handle. {}", e),
+            VcpuMmioBusUpdate => write!(f, "vCPUs MMIO bus update failed."),
+            VcpuPause => write!(f, "vCPUs pause failed."),
+            VcpuReset => write!(f, "vCPUs reset failed."),
             VcpuResume => write!(f, "vCPUs resume failed."),
             VcpuSpawn(e) => write!(f, "Cannot spawn Vcpu thread: {}", e),
             Vm(e) => write!(f, "Vm error: {}", e),
@@ -204,6 +264,19 @@ pub trait VmmEventsObserver {
 /// Shorthand result type for internal VMM commands.
 pub type Result<T> = std::result::Result<T, Error>;
 
+// What the boot information written to guest memory needs to know about the vCPUs: their
+// number on x86_64 and their MPIDRs on aarch64.
+#[cfg(target_arch = "x86_64")]
+type BootVcpus = u8;
+#[cfg(target_arch = "aarch64")]
+type BootVcpus = Vec<u64>;
+
+// What the VMM needs to boot the guest again when it reboots.
+struct RestartConfig {
+    boot_config: BootConfig,
+    vcpus: BootVcpus,
+}
+
 /// Contains the state and associated methods required for the Firecracker VMM.
 pub struct Vmm {
     events_observer: Option<Box<dyn VmmEventsObserver>>,
@@ -216,6 +289,14 @@ pub struct Vmm {
     vcpus_handles: Vec<VcpuHandle>,
     exit_evt: EventFd,
     vm: Vm,
+    // Fires when a graceful shutdown of the guest takes too long.
+    shutdown_timer: TimerFd,
+    // When the pending graceful shutdown was requested, if any.
+    shutdown_requested: Option<Instant>,
+    // Set when the guest is booted again on reboot, instead of stopping the VMM.
+    restart_config: Option<RestartConfig>,
+    // Shared with the API server, which reports the state of the microVM.
+    instance_info: Arc<RwLock<InstanceInfo>>,
 
     // Guest VM devices.
     mmio_device_manager: MMIODeviceManager,
@@ -289,29 +370,97 @@ impl Vmm {
         Ok(())
     }
 
+    /// Plugs a virtio device into one of the slots reserved at boot. Returns the location of the
+    /// device, which the guest needs in order to discover it.
+    pub fn hotplug_mmio_device(
+        &mut self,
+        device: MmioTransport,
+        device_id: &str,
+    ) -> Result<MMIODeviceInfo> {
+        let type_id = device.locked_device().device_type();
+        let dev_info = self
+            .mmio_device_manager
+            .hotplug_mmio_device(self.vm.fd(), device, type_id, device_id)
+            .map_err(Error::RegisterMMIODevice)?;
+        self.update_vcpus_mmio_bus()?;
+
+        Ok(dev_info)
+    }
+
+    /// Unplugs a virtio device from the running microVM and returns it.
+    pub fn unplug_mmio_device(
+        &mut self,
+        type_id: u32,
+        device_id: &str,
+    ) -> Result<Arc<Mutex<dyn BusDevice>>> {
+        let device = self
+            .mmio_device_manager
+            .remove_mmio_device(self.vm.fd(), type_id, device_id)
+            .map_err(Error::RemoveMMIODevice)?;
+        self.update_vcpus_mmio_bus()?;
+
+        Ok(device)
+    }
+
+    // The vCPUs route MMIO exits through their own copy of the bus, which has to be refreshed
+    // whenever devices are plugged in or removed.
+    fn update_vcpus_mmio_bus(&mut self) -> Result<()> {
+        for handle in self.vcpus_handles.iter() {
+            handle
+                .send_event(VcpuEvent::UpdateMmioBus(Box::new(
+                    self.mmio_device_manager.bus.clone(),
+                )))
+                .map_err(Error::VcpuEvent)?;
+        }
+        for handle in self.vcpus_handles.iter() {
+            match handle
+                .response_receiver()
+                .recv_timeout(Duration::from_millis(1000))
+            {
+                Ok(VcpuResponse::MmioBusUpdated) => (),
+                _ => return Err(Error::VcpuMmioBusUpdate),
+            }
+        }
+        Ok(())
+    }
+
     /// Configures the system for boot.
     pub fn configure_system(&self, vcpus: &[Vcpu], initrd: &Option<InitrdConfig>) -> Result<()> {
+        self.write_boot_info(&Self::boot_vcpus(vcpus), initrd)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn boot_vcpus(vcpus: &[Vcpu]) -> BootVcpus {
+        vcpus.len() as u8
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn boot_vcpus(vcpus: &[Vcpu]) -> BootVcpus {
+        vcpus.iter().map(|cpu| cpu.get_mpidr()).collect()
+    }
+
+    // Writes the information the kernel boots with to guest memory.
+    fn write_boot_info(&self, vcpus: &BootVcpus, initrd: &Option<InitrdConfig>) -> Result<()> {
         #[cfg(target_arch = "x86_64")]
         arch::x86_64::configure_system(
             &self.guest_memory,
-            vm_memory::GuestAddress(arch::x86_64::layout::CMDLINE_START),
+            GuestAddress(arch::x86_64::layout::CMDLINE_START),
             self.kernel_cmdline.len() + 1,
             initrd,
-            vcpus.len() as u8,
+            *vcpus,
         )
         .map_err(Error::ConfigureSystem)?;
 
         #[cfg(target_arch = "aarch64")]
         {
-            let vcpu_mpidr = vcpus.into_iter().map(|cpu| cpu.get_mpidr()).collect();
             arch::aarch64::configure_system(
                 &self.guest_memory,
                 &self
                     .kernel_cmdline
                     .as_cstring()
                     .map_err(Error::LoadCommandline)?,
-                vcpu_mpidr,
-                self.mmio_device_manager.get_device_info(),
+                vcpus.clone(),
+                &self.mmio_device_manager.get_device_info(),
                 self.vm.get_irqchip(),
                 initrd,
             )
@@ -320,6 +469,131 @@ impl Vmm {
         Ok(())
     }
 
+    // Boots the guest again after it rebooted. The virtio devices are restarted and the guest
+    // memory is discarded before the kernel and initrd are loaded again, then the interrupt
+    // controllers and the vCPUs are brought back to their boot state. The devices keep their
+    // backends. `stopped_vcpus` are the vCPUs which already reported they stopped, and are
+    // paused.
+    fn restart(
+        &mut self,
+        restart_config: &RestartConfig,
+        stopped_vcpus: &[usize],
+    ) -> std::result::Result<(), StartMicrovmError> {
+        use self::StartMicrovmError::Internal;
+
+        self.set_instance_state(InstanceState::Rebooting)
+            .map_err(Internal)?;
+        self.pause_running_vcpus(stopped_vcpus).map_err(Internal)?;
+        self.mmio_device_manager.restart_virtio_devices();
+        self.discard_guest_memory().map_err(Internal)?;
+
+        let boot_config = &restart_config.boot_config;
+        let entry_addr = builder::load_kernel(boot_config, &self.guest_memory)?;
+        let initrd = builder::load_initrd_from_config(boot_config, &self.guest_memory)?;
+        #[cfg(target_arch = "x86_64")]
+        builder::load_cmdline(self)?;
+        self.write_boot_info(&restart_config.vcpus, &initrd)
+            .map_err(Internal)?;
+
+        #[cfg(target_arch = "x86_64")]
+        self.vm
+            .reset_irqchip()
+            .map_err(Error::Vm)
+            .map_err(Internal)?;
+        self.reset_vcpus(entry_addr).map_err(Internal)?;
+        self.resume_vcpus().map_err(Internal)?;
+        self.set_instance_state(InstanceState::Running)
+            .map_err(Internal)
+    }
+
+    fn pause_running_vcpus(&mut self, stopped_vcpus: &[usize]) -> Result<()> {
+        let running_vcpus = || {
+            self.vcpus_handles
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !stopped_vcpus.contains(index))
+                .map(|(_, handle)| handle)
+        };
+        for handle in running_vcpus() {
+            handle
+                .send_event(VcpuEvent::Pause)
+                .map_err(Error::VcpuEvent)?;
+        }
+        for handle in running_vcpus() {
+            match handle
+                .response_receiver()
+                .recv_timeout(Duration::from_millis(1000))
+            {
+                // A vCPU can stop on its own before handling the request, and is paused then.
+                Ok(VcpuResponse::Paused) | Ok(VcpuResponse::Exited(FC_EXIT_CODE_OK)) => (),
+                _ => return Err(Error::VcpuPause),
+            }
+        }
+        // Consume the exit notifications of the vCPUs which stopped meanwhile.
+        let _ = self.exit_evt.read();
+        Ok(())
+    }
+
+    fn discard_guest_memory(&self) -> Result<()> {
+        self.guest_memory
+            .with_regions(|_, region| {
+                // It's safe to unwrap because the guest address is valid.
+                let host_addr = self
+                    .guest_memory
+                    .get_host_address(region.start_addr())
+                    .unwrap();
+                // Safe because the range is an anonymous private mapping of the guest memory,
+                // which reads back as zeroes afterwards.
+                let ret = unsafe {
+                    libc::madvise(
+                        host_addr as *mut libc::c_void,
+                        region.len() as usize,
+                        libc::MADV_DONTNEED,
+                    )
+                };
+                if ret == 0 {
+                    Ok(())
+                } else {
+                    Err(io::Error::last_os_error())
+                }
+            })
+            .map_err(Error::DiscardMemory)
+    }
+
+    fn reset_vcpus(&mut self, entry_addr: GuestAddress) -> Result<()> {
+        for handle in self.vcpus_handles.iter() {
+            handle
+                .send_event(VcpuEvent::Reset(self.guest_memory.clone(), entry_addr))
+                .map_err(Error::VcpuEvent)?;
+        }
+        for handle in self.vcpus_handles.iter() {
+            match handle
+                .response_receiver()
+                .recv_timeout(Duration::from_millis(1000))
+            {
+                Ok(VcpuResponse::Reset) => (),
+                _ => return Err(Error::VcpuReset),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current state of the microVM.
+    pub fn instance_state(&self) -> InstanceState {
+        self.instance_info
+            .read()
+            .expect("Poisoned instance info lock")
+            .state()
+    }
+
+    pub(crate) fn set_instance_state(&self, state: InstanceState) -> Result<()> {
+        self.instance_info
+            .write()
+            .expect("Poisoned instance info lock")
+            .set_state(state)
+            .map_err(Error::InstanceState)
+    }
+
     /// Returns a reference to the inner `GuestMemoryMmap` object if present, or `None` otherwise.
     pub fn guest_memory(&self) -> &GuestMemoryMmap {
         &self.guest_memory
@@ -336,9 +610,49 @@ impl Vmm {
             .map_err(Error::I8042Error)
     }
 
-    /// Waits for all vCPUs to exit and terminates the Firecracker process.
-    pub fn stop(&mut self, exit_code: i32) {
+    /// Asks the guest to shut down, through the CTRL+ALT+DEL keystroke combo on x86_64 and the
+    /// power key on aarch64. If the guest doesn't exit within `timeout`, the VMM is stopped.
+    /// Which of the two happened is reported through the `Shutdown` event.
+    pub fn shutdown_gracefully(&mut self, timeout: Duration) -> Result<()> {
+        if self.shutdown_requested.is_some() {
+            return Err(Error::ShutdownInProgress);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        self.send_ctrl_alt_del()?;
+        #[cfg(target_arch = "aarch64")]
+        {
+            if let Some(gpio) = self.mmio_device_manager.gpio.as_ref() {
+                gpio.lock()
+                    .expect("GPIO lock was poisoned")
+                    .press_power_key()
+                    .map_err(Error::GpioError)?;
+            }
+        }
+
+        self.shutdown_timer
+            .set_state(TimerState::Oneshot(timeout), SetTimeFlags::Default);
+        self.shutdown_requested = Some(Instant::now());
+        self.set_instance_state(InstanceState::ShuttingDown)
+    }
+
+    // Emits the outcome of the pending graceful shutdown, if any.
+    fn report_shutdown(&mut self, path: ShutdownPath) {
+        if let Some(requested) = self.shutdown_requested.take() {
+            let elapsed = requested.elapsed();
+            let elapsed_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+            EVENTS.emit(Event::Shutdown { path, elapsed_ms });
+        }
+    }
+
+    /// Terminates the Firecracker process with `exit_code`. The metrics and the exit status are
+    /// written, with `reason` for the exit, and the `VmmStop` event is emitted before the
+    /// process exits, without waiting for the vCPUs.
+    pub fn stop(&mut self, exit_code: i32, reason: ExitReason) {
         info!("Vmm is stopping.");
+        if let Err(e) = self.set_instance_state(InstanceState::Halted) {
+            warn!("{}", e);
+        }
 
         if let Some(observer) = self.events_observer.as_mut() {
             if let Err(e) = observer.on_vmm_stop() {
@@ -346,9 +660,13 @@ impl Vmm {
             }
         }
 
-        // Write the metrics before exiting.
-        if let Err(e) = METRICS.write() {
-            error!("Failed to write metrics while stopping: {}", e);
+        // Write the metrics and the exit status before exiting.
+        report_exit(exit_code, reason);
+
+        // Give the subscribers a chance to learn about the exit.
+        EVENTS.emit(Event::VmmStop { exit_code });
+        if !EVENTS.flush(EVENTS_FLUSH_TIMEOUT) {
+            warn!("The events of the VMM were not all delivered before stopping.");
         }
 
         // Exit from Firecracker using the provided exit code. Safe because we're terminating
@@ -390,24 +708,68 @@ impl Subscriber for Vmm {
             // If the exit_code can't be found on any vcpu, it means that the exit signal
             // has been issued by the i8042 controller in which case we exit with
             // FC_EXIT_CODE_OK.
-            let exit_code = self
-                .vcpus_handles
-                .iter()
-                .find_map(|handle| match handle.response_receiver().try_recv() {
-                    Ok(VcpuResponse::Exited(exit_code)) => Some(exit_code),
-                    _ => None,
-                })
-                .unwrap_or(FC_EXIT_CODE_OK);
-            self.stop(i32::from(exit_code));
+            let mut exit_code = None;
+            let mut stopped_vcpus = Vec::new();
+            for (index, handle) in self.vcpus_handles.iter().enumerate() {
+                if let Ok(VcpuResponse::Exited(vcpu_exit_code)) =
+                    handle.response_receiver().try_recv()
+                {
+                    EVENTS.emit(Event::VcpuExit {
+                        vcpu: index,
+                        exit_code: vcpu_exit_code,
+                    });
+                    if vcpu_exit_code != FC_EXIT_CODE_OK {
+                        exit_code = exit_code.or(Some(vcpu_exit_code));
+                    }
+                    stopped_vcpus.push(index);
+                }
+            }
+
+            // A guest which rebooted on its own boots again, if asked to.
+            if exit_code.is_none() && self.shutdown_requested.is_none() {
+                if let Some(restart_config) = self.restart_config.take() {
+                    match self.restart(&restart_config, &stopped_vcpus) {
+                        Ok(()) => {
+                            self.restart_config = Some(restart_config);
+                            info!("The guest rebooted and was started again.");
+                            EVENTS.emit(Event::Reboot);
+                        }
+                        Err(e) => {
+                            error!("Failed to restart the guest: {}", e);
+                            self.stop(
+                                i32::from(FC_EXIT_CODE_GENERIC_ERROR),
+                                ExitReason::InternalError,
+                            );
+                        }
+                    }
+                    return;
+                }
+            }
+
+            let reason = if exit_code.is_some() {
+                ExitReason::VcpuError
+            } else if self.shutdown_requested.is_some() {
+                ExitReason::ApiRequest
+            } else {
+                ExitReason::GuestShutdown
+            };
+            self.report_shutdown(ShutdownPath::Guest);
+            self.stop(i32::from(exit_code.unwrap_or(FC_EXIT_CODE_OK)), reason);
+        } else if source == self.shutdown_timer.as_raw_fd() && event_set == EventSet::IN {
+            // Consume the timer expiration.
+            let _ = self.shutdown_timer.read();
+            warn!("The guest did not shut down in time, stopping the VMM.");
+            self.report_shutdown(ShutdownPath::Timeout);
+            self.stop(i32::from(FC_EXIT_CODE_OK), ExitReason::ApiRequest);
         } else {
             error!("Spurious EventManager event for handler: Vmm");
         }
     }
 
     fn interest_list(&self) -> Vec<EpollEvent> {
-        vec![EpollEvent::new(
-            EventSet::IN,
-            self.exit_evt.as_raw_fd() as u64,
-        )]
+        vec![
+            EpollEvent::new(EventSet::IN, self.exit_evt.as_raw_fd() as u64),
+            EpollEvent::new(EventSet::IN, self.shutdown_timer.as_raw_fd() as u64),
+        ]
     }
 }
@@ -3,18 +3,31 @@
 
 #![deny(warnings)]
 
+use std::fmt::{Display, Formatter};
 use std::fs::{File, OpenOptions};
-use std::path::PathBuf;
-
+use std::path::{Path, PathBuf};
+
+use device_manager::mmio::{MMIODeviceInfo, MMIO_LEN};
+use mmds::MMDS;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use utils::net::Tap;
 use vmm_config::boot_source::{
     BootConfig, BootSourceConfig, BootSourceConfigError, DEFAULT_KERNEL_CMDLINE,
 };
 use vmm_config::drive::*;
-use vmm_config::logger::{init_logger, LoggerConfig, LoggerConfigError};
+use vmm_config::logger::{
+    init_logger, init_logger_with_dest, open_log_dest, LoggerConfig, LoggerConfigError,
+};
 use vmm_config::machine_config::{VmConfig, VmConfigError};
-use vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
+use vmm_config::metrics::{
+    init_metrics, init_metrics_with_dest, open_metrics_dest, MetricsConfig, MetricsConfigError,
+};
+use vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use vmm_config::net::*;
 use vmm_config::vsock::*;
+use vmm_config::Writer;
 use vstate::VcpuConfig;
 
 type Result<E> = std::result::Result<(), E>;
@@ -23,7 +36,7 @@ type Result<E> = std::result::Result<(), E>;
 #[derive(Debug)]
 pub enum Error {
     /// JSON is invalid.
-    InvalidJson,
+    InvalidJson(serde_json::Error),
     /// Block device configuration error.
     BlockDevice(DriveError),
     /// Net device configuration error.
@@ -34,29 +47,366 @@ pub enum Error {
     Logger(LoggerConfigError),
     /// Metrics system configuration error.
     Metrics(MetricsConfigError),
+    /// MMDS configuration error.
+    MmdsConfig(MmdsConfigError),
     /// microVM vCpus or memory configuration error.
     VmConfig(VmConfigError),
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::Error::*;
+
+        match self {
+            InvalidJson(err) => write!(f, "Invalid JSON: {}", err),
+            BlockDevice(err) => write!(f, "Invalid drive: {}", err),
+            NetDevice(err) => write!(f, "Invalid network interface: {}", err),
+            BootSource(err) => write!(f, "Invalid boot source: {}", err),
+            Logger(err) => write!(f, "Cannot configure the logger: {}", err),
+            Metrics(err) => write!(f, "Cannot configure the metrics system: {}", err),
+            MmdsConfig(err) => write!(f, "Invalid MMDS configuration: {}", err),
+            VmConfig(err) => write!(f, "Invalid machine configuration: {}", err),
+        }
+    }
+}
+
 /// Used for configuring a vmm from one single json passed to the Firecracker process.
-#[derive(Deserialize)]
+/// It is also the format the configuration of a microVM is exported in, so that it can
+/// be used to configure another one.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct VmmConfig {
-    #[serde(rename = "boot-source")]
-    boot_source: BootSourceConfig,
+    // The boot source is mandatory in the json, but is only known to `VmResources` once it
+    // has been configured.
+    #[serde(
+        rename = "boot-source",
+        deserialize_with = "deserialize_boot_source",
+        skip_serializing_if = "Option::is_none"
+    )]
+    boot_source: Option<BootSourceConfig>,
     #[serde(rename = "drives")]
     block_devices: Vec<BlockDeviceConfig>,
     #[serde(rename = "network-interfaces", default)]
     net_devices: Vec<NetworkInterfaceConfig>,
-    #[serde(rename = "logger")]
+    #[serde(rename = "logger", skip_serializing_if = "Option::is_none")]
     logger: Option<LoggerConfig>,
-    #[serde(rename = "machine-config")]
+    #[serde(rename = "machine-config", skip_serializing_if = "Option::is_none")]
     machine_config: Option<VmConfig>,
-    #[serde(rename = "metrics")]
+    #[serde(rename = "metrics", skip_serializing_if = "Option::is_none")]
     metrics: Option<MetricsConfig>,
-    #[serde(rename = "vsock")]
+    #[serde(rename = "mmds-config", skip_serializing_if = "Option::is_none")]
+    mmds_config: Option<MmdsConfig>,
+    #[serde(rename = "vsock", skip_serializing_if = "Option::is_none")]
     vsock_device: Option<VsockDeviceConfig>,
 }
 
+fn deserialize_boot_source<'de, D>(d: D) -> std::result::Result<Option<BootSourceConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    BootSourceConfig::deserialize(d).map(Some)
+}
+
+/// An error found while validating a microVM configuration, along with the location of the
+/// invalid value in the JSON document.
+#[derive(Debug, PartialEq)]
+pub struct ValidationError {
+    /// The path of the invalid value, e.g. `drives[1].path_on_host`. It is empty when the
+    /// document itself is invalid.
+    pub path: String,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new<T: Display>(path: &str, message: T) -> Self {
+        ValidationError {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Checks a microVM configuration in the format accepted by `VmResources::from_json`.
+/// The resources are configured on a scratch `VmResources`, without initializing the logger,
+/// the metrics system or the MMDS.
+struct ConfigValidator<'a> {
+    config: &'a Map<String, Value>,
+    resources: VmResources,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> ConfigValidator<'a> {
+    fn new(config: &'a Map<String, Value>) -> Self {
+        ConfigValidator {
+            config,
+            resources: VmResources::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error<T: Display>(&mut self, path: &str, message: T) {
+        self.errors.push(ValidationError::new(path, message));
+    }
+
+    // Deserializes the value found at `path`. A value that is missing and `required` is
+    // reported as an error.
+    fn parse<T: DeserializeOwned>(
+        &mut self,
+        value: Option<&Value>,
+        path: &str,
+        required: bool,
+    ) -> Option<T> {
+        match value {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| self.error(path, e))
+                .ok(),
+            None if required => {
+                self.error(path, "missing field");
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Deserializes the elements of the list found at `key`, along with their paths.
+    fn parse_list<T: DeserializeOwned>(&mut self, key: &str, required: bool) -> Vec<(String, T)> {
+        let config = self.config;
+        match config.get(key) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .enumerate()
+                .filter_map(|(index, value)| {
+                    let path = format!("{}[{}]", key, index);
+                    self.parse(Some(value), &path, true).map(|cfg| (path, cfg))
+                })
+                .collect(),
+            Some(_) => {
+                self.error(key, "invalid type, expected a list");
+                Vec::new()
+            }
+            None => {
+                if required {
+                    self.error(key, "missing field");
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    fn validate(mut self) -> Vec<ValidationError> {
+        let config = self.config;
+
+        if let Some(logger) = self.parse::<LoggerConfig>(config.get("logger"), "logger", false) {
+            if let Err(e) = Writer::new(logger.log_path) {
+                self.error(
+                    "logger.log_path",
+                    LoggerConfigError::InitializationFailure(e.to_string()),
+                );
+            }
+        }
+        if let Some(metrics) = self.parse::<MetricsConfig>(config.get("metrics"), "metrics", false)
+        {
+            if let Err(e) = Writer::new(metrics.metrics_path) {
+                self.error(
+                    "metrics.metrics_path",
+                    MetricsConfigError::InitializationFailure(e.to_string()),
+                );
+            }
+        }
+        if let Some(machine_config) =
+            self.parse::<VmConfig>(config.get("machine-config"), "machine-config", false)
+        {
+            if let Err(e) = self.resources.set_vm_config(&machine_config) {
+                self.error("machine-config", e);
+            }
+        }
+        if let Some(boot_source) =
+            self.parse::<BootSourceConfig>(config.get("boot-source"), "boot-source", true)
+        {
+            self.validate_boot_source(boot_source);
+        }
+        for (path, drive_config) in self.parse_list::<BlockDeviceConfig>("drives", true) {
+            self.validate_block_device(&path, drive_config);
+        }
+        for (path, net_config) in
+            self.parse_list::<NetworkInterfaceConfig>("network-interfaces", false)
+        {
+            self.validate_net_device(&path, net_config);
+        }
+        if let Some(vsock_config) =
+            self.parse::<VsockDeviceConfig>(config.get("vsock"), "vsock", false)
+        {
+            self.resources.set_vsock_device(vsock_config);
+        }
+        if let Some(mmds_config) =
+            self.parse::<MmdsConfig>(config.get("mmds-config"), "mmds-config", false)
+        {
+            if let Err(e) = self.resources.validate_mmds_config(&mmds_config) {
+                let path = match e {
+                    MmdsConfigError::InvalidIpv4Addr => "mmds-config.ipv4_address",
+                    MmdsConfigError::InvalidIpv6Addr => "mmds-config.ipv6_address",
+                    MmdsConfigError::InvalidNetworkInterfaceId(_) => {
+                        "mmds-config.network_interfaces"
+                    }
+                };
+                self.error(path, e);
+            }
+        }
+        self.validate_kernel_cmdline();
+
+        self.errors
+    }
+
+    fn validate_boot_source(&mut self, boot_source: BootSourceConfig) {
+        use self::BootSourceConfigError::*;
+
+        if let Err(e) = self.resources.set_boot_source(boot_source) {
+            let path = match e {
+                InvalidKernelPath(_) => "boot-source.kernel_image_path",
+                InvalidInitrdPath(_) => "boot-source.initrd_path",
+                InvalidKernelCommandLine(_) => "boot-source.boot_args",
+                _ => "boot-source",
+            };
+            self.error(path, e);
+            return;
+        }
+
+        // Safe to unwrap because the boot source was just configured.
+        let check_result = self
+            .resources
+            .boot_source()
+            .unwrap()
+            .kernel_file
+            .try_clone()
+            .map_err(InvalidKernelPath)
+            .map(|mut kernel_file| {
+                kernel::loader::check_kernel(&mut kernel_file, arch::get_kernel_start())
+            });
+        match check_result {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => self.error(
+                "boot-source.kernel_image_path",
+                format!("The kernel image is invalid: {}", e),
+            ),
+            Err(e) => self.error("boot-source.kernel_image_path", e),
+        }
+    }
+
+    fn validate_block_device(&mut self, path: &str, drive_config: BlockDeviceConfig) {
+        use self::DriveError::*;
+
+        // The block device is opened with the same permissions as when the microVM is built.
+        if let Err(e) = OpenOptions::new()
+            .read(true)
+            .write(!drive_config.is_read_only)
+            .open(&drive_config.path_on_host)
+        {
+            self.error(&format!("{}.path_on_host", path), CannotOpenBlockDevice(e));
+            return;
+        }
+        if let Err(e) = self.resources.set_block_device(drive_config) {
+            let field = match e {
+                InvalidBlockDevicePath | BlockDevicePathAlreadyExists => ".path_on_host",
+                RootBlockDeviceAlreadyAdded => ".is_root_device",
+                _ => "",
+            };
+            self.error(&format!("{}{}", path, field), e);
+        }
+    }
+
+    fn validate_net_device(&mut self, path: &str, net_config: NetworkInterfaceConfig) {
+        use self::NetworkInterfaceError::*;
+
+        // The tap device is looked up first, since configuring the interface opens the tap
+        // device, which would create it if it does not exist.
+        let host_dev_name = net_config.host_dev_name.clone();
+        if host_dev_name.contains('/') || !Path::new("/sys/class/net").join(&host_dev_name).exists()
+        {
+            self.error(
+                &format!("{}.host_dev_name", path),
+                format!("The tap device {} does not exist.", host_dev_name),
+            );
+            return;
+        }
+        if let Err(e) = self.resources.set_net_device(net_config) {
+            let field = match e {
+                GuestMacAddressInUse(_) => ".guest_mac",
+                HostDeviceNameInUse(_) | OpenTap(_) => ".host_dev_name",
+                _ => "",
+            };
+            self.error(&format!("{}{}", path, field), e);
+        }
+    }
+
+    // Checks that the kernel command line still fits once the builder appends the root
+    // device and, on x86_64, the location of the virtio devices.
+    fn validate_kernel_cmdline(&mut self) {
+        let mut cmdline = match self.resources.boot_source() {
+            Some(boot_config) => boot_config.cmdline.clone(),
+            None => return,
+        };
+        let mut result = Ok(());
+        if let Some(root) = self
+            .resources
+            .block
+            .config_list
+            .iter()
+            .find(|drive_config| drive_config.is_root_device)
+        {
+            result = match root.get_partuuid() {
+                Some(partuuid) => cmdline.insert_str(format!("root=PARTUUID={}", partuuid)),
+                None => cmdline.insert_str("root=/dev/vda"),
+            }
+            .and_then(|_| cmdline.insert_str(if root.is_read_only() { "ro" } else { "rw" }));
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let device_count = self.resources.block.config_list.len()
+                + self.resources.network_interface.iter().count()
+                + self.resources.vsock.iter().count()
+                + self.resources.vm_config().hotplug_slots.unwrap_or(0) as usize;
+            let irq_count = (arch::IRQ_MAX - arch::IRQ_BASE + 1) as usize;
+            if device_count > irq_count {
+                self.error(
+                    "",
+                    format!(
+                        "Too many devices: {} are configured, but at most {} are supported.",
+                        device_count, irq_count
+                    ),
+                );
+                return;
+            }
+            for index in 0..device_count {
+                let dev_info = MMIODeviceInfo::new(
+                    arch::MMIO_MEM_START + index as u64 * MMIO_LEN,
+                    arch::IRQ_BASE + index as u32,
+                );
+                result = result.and_then(|_| {
+                    cmdline.insert("virtio_mmio.device", &dev_info.device_descriptor())
+                });
+            }
+        }
+
+        if let Err(e) = result {
+            self.error(
+                "boot-source.boot_args",
+                BootSourceConfigError::InvalidKernelCommandLine(e.to_string()),
+            );
+        }
+    }
+}
+
 /// A data structure that encapsulates the device configurations
 /// held in the Vmm.
 #[derive(Default)]
@@ -65,12 +415,20 @@ pub struct VmResources {
     vm_config: VmConfig,
     /// The boot configuration for this microVM.
     boot_config: Option<BootConfig>,
+    /// The boot source configuration `boot_config` was built from.
+    boot_source_config: Option<BootSourceConfig>,
     /// The configurations for block devices.
     pub block: BlockDeviceConfigs,
     /// The configurations for network interface devices.
     pub network_interface: NetworkInterfaceConfigs,
     /// The configurations for vsock devices.
     pub vsock: Option<VsockDeviceConfig>,
+    /// The MMDS configuration.
+    mmds_config: MmdsConfig,
+    /// The logger configuration, if the logger was configured through the VMM.
+    logger_config: Option<LoggerConfig>,
+    /// The metrics configuration, if the metrics system was configured through the VMM.
+    metrics_config: Option<MetricsConfig>,
 }
 
 impl VmResources {
@@ -80,25 +438,36 @@ impl VmResources {
         firecracker_version: &str,
     ) -> std::result::Result<Self, Error> {
         let vmm_config: VmmConfig = serde_json::from_slice::<VmmConfig>(config_json.as_bytes())
-            .map_err(|_| Error::InvalidJson)?;
+            .map_err(Error::InvalidJson)?;
 
-        if let Some(logger) = vmm_config.logger {
-            init_logger(logger, firecracker_version).map_err(Error::Logger)?;
-        }
-
-        if let Some(metrics) = vmm_config.metrics {
-            init_metrics(metrics).map_err(Error::Metrics)?;
-        }
+        let mut resources: Self = Self::default();
+        resources.apply_vmm_config(vmm_config, firecracker_version)?;
+        Ok(resources)
+    }
 
+    /// Replaces the configuration of the microVM with `vmm_config`. Either all of it is applied
+    /// or nothing changes, except when the logger fails to be initialized after the metrics
+    /// system, which the error then reports. The logger and the metrics system, which cannot be
+    /// reset once initialized, are set up last and kept as they are when they are left out of
+    /// `vmm_config` or their configuration is unchanged. Changing them once configured is an
+    /// error.
+    pub fn apply_vmm_config(
+        &mut self,
+        vmm_config: VmmConfig,
+        firecracker_version: &str,
+    ) -> std::result::Result<(), Error> {
         let mut resources: Self = Self::default();
         if let Some(machine_config) = vmm_config.machine_config {
             resources
                 .set_vm_config(&machine_config)
                 .map_err(Error::VmConfig)?;
         }
-        resources
-            .set_boot_source(vmm_config.boot_source)
-            .map_err(Error::BootSource)?;
+        // The boot source is always present after deserialization.
+        if let Some(boot_source) = vmm_config.boot_source {
+            resources
+                .set_boot_source(boot_source)
+                .map_err(Error::BootSource)?;
+        }
         for drive_config in vmm_config.block_devices.into_iter() {
             resources
                 .set_block_device(drive_config)
@@ -112,7 +481,122 @@ impl VmResources {
         if let Some(vsock_config) = vmm_config.vsock_device {
             resources.set_vsock_device(vsock_config);
         }
-        Ok(resources)
+        let mmds_config = vmm_config.mmds_config.unwrap_or_default();
+        resources
+            .validate_mmds_config(&mmds_config)
+            .map_err(Error::MmdsConfig)?;
+
+        // Neither the logger nor the metrics system can be initialized twice, so everything that
+        // may fail is checked for both before either is initialized.
+        let logger_config = vmm_config
+            .logger
+            .filter(|logger| self.logger_config.as_ref() != Some(logger));
+        let metrics_config = vmm_config
+            .metrics
+            .filter(|metrics| self.metrics_config.as_ref() != Some(metrics));
+        if logger_config.is_some() && self.logger_config.is_some() {
+            return Err(Error::Logger(LoggerConfigError::InitializationFailure(
+                "The logger is already configured.".to_string(),
+            )));
+        }
+        if metrics_config.is_some() && self.metrics_config.is_some() {
+            return Err(Error::Metrics(MetricsConfigError::InitializationFailure(
+                "The metrics system is already configured.".to_string(),
+            )));
+        }
+        let logger = logger_config
+            .map(|logger| open_log_dest(&logger).map(|log_dest| (logger, log_dest)))
+            .transpose()
+            .map_err(Error::Logger)?;
+        let metrics = metrics_config
+            .map(|metrics| open_metrics_dest(&metrics).map(|metrics_dest| (metrics, metrics_dest)))
+            .transpose()
+            .map_err(Error::Metrics)?;
+
+        // The metrics system is initialized first, so that nothing changes when it fails.
+        let metrics_initialized = metrics.is_some();
+        if let Some((metrics, metrics_dest)) = metrics {
+            // The metrics system was checked to be uninitialized above.
+            init_metrics_with_dest(metrics_dest).map_err(Error::Metrics)?;
+            self.metrics_config = Some(metrics);
+        }
+        if let Some((logger, log_dest)) = logger {
+            init_logger_with_dest(logger.clone(), log_dest, firecracker_version).map_err(|e| {
+                match e {
+                    // The metrics system stays initialized, which the client has to know.
+                    LoggerConfigError::InitializationFailure(msg) if metrics_initialized => {
+                        Error::Logger(LoggerConfigError::InitializationFailure(format!(
+                            "{} The metrics system was configured nonetheless.",
+                            msg
+                        )))
+                    }
+                    e => Error::Logger(e),
+                }
+            })?;
+            self.logger_config = Some(logger);
+        }
+        // The MMDS configuration was validated above, so this only updates the MMDS version.
+        resources
+            .set_mmds_config(mmds_config)
+            .map_err(Error::MmdsConfig)?;
+
+        resources.logger_config = self.logger_config.take();
+        resources.metrics_config = self.metrics_config.take();
+        *self = resources;
+        Ok(())
+    }
+
+    /// Checks the microVM configuration described by the `config_json` param without
+    /// building anything: the files it refers to are opened, the kernel image headers, the
+    /// tap devices and the kernel command line length are checked, but the logger, the metrics
+    /// system and the MMDS are not initialized. All the errors found are returned.
+    pub fn validate_json(config_json: &str) -> std::result::Result<(), Vec<ValidationError>> {
+        let config = serde_json::from_str::<Map<String, Value>>(config_json)
+            .map_err(|e| vec![ValidationError::new("", e)])?;
+        let errors = ConfigValidator::new(&config).validate();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the configuration of the microVM, in the format accepted by `from_json`.
+    pub fn vmm_config(&self) -> VmmConfig {
+        VmmConfig {
+            boot_source: self.boot_source_config.clone(),
+            block_devices: self.block.config_list.iter().cloned().collect(),
+            net_devices: self.network_interface.iter().cloned().collect(),
+            logger: self.logger_config.clone(),
+            machine_config: Some(self.vm_config.clone()),
+            metrics: self.metrics_config.clone(),
+            mmds_config: Some(self.mmds_config.clone()),
+            vsock_device: self.vsock.clone(),
+        }
+    }
+
+    /// Gets a reference to the logger configuration, if the logger was set up through
+    /// `set_logger`.
+    pub fn logger_config(&self) -> Option<&LoggerConfig> {
+        self.logger_config.as_ref()
+    }
+
+    /// Initializes the logger and keeps its configuration.
+    pub fn set_logger(
+        &mut self,
+        logger_config: LoggerConfig,
+        firecracker_version: &str,
+    ) -> Result<LoggerConfigError> {
+        init_logger(logger_config.clone(), firecracker_version)?;
+        self.logger_config = Some(logger_config);
+        Ok(())
+    }
+
+    /// Initializes the metrics system and keeps its configuration.
+    pub fn set_metrics(&mut self, metrics_config: MetricsConfig) -> Result<MetricsConfigError> {
+        init_metrics(metrics_config.clone())?;
+        self.metrics_config = Some(metrics_config);
+        Ok(())
     }
 
     /// Returns a VcpuConfig based on the vm config.
@@ -167,6 +651,14 @@ impl VmResources {
             self.vm_config.cpu_template = machine_config.cpu_template;
         }
 
+        if machine_config.reboot_policy.is_some() {
+            self.vm_config.reboot_policy = machine_config.reboot_policy;
+        }
+
+        if machine_config.hotplug_slots.is_some() {
+            self.vm_config.hotplug_slots = machine_config.hotplug_slots;
+        }
+
         Ok(())
     }
 
@@ -175,6 +667,11 @@ impl VmResources {
         self.boot_config.as_ref()
     }
 
+    /// Gets a reference to the configuration the boot source was set up with.
+    pub fn boot_source_config(&self) -> Option<&BootSourceConfig> {
+        self.boot_source_config.as_ref()
+    }
+
     /// Set the guest boot source configuration.
     pub fn set_boot_source(
         &mut self,
@@ -205,6 +702,7 @@ impl VmResources {
             kernel_file,
             initrd_file,
         });
+        self.boot_source_config = Some(boot_source_cfg);
         Ok(())
     }
 
@@ -218,6 +716,12 @@ impl VmResources {
         self.block.insert(block_device_config)
     }
 
+    /// Removes the block device with id `drive_id`, so that it is not attached when the
+    /// VM starts.
+    pub fn remove_block_device(&mut self, drive_id: &str) -> Result<DriveError> {
+        self.block.remove(drive_id).map(|_| ())
+    }
+
     /// Updates the path of the host file backing the emulated block device with id `drive_id`.
     pub fn update_block_device_path(
         &mut self,
@@ -252,45 +756,103 @@ impl VmResources {
         self.network_interface.insert(body)
     }
 
+    /// Removes the network device with id `iface_id`. The interface is also removed from
+    /// the interfaces forwarding requests to the MMDS.
+    pub fn remove_net_device(&mut self, iface_id: &str) -> Result<NetworkInterfaceError> {
+        self.network_interface.remove(iface_id)?;
+        if let Some(ref mut iface_ids) = self.mmds_config.network_interfaces {
+            iface_ids.retain(|id| id != iface_id);
+        }
+        Ok(())
+    }
+
     /// Updates configuration for an emulated net device as described in `new_cfg`.
-    pub fn update_net_rate_limiters(
+    pub fn update_net_device(
         &mut self,
         new_cfg: NetworkInterfaceUpdateConfig,
     ) -> Result<NetworkInterfaceError> {
-        let old_cfg = self
-            .network_interface
-            .iter_mut()
-            .find(|&&mut ref c| c.iface_id == new_cfg.iface_id)
-            .ok_or(NetworkInterfaceError::DeviceIdNotFound)?;
-
-        macro_rules! update_rate_limiter {
-            ($rate_limiter: ident) => {{
-                if let Some(new_rlim_cfg) = new_cfg.$rate_limiter {
-                    if let Some(ref mut old_rlim_cfg) = old_cfg.$rate_limiter {
-                        // We already have an RX rate limiter set, so we'll update it.
-                        old_rlim_cfg.update(&new_rlim_cfg);
-                    } else {
-                        // No old RX rate limiter; create one now.
-                        old_cfg.$rate_limiter = Some(new_rlim_cfg);
-                    }
-                }
-            }};
+        self.network_interface.validate_partial_update(&new_cfg)?;
+
+        // Check that the new tap can be opened.
+        if let Some(ref host_dev_name) = new_cfg.host_dev_name {
+            Tap::open_named(host_dev_name).map_err(NetworkInterfaceError::OpenTap)?;
         }
 
-        update_rate_limiter!(rx_rate_limiter);
-        update_rate_limiter!(tx_rate_limiter);
-        Ok(())
+        self.network_interface.apply_partial_update(new_cfg)
     }
 
     /// Sets a vsock device to be attached when the VM starts.
     pub fn set_vsock_device(&mut self, config: VsockDeviceConfig) {
         self.vsock = Some(config);
     }
+
+    /// Removes the vsock device, so that it is not attached when the VM starts.
+    pub fn remove_vsock_device(&mut self) -> Result<VsockError> {
+        self.vsock
+            .take()
+            .map(|_| ())
+            .ok_or(VsockError::DeviceNotFound)
+    }
+
+    /// Returns the MMDS configuration.
+    pub fn mmds_config(&self) -> &MmdsConfig {
+        &self.mmds_config
+    }
+
+    /// Checks that the MMDS addresses are link-local and that the listed network interfaces
+    /// are configured.
+    fn validate_mmds_config(&self, config: &MmdsConfig) -> Result<MmdsConfigError> {
+        if let Some(addr) = config.ipv4_address {
+            if !addr.is_link_local() {
+                return Err(MmdsConfigError::InvalidIpv4Addr);
+            }
+        }
+        // Link-local unicast addresses are in fe80::/10.
+        if let Some(addr) = config.ipv6_address {
+            if addr.segments()[0] & 0xffc0 != 0xfe80 {
+                return Err(MmdsConfigError::InvalidIpv6Addr);
+            }
+        }
+
+        if let Some(ref iface_ids) = config.network_interfaces {
+            if let Some(iface_id) = iface_ids.iter().find(|iface_id| {
+                !self
+                    .network_interface
+                    .iter()
+                    .any(|netif| netif.iface_id == **iface_id)
+            }) {
+                return Err(MmdsConfigError::InvalidNetworkInterfaceId(iface_id.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the MMDS address and the network interfaces that forward requests to it.
+    // The listed interfaces must already be configured, since their `allow_mmds_requests`
+    // flag is updated here.
+    pub fn set_mmds_config(&mut self, config: MmdsConfig) -> Result<MmdsConfigError> {
+        self.validate_mmds_config(&config)?;
+
+        if let Some(ref iface_ids) = config.network_interfaces {
+            for netif in self.network_interface.iter_mut() {
+                netif.allow_mmds_requests = iface_ids.contains(&netif.iface_id);
+            }
+        }
+
+        MMDS.lock()
+            .expect("Failed to acquire lock on MMDS")
+            .set_version(config.version);
+
+        self.mmds_config = config;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::fs::{self, File};
+    use std::net::Ipv4Addr;
     use std::os::linux::fs::MetadataExt;
 
     use super::*;
@@ -299,7 +861,9 @@ mod tests {
     use utils::tempfile::TempFile;
     use vmm_config::boot_source::{BootConfig, BootSourceConfig, DEFAULT_KERNEL_CMDLINE};
     use vmm_config::drive::{BlockDeviceConfig, BlockDeviceConfigs, DriveError};
-    use vmm_config::machine_config::{CpuFeaturesTemplate, VmConfig, VmConfigError};
+    use vmm_config::logger::LoggerLevel;
+    use vmm_config::machine_config::{CpuFeaturesTemplate, RebootPolicy, VmConfig, VmConfigError};
+    use vmm_config::mmds::{MmdsConfig, MmdsConfigError, MmdsVersion};
     use vmm_config::net::{
         NetworkInterfaceConfig, NetworkInterfaceConfigs, NetworkInterfaceError,
         NetworkInterfaceUpdateConfig,
@@ -361,9 +925,13 @@ mod tests {
         VmResources {
             vm_config: VmConfig::default(),
             boot_config: Some(default_boot_cfg()),
+            boot_source_config: None,
             block: default_block_cfgs(),
             network_interface: default_net_cfgs(),
             vsock: None,
+            mmds_config: MmdsConfig::default(),
+            logger_config: None,
+            metrics_config: None,
         }
     }
 
@@ -601,6 +1169,40 @@ mod tests {
         // Let's try now passing a valid configuration. We won't include any logger
         // or metrics configuration because these were already initialized in other
         // tests of this module and the reinitialization of them will cause crashing.
+        json = format!(
+            r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "{}",
+                        "boot_args": "console=ttyS0 reboot=k panic=1 pci=off"
+                    }},
+                    "drives": [
+                        {{
+                            "drive_id": "rootfs",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": false
+                        }}
+                    ],
+                    "network-interfaces": [
+                        {{
+                            "iface_id": "netif",
+                            "host_dev_name": "hostname8"
+                        }}
+                    ],
+                    "mmds-config": {{
+                        "network_interfaces": ["invalid_netif"]
+                    }}
+            }}"#,
+            kernel_file.as_path().to_str().unwrap(),
+            rootfs_file.as_path().to_str().unwrap(),
+        );
+
+        match VmResources::from_json(json.as_str(), "some_version") {
+            Err(Error::MmdsConfig(MmdsConfigError::InvalidNetworkInterfaceId(_))) => (),
+            _ => unreachable!(),
+        }
+
+        // Valid config.
         json = format!(
             r#"{{
                     "boot-source": {{
@@ -625,13 +1227,338 @@ mod tests {
                             "vcpu_count": 2,
                             "mem_size_mib": 1024,
                             "ht_enabled": false
-                     }}
+                     }},
+                    "mmds-config": {{
+                        "ipv4_address": "169.254.170.2",
+                        "network_interfaces": ["netif"]
+                    }}
+            }}"#,
+            kernel_file.as_path().to_str().unwrap(),
+            rootfs_file.as_path().to_str().unwrap(),
+        );
+
+        let resources = VmResources::from_json(json.as_str(), "some_version").unwrap();
+        assert!(resources
+            .network_interface
+            .iter()
+            .next()
+            .unwrap()
+            .allow_mmds_requests());
+    }
+
+    #[test]
+    fn test_validate_json() {
+        let kernel_file = TempFile::new().unwrap();
+        let rootfs_file = TempFile::new().unwrap();
+
+        // Syntax errors keep their location.
+        let errors = VmResources::validate_json(r#"{"boot-source": }"#).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].path.is_empty());
+        assert!(errors[0].to_string().contains("line 1 column 17"));
+
+        // All the errors are reported, along with their paths.
+        let json = format!(
+            r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "/invalid/path"
+                    }},
+                    "drives": [
+                        {{
+                            "drive_id": "rootfs",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": false
+                        }},
+                        {{
+                            "drive_id": "scratch",
+                            "is_root_device": true,
+                            "is_read_only": false
+                        }},
+                        {{
+                            "drive_id": "data",
+                            "path_on_host": "/invalid/path",
+                            "is_root_device": false,
+                            "is_read_only": true
+                        }}
+                    ],
+                    "network-interfaces": [
+                        {{
+                            "iface_id": "netif",
+                            "host_dev_name": "invalid/tap"
+                        }}
+                    ],
+                    "machine-config": {{
+                        "vcpu_count": 0
+                    }},
+                    "mmds-config": {{
+                        "ipv4_address": "10.0.0.1"
+                    }}
+            }}"#,
+            rootfs_file.as_path().to_str().unwrap()
+        );
+        let errors = VmResources::validate_json(json.as_str()).unwrap_err();
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "machine-config",
+                "boot-source.kernel_image_path",
+                "drives[1]",
+                "drives[2].path_on_host",
+                "network-interfaces[0].host_dev_name",
+                "mmds-config.ipv4_address",
+            ]
+        );
+        assert!(errors[2].message.contains("missing field `path_on_host`"));
+        assert_eq!(
+            errors[4].to_string(),
+            "network-interfaces[0].host_dev_name: The tap device invalid/tap does not exist."
+        );
+
+        // The boot source and the drives are mandatory.
+        let errors = VmResources::validate_json("{}").unwrap_err();
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["boot-source", "drives"]);
+
+        // The kernel image headers are checked.
+        let json = format!(
+            r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "{}"
+                    }},
+                    "drives": []
+            }}"#,
+            kernel_file.as_path().to_str().unwrap()
+        );
+        let errors = VmResources::validate_json(json.as_str()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "boot-source.kernel_image_path");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_validate_json_kernel() {
+        let kernel_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join("kernel/src/loader/test_elf.bin");
+        let rootfs_file = TempFile::new().unwrap();
+        let json = format!(
+            r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "{}",
+                        "boot_args": "{}"
+                    }},
+                    "drives": [
+                        {{
+                            "drive_id": "rootfs",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": false
+                        }}
+                    ]
+            }}"#,
+            kernel_path.to_str().unwrap(),
+            "{}",
+            rootfs_file.as_path().to_str().unwrap()
+        );
+
+        let valid_json = json.replacen("{}", DEFAULT_KERNEL_CMDLINE, 1);
+        assert!(VmResources::validate_json(valid_json.as_str()).is_ok());
+
+        // The boot arguments fit on their own, but not along with the root device and
+        // the virtio device.
+        let long_json = json.replacen("{}", &"a".repeat(arch::CMDLINE_MAX_SIZE - 10), 1);
+        let errors = VmResources::validate_json(long_json.as_str()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "boot-source.boot_args");
+    }
+
+    #[test]
+    fn test_vmm_config() {
+        // The boot source is mandatory when configuring a microVM from json.
+        let json = r#"{ "drives": [] }"#;
+        match VmResources::from_json(json, "some_version") {
+            Err(Error::InvalidJson(_)) => (),
+            _ => unreachable!(),
+        }
+
+        // Without a boot source, it is left out of the exported configuration.
+        let vm_resources = VmResources::default();
+        let exported = serde_json::to_value(vm_resources.vmm_config()).unwrap();
+        assert!(exported.get("boot-source").is_none());
+        assert_eq!(exported["drives"], serde_json::json!([]));
+        assert_eq!(exported["machine-config"]["vcpu_count"], 1);
+
+        let kernel_file = TempFile::new().unwrap();
+        let rootfs_file = TempFile::new().unwrap();
+        let json = format!(
+            r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "{}",
+                        "boot_args": "console=ttyS0 reboot=k panic=1 pci=off"
+                    }},
+                    "drives": [
+                        {{
+                            "drive_id": "rootfs",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": false,
+                            "rate_limiter": {{
+                                "bandwidth": {{ "size": 1000, "refill_time": 100 }}
+                            }}
+                        }}
+                    ],
+                    "network-interfaces": [
+                        {{
+                            "iface_id": "netif",
+                            "host_dev_name": "hostname8",
+                            "guest_mac": "01:23:45:67:89:0a"
+                        }}
+                    ],
+                    "machine-config": {{
+                        "vcpu_count": 2,
+                        "mem_size_mib": 1024,
+                        "ht_enabled": false
+                    }},
+                    "vsock": {{
+                        "vsock_id": "vsock",
+                        "guest_cid": 3,
+                        "uds_path": "/tmp/vsock"
+                    }}
             }}"#,
             kernel_file.as_path().to_str().unwrap(),
             rootfs_file.as_path().to_str().unwrap(),
         );
+        let vm_resources = VmResources::from_json(json.as_str(), "some_version").unwrap();
+        let vmm_config = vm_resources.vmm_config();
 
-        assert!(VmResources::from_json(json.as_str(), "some_version").is_ok());
+        let exported = serde_json::to_value(&vmm_config).unwrap();
+        assert_eq!(
+            exported["boot-source"]["boot_args"],
+            "console=ttyS0 reboot=k panic=1 pci=off"
+        );
+        assert!(exported["boot-source"]
+            .get("initrd_path")
+            .unwrap()
+            .is_null());
+        assert_eq!(
+            exported["drives"][0]["rate_limiter"],
+            serde_json::json!({ "bandwidth": { "size": 1000, "refill_time": 100 } })
+        );
+        assert!(exported["drives"][0].get("partuuid").is_none());
+        assert_eq!(
+            exported["network-interfaces"][0]["guest_mac"],
+            "01:23:45:67:89:0a"
+        );
+        assert!(exported["network-interfaces"][0]
+            .get("rx_rate_limiter")
+            .is_none());
+        assert_eq!(exported["machine-config"]["vcpu_count"], 2);
+        assert_eq!(exported["vsock"]["guest_cid"], 3);
+        assert!(exported.get("logger").is_none());
+
+        // The exported configuration configures an identical microVM.
+        let replayed =
+            VmResources::from_json(&serde_json::to_string(&vmm_config).unwrap(), "some_version")
+                .unwrap();
+        assert_eq!(replayed.vmm_config(), vmm_config);
+    }
+
+    #[test]
+    fn test_apply_vmm_config() {
+        let kernel_file = TempFile::new().unwrap();
+        let rootfs_file = TempFile::new().unwrap();
+        let config = |vcpu_count: u8, path_on_host: &str| -> VmmConfig {
+            serde_json::from_str(&format!(
+                r#"{{
+                    "boot-source": {{ "kernel_image_path": "{}" }},
+                    "drives": [
+                        {{
+                            "drive_id": "rootfs",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": false
+                        }}
+                    ],
+                    "machine-config": {{
+                        "vcpu_count": {},
+                        "mem_size_mib": 1024,
+                        "ht_enabled": false
+                    }}
+                }}"#,
+                kernel_file.as_path().to_str().unwrap(),
+                path_on_host,
+                vcpu_count
+            ))
+            .unwrap()
+        };
+        let rootfs_path = rootfs_file.as_path().to_str().unwrap();
+
+        let mut vm_resources = default_vm_resources();
+        vm_resources
+            .apply_vmm_config(config(2, rootfs_path), "some_version")
+            .unwrap();
+        let applied = vm_resources.vmm_config();
+        assert_eq!(applied.block_devices, config(2, rootfs_path).block_devices);
+        assert_eq!(vm_resources.vm_config().vcpu_count, Some(2));
+        // The previous configuration is replaced as a whole.
+        assert!(vm_resources.network_interface.iter().next().is_none());
+
+        // Nothing changes when part of the configuration is invalid.
+        match vm_resources.apply_vmm_config(config(4, "/invalid/path"), "some_version") {
+            Err(Error::BlockDevice(DriveError::InvalidBlockDevicePath)) => (),
+            _ => unreachable!(),
+        }
+        assert_eq!(vm_resources.vmm_config(), applied);
+        match vm_resources.apply_vmm_config(config(0, rootfs_path), "some_version") {
+            Err(Error::VmConfig(VmConfigError::InvalidVcpuCount)) => (),
+            _ => unreachable!(),
+        }
+        assert_eq!(vm_resources.vmm_config(), applied);
+
+        vm_resources
+            .apply_vmm_config(config(4, rootfs_path), "some_version")
+            .unwrap();
+        assert_eq!(vm_resources.vm_config().vcpu_count, Some(4));
+
+        // The logger is left alone when the metrics system can't be set up.
+        let log_file = TempFile::new().unwrap();
+        let logger_config = LoggerConfig {
+            log_path: log_file.as_path().to_path_buf(),
+            level: LoggerLevel::Debug,
+            show_level: false,
+            show_log_origin: false,
+        };
+        let mut vmm_config = config(4, rootfs_path);
+        vmm_config.logger = Some(logger_config.clone());
+        vmm_config.metrics = Some(MetricsConfig {
+            metrics_path: PathBuf::from("/invalid/path"),
+        });
+        match vm_resources.apply_vmm_config(vmm_config, "some_version") {
+            Err(Error::Metrics(MetricsConfigError::InitializationFailure(_))) => (),
+            _ => unreachable!(),
+        }
+        assert!(vm_resources.logger_config().is_none());
+        assert!(fs::read_to_string(log_file.as_path()).unwrap().is_empty());
+
+        // The same goes for a metrics system that is already set up.
+        let metrics_file = TempFile::new().unwrap();
+        vm_resources.metrics_config = Some(MetricsConfig {
+            metrics_path: metrics_file.as_path().to_path_buf(),
+        });
+        let mut vmm_config = config(4, rootfs_path);
+        vmm_config.logger = Some(logger_config);
+        vmm_config.metrics = Some(MetricsConfig {
+            metrics_path: log_file.as_path().to_path_buf(),
+        });
+        match vm_resources.apply_vmm_config(vmm_config, "some_version") {
+            Err(Error::Metrics(MetricsConfigError::InitializationFailure(_))) => (),
+            _ => unreachable!(),
+        }
+        assert!(vm_resources.logger_config().is_none());
+        assert!(fs::read_to_string(log_file.as_path()).unwrap().is_empty());
     }
 
     #[test]
@@ -663,6 +1590,8 @@ mod tests {
             mem_size_mib: Some(512),
             ht_enabled: Some(true),
             cpu_template: Some(CpuFeaturesTemplate::T2),
+            reboot_policy: Some(RebootPolicy::Restart),
+            hotplug_slots: Some(2),
         };
 
         assert_ne!(vm_resources.vm_config, aux_vm_config);
@@ -771,6 +1700,106 @@ mod tests {
         assert_eq!(actual_vsock_cfg, new_vsock_cfg);
     }
 
+    #[test]
+    fn test_remove_devices() {
+        let mut vm_resources = default_vm_resources();
+
+        assert!(vm_resources.remove_block_device("block2").is_err());
+        vm_resources.remove_block_device("block1").unwrap();
+        assert_eq!(vm_resources.block.config_list.len(), 0);
+
+        vm_resources.mmds_config.network_interfaces = Some(vec!["net_if1".to_string()]);
+        assert!(vm_resources.remove_net_device("net_if2").is_err());
+        vm_resources.remove_net_device("net_if1").unwrap();
+        assert_eq!(vm_resources.network_interface.len(), 0);
+        assert_eq!(vm_resources.mmds_config.network_interfaces, Some(vec![]));
+
+        match vm_resources.remove_vsock_device() {
+            Err(VsockError::DeviceNotFound) => (),
+            _ => unreachable!(),
+        }
+        vm_resources.set_vsock_device(VsockDeviceConfig {
+            vsock_id: "vsock".to_string(),
+            guest_cid: 3,
+            uds_path: String::from("uds_path"),
+        });
+        vm_resources.remove_vsock_device().unwrap();
+        assert!(vm_resources.vsock.is_none());
+    }
+
+    #[test]
+    fn test_set_mmds_config() {
+        let mut vm_resources = default_vm_resources();
+        assert_eq!(vm_resources.mmds_config(), &MmdsConfig::default());
+
+        // The address must be link-local.
+        assert_eq!(
+            vm_resources.set_mmds_config(MmdsConfig {
+                ipv4_address: Some(Ipv4Addr::new(10, 0, 0, 1)),
+                ..Default::default()
+            }),
+            Err(MmdsConfigError::InvalidIpv4Addr)
+        );
+        assert_eq!(
+            vm_resources.set_mmds_config(MmdsConfig {
+                ipv6_address: Some("2001:db8::1".parse().unwrap()),
+                ..Default::default()
+            }),
+            Err(MmdsConfigError::InvalidIpv6Addr)
+        );
+        assert_eq!(
+            vm_resources.set_mmds_config(MmdsConfig {
+                ipv6_address: Some("fec0::1".parse().unwrap()),
+                ..Default::default()
+            }),
+            Err(MmdsConfigError::InvalidIpv6Addr)
+        );
+
+        // The interfaces must be configured.
+        assert_eq!(
+            vm_resources.set_mmds_config(MmdsConfig {
+                network_interfaces: Some(vec!["net_if1".to_string(), "net_if2".to_string()]),
+                ..Default::default()
+            }),
+            Err(MmdsConfigError::InvalidNetworkInterfaceId(
+                "net_if2".to_string()
+            ))
+        );
+        assert_eq!(vm_resources.mmds_config(), &MmdsConfig::default());
+
+        let mmds_config = MmdsConfig {
+            version: MmdsVersion::V2,
+            ipv4_address: Some(Ipv4Addr::new(169, 254, 170, 2)),
+            ipv6_address: Some("febf::2".parse().unwrap()),
+            network_interfaces: Some(vec!["net_if1".to_string()]),
+            dns_enabled: true,
+            sntp_enabled: false,
+        };
+        vm_resources.set_mmds_config(mmds_config.clone()).unwrap();
+        // The MMDS version is global, so V1 is restored before anything is checked.
+        let version = MMDS.lock().unwrap().version();
+        MMDS.lock().unwrap().set_version(MmdsVersion::V1);
+        assert_eq!(version, MmdsVersion::V2);
+        assert_eq!(vm_resources.mmds_config(), &mmds_config);
+        assert!(vm_resources
+            .network_interface
+            .iter()
+            .all(|netif| netif.allow_mmds_requests()));
+
+        // An empty list disables the MMDS on all the interfaces.
+        vm_resources
+            .set_mmds_config(MmdsConfig {
+                network_interfaces: Some(vec![]),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(vm_resources.mmds_config().version, MmdsVersion::V1);
+        assert!(vm_resources
+            .network_interface
+            .iter()
+            .all(|netif| !netif.allow_mmds_requests()));
+    }
+
     #[test]
     fn test_set_net_device() {
         let mut vm_resources = default_vm_resources();
@@ -823,7 +1852,7 @@ mod tests {
     }
 
     #[test]
-    fn test_update_net_rate_limiters() {
+    fn test_update_net_device() {
         let bw_tb = TokenBucketConfig {
             size: 15,
             one_time_burst: Some(5),
@@ -852,9 +1881,10 @@ mod tests {
             iface_id: actual_net_cfg.iface_id.clone(),
             rx_rate_limiter: Some(expected_rl_cfg),
             tx_rate_limiter: Some(expected_rl_cfg),
+            ..Default::default()
         };
         vm_resources
-            .update_net_rate_limiters(net_if_cfg_update.clone())
+            .update_net_device(net_if_cfg_update.clone())
             .unwrap();
         let actual_net_cfg = vm_resources.network_interface.iter().next().unwrap();
         let actual_rx_rl_cfg = actual_net_cfg.rx_rate_limiter.unwrap();
@@ -862,9 +1892,20 @@ mod tests {
         assert_eq!(actual_rx_rl_cfg, expected_rl_cfg);
         assert_eq!(actual_tx_rl_cfg, expected_rl_cfg);
 
+        // Update the backing tap and the guest MAC.
+        let new_mac = MacAddr::parse_str("01:23:45:67:89:0b").unwrap();
+        net_if_cfg_update.host_dev_name = Some("net_if_swapped".to_string());
+        net_if_cfg_update.guest_mac = Some(new_mac);
+        vm_resources
+            .update_net_device(net_if_cfg_update.clone())
+            .unwrap();
+        let actual_net_cfg = vm_resources.network_interface.iter().next().unwrap();
+        assert_eq!(actual_net_cfg.host_dev_name, "net_if_swapped");
+        assert_eq!(actual_net_cfg.guest_mac, Some(new_mac));
+
         // DeviceIdNotFound.
         net_if_cfg_update.iface_id = "net_if_does_not_exist".to_string();
-        match vm_resources.update_net_rate_limiters(net_if_cfg_update) {
+        match vm_resources.update_net_device(net_if_cfg_update) {
             Err(NetworkInterfaceError::DeviceIdNotFound { .. }) => (),
             _ => unreachable!(),
         }
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::fmt::{Display, Formatter};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use super::Vmm;
 
@@ -10,18 +11,20 @@ use super::Error as VmmError;
 use builder::StartMicrovmError;
 use controller::VmmController;
 use polly::event_manager::EventManager;
-use resources::VmResources;
+use resources::{Error as ResourcesError, VmResources, VmmConfig};
 use seccomp::BpfProgram;
-use vmm_config;
 use vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
-use vmm_config::drive::{BlockDeviceConfig, DriveError};
+use vmm_config::drive::{BlockDeviceConfig, BlockDeviceStatus, DriveError};
+use vmm_config::instance_info::{InstanceInfo, InstanceState};
 use vmm_config::logger::{LoggerConfig, LoggerConfigError};
 use vmm_config::machine_config::{VmConfig, VmConfigError};
 use vmm_config::metrics::{MetricsConfig, MetricsConfigError};
+use vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use vmm_config::net::{
-    NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
+    NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceStatus,
+    NetworkInterfaceUpdateConfig,
 };
-use vmm_config::vsock::{VsockDeviceConfig, VsockError};
+use vmm_config::vsock::{VsockDeviceConfig, VsockDeviceStatus, VsockError};
 
 /// This enum represents the public interface of the VMM. Each action contains various
 /// bits of information (ids, paths, etc.).
@@ -36,17 +39,40 @@ pub enum VmmAction {
     /// Configure the metrics using as input the `MetricsConfig`. This action can only be called
     /// before the microVM has booted.
     ConfigureMetrics(MetricsConfig),
+    /// Replace the whole configuration of the microVM with the `VmmConfig`, atomically, and
+    /// start the microVM afterwards if the flag is set. This action can only be called before
+    /// the microVM has booted.
+    ConfigureMicroVm(VmmConfig, bool),
+    /// Get the configuration and state of the block device with the given `drive_id`.
+    GetBlockDevice(String),
+    /// Get the configuration and state of every block device.
+    GetBlockDevices,
+    /// Get the boot source configuration.
+    GetBootSource,
+    /// Get the logger configuration.
+    GetLogger,
+    /// Get the configuration and state of the network interface with the given `iface_id`.
+    GetNetworkInterface(String),
+    /// Get the configuration and state of every network interface.
+    GetNetworkInterfaces,
     /// Get the configuration of the microVM.
     GetVmConfiguration,
+    /// Get the full configuration of the microVM, in the format accepted by `--config-file`.
+    GetFullVmConfiguration,
+    /// Get the configuration and state of the vsock device.
+    GetVsockDevice,
     /// Flush the metrics. This action can only be called after the logger has been configured.
     FlushMetrics,
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
     /// input. This action can only be called before the microVM has booted.
     InsertBlockDevice(BlockDeviceConfig),
     /// Add a new network interface config or update one that already exists using the
-    /// `NetworkInterfaceConfig` as input. This action can only be called before the microVM has
-    /// booted.
+    /// `NetworkInterfaceConfig` as input. After the microVM has booted, this action can only
+    /// add new network interfaces, which are hot-plugged into the guest.
     InsertNetworkDevice(NetworkInterfaceConfig),
+    /// Set the MMDS address and the network interfaces that forward requests to it using the
+    /// `MmdsConfig` as input. This action can only be called before the microVM has booted.
+    SetMmdsConfiguration(MmdsConfig),
     /// Set the vsock device or update the one that already exists using the
     /// `VsockDeviceConfig` as input. This action can only be called before the microVM has
     /// booted.
@@ -56,23 +82,106 @@ pub enum VmmAction {
     SetVmConfiguration(VmConfig),
     /// Launch the microVM. This action can only be called before the microVM has booted.
     StartMicroVm,
+    /// Remove the block device with the given `drive_id`. This action can only be called
+    /// before the microVM has booted.
+    RemoveBlockDevice(String),
+    /// Remove the network interface with the given `iface_id`. After the microVM has booted,
+    /// the interface is unplugged from the guest.
+    RemoveNetworkDevice(String),
+    /// Remove the vsock device. This action can only be called before the microVM has booted.
+    RemoveVsockDevice,
     /// Send CTRL+ALT+DEL to the microVM, using the i8042 keyboard function. If an AT-keyboard
     /// driver is listening on the guest end, this can be used to shut down the microVM gracefully.
     #[cfg(target_arch = "x86_64")]
     SendCtrlAltDel,
+    /// Ask the guest to shut down and stop the VMM if it doesn't exit within the given timeout.
+    /// This action can only be called after the microVM has booted.
+    ShutdownGracefully(Duration),
     /// Update the path of an existing block device. The data associated with this variant
     /// represents the `drive_id` and the `path_on_host`.
     UpdateBlockDevicePath(String, String),
-    /// Update a network interface, after microVM start. Currently, the only updatable properties
-    /// are the RX and TX rate limiters.
+    /// Update a network interface. The RX and TX rate limiters, the guest MAC address and the
+    /// backing tap device can be updated at any time, while the link state can only be changed
+    /// after microVM start.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
 }
 
+// The states in which the VMM handles requests, before and after the guest was booted.
+const PRE_BOOT: &[InstanceState] = &[InstanceState::NotStarted];
+const POST_BOOT: &[InstanceState] = &[
+    InstanceState::Running,
+    InstanceState::Rebooting,
+    InstanceState::Paused,
+    InstanceState::ShuttingDown,
+];
+const ANY_STATE: &[InstanceState] = &[
+    InstanceState::NotStarted,
+    InstanceState::Running,
+    InstanceState::Rebooting,
+    InstanceState::Paused,
+    InstanceState::ShuttingDown,
+];
+
+impl VmmAction {
+    /// Returns the states of the microVM in which the action can be handled.
+    pub fn valid_states(&self) -> &'static [InstanceState] {
+        use self::VmmAction::*;
+
+        match self {
+            ConfigureBootSource(_)
+            | ConfigureLogger(_)
+            | ConfigureMetrics(_)
+            | ConfigureMicroVm(_, _)
+            | InsertBlockDevice(_)
+            | RemoveBlockDevice(_)
+            | RemoveVsockDevice
+            | SetMmdsConfiguration(_)
+            | SetVsockDevice(_)
+            | SetVmConfiguration(_)
+            | StartMicroVm => PRE_BOOT,
+            GetBlockDevice(_)
+            | GetBlockDevices
+            | GetBootSource
+            | GetLogger
+            | GetNetworkInterface(_)
+            | GetNetworkInterfaces
+            | GetVmConfiguration
+            | GetFullVmConfiguration
+            | GetVsockDevice
+            | InsertNetworkDevice(_)
+            | RemoveNetworkDevice(_)
+            | UpdateBlockDevicePath(_, _) => ANY_STATE,
+            // The link state is only meaningful for a running device.
+            UpdateNetworkInterface(netif_update) if netif_update.link_up.is_some() => POST_BOOT,
+            UpdateNetworkInterface(_) => ANY_STATE,
+            FlushMetrics => POST_BOOT,
+            #[cfg(target_arch = "x86_64")]
+            SendCtrlAltDel => POST_BOOT,
+            ShutdownGracefully(_) => &[InstanceState::Running],
+        }
+    }
+
+    // Checks that the action can be handled while the microVM is in `state`.
+    fn check_state(&self, state: InstanceState) -> std::result::Result<(), VmmActionError> {
+        if self.valid_states().contains(&state) {
+            Ok(())
+        } else if let VmmAction::StartMicroVm = *self {
+            Err(VmmActionError::StartMicrovm(
+                StartMicrovmError::MicroVMAlreadyRunning,
+            ))
+        } else {
+            Err(VmmActionError::OperationNotSupported(state))
+        }
+    }
+}
+
 /// Wrapper for all errors associated with VMM actions.
 #[derive(Debug)]
 pub enum VmmActionError {
     /// The action `ConfigureBootSource` failed because of bad user input.
     BootSource(BootSourceConfigError),
+    /// The action `ConfigureMicroVm` failed because of bad user input.
+    Configuration(ResourcesError),
     /// One of the actions `InsertBlockDevice` or `UpdateBlockDevicePath`
     /// failed because of bad user input.
     DriveConfig(DriveError),
@@ -84,12 +193,12 @@ pub enum VmmActionError {
     MachineConfig(VmConfigError),
     /// The action `ConfigureMetrics` failed because of bad user input.
     Metrics(MetricsConfigError),
+    /// The action `SetMmdsConfiguration` failed because of bad user input.
+    MmdsConfig(MmdsConfigError),
     /// The action `InsertNetworkDevice` failed because of bad user input.
     NetworkConfig(NetworkInterfaceError),
-    /// The requested operation is not supported after starting the microVM.
-    OperationNotSupportedPostBoot,
-    /// The requested operation is not supported before starting the microVM.
-    OperationNotSupportedPreBoot,
+    /// The requested operation is not supported in the current state of the microVM.
+    OperationNotSupported(InstanceState),
     /// The action `StartMicroVm` failed because of an internal error.
     StartMicrovm(StartMicrovmError),
     /// The action `set_vsock_device` failed because of bad user input.
@@ -105,20 +214,23 @@ impl Display for VmmActionError {
             "{}",
             match self {
                 BootSource(err) => err.to_string(),
+                Configuration(err) => err.to_string(),
                 DriveConfig(err) => err.to_string(),
                 InternalVmm(err) => format!("Internal Vmm error: {}", err),
                 Logger(err) => err.to_string(),
                 MachineConfig(err) => err.to_string(),
                 Metrics(err) => err.to_string(),
+                MmdsConfig(err) => err.to_string(),
                 NetworkConfig(err) => err.to_string(),
-                OperationNotSupportedPostBoot => {
-                    "The requested operation is not supported after starting the microVM."
-                        .to_string()
-                }
-                OperationNotSupportedPreBoot => {
+                OperationNotSupported(InstanceState::NotStarted) => {
                     "The requested operation is not supported before starting the microVM."
                         .to_string()
                 }
+                OperationNotSupported(state) => format!(
+                    "The requested operation is not supported while the microVM is in the {} \
+                     state.",
+                    state
+                ),
                 StartMicrovm(err) => err.to_string(),
                 VsockConfig(err) => err.to_string(),
             }
@@ -132,8 +244,44 @@ impl Display for VmmActionError {
 pub enum VmmData {
     /// No data is sent on the channel.
     Empty,
+    /// The configuration and state of a block device.
+    BlockDevice(BlockDeviceStatus),
+    /// The configuration and state of every block device.
+    BlockDevices(Vec<BlockDeviceStatus>),
+    /// The boot source configuration.
+    BootSource(BootSourceConfig),
+    /// The logger configuration.
+    Logger(LoggerConfig),
     /// The microVM configuration represented by `VmConfig`.
     MachineConfiguration(VmConfig),
+    /// The full microVM configuration represented by `VmmConfig`.
+    FullVmConfiguration(VmmConfig),
+    /// The configuration and state of a network interface.
+    NetworkInterface(NetworkInterfaceStatus),
+    /// The configuration and state of every network interface.
+    NetworkInterfaces(Vec<NetworkInterfaceStatus>),
+    /// The configuration and state of the vsock device.
+    VsockDevice(VsockDeviceStatus),
+    /// The `<size>@<baseaddr>:<irq>` location of a device plugged into the running microVM.
+    MmioDeviceLocation(String),
+}
+
+// The boot source and the logger are configured before boot, so requests for them are
+// served the same way before and after starting the microVM.
+fn get_boot_source(vm_resources: &VmResources) -> std::result::Result<VmmData, VmmActionError> {
+    vm_resources
+        .boot_source_config()
+        .map(|config| VmmData::BootSource(config.clone()))
+        .ok_or(VmmActionError::BootSource(
+            BootSourceConfigError::NotConfigured,
+        ))
+}
+
+fn get_logger(vm_resources: &VmResources) -> std::result::Result<VmmData, VmmActionError> {
+    vm_resources
+        .logger_config()
+        .map(|config| VmmData::Logger(config.clone()))
+        .ok_or(VmmActionError::Logger(LoggerConfigError::NotConfigured))
 }
 
 /// Enables pre-boot setup and instantiation of a Firecracker VMM.
@@ -142,6 +290,7 @@ pub struct PrebootApiController<'a> {
     firecracker_version: String,
     vm_resources: &'a mut VmResources,
     event_manager: &'a mut EventManager,
+    instance_info: Arc<RwLock<InstanceInfo>>,
 
     built_vmm: Option<Arc<Mutex<Vmm>>>,
 }
@@ -153,12 +302,14 @@ impl<'a> PrebootApiController<'a> {
         firecracker_version: String,
         vm_resources: &'a mut VmResources,
         event_manager: &'a mut EventManager,
+        instance_info: Arc<RwLock<InstanceInfo>>,
     ) -> PrebootApiController<'a> {
         PrebootApiController {
             seccomp_filter,
             firecracker_version,
             vm_resources,
             event_manager,
+            instance_info,
             built_vmm: None,
         }
     }
@@ -172,6 +323,7 @@ impl<'a> PrebootApiController<'a> {
         seccomp_filter: BpfProgram,
         event_manager: &mut EventManager,
         firecracker_version: String,
+        instance_info: Arc<RwLock<InstanceInfo>>,
         recv_req: F,
         respond: G,
     ) -> (VmResources, Arc<Mutex<Vmm>>)
@@ -185,6 +337,7 @@ impl<'a> PrebootApiController<'a> {
             firecracker_version,
             &mut vm_resources,
             event_manager,
+            instance_info,
         );
         // Configure and start microVM through successive API calls.
         // Iterate through API calls to configure microVm.
@@ -207,24 +360,87 @@ impl<'a> PrebootApiController<'a> {
     ) -> std::result::Result<VmmData, VmmActionError> {
         use self::VmmAction::*;
 
+        let state = self
+            .instance_info
+            .read()
+            .expect("Poisoned instance info lock")
+            .state();
+        request.check_state(state)?;
         match request {
-            // Supported operations allowed pre-boot.
             ConfigureBootSource(boot_source_body) => self
                 .vm_resources
                 .set_boot_source(boot_source_body)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::BootSource),
-            ConfigureLogger(logger_cfg) => {
-                vmm_config::logger::init_logger(logger_cfg, &self.firecracker_version)
-                    .map(|_| VmmData::Empty)
-                    .map_err(VmmActionError::Logger)
-            }
-            ConfigureMetrics(metrics_cfg) => vmm_config::metrics::init_metrics(metrics_cfg)
+            ConfigureLogger(logger_cfg) => self
+                .vm_resources
+                .set_logger(logger_cfg, &self.firecracker_version)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::Logger),
+            ConfigureMetrics(metrics_cfg) => self
+                .vm_resources
+                .set_metrics(metrics_cfg)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::Metrics),
+            ConfigureMicroVm(vmm_config, start) => {
+                self.vm_resources
+                    .apply_vmm_config(vmm_config, &self.firecracker_version)
+                    .map_err(VmmActionError::Configuration)?;
+                if start {
+                    self.start_microvm()
+                } else {
+                    Ok(VmmData::Empty)
+                }
+            }
+            GetBlockDevice(drive_id) => self
+                .vm_resources
+                .block
+                .get(&drive_id)
+                .map(|config| VmmData::BlockDevice(BlockDeviceStatus::from(config.clone())))
+                .ok_or(VmmActionError::DriveConfig(
+                    DriveError::InvalidBlockDeviceID,
+                )),
+            GetBlockDevices => Ok(VmmData::BlockDevices(
+                self.vm_resources
+                    .block
+                    .config_list
+                    .iter()
+                    .cloned()
+                    .map(BlockDeviceStatus::from)
+                    .collect(),
+            )),
+            GetBootSource => get_boot_source(&self.vm_resources),
+            GetLogger => get_logger(&self.vm_resources),
+            GetNetworkInterface(iface_id) => self
+                .vm_resources
+                .network_interface
+                .get(&iface_id)
+                .map(|config| {
+                    VmmData::NetworkInterface(NetworkInterfaceStatus::from(config.clone()))
+                })
+                .ok_or(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::DeviceIdNotFound,
+                )),
+            GetNetworkInterfaces => Ok(VmmData::NetworkInterfaces(
+                self.vm_resources
+                    .network_interface
+                    .iter()
+                    .cloned()
+                    .map(NetworkInterfaceStatus::from)
+                    .collect(),
+            )),
             GetVmConfiguration => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.vm_config().clone(),
             )),
+            GetFullVmConfiguration => {
+                Ok(VmmData::FullVmConfiguration(self.vm_resources.vmm_config()))
+            }
+            GetVsockDevice => self
+                .vm_resources
+                .vsock
+                .as_ref()
+                .map(|config| VmmData::VsockDevice(VsockDeviceStatus::from(config.clone())))
+                .ok_or(VmmActionError::VsockConfig(VsockError::DeviceNotFound)),
             InsertBlockDevice(block_device_config) => self
                 .vm_resources
                 .set_block_device(block_device_config)
@@ -235,6 +451,11 @@ impl<'a> PrebootApiController<'a> {
                 .set_net_device(netif_body)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::NetworkConfig),
+            SetMmdsConfiguration(mmds_cfg) => self
+                .vm_resources
+                .set_mmds_config(mmds_cfg)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::MmdsConfig),
             SetVsockDevice(vsock_cfg) => {
                 self.vm_resources.set_vsock_device(vsock_cfg);
                 Ok(VmmData::Empty)
@@ -251,63 +472,192 @@ impl<'a> PrebootApiController<'a> {
                 .map_err(VmmActionError::DriveConfig),
             UpdateNetworkInterface(netif_update) => self
                 .vm_resources
-                .update_net_rate_limiters(netif_update)
+                .update_net_device(netif_update)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::NetworkConfig),
+            StartMicroVm => self.start_microvm(),
+
+            RemoveBlockDevice(drive_id) => self
+                .vm_resources
+                .remove_block_device(&drive_id)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::DriveConfig),
+            RemoveNetworkDevice(iface_id) => self
+                .vm_resources
+                .remove_net_device(&iface_id)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::NetworkConfig),
-            StartMicroVm => super::builder::build_microvm(
-                &self.vm_resources,
-                &mut self.event_manager,
-                &self.seccomp_filter,
-            )
-            .map(|vmm| {
-                self.built_vmm = Some(vmm);
-                VmmData::Empty
-            })
-            .map_err(VmmActionError::StartMicrovm),
-
-            // Operations not allowed pre-boot.
-            FlushMetrics => Err(VmmActionError::OperationNotSupportedPreBoot),
+            RemoveVsockDevice => self
+                .vm_resources
+                .remove_vsock_device()
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::VsockConfig),
+
+            // These are rejected by the state check before the microVM is started.
+            FlushMetrics | ShutdownGracefully(_) => {
+                Err(VmmActionError::OperationNotSupported(state))
+            }
             #[cfg(target_arch = "x86_64")]
-            SendCtrlAltDel => Err(VmmActionError::OperationNotSupportedPreBoot),
+            SendCtrlAltDel => Err(VmmActionError::OperationNotSupported(state)),
         }
     }
+
+    // Builds and starts the microVM out of the configured resources. After a failed start, the
+    // microVM can be configured and started again.
+    fn start_microvm(&mut self) -> std::result::Result<VmmData, VmmActionError> {
+        super::builder::build_microvm(
+            &self.vm_resources,
+            &mut self.event_manager,
+            &self.seccomp_filter,
+            self.instance_info.clone(),
+        )
+        .map(|vmm| {
+            self.built_vmm = Some(vmm);
+            VmmData::Empty
+        })
+        .map_err(|e| {
+            if let Err(e) = self
+                .instance_info
+                .write()
+                .expect("Poisoned instance info lock")
+                .set_state(InstanceState::NotStarted)
+            {
+                warn!("{}", e);
+            }
+            VmmActionError::StartMicrovm(e)
+        })
+    }
 }
 
 /// Enables RPC interraction with a running Firecracker VMM.
 pub struct RuntimeApiController(pub VmmController);
 impl RuntimeApiController {
     /// Handles the incoming runtime `VmmAction` request and provides a response for it.
+    /// The `event_manager` driving the microVM is needed by requests that change which
+    /// file descriptors a device is listening on.
     pub fn handle_request(
         &mut self,
         request: VmmAction,
+        event_manager: &mut EventManager,
     ) -> std::result::Result<VmmData, VmmActionError> {
         use self::VmmAction::*;
+
+        let state = self.0.instance_state();
+        request.check_state(state)?;
         match request {
-            // Supported operations allowed post-boot.
             FlushMetrics => self.0.flush_metrics().map(|_| VmmData::Empty),
+            GetBlockDevice(drive_id) => self
+                .0
+                .block_device_status(&drive_id)
+                .map(VmmData::BlockDevice),
+            GetBlockDevices => Ok(VmmData::BlockDevices(self.0.block_devices_status())),
+            GetBootSource => get_boot_source(self.0.vm_resources()),
+            GetLogger => get_logger(self.0.vm_resources()),
+            GetNetworkInterface(iface_id) => self
+                .0
+                .net_device_status(&iface_id)
+                .map(VmmData::NetworkInterface),
+            GetNetworkInterfaces => Ok(VmmData::NetworkInterfaces(self.0.net_devices_status())),
             GetVmConfiguration => Ok(VmmData::MachineConfiguration(self.0.vm_config().clone())),
+            GetFullVmConfiguration => Ok(VmmData::FullVmConfiguration(self.0.vmm_config())),
+            GetVsockDevice => self.0.vsock_device_status().map(VmmData::VsockDevice),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.0.send_ctrl_alt_del().map(|_| VmmData::Empty),
+            ShutdownGracefully(timeout) => {
+                self.0.shutdown_gracefully(timeout).map(|_| VmmData::Empty)
+            }
             UpdateBlockDevicePath(drive_id, path_on_host) => self
                 .0
                 .update_block_device_path(drive_id, path_on_host)
                 .map(|_| VmmData::Empty),
             UpdateNetworkInterface(netif_update) => self
                 .0
-                .update_net_rate_limiters(netif_update)
+                .update_net_device(netif_update, event_manager)
+                .map(|_| VmmData::Empty),
+            InsertNetworkDevice(netif_body) => self
+                .0
+                .insert_net_device(netif_body, event_manager)
+                .map(VmmData::MmioDeviceLocation),
+            RemoveNetworkDevice(iface_id) => self
+                .0
+                .remove_net_device(&iface_id, event_manager)
                 .map(|_| VmmData::Empty),
 
-            // Operations not allowed post-boot.
+            // These are rejected by the state check once the microVM is started.
+            StartMicroVm => Err(VmmActionError::StartMicrovm(
+                StartMicrovmError::MicroVMAlreadyRunning,
+            )),
             ConfigureBootSource(_)
             | ConfigureLogger(_)
             | ConfigureMetrics(_)
+            | ConfigureMicroVm(_, _)
             | InsertBlockDevice(_)
-            | InsertNetworkDevice(_)
+            | RemoveBlockDevice(_)
+            | RemoveVsockDevice
+            | SetMmdsConfiguration(_)
             | SetVsockDevice(_)
-            | SetVmConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
-            StartMicroVm => Err(VmmActionError::StartMicrovm(
-                StartMicrovmError::MicroVMAlreadyRunning,
-            )),
+            | SetVmConfiguration(_) => Err(VmmActionError::OperationNotSupported(state)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_state() {
+        use self::InstanceState::*;
+
+        assert!(VmmAction::StartMicroVm.check_state(NotStarted).is_ok());
+        assert!(VmmAction::GetVmConfiguration.check_state(Running).is_ok());
+        assert!(VmmAction::FlushMetrics.check_state(ShuttingDown).is_ok());
+        assert!(VmmAction::ShutdownGracefully(Duration::from_secs(1))
+            .check_state(Running)
+            .is_ok());
+
+        // Only running network interfaces have a link state.
+        let netif_update = NetworkInterfaceUpdateConfig {
+            iface_id: "net0".to_string(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            guest_mac: None,
+            host_dev_name: None,
+            link_up: Some(false),
+        };
+        let update = VmmAction::UpdateNetworkInterface(netif_update);
+        assert!(update.check_state(Running).is_ok());
+        assert_eq!(
+            update.check_state(NotStarted).unwrap_err().to_string(),
+            "The requested operation is not supported before starting the microVM."
+        );
+
+        assert_eq!(
+            VmmAction::RemoveVsockDevice
+                .check_state(Running)
+                .unwrap_err()
+                .to_string(),
+            "The requested operation is not supported while the microVM is in the Running state."
+        );
+        match VmmAction::StartMicroVm.check_state(Paused) {
+            Err(VmmActionError::StartMicrovm(StartMicrovmError::MicroVMAlreadyRunning)) => (),
+            _ => panic!("Starting the microVM again should fail."),
         }
+        assert!(VmmAction::ShutdownGracefully(Duration::from_secs(1))
+            .check_state(ShuttingDown)
+            .is_err());
+        // No request is handled while the microVM is being started.
+        assert_eq!(
+            VmmAction::GetVmConfiguration
+                .check_state(Starting)
+                .unwrap_err()
+                .to_string(),
+            "The requested operation is not supported while the microVM is in the Starting state."
+        );
+        // Requests keep being handled while a rebooted guest is booted again.
+        assert!(VmmAction::GetVmConfiguration.check_state(Rebooting).is_ok());
+        assert!(VmmAction::ShutdownGracefully(Duration::from_secs(1))
+            .check_state(Rebooting)
+            .is_err());
     }
 }
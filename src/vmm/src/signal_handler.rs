@@ -3,7 +3,7 @@
 
 use libc::{_exit, c_int, c_void, siginfo_t, SIGBUS, SIGSEGV, SIGSYS};
 
-use logger::{Metric, METRICS};
+use logger::{ExitReason, Metric, EXIT_STATUS, METRICS};
 use utils::signal::register_signal_handler;
 
 // The offset of `si_syscall` (offending syscall identifier) within the siginfo structure
@@ -17,8 +17,8 @@ const SYS_SECCOMP_CODE: i32 = 1;
 
 /// Signal handler for `SIGSYS`.
 ///
-/// Increments the `seccomp.num_faults` metric, logs an error message and terminates the process
-/// with a specific exit code.
+/// Increments the `seccomp.num_faults` metric, logs an error message, reports the exit status and
+/// terminates the process with a specific exit code.
 extern "C" fn sigsys_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
     // Safe because we're just reading some fields from a supposedly valid argument.
     let si_signo = unsafe { (*info).si_signo };
@@ -38,10 +38,14 @@ extern "C" fn sigsys_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_v
         "Shutting down VM after intercepting a bad syscall ({}).",
         syscall
     );
-    // Write the metrics before exiting.
+    // Write the metrics and the exit status before exiting.
     if let Err(e) = METRICS.write() {
         error!("Failed to write metrics while stopping: {}", e);
     }
+    EXIT_STATUS.write_from_signal_handler(
+        i32::from(super::FC_EXIT_CODE_BAD_SYSCALL),
+        ExitReason::SeccompViolation,
+    );
 
     // Safe because we're terminating the process anyway. We don't actually do anything when
     // running unit tests.
@@ -53,7 +57,8 @@ extern "C" fn sigsys_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_v
 
 /// Signal handler for `SIGBUS` and `SIGSEGV`.
 ///
-/// Logs an error message and terminates the process with a specific exit code.
+/// Logs an error message, reports the exit status and terminates the process with a specific
+/// exit code.
 extern "C" fn sigbus_sigsegv_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
     // Safe because we're just reading some fields from a supposedly valid argument.
     let si_signo = unsafe { (*info).si_signo };
@@ -77,20 +82,22 @@ extern "C" fn sigbus_sigsegv_handler(num: c_int, info: *mut siginfo_t, _unused:
         "Shutting down VM after intercepting signal {}, code {}.",
         si_signo, si_code
     );
-    // Write the metrics before exiting.
+    let exit_code = i32::from(match si_signo {
+        SIGBUS => super::FC_EXIT_CODE_SIGBUS,
+        SIGSEGV => super::FC_EXIT_CODE_SIGSEGV,
+        _ => super::FC_EXIT_CODE_UNEXPECTED_ERROR,
+    });
+    // Write the metrics and the exit status before exiting.
     if let Err(e) = METRICS.write() {
         error!("Failed to write metrics while stopping: {}", e);
     }
+    EXIT_STATUS.write_from_signal_handler(exit_code, ExitReason::FatalSignal);
 
     // Safe because we're terminating the process anyway. We don't actually do anything when
     // running unit tests.
     #[cfg(not(test))]
     unsafe {
-        _exit(i32::from(match si_signo {
-            SIGBUS => super::FC_EXIT_CODE_SIGBUS,
-            SIGSEGV => super::FC_EXIT_CODE_SIGSEGV,
-            _ => super::FC_EXIT_CODE_UNEXPECTED_ERROR,
-        }))
+        _exit(exit_code)
     };
 }
 
@@ -19,7 +19,7 @@ pub const DEFAULT_KERNEL_CMDLINE: &str = "reboot=k panic=1 pci=off nomodules 825
 
 /// Strongly typed data structure used to configure the boot source of the
 /// microvm.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BootSourceConfig {
     /// Path of the kernel image.
@@ -41,6 +41,8 @@ pub enum BootSourceConfigError {
     InvalidInitrdPath(io::Error),
     /// The kernel command line is invalid.
     InvalidKernelCommandLine(String),
+    /// The boot source is not configured.
+    NotConfigured,
     /// The boot source cannot be update post boot.
     UpdateNotAllowedPostBoot,
 }
@@ -59,6 +61,7 @@ impl Display for BootSourceConfigError {
             InvalidKernelCommandLine(ref e) => {
                 write!(f, "The kernel command line is invalid: {}", e.as_str())
             }
+            NotConfigured => write!(f, "The boot source is not configured."),
             UpdateNotAllowedPostBoot => {
                 write!(f, "The update operation is not allowed after boot.")
             }
@@ -76,3 +79,17 @@ pub struct BootConfig {
     /// The descriptor to the initrd file, if there is one
     pub initrd_file: Option<std::fs::File>,
 }
+
+impl BootConfig {
+    /// Creates a new `BootConfig` referring to the same kernel and initrd files.
+    pub fn try_clone(&self) -> io::Result<BootConfig> {
+        Ok(BootConfig {
+            cmdline: self.cmdline.clone(),
+            kernel_file: self.kernel_file.try_clone()?,
+            initrd_file: match &self.initrd_file {
+                Some(f) => Some(f.try_clone()?),
+                None => None,
+            },
+        })
+    }
+}
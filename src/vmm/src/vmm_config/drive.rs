@@ -6,7 +6,7 @@ use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use std::result;
 
-use super::RateLimiterConfig;
+use super::{RateLimiterBudget, RateLimiterConfig};
 
 type Result<T> = result::Result<T, DriveError>;
 
@@ -57,7 +57,7 @@ impl Display for DriveError {
 }
 
 /// Use this structure to set up the Block Device before booting the kernel.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BlockDeviceConfig {
     /// Unique identifier of the drive.
@@ -70,11 +70,13 @@ pub struct BlockDeviceConfig {
     pub is_root_device: bool,
     /// Part-UUID. Represents the unique id of the boot partition of this device. It is
     /// optional and it will be used only if the `is_root_device` field is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub partuuid: Option<String>,
     /// If set to true, the drive is opened in read-only mode. Otherwise, the
     /// drive is opened as read-write.
     pub is_read_only: bool,
     /// Rate Limiter for I/O operations.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rate_limiter: Option<RateLimiterConfig>,
 }
 
@@ -95,6 +97,30 @@ impl BlockDeviceConfig {
     }
 }
 
+/// The configuration of a block device, along with the state of the emulated device once
+/// the microVM is running.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BlockDeviceStatus {
+    /// The current configuration of the drive.
+    #[serde(flatten)]
+    pub config: BlockDeviceConfig,
+    /// Whether the guest driver activated the device.
+    pub activated: bool,
+    /// The tokens left in the rate limiter of the device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limiter_budget: Option<RateLimiterBudget>,
+}
+
+impl From<BlockDeviceConfig> for BlockDeviceStatus {
+    fn from(config: BlockDeviceConfig) -> Self {
+        BlockDeviceStatus {
+            config,
+            activated: false,
+            rate_limiter_budget: None,
+        }
+    }
+}
+
 /// Wrapper for the collection that holds all the Block Devices Configs
 #[derive(Default)]
 pub struct BlockDeviceConfigs {
@@ -131,6 +157,11 @@ impl BlockDeviceConfigs {
         self.has_partuuid_root
     }
 
+    /// Returns the configuration of the drive with the specified `drive_id`.
+    pub fn get(&self, drive_id: &str) -> Option<&BlockDeviceConfig> {
+        self.config_list.iter().find(|cfg| cfg.drive_id == drive_id)
+    }
+
     /// Gets the index of the device with the specified `drive_id` if it exists in the list.
     pub fn get_index_of_drive_id(&self, drive_id: &str) -> Option<usize> {
         self.config_list
@@ -156,6 +187,22 @@ impl BlockDeviceConfigs {
         }
     }
 
+    /// Removes the drive with the specified `drive_id` from the list. Removing the root
+    /// block device allows another drive to become root.
+    pub fn remove(&mut self, drive_id: &str) -> Result<BlockDeviceConfig> {
+        let index = self
+            .get_index_of_drive_id(drive_id)
+            .ok_or(DriveError::InvalidBlockDeviceID)?;
+        // Safe to unwrap because the index was just looked up.
+        let block_device_config = self.config_list.remove(index).unwrap();
+        if block_device_config.is_root_device {
+            self.has_root_block = false;
+            self.read_only_root = false;
+            self.has_partuuid_root = false;
+        }
+        Ok(block_device_config)
+    }
+
     fn create(&mut self, block_device_config: BlockDeviceConfig) -> Result<()> {
         // check if the path exists
         if !block_device_config.path_on_host.exists() {
@@ -238,21 +285,6 @@ mod tests {
         }
     }
 
-    // This implementation is used only in tests.
-    // We cannot directly derive clone because RateLimiter does not implement clone.
-    impl Clone for BlockDeviceConfig {
-        fn clone(&self) -> Self {
-            BlockDeviceConfig {
-                path_on_host: self.path_on_host.clone(),
-                is_root_device: self.is_root_device,
-                partuuid: self.partuuid.clone(),
-                is_read_only: self.is_read_only,
-                drive_id: self.drive_id.clone(),
-                rate_limiter: None,
-            }
-        }
-    }
-
     #[test]
     fn test_create_block_devices_configs() {
         let block_devices_configs = BlockDeviceConfigs::new();
@@ -318,6 +350,66 @@ mod tests {
         assert_eq!(block_devices_configs.has_read_only_root(), true);
     }
 
+    #[test]
+    fn test_remove_block_device() {
+        let dummy_file_1 = TempFile::new().unwrap();
+        let root_block_device = BlockDeviceConfig {
+            path_on_host: dummy_file_1.as_path().to_path_buf(),
+            is_root_device: true,
+            partuuid: Some("0eaa91a0-01".to_string()),
+            is_read_only: true,
+            drive_id: String::from("1"),
+            rate_limiter: None,
+        };
+        let dummy_file_2 = TempFile::new().unwrap();
+        let mut new_root_block_device = BlockDeviceConfig {
+            path_on_host: dummy_file_2.as_path().to_path_buf(),
+            is_root_device: true,
+            partuuid: None,
+            is_read_only: false,
+            drive_id: String::from("2"),
+            rate_limiter: None,
+        };
+
+        let mut block_devices_configs = BlockDeviceConfigs::new();
+        assert_eq!(
+            block_devices_configs.remove("1").unwrap_err(),
+            DriveError::InvalidBlockDeviceID
+        );
+        block_devices_configs
+            .insert(root_block_device.clone())
+            .unwrap();
+        assert_eq!(
+            block_devices_configs
+                .insert(new_root_block_device.clone())
+                .unwrap_err(),
+            DriveError::RootBlockDeviceAlreadyAdded
+        );
+
+        // Once the root device is removed, another drive can take its place.
+        assert_eq!(
+            block_devices_configs.remove("1").unwrap(),
+            root_block_device
+        );
+        assert!(!block_devices_configs.has_root_block_device());
+        assert!(!block_devices_configs.has_read_only_root());
+        assert!(!block_devices_configs.has_partuuid_root);
+        assert!(block_devices_configs.get("1").is_none());
+        block_devices_configs
+            .insert(new_root_block_device.clone())
+            .unwrap();
+        assert!(block_devices_configs.has_root_block_device());
+
+        // Removing a non-root drive keeps the root device.
+        new_root_block_device.drive_id = String::from("3");
+        new_root_block_device.is_root_device = false;
+        new_root_block_device.path_on_host = dummy_file_1.as_path().to_path_buf();
+        block_devices_configs.insert(new_root_block_device).unwrap();
+        block_devices_configs.remove("3").unwrap();
+        assert!(block_devices_configs.has_root_block_device());
+        assert_eq!(block_devices_configs.config_list.len(), 1);
+    }
+
     #[test]
     fn test_add_two_root_block_devices_configs() {
         let dummy_file_1 = TempFile::new().unwrap();
@@ -602,5 +694,19 @@ mod tests {
         );
         assert_eq!(block_config.path_on_host(), dummy_block_file.as_path());
         assert_eq!(block_config.is_read_only(), expected_is_read_only);
+
+        let mut block_devices_configs = BlockDeviceConfigs::new();
+        assert!(block_devices_configs.insert(block_config.clone()).is_ok());
+        assert_eq!(
+            block_devices_configs.get("dummy_drive"),
+            Some(&block_config)
+        );
+        assert!(block_devices_configs.get("other_drive").is_none());
+
+        // The status holds the fields of the configuration, along with the device state.
+        let status = serde_json::to_value(BlockDeviceStatus::from(block_config)).unwrap();
+        assert_eq!(status["drive_id"], "dummy_drive");
+        assert_eq!(status["activated"], false);
+        assert!(status.get("rate_limiter_budget").is_none());
     }
 }
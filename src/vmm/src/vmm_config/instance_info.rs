@@ -1,15 +1,235 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use utils::time::{get_time, ClockType};
+
+/// The states a microVM goes through during its lifetime.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum InstanceState {
+    /// The microVM is being configured and was not started yet.
+    NotStarted,
+    /// The microVM is being built and booted.
+    Starting,
+    /// The guest is running.
+    Running,
+    /// The guest rebooted and is being booted again.
+    Rebooting,
+    /// The vCPUs of the guest are paused, and the guest doesn't run until they are resumed.
+    Paused,
+    /// The guest was asked to shut down and is expected to exit.
+    ShuttingDown,
+    /// The VMM stopped and the process is exiting.
+    Halted,
+}
+
+impl InstanceState {
+    // Whether the microVM can go from this state to `next`.
+    fn can_move_to(self, next: InstanceState) -> bool {
+        use self::InstanceState::*;
+
+        match (self, next) {
+            // A microVM which failed to start can be configured and started again.
+            (NotStarted, Starting) | (Starting, NotStarted) | (Starting, Running) => true,
+            (Running, Rebooting) | (Rebooting, Running) => true,
+            (Running, Paused) | (Paused, Running) => true,
+            (Running, ShuttingDown) | (Paused, ShuttingDown) => true,
+            // The VMM stops only once the microVM was started, and nothing comes after.
+            (NotStarted, Halted) | (Halted, _) => false,
+            (_, Halted) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for InstanceState {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Errors associated with the lifecycle of the microVM.
+#[derive(Debug, PartialEq)]
+pub enum InstanceStateError {
+    /// The microVM can't go from the first state to the second one.
+    InvalidTransition(InstanceState, InstanceState),
+}
+
+impl Display for InstanceStateError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::InstanceStateError::*;
+
+        match self {
+            InvalidTransition(from, to) => write!(
+                f,
+                "The microVM can't go from the {} state to the {} state.",
+                from, to
+            ),
+        }
+    }
+}
+
 /// The strongly typed that contains general information about the microVM.
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
 pub struct InstanceInfo {
     /// The ID of the microVM.
     pub id: String,
-    /// Whether the microVM has been started.
-    pub started: bool,
+    // The current state of the microVM.
+    state: InstanceState,
+    // When the microVM last entered each of the states it went through, in milliseconds since
+    // the UNIX epoch.
+    state_timestamps: BTreeMap<InstanceState, u64>,
     /// The version of the VMM that runs the microVM.
     pub vmm_version: String,
     /// The name of the application that runs the microVM.
     pub app_name: String,
 }
+
+impl InstanceInfo {
+    /// Creates the information of a microVM which was not started yet.
+    pub fn new(id: String, vmm_version: String, app_name: String) -> InstanceInfo {
+        let mut instance_info = InstanceInfo {
+            id,
+            state: InstanceState::NotStarted,
+            state_timestamps: BTreeMap::new(),
+            vmm_version,
+            app_name,
+        };
+        instance_info.record_state_timestamp();
+        instance_info
+    }
+
+    /// Whether the microVM was started. Kept for the clients which predate `state`.
+    pub fn started(&self) -> bool {
+        self.state > InstanceState::Starting
+    }
+
+    /// Returns the current state of the microVM.
+    pub fn state(&self) -> InstanceState {
+        self.state
+    }
+
+    /// Moves the microVM to `state`, recording when it happened. Fails if the microVM can't go
+    /// there from its current state.
+    pub fn set_state(
+        &mut self,
+        state: InstanceState,
+    ) -> std::result::Result<(), InstanceStateError> {
+        if !self.state.can_move_to(state) {
+            return Err(InstanceStateError::InvalidTransition(self.state, state));
+        }
+        self.state = state;
+        self.record_state_timestamp();
+        Ok(())
+    }
+
+    fn record_state_timestamp(&mut self) {
+        self.state_timestamps
+            .insert(self.state, get_time(ClockType::Real) / 1_000_000);
+    }
+}
+
+impl Serialize for InstanceInfo {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("InstanceInfo", 6)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("started", &self.started())?;
+        state.serialize_field("state", &self.state)?;
+        state.serialize_field("state_timestamps", &self.state_timestamps)?;
+        state.serialize_field("vmm_version", &self.vmm_version)?;
+        state.serialize_field("app_name", &self.app_name)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_state() {
+        let mut instance_info =
+            InstanceInfo::new("id".to_string(), "0.1".to_string(), "app".to_string());
+        assert_eq!(instance_info.state(), InstanceState::NotStarted);
+        assert!(!instance_info.started());
+        assert_eq!(instance_info.state_timestamps.len(), 1);
+
+        instance_info.set_state(InstanceState::Starting).unwrap();
+        assert!(!instance_info.started());
+        instance_info.set_state(InstanceState::Running).unwrap();
+        assert_eq!(instance_info.state(), InstanceState::Running);
+        assert!(instance_info.started());
+        assert_eq!(
+            instance_info.state_timestamps.keys().collect::<Vec<_>>(),
+            vec![
+                &InstanceState::NotStarted,
+                &InstanceState::Starting,
+                &InstanceState::Running
+            ]
+        );
+        assert!(
+            instance_info.state_timestamps[&InstanceState::NotStarted]
+                <= instance_info.state_timestamps[&InstanceState::Running]
+        );
+        assert_eq!(InstanceState::ShuttingDown.to_string(), "ShuttingDown");
+
+        let json = serde_json::to_value(&instance_info).unwrap();
+        assert_eq!(json["started"], true);
+        assert_eq!(json["state"], "Running");
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        use self::InstanceState::*;
+
+        let mut instance_info =
+            InstanceInfo::new("id".to_string(), "0.1".to_string(), "app".to_string());
+        assert_eq!(
+            instance_info.set_state(Running),
+            Err(InstanceStateError::InvalidTransition(NotStarted, Running))
+        );
+        assert!(instance_info.set_state(Halted).is_err());
+
+        // A microVM which failed to start goes back to `NotStarted`, but a started one doesn't.
+        instance_info.set_state(Starting).unwrap();
+        instance_info.set_state(NotStarted).unwrap();
+        instance_info.set_state(Starting).unwrap();
+        instance_info.set_state(Running).unwrap();
+        assert!(instance_info.set_state(NotStarted).is_err());
+        assert!(instance_info.set_state(Starting).is_err());
+
+        instance_info.set_state(Rebooting).unwrap();
+        assert!(instance_info.set_state(ShuttingDown).is_err());
+        instance_info.set_state(Running).unwrap();
+        instance_info.set_state(ShuttingDown).unwrap();
+        assert!(instance_info.set_state(Running).is_err());
+
+        // Nothing comes after `Halted`.
+        instance_info.set_state(Halted).unwrap();
+        assert_eq!(instance_info.state(), Halted);
+        for state in &[
+            NotStarted,
+            Starting,
+            Running,
+            Rebooting,
+            Paused,
+            ShuttingDown,
+            Halted,
+        ] {
+            assert_eq!(
+                instance_info.set_state(*state),
+                Err(InstanceStateError::InvalidTransition(Halted, *state))
+            );
+        }
+        assert_eq!(
+            InstanceStateError::InvalidTransition(Halted, Running).to_string(),
+            "The microVM can't go from the Halted state to the Running state."
+        );
+    }
+}
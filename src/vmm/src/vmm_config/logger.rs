@@ -99,6 +99,8 @@ impl LoggerConfig {
 pub enum LoggerConfigError {
     /// Cannot initialize the logger due to bad user input.
     InitializationFailure(String),
+    /// The logger was not configured through the API or the configuration file.
+    NotConfigured,
 }
 
 impl Display for LoggerConfigError {
@@ -106,6 +108,7 @@ impl Display for LoggerConfigError {
         use self::LoggerConfigError::*;
         match *self {
             InitializationFailure(ref err_msg) => write!(f, "{}", err_msg.replace("\"", "")),
+            NotConfigured => write!(f, "The logger is not configured."),
         }
     }
 }
@@ -114,6 +117,22 @@ impl Display for LoggerConfigError {
 pub fn init_logger(
     logger_cfg: LoggerConfig,
     firecracker_version: &str,
+) -> std::result::Result<(), LoggerConfigError> {
+    let log_dest = open_log_dest(&logger_cfg)?;
+    init_logger_with_dest(logger_cfg, log_dest, firecracker_version)
+}
+
+/// Opens the named pipe or file the logs described by `logger_cfg` are written to.
+pub fn open_log_dest(logger_cfg: &LoggerConfig) -> std::result::Result<Writer, LoggerConfigError> {
+    Writer::new(logger_cfg.log_path.clone())
+        .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))
+}
+
+/// Configures the logger as described in `logger_cfg`, with the logs written to `log_dest`.
+pub fn init_logger_with_dest(
+    logger_cfg: LoggerConfig,
+    log_dest: Writer,
+    firecracker_version: &str,
 ) -> std::result::Result<(), LoggerConfigError> {
     LOGGER
         .set_level(logger_cfg.level.into())
@@ -123,10 +142,7 @@ pub fn init_logger(
     LOGGER
         .init(
             format!("Running {} v{}", "Firecracker", firecracker_version),
-            Box::new(
-                Writer::new(logger_cfg.log_path)
-                    .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))?,
-            ),
+            Box::new(log_dest),
         )
         .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))
 }
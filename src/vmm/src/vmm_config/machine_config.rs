@@ -58,6 +58,12 @@ pub struct VmConfig {
     /// A CPU template that it is used to filter the CPU features exposed to the guest.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cpu_template: Option<CpuFeaturesTemplate>,
+    /// What the VMM does when the guest reboots.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reboot_policy: Option<RebootPolicy>,
+    /// The number of slots reserved at boot for network interfaces hot-plugged later.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplug_slots: Option<u8>,
 }
 
 impl Default for VmConfig {
@@ -67,6 +73,8 @@ impl Default for VmConfig {
             mem_size_mib: Some(128),
             ht_enabled: Some(false),
             cpu_template: None,
+            reboot_policy: None,
+            hotplug_slots: None,
         }
     }
 }
@@ -79,9 +87,11 @@ impl fmt::Display for VmConfig {
         let cpu_template = self
             .cpu_template
             .map_or("Uninitialized".to_string(), |c| c.to_string());
+        let reboot_policy = self.reboot_policy.unwrap_or(RebootPolicy::Exit);
+        let hotplug_slots = self.hotplug_slots.unwrap_or(0);
 
-        write!(f, "{{ \"vcpu_count\": {:?}, \"mem_size_mib\": {:?},  \"ht_enabled\": {:?},  \"cpu_template\": {:?} }}",
-               vcpu_count, mem_size, ht_enabled, cpu_template)
+        write!(f, "{{ \"vcpu_count\": {:?}, \"mem_size_mib\": {:?},  \"ht_enabled\": {:?},  \"cpu_template\": {:?},  \"reboot_policy\": {:?},  \"hotplug_slots\": {:?} }}",
+               vcpu_count, mem_size, ht_enabled, cpu_template, reboot_policy.to_string(), hotplug_slots)
     }
 }
 
@@ -120,6 +130,25 @@ impl fmt::Display for CpuFeaturesTemplate {
     }
 }
 
+/// What the VMM does when the guest reboots.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RebootPolicy {
+    /// The VMM exits, as it does when the guest shuts down.
+    Exit,
+    /// The VMM boots the guest again, keeping its devices and the API server.
+    Restart,
+}
+
+impl fmt::Display for RebootPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RebootPolicy::Exit => write!(f, "exit"),
+            RebootPolicy::Restart => write!(f, "restart"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,6 +159,12 @@ mod tests {
         assert_eq!(CpuFeaturesTemplate::T2.to_string(), "T2".to_string());
     }
 
+    #[test]
+    fn test_display_reboot_policy() {
+        assert_eq!(RebootPolicy::Exit.to_string(), "exit");
+        assert_eq!(RebootPolicy::Restart.to_string(), "restart");
+    }
+
     #[test]
     fn test_display_vm_config_error() {
         let expected_str = "The vCPU number is invalid! The vCPU number can only \
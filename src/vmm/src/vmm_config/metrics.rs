@@ -39,10 +39,21 @@ impl Display for MetricsConfigError {
 
 /// Configures the metrics as described in `metrics_cfg`.
 pub fn init_metrics(metrics_cfg: MetricsConfig) -> std::result::Result<(), MetricsConfigError> {
+    init_metrics_with_dest(open_metrics_dest(&metrics_cfg)?)
+}
+
+/// Opens the named pipe or file the metrics described by `metrics_cfg` are written to.
+pub fn open_metrics_dest(
+    metrics_cfg: &MetricsConfig,
+) -> std::result::Result<Writer, MetricsConfigError> {
+    Writer::new(metrics_cfg.metrics_path.clone())
+        .map_err(|e| MetricsConfigError::InitializationFailure(e.to_string()))
+}
+
+/// Configures the metrics to be written to `metrics_dest`.
+pub fn init_metrics_with_dest(metrics_dest: Writer) -> std::result::Result<(), MetricsConfigError> {
     METRICS
-        .init(Box::new(Writer::new(metrics_cfg.metrics_path).map_err(
-            |e| MetricsConfigError::InitializationFailure(e.to_string()),
-        )?))
+        .init(Box::new(metrics_dest))
         .map_err(|e| MetricsConfigError::InitializationFailure(e.to_string()))
 }
 
@@ -0,0 +1,59 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub use mmds::data_store::MmdsVersion;
+
+/// This struct represents the strongly typed equivalent of the json body
+/// from MMDS config related requests.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MmdsConfig {
+    /// The MMDS version. `V2` requires the guest to present a session token.
+    #[serde(default)]
+    pub version: MmdsVersion,
+    /// The link-local IPv4 address the MMDS is reachable at. Defaults to `169.254.169.254`.
+    pub ipv4_address: Option<Ipv4Addr>,
+    /// The link-local IPv6 address the MMDS is reachable at. Defaults to `fe80::a9fe:a9fe`.
+    pub ipv6_address: Option<Ipv6Addr>,
+    /// The IDs of the network interfaces that forward guest requests to the MMDS. When
+    /// provided, it replaces the `allow_mmds_requests` setting of every interface.
+    pub network_interfaces: Option<Vec<String>>,
+    /// Whether the MMDS answers the DNS queries for the names defined under its `/dns` key.
+    #[serde(default)]
+    pub dns_enabled: bool,
+    /// Whether the MMDS serves the host time over SNTP.
+    #[serde(default)]
+    pub sntp_enabled: bool,
+}
+
+/// Errors associated with the MMDS configuration.
+#[derive(Debug, PartialEq)]
+pub enum MmdsConfigError {
+    /// The provided IPv4 address is not link-local.
+    InvalidIpv4Addr,
+    /// The provided IPv6 address is not link-local.
+    InvalidIpv6Addr,
+    /// No network interface with the given ID is configured.
+    InvalidNetworkInterfaceId(String),
+}
+
+impl Display for MmdsConfigError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::MmdsConfigError::*;
+        match *self {
+            InvalidIpv4Addr => write!(
+                f,
+                "The MMDS IPv4 address is not link-local (169.254.0.0/16)."
+            ),
+            InvalidIpv6Addr => write!(f, "The MMDS IPv6 address is not link-local (fe80::/10)."),
+            InvalidNetworkInterfaceId(ref iface_id) => write!(
+                f,
+                "The MMDS network interface {} is not configured.",
+                iface_id
+            ),
+        }
+    }
+}
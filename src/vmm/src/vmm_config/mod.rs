@@ -24,6 +24,8 @@ pub mod logger;
 pub mod machine_config;
 /// Wrapper for configuring the metrics.
 pub mod metrics;
+/// Wrapper for configuring the microVM metadata service.
+pub mod mmds;
 /// Wrapper for configuring the network devices attached to the microVM.
 pub mod net;
 /// Wrapper for configuring the vsock devices attached to the microVM.
@@ -40,11 +42,12 @@ pub mod vsock;
 
 /// A public-facing, stateless structure, holding all the data we need to create a TokenBucket
 /// (live) object.
-#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct TokenBucketConfig {
     /// See TokenBucket::size.
     pub size: u64,
     /// See TokenBucket::one_time_burst.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub one_time_burst: Option<u64>,
     /// See TokenBucket::refill_time.
     pub refill_time: u64,
@@ -58,12 +61,14 @@ impl Into<TokenBucket> for TokenBucketConfig {
 
 /// A public-facing, stateless structure, holding all the data we need to create a RateLimiter
 /// (live) object.
-#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct RateLimiterConfig {
     /// Data used to initialize the RateLimiter::bandwidth bucket.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub bandwidth: Option<TokenBucketConfig>,
     /// Data used to initialize the RateLimiter::ops bucket.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ops: Option<TokenBucketConfig>,
 }
 
@@ -96,6 +101,44 @@ impl TryInto<RateLimiter> for RateLimiterConfig {
     }
 }
 
+/// The tokens left in a `TokenBucket` of a running device.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct TokenBucketBudget {
+    /// See TokenBucket::budget.
+    pub budget: u64,
+    /// See TokenBucket::one_time_burst.
+    pub one_time_burst: u64,
+}
+
+impl<'a> From<&'a TokenBucket> for TokenBucketBudget {
+    fn from(bucket: &TokenBucket) -> Self {
+        TokenBucketBudget {
+            budget: bucket.budget(),
+            one_time_burst: bucket.one_time_burst(),
+        }
+    }
+}
+
+/// The tokens left in the buckets of the `RateLimiter` of a running device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct RateLimiterBudget {
+    /// The tokens left in the RateLimiter::bandwidth bucket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<TokenBucketBudget>,
+    /// The tokens left in the RateLimiter::ops bucket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ops: Option<TokenBucketBudget>,
+}
+
+impl<'a> From<&'a RateLimiter> for RateLimiterBudget {
+    fn from(rate_limiter: &RateLimiter) -> Self {
+        RateLimiterBudget {
+            bandwidth: rate_limiter.bandwidth().map(TokenBucketBudget::from),
+            ops: rate_limiter.ops().map(TokenBucketBudget::from),
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, std::io::Error>;
 
 /// Structure `Writer` used for writing to a FIFO.
@@ -146,6 +189,7 @@ impl io::Write for Writer {
 mod tests {
     use std::io::Write;
 
+    use rate_limiter::TokenType;
     use utils::tempfile::TempFile;
 
     use super::*;
@@ -205,6 +249,40 @@ mod tests {
         assert_eq!(rlconf.ops.unwrap().refill_time, REFILL_TIME * 2);
     }
 
+    #[test]
+    fn test_rate_limiter_budget() {
+        let mut rl: RateLimiter = RateLimiterConfig {
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: Some(100),
+                refill_time: 1000,
+            }),
+            ops: None,
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(
+            RateLimiterBudget::from(&rl),
+            RateLimiterBudget {
+                bandwidth: Some(TokenBucketBudget {
+                    budget: 1000,
+                    one_time_burst: 100,
+                }),
+                ops: None,
+            }
+        );
+
+        // The one time burst is used up first.
+        assert!(rl.consume(300, TokenType::Bytes));
+        let budget = RateLimiterBudget::from(&rl);
+        assert_eq!(budget.bandwidth.unwrap().one_time_burst, 0);
+        assert!(budget.bandwidth.unwrap().budget <= 800);
+        assert_eq!(
+            serde_json::to_value(RateLimiterBudget::default()).unwrap(),
+            serde_json::json!({})
+        );
+    }
+
     #[test]
     fn test_log_writer() {
         let log_file_temp =
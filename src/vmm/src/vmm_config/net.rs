@@ -4,14 +4,15 @@
 use std::fmt::{Display, Formatter, Result};
 use std::result;
 
-use super::RateLimiterConfig;
+use super::{RateLimiterBudget, RateLimiterConfig};
+use builder::StartMicrovmError;
 use devices;
 use dumbo::MacAddr;
 use utils::net::{Tap, TapError};
 
 /// This struct represents the strongly typed equivalent of the json body from net iface
 /// related requests.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceConfig {
     /// ID of the guest network interface.
@@ -19,10 +20,13 @@ pub struct NetworkInterfaceConfig {
     /// Host level path for the guest network interface.
     pub host_dev_name: String,
     /// Guest MAC address.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub guest_mac: Option<MacAddr>,
     /// Rate Limiter for received packages.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rx_rate_limiter: Option<RateLimiterConfig>,
     /// Rate Limiter for transmitted packages.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tx_rate_limiter: Option<RateLimiterConfig>,
     #[serde(default = "default_allow_mmds_requests")]
     /// If this field is set, the device model will reply to HTTP GET
@@ -58,13 +62,20 @@ impl NetworkInterfaceConfig {
     }
 }
 
-/// The data fed into a network iface update request. Currently, only the RX and TX rate limiters
-/// can be updated.
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+/// The data fed into a network iface update request. Only the provided fields are updated.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceUpdateConfig {
     /// The net iface ID, as provided by the user at iface creation time.
     pub iface_id: String,
+    /// New host level path for the guest network interface. After the microVM has started,
+    /// the backing tap device is swapped without detaching the interface from the guest.
+    pub host_dev_name: Option<String>,
+    /// New guest MAC address.
+    pub guest_mac: Option<MacAddr>,
+    /// New link state of the interface, as reported to the guest. The link state can only be
+    /// changed after the microVM has started.
+    pub link_up: Option<bool>,
     /// New RX rate limiter config. Only provided data will be updated. I.e. if any optional data
     /// is missing, it will not be nullified, but left unchanged.
     pub rx_rate_limiter: Option<RateLimiterConfig>,
@@ -73,6 +84,38 @@ pub struct NetworkInterfaceUpdateConfig {
     pub tx_rate_limiter: Option<RateLimiterConfig>,
 }
 
+/// The configuration of a network interface, along with the state of the emulated device
+/// once the microVM is running.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NetworkInterfaceStatus {
+    /// The current configuration of the interface.
+    #[serde(flatten)]
+    pub config: NetworkInterfaceConfig,
+    /// Whether the guest driver activated the device.
+    pub activated: bool,
+    /// The link state of the interface, as reported to the guest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_up: Option<bool>,
+    /// The tokens left in the RX rate limiter of the device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_rate_limiter_budget: Option<RateLimiterBudget>,
+    /// The tokens left in the TX rate limiter of the device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_rate_limiter_budget: Option<RateLimiterBudget>,
+}
+
+impl From<NetworkInterfaceConfig> for NetworkInterfaceStatus {
+    fn from(config: NetworkInterfaceConfig) -> Self {
+        NetworkInterfaceStatus {
+            config,
+            activated: false,
+            link_up: None,
+            rx_rate_limiter_budget: None,
+            tx_rate_limiter_budget: None,
+        }
+    }
+}
+
 /// Errors associated with `NetworkInterfaceConfig`.
 #[derive(Debug)]
 pub enum NetworkInterfaceError {
@@ -82,10 +125,22 @@ pub enum NetworkInterfaceError {
     HostDeviceNameInUse(String),
     /// Couldn't find the interface to update (patch).
     DeviceIdNotFound,
+    /// The interface ID is already in use by an attached interface.
+    DeviceIdInUse(String),
+    /// The guest MAC address can't be set on a running interface created without one.
+    GuestMacNotSupported,
+    /// The interface could not be plugged into the running microVM.
+    HotplugFailed(StartMicrovmError),
+    /// The guest did not release the interface, which can't be removed yet.
+    DeviceNotReleased(String),
     /// Cannot open/create tap device.
     OpenTap(TapError),
     /// Error updating (patching) the rate limiters.
     RateLimiterUpdateFailed(devices::Error),
+    /// Error notifying the guest of a MAC address or link state change.
+    DeviceUpdateFailed(devices::Error),
+    /// Error swapping the tap device backing the interface.
+    TapSwapFailed(devices::virtio::net::Error),
 }
 
 impl Display for NetworkInterfaceError {
@@ -103,6 +158,21 @@ impl Display for NetworkInterfaceError {
                 format!("The host device name {} is already in use.", host_dev_name)
             ),
             DeviceIdNotFound => write!(f, "Invalid interface ID - not found."),
+            DeviceIdInUse(ref iface_id) => {
+                write!(f, "The interface ID {} is already in use.", iface_id)
+            }
+            GuestMacNotSupported => write!(
+                f,
+                "The guest MAC address can only be updated after boot for interfaces created \
+                 with one."
+            ),
+            HotplugFailed(ref e) => write!(f, "Unable to hot-plug the interface: {}", e),
+            DeviceNotReleased(ref iface_id) => write!(
+                f,
+                "The guest did not release the interface {}, which has to be unbound from its \
+                 driver before being removed.",
+                iface_id
+            ),
             OpenTap(ref e) => {
                 // We are propagating the Tap Error. This error can contain
                 // imbricated quotes which would result in an invalid json.
@@ -117,6 +187,8 @@ impl Display for NetworkInterfaceError {
                 )
             }
             RateLimiterUpdateFailed(ref e) => write!(f, "Unable to update rate limiter: {:?}", e),
+            DeviceUpdateFailed(ref e) => write!(f, "Unable to update the net device: {:?}", e),
+            TapSwapFailed(ref e) => write!(f, "Unable to swap the TAP device: {:?}", e),
         }
     }
 }
@@ -162,6 +234,23 @@ impl NetworkInterfaceConfigs {
         }
     }
 
+    /// Returns the configuration of the network interface with the id `iface_id`.
+    pub fn get(&self, iface_id: &str) -> Option<&NetworkInterfaceConfig> {
+        self.if_list.iter().find(|netif| netif.iface_id == iface_id)
+    }
+
+    /// Removes the network interface with the id `iface_id` from the configuration list.
+    pub fn remove(
+        &mut self,
+        iface_id: &str,
+    ) -> result::Result<NetworkInterfaceConfig, NetworkInterfaceError> {
+        self.if_list
+            .iter()
+            .position(|netif| netif.iface_id == iface_id)
+            .map(|index| self.if_list.remove(index))
+            .ok_or(NetworkInterfaceError::DeviceIdNotFound)
+    }
+
     fn get_index_of_mac(&self, mac: MacAddr) -> Option<usize> {
         self.if_list
             .iter()
@@ -201,6 +290,81 @@ impl NetworkInterfaceConfigs {
         Ok(())
     }
 
+    /// Checks that `update_cfg` refers to an existing interface and that the new guest MAC
+    /// and host device name, if any, are not used by another interface. Returns the index of
+    /// the interface to be updated.
+    pub fn validate_partial_update(
+        &self,
+        update_cfg: &NetworkInterfaceUpdateConfig,
+    ) -> result::Result<usize, NetworkInterfaceError> {
+        let index = self
+            .if_list
+            .iter()
+            .position(|netif| netif.iface_id == update_cfg.iface_id)
+            .ok_or(NetworkInterfaceError::DeviceIdNotFound)?;
+
+        if let Some(mac) = update_cfg.guest_mac {
+            match self.get_index_of_mac(mac) {
+                Some(mac_index) if mac_index != index => {
+                    return Err(NetworkInterfaceError::GuestMacAddressInUse(mac.to_string()))
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(ref host_dev_name) = update_cfg.host_dev_name {
+            match self.get_index_of_dev_name(host_dev_name) {
+                Some(dev_name_index) if dev_name_index != index => {
+                    return Err(NetworkInterfaceError::HostDeviceNameInUse(
+                        host_dev_name.clone(),
+                    ))
+                }
+                _ => (),
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Merges `update_cfg` into the configuration of the interface it refers to. The update
+    /// is expected to have been checked with `validate_partial_update`.
+    pub fn apply_partial_update(
+        &mut self,
+        update_cfg: NetworkInterfaceUpdateConfig,
+    ) -> result::Result<(), NetworkInterfaceError> {
+        let netif = self
+            .if_list
+            .iter_mut()
+            .find(|netif| netif.iface_id == update_cfg.iface_id)
+            .ok_or(NetworkInterfaceError::DeviceIdNotFound)?;
+
+        macro_rules! update_rate_limiter {
+            ($rate_limiter: ident) => {{
+                if let Some(new_rlim_cfg) = update_cfg.$rate_limiter {
+                    if let Some(ref mut old_rlim_cfg) = netif.$rate_limiter {
+                        // We already have a rate limiter set, so we'll update it.
+                        old_rlim_cfg.update(&new_rlim_cfg);
+                    } else {
+                        // No old rate limiter; create one now.
+                        netif.$rate_limiter = Some(new_rlim_cfg);
+                    }
+                }
+            }};
+        }
+
+        update_rate_limiter!(rx_rate_limiter);
+        update_rate_limiter!(tx_rate_limiter);
+
+        if let Some(host_dev_name) = update_cfg.host_dev_name {
+            netif.host_dev_name = host_dev_name;
+        }
+        if update_cfg.guest_mac.is_some() {
+            netif.guest_mac = update_cfg.guest_mac;
+        }
+
+        Ok(())
+    }
+
     fn update(
         &mut self,
         index: usize,
@@ -281,19 +445,6 @@ mod tests {
         }
     }
 
-    impl Clone for NetworkInterfaceConfig {
-        fn clone(&self) -> Self {
-            NetworkInterfaceConfig {
-                iface_id: self.iface_id.clone(),
-                host_dev_name: self.host_dev_name.clone(),
-                guest_mac: self.guest_mac,
-                rx_rate_limiter: None,
-                tx_rate_limiter: None,
-                allow_mmds_requests: self.allow_mmds_requests,
-            }
-        }
-    }
-
     #[test]
     fn test_insert() {
         let mut netif_configs = NetworkInterfaceConfigs::new();
@@ -419,6 +570,26 @@ mod tests {
             NetworkInterfaceError::DeviceIdNotFound,
             NetworkInterfaceError::DeviceIdNotFound
         );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::DeviceIdInUse("id".to_string()),
+            NetworkInterfaceError::DeviceIdInUse("id".to_string())
+        );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::GuestMacNotSupported,
+            NetworkInterfaceError::GuestMacNotSupported
+        );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::HotplugFailed(StartMicrovmError::NetDeviceNotConfigured),
+            NetworkInterfaceError::HotplugFailed(StartMicrovmError::NetDeviceNotConfigured)
+        );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::DeviceNotReleased("id".to_string()),
+            NetworkInterfaceError::DeviceNotReleased("id".to_string())
+        );
         let _ = format!(
             "{}{:?}",
             NetworkInterfaceError::OpenTap(TapError::InvalidIfname),
@@ -433,6 +604,103 @@ mod tests {
                 io::Error::last_os_error()
             ))
         );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::DeviceUpdateFailed(devices::Error::IoError(
+                io::Error::last_os_error()
+            )),
+            NetworkInterfaceError::DeviceUpdateFailed(devices::Error::IoError(
+                io::Error::last_os_error()
+            ))
+        );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::TapSwapFailed(devices::virtio::net::Error::TapSetOffload(
+                TapError::InvalidIfname
+            )),
+            NetworkInterfaceError::TapSwapFailed(devices::virtio::net::Error::TapSetOffload(
+                TapError::InvalidIfname
+            ))
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut netif_configs = NetworkInterfaceConfigs::new();
+        assert!(netif_configs
+            .insert(create_netif("id_1", "dev8", "01:23:45:67:89:0a"))
+            .is_ok());
+
+        assert_eq!(netif_configs.get("id_1").unwrap().host_dev_name, "dev8");
+        assert_eq!(netif_configs.remove("id_1").unwrap().host_dev_name, "dev8");
+        assert!(netif_configs.get("id_1").is_none());
+        assert!(netif_configs.is_empty());
+        match netif_configs.remove("id_1") {
+            Err(NetworkInterfaceError::DeviceIdNotFound) => (),
+            _ => unreachable!(),
+        }
+
+        // The MAC address and the tap are free to be used again.
+        assert!(netif_configs
+            .insert(create_netif("id_2", "dev8", "01:23:45:67:89:0a"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_partial_update() {
+        let mut netif_configs = NetworkInterfaceConfigs::new();
+        assert!(netif_configs
+            .insert(create_netif("id_1", "dev5", "01:23:45:67:89:0a"))
+            .is_ok());
+        assert!(netif_configs
+            .insert(create_netif("id_2", "dev6", "01:23:45:67:89:0b"))
+            .is_ok());
+
+        // Unknown interface.
+        let mut update_cfg = NetworkInterfaceUpdateConfig {
+            iface_id: "id_3".to_string(),
+            ..Default::default()
+        };
+        match netif_configs.validate_partial_update(&update_cfg) {
+            Err(NetworkInterfaceError::DeviceIdNotFound) => (),
+            _ => unreachable!(),
+        }
+
+        // MAC address used by another interface.
+        update_cfg.iface_id = "id_1".to_string();
+        update_cfg.guest_mac = Some(MacAddr::parse_str("01:23:45:67:89:0b").unwrap());
+        match netif_configs.validate_partial_update(&update_cfg) {
+            Err(NetworkInterfaceError::GuestMacAddressInUse(_)) => (),
+            _ => unreachable!(),
+        }
+
+        // Host device name used by another interface.
+        update_cfg.guest_mac = Some(MacAddr::parse_str("01:23:45:67:89:0c").unwrap());
+        update_cfg.host_dev_name = Some("dev6".to_string());
+        match netif_configs.validate_partial_update(&update_cfg) {
+            Err(NetworkInterfaceError::HostDeviceNameInUse(_)) => (),
+            _ => unreachable!(),
+        }
+
+        // Reusing the interface's own values is fine.
+        update_cfg.host_dev_name = Some("dev5".to_string());
+        assert_eq!(
+            netif_configs.validate_partial_update(&update_cfg).unwrap(),
+            0
+        );
+
+        update_cfg.host_dev_name = Some("dev7".to_string());
+        assert_eq!(
+            netif_configs.validate_partial_update(&update_cfg).unwrap(),
+            0
+        );
+        assert!(netif_configs.apply_partial_update(update_cfg).is_ok());
+        let netif = netif_configs.iter().next().unwrap();
+        assert_eq!(netif.host_dev_name, "dev7");
+        assert_eq!(
+            netif.guest_mac,
+            Some(MacAddr::parse_str("01:23:45:67:89:0c").unwrap())
+        );
     }
 
     #[test]
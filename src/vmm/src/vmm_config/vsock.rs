@@ -16,9 +16,31 @@ pub struct VsockDeviceConfig {
     pub uds_path: String,
 }
 
+/// The configuration of the vsock device, along with the state of the emulated device once
+/// the microVM is running.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct VsockDeviceStatus {
+    /// The configuration of the vsock device.
+    #[serde(flatten)]
+    pub config: VsockDeviceConfig,
+    /// Whether the guest driver activated the device.
+    pub activated: bool,
+}
+
+impl From<VsockDeviceConfig> for VsockDeviceStatus {
+    fn from(config: VsockDeviceConfig) -> Self {
+        VsockDeviceStatus {
+            config,
+            activated: false,
+        }
+    }
+}
+
 /// Errors associated with `VsockDeviceConfig`.
 #[derive(Debug)]
 pub enum VsockError {
+    /// No vsock device is configured.
+    DeviceNotFound,
     /// The update is not allowed after booting the microvm.
     UpdateNotAllowedPostBoot,
 }
@@ -27,6 +49,7 @@ impl Display for VsockError {
     fn fmt(&self, f: &mut Formatter) -> Result {
         use self::VsockError::*;
         match *self {
+            DeviceNotFound => write!(f, "No vsock device is configured."),
             UpdateNotAllowedPostBoot => {
                 write!(f, "The update operation is not allowed after boot.",)
             }
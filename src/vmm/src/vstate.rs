@@ -385,6 +385,10 @@ pub struct Vm {
     supported_cpuid: CpuId,
     #[cfg(target_arch = "x86_64")]
     supported_msrs: MsrList,
+    // The state of the PIT and of the interrupt controllers right after they were created, which
+    // a restarted guest boots with.
+    #[cfg(target_arch = "x86_64")]
+    irqchip_boot_state: Option<IrqchipState>,
 
     // Arm specific fields.
     // On aarch64 we need to keep around the fd obtained by creating the VGIC device.
@@ -412,6 +416,8 @@ impl Vm {
             supported_cpuid,
             #[cfg(target_arch = "x86_64")]
             supported_msrs,
+            #[cfg(target_arch = "x86_64")]
+            irqchip_boot_state: None,
             #[cfg(target_arch = "aarch64")]
             irqchip_handle: None,
         })
@@ -467,13 +473,25 @@ impl Vm {
 
     /// Creates the irq chip and an in-kernel device model for the PIT.
     #[cfg(target_arch = "x86_64")]
-    pub fn setup_irqchip(&self) -> Result<()> {
+    pub fn setup_irqchip(&mut self) -> Result<()> {
         self.fd.create_irq_chip().map_err(Error::VmSetup)?;
         let mut pit_config = kvm_pit_config::default();
         // We need to enable the emulation of a dummy speaker port stub so that writing to port 0x61
         // (i.e. KVM_SPEAKER_BASE_ADDRESS) does not trigger an exit to user space.
         pit_config.flags = KVM_PIT_SPEAKER_DUMMY;
-        self.fd.create_pit2(pit_config).map_err(Error::VmSetup)
+        self.fd.create_pit2(pit_config).map_err(Error::VmSetup)?;
+        self.irqchip_boot_state = Some(self.save_irqchip_state()?);
+        Ok(())
+    }
+
+    /// Brings the PIT and the interrupt controllers back to the state they were created in, for a
+    /// guest which restarts. The clock keeps running.
+    #[cfg(target_arch = "x86_64")]
+    pub fn reset_irqchip(&self) -> Result<()> {
+        match self.irqchip_boot_state.as_ref() {
+            Some(state) => self.restore_irqchip_state(state),
+            None => Ok(()),
+        }
     }
 
     /// Creates the GIC (Global Interrupt Controller).
@@ -500,12 +518,27 @@ impl Vm {
     #[cfg(target_arch = "x86_64")]
     /// Saves and returns the Kvm Vm state.
     pub fn save_state(&self) -> Result<VmState> {
-        let pitstate = self.fd.get_pit2().map_err(Error::VmGetPit2)?;
+        let irqchip = self.save_irqchip_state()?;
 
         let mut clock = self.fd.get_clock().map_err(Error::VmGetClock)?;
         // This bit is not accepted in SET_CLOCK, clear it.
         clock.flags &= !KVM_CLOCK_TSC_STABLE;
 
+        Ok(VmState { irqchip, clock })
+    }
+
+    #[allow(unused)]
+    #[cfg(target_arch = "x86_64")]
+    /// Restores the Kvm Vm state.
+    pub fn restore_state(&self, state: &VmState) -> Result<()> {
+        self.restore_irqchip_state(&state.irqchip)?;
+        self.fd.set_clock(&state.clock).map_err(Error::VmSetClock)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn save_irqchip_state(&self) -> Result<IrqchipState> {
+        let pitstate = self.fd.get_pit2().map_err(Error::VmGetPit2)?;
+
         let mut pic_master = kvm_irqchip::default();
         pic_master.chip_id = KVM_IRQCHIP_PIC_MASTER;
         self.fd
@@ -524,23 +557,19 @@ impl Vm {
             .get_irqchip(&mut ioapic)
             .map_err(Error::VmGetIrqChip)?;
 
-        Ok(VmState {
+        Ok(IrqchipState {
             pitstate,
-            clock,
             pic_master,
             pic_slave,
             ioapic,
         })
     }
 
-    #[allow(unused)]
     #[cfg(target_arch = "x86_64")]
-    /// Restores the Kvm Vm state.
-    pub fn restore_state(&self, state: &VmState) -> Result<()> {
+    fn restore_irqchip_state(&self, state: &IrqchipState) -> Result<()> {
         self.fd
             .set_pit2(&state.pitstate)
             .map_err(Error::VmSetPit2)?;
-        self.fd.set_clock(&state.clock).map_err(Error::VmSetClock)?;
         self.fd
             .set_irqchip(&state.pic_master)
             .map_err(Error::VmSetIrqChip)?;
@@ -554,17 +583,23 @@ impl Vm {
     }
 }
 
-#[allow(unused)]
 #[cfg(target_arch = "x86_64")]
-/// Structure holding VM kvm state.
-pub struct VmState {
+/// Structure holding the state of the in-kernel PIT and interrupt controllers.
+pub struct IrqchipState {
     pitstate: kvm_pit_state2,
-    clock: kvm_clock_data,
     pic_master: kvm_irqchip,
     pic_slave: kvm_irqchip,
     ioapic: kvm_irqchip,
 }
 
+#[allow(unused)]
+#[cfg(target_arch = "x86_64")]
+/// Structure holding VM kvm state.
+pub struct VmState {
+    irqchip: IrqchipState,
+    clock: kvm_clock_data,
+}
+
 /// Encapsulates configuration parameters for the guest vCPUS.
 #[derive(Debug, PartialEq)]
 pub struct VcpuConfig {
@@ -593,9 +628,19 @@ pub struct Vcpu {
     cpuid: CpuId,
     #[cfg(target_arch = "x86_64")]
     msr_list: MsrList,
+    // The local APIC state the vCPU was created with, used again to reset it.
+    #[cfg(target_arch = "x86_64")]
+    boot_lapic: kvm_lapic_state,
 
     #[cfg(target_arch = "aarch64")]
     mpidr: u64,
+    // The initialization parameters the vCPU was configured with, used again to reset it.
+    #[cfg(target_arch = "aarch64")]
+    kvi: kvm_bindings::kvm_vcpu_init,
+
+    // Set when the guest boots again after it stops, in which case the vCPU waits to be reset
+    // instead of exiting.
+    restart_on_stop: bool,
 
     // The receiving end of events channel owned by the vcpu side.
     event_receiver: Receiver<VcpuEvent>,
@@ -725,6 +770,8 @@ impl Vcpu {
             io_bus,
             cpuid,
             msr_list,
+            boot_lapic: kvm_lapic_state::default(),
+            restart_on_stop: false,
             event_receiver,
             event_sender: Some(event_sender),
             response_receiver: Some(response_receiver),
@@ -758,6 +805,8 @@ impl Vcpu {
             mmio_bus: None,
             exit_evt,
             mpidr: 0,
+            kvi: kvm_bindings::kvm_vcpu_init::default(),
+            restart_on_stop: false,
             event_receiver,
             event_sender: Some(event_sender),
             response_receiver: Some(response_receiver),
@@ -781,6 +830,11 @@ impl Vcpu {
         self.mmio_bus = Some(mmio_bus);
     }
 
+    /// Makes the vcpu wait to be reset when the guest stops, instead of exiting.
+    pub fn set_restart_on_stop(&mut self, restart_on_stop: bool) {
+        self.restart_on_stop = restart_on_stop;
+    }
+
     #[cfg(target_arch = "x86_64")]
     /// Configures a x86_64 specific vcpu and should be called once per vcpu.
     ///
@@ -818,7 +872,18 @@ impl Vcpu {
         self.fd
             .set_cpuid2(&self.cpuid)
             .map_err(Error::VcpuSetCpuid)?;
+        self.boot_lapic = self.fd.get_lapic().map_err(Error::VcpuGetLapic)?;
+
+        self.setup_boot_state(guest_mem, kernel_start_addr)
+    }
 
+    #[cfg(target_arch = "x86_64")]
+    // Sets up the registers the vcpu boots the kernel with.
+    fn setup_boot_state(
+        &self,
+        guest_mem: &GuestMemoryMmap,
+        kernel_start_addr: GuestAddress,
+    ) -> Result<()> {
         arch::x86_64::msr::setup_msrs(&self.fd).map_err(Error::MSRSConfiguration)?;
         arch::x86_64::regs::setup_regs(&self.fd, kernel_start_addr.raw_value() as u64)
             .map_err(Error::REGSConfiguration)?;
@@ -860,10 +925,48 @@ impl Vcpu {
             .map_err(Error::REGSConfiguration)?;
 
         self.mpidr = arch::aarch64::regs::read_mpidr(&self.fd).map_err(Error::REGSConfiguration)?;
+        self.kvi = kvi;
 
         Ok(())
     }
 
+    /// Brings the vcpu back to the state it booted the kernel in, for a guest which restarts
+    /// with the kernel loaded at `kernel_start_addr`. The local APIC is reset as well, while the
+    /// cpuid set up when the vcpu was configured is kept.
+    #[cfg(target_arch = "x86_64")]
+    pub fn reset(
+        &self,
+        guest_mem: &GuestMemoryMmap,
+        kernel_start_addr: GuestAddress,
+    ) -> Result<()> {
+        // The boot vcpu runs the kernel, while the others wait for it to wake them up.
+        let mp_state = kvm_mp_state {
+            mp_state: if self.id == 0 {
+                kvm_bindings::KVM_MP_STATE_RUNNABLE
+            } else {
+                kvm_bindings::KVM_MP_STATE_UNINITIALIZED
+            },
+        };
+        self.fd
+            .set_mp_state(mp_state)
+            .map_err(Error::VcpuSetMpState)?;
+        self.fd
+            .set_lapic(&self.boot_lapic)
+            .map_err(Error::VcpuSetLapic)?;
+
+        self.setup_boot_state(guest_mem, kernel_start_addr)
+    }
+
+    /// Brings the vcpu back to the state it booted the kernel in, for a guest which restarts
+    /// with the kernel loaded at `kernel_load_addr`.
+    #[cfg(target_arch = "aarch64")]
+    pub fn reset(&self, guest_mem: &GuestMemoryMmap, kernel_load_addr: GuestAddress) -> Result<()> {
+        // Initializing the vcpu again resets its registers, and powers off the non-boot vcpus.
+        self.fd.vcpu_init(&self.kvi).map_err(Error::VcpuArmInit)?;
+        arch::aarch64::regs::setup_regs(&self.fd, self.id, kernel_load_addr.raw_value(), guest_mem)
+            .map_err(Error::REGSConfiguration)
+    }
+
     /// Moves the vcpu to its own thread and constructs a VcpuHandle.
     /// The handle can be used to control the remote vcpu.
     pub fn start_threaded(mut self, seccomp_filter: BpfProgram) -> Result<VcpuHandle> {
@@ -1055,6 +1158,11 @@ impl Vcpu {
                     info!("Received KVM_EXIT_SHUTDOWN signal");
                     Ok(VcpuEmulation::Stopped)
                 }
+                // The guest asked for a reset or a power off through PSCI.
+                VcpuExit::SystemEvent => {
+                    info!("Received KVM_EXIT_SYSTEM_EVENT signal");
+                    Ok(VcpuEmulation::Stopped)
+                }
                 // Documentation specifies that below kvm exits are considered
                 // errors.
                 VcpuExit::FailEntry => {
@@ -1132,6 +1240,12 @@ impl Vcpu {
                 // Moreover if we allow the vCPU0 thread to finish execution, this might generate a
                 // seccomp failure because musl calls `sigprocmask` as part of `pthread_exit`.
                 // So we pause vCPU0 and send a signal to the emulation thread to stop the VMM.
+                // When the guest is restarted instead, vCPU0 waits in the paused state to be
+                // reset.
+                Ok(VcpuEmulation::Stopped) if self.restart_on_stop => {
+                    self.notify_exit(FC_EXIT_CODE_OK);
+                    return StateMachine::next(Self::paused);
+                }
                 Ok(VcpuEmulation::Stopped) => return self.exit(FC_EXIT_CODE_OK),
                 // Emulation errors lead to vCPU exit.
                 Err(_) => return self.exit(FC_EXIT_CODE_GENERIC_ERROR),
@@ -1161,6 +1275,9 @@ impl Vcpu {
                     .send(VcpuResponse::Resumed)
                     .expect("failed to send resume status");
             }
+            Ok(VcpuEvent::UpdateMmioBus(mmio_bus)) => self.update_mmio_bus(*mmio_bus),
+            // A running vcpu cannot be reset, leave it be.
+            Ok(VcpuEvent::Reset(_, _)) => (),
             // Unhandled exit of the other end.
             Err(TryRecvError::Disconnected) => {
                 // Move to 'exited' state.
@@ -1185,6 +1302,21 @@ impl Vcpu {
                 // Move to 'running' state.
                 StateMachine::next(Self::running)
             }
+            Ok(VcpuEvent::UpdateMmioBus(mmio_bus)) => {
+                self.update_mmio_bus(*mmio_bus);
+                StateMachine::next(Self::paused)
+            }
+            Ok(VcpuEvent::Reset(guest_mem, kernel_start_addr)) => {
+                if let Err(e) = self.reset(&guest_mem, kernel_start_addr) {
+                    METRICS.vcpu.failures.inc();
+                    error!("Failed to reset vcpu {}: {}", self.id, e);
+                    return self.exit(FC_EXIT_CODE_GENERIC_ERROR);
+                }
+                self.response_sender
+                    .send(VcpuResponse::Reset)
+                    .expect("failed to send reset status");
+                StateMachine::next(Self::paused)
+            }
             // All other events have no effect on current 'paused' state.
             Ok(_) => StateMachine::next(Self::paused),
             // Unhandled exit of the other end.
@@ -1195,8 +1327,16 @@ impl Vcpu {
         }
     }
 
-    // Transition to the exited state
-    fn exit(&mut self, exit_code: u8) -> StateMachine<Self> {
+    // Replaces the MMIO bus seen by this vCPU, which is a snapshot of the device manager's bus.
+    fn update_mmio_bus(&mut self, mmio_bus: devices::Bus) {
+        self.set_mmio_bus(mmio_bus);
+        self.response_sender
+            .send(VcpuResponse::MmioBusUpdated)
+            .expect("failed to send mmio bus update status");
+    }
+
+    // Lets the VMM know this vcpu exited with `exit_code`.
+    fn notify_exit(&mut self, exit_code: u8) {
         self.response_sender
             .send(VcpuResponse::Exited(exit_code))
             .expect("failed to send Exited status");
@@ -1205,6 +1345,11 @@ impl Vcpu {
             METRICS.vcpu.failures.inc();
             error!("Failed signaling vcpu exit event: {}", e);
         }
+    }
+
+    // Transition to the exited state
+    fn exit(&mut self, exit_code: u8) -> StateMachine<Self> {
+        self.notify_exit(exit_code);
 
         // State machine reached its end.
         StateMachine::next(Self::exited)
@@ -1251,6 +1396,10 @@ pub enum VcpuEvent {
     Pause,
     /// Event that should resume the Vcpu.
     Resume,
+    /// Replace the MMIO bus of the Vcpu, after devices were plugged in or removed.
+    UpdateMmioBus(Box<devices::Bus>),
+    /// Bring the paused Vcpu back to its boot state, for the kernel loaded at the given address.
+    Reset(GuestMemoryMmap, GuestAddress),
     // Serialize and Deserialize to follow after we get the support from kvm-ioctls.
 }
 
@@ -1261,6 +1410,10 @@ pub enum VcpuResponse {
     Paused,
     /// Vcpu is resumed.
     Resumed,
+    /// Vcpu uses the new MMIO bus.
+    MmioBusUpdated,
+    /// Vcpu is back to its boot state.
+    Reset,
     /// Vcpu is stopped.
     Exited(u8),
 }
@@ -1318,6 +1471,8 @@ mod tests {
     use super::*;
 
     use utils::signal::validate_signal_num;
+    #[cfg(target_arch = "x86_64")]
+    use vm_memory::Bytes;
     use vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
 
     // Auxiliary function being used throughout the tests.
@@ -1398,7 +1553,7 @@ mod tests {
     #[test]
     fn test_setup_irqchip() {
         let kvm_context = KvmContext::new().unwrap();
-        let vm = Vm::new(kvm_context.fd()).expect("Cannot create new vm");
+        let mut vm = Vm::new(kvm_context.fd()).expect("Cannot create new vm");
 
         vm.setup_irqchip().expect("Cannot setup irqchip");
         // Trying to setup two irqchips will result in EEXIST error. At the moment
@@ -1712,6 +1867,89 @@ mod tests {
 
         // Queue a Resume event, expect a response.
         queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+
+        // Swap the MMIO bus while running, expect a response.
+        queue_event_expect_response(
+            &vcpu_handle,
+            VcpuEvent::UpdateMmioBus(Box::new(devices::Bus::new())),
+            VcpuResponse::MmioBusUpdated,
+        );
+
+        // Swap the MMIO bus while paused, expect a response.
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Pause, VcpuResponse::Paused);
+        queue_event_expect_response(
+            &vcpu_handle,
+            VcpuEvent::UpdateMmioBus(Box::new(devices::Bus::new())),
+            VcpuResponse::MmioBusUpdated,
+        );
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+
+        // Reset while running, expect no response.
+        queue_event_expect_timeout(&vcpu_handle, VcpuEvent::Reset(vm_mem.clone(), entry_addr));
+
+        // Reset while paused, expect a response.
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Pause, VcpuResponse::Paused);
+        queue_event_expect_response(
+            &vcpu_handle,
+            VcpuEvent::Reset(vm_mem.clone(), entry_addr),
+            VcpuResponse::Reset,
+        );
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_vcpu_stop() {
+        Vcpu::register_kick_signal_handler();
+        for &restart_on_stop in [false, true].iter() {
+            let (_vm, mut vcpu, vm_mem) = setup_vcpu(0x10_0000);
+            let vcpu_exit_evt = vcpu.exit_evt.try_clone().unwrap();
+
+            // The guest runs into an undefined instruction. The boot IDT has no handler for it,
+            // so the vcpu triple faults and shuts down.
+            let entry_addr = GuestAddress(0x1_0000);
+            vm_mem.write_slice(&[0x0f, 0x0b], entry_addr).unwrap();
+            let vcpu_config = VcpuConfig {
+                vcpu_count: 1,
+                ht_enabled: false,
+                cpu_template: None,
+            };
+            vcpu.configure_x86_64(&vm_mem, entry_addr, &vcpu_config)
+                .expect("failed to configure vcpu");
+            // The test vcpu is not the boot one, which would wait for the boot one to wake it up.
+            vcpu.fd
+                .set_mp_state(kvm_mp_state {
+                    mp_state: kvm_bindings::KVM_MP_STATE_RUNNABLE,
+                })
+                .unwrap();
+            vcpu.set_restart_on_stop(restart_on_stop);
+
+            let seccomp_filter = seccomp::SeccompFilter::empty().try_into().unwrap();
+            let vcpu_handle = vcpu
+                .start_threaded(seccomp_filter)
+                .expect("failed to start vcpu");
+            queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+
+            // The VMM is told the vcpu stopped either way.
+            assert_eq!(
+                vcpu_handle
+                    .response_receiver()
+                    .recv_timeout(Duration::from_millis(100))
+                    .unwrap(),
+                VcpuResponse::Exited(FC_EXIT_CODE_OK)
+            );
+
+            let reset_evt = VcpuEvent::Reset(vm_mem.clone(), entry_addr);
+            if restart_on_stop {
+                // The vcpu is paused, waiting to be reset.
+                queue_event_expect_response(&vcpu_handle, reset_evt, VcpuResponse::Reset);
+            } else {
+                // The vcpu exited.
+                queue_event_expect_timeout(&vcpu_handle, reset_evt);
+            }
+            // The vcpu signaled the exit event before handling the reset.
+            assert_eq!(vcpu_exit_evt.read().unwrap(), 1);
+        }
     }
 
     #[test]
@@ -1730,18 +1968,48 @@ mod tests {
         let (vm, _, _mem) = setup_vcpu(0x1000);
         let vm_state = vm.save_state().unwrap();
         assert_eq!(
-            vm_state.pitstate.flags | KVM_PIT_SPEAKER_DUMMY,
+            vm_state.irqchip.pitstate.flags | KVM_PIT_SPEAKER_DUMMY,
             KVM_PIT_SPEAKER_DUMMY
         );
         assert_eq!(vm_state.clock.flags & KVM_CLOCK_TSC_STABLE, 0);
-        assert_eq!(vm_state.pic_master.chip_id, KVM_IRQCHIP_PIC_MASTER);
-        assert_eq!(vm_state.pic_slave.chip_id, KVM_IRQCHIP_PIC_SLAVE);
-        assert_eq!(vm_state.ioapic.chip_id, KVM_IRQCHIP_IOAPIC);
+        assert_eq!(vm_state.irqchip.pic_master.chip_id, KVM_IRQCHIP_PIC_MASTER);
+        assert_eq!(vm_state.irqchip.pic_slave.chip_id, KVM_IRQCHIP_PIC_SLAVE);
+        assert_eq!(vm_state.irqchip.ioapic.chip_id, KVM_IRQCHIP_IOAPIC);
 
         let (vm, _, _mem) = setup_vcpu(0x1000);
         assert!(vm.restore_state(&vm_state).is_ok());
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_reset_irqchip() {
+        let kvm_fd = Kvm::new().unwrap();
+        let vm = Vm::new(&kvm_fd).expect("new vm failed");
+        // There is nothing to reset without irqchip.
+        assert!(vm.reset_irqchip().is_ok());
+
+        let (vm, _, _mem) = setup_vcpu(0x1000);
+        let boot_state = vm.save_irqchip_state().unwrap();
+        // The guest programs the PIT and masks interrupts of the PIC.
+        let mut state = vm.save_irqchip_state().unwrap();
+        state.pitstate.channels[0].count = 0x1234;
+        unsafe { state.pic_master.chip.pic.imr = 0xab };
+        vm.restore_irqchip_state(&state).unwrap();
+        let state = vm.save_irqchip_state().unwrap();
+        assert_eq!(state.pitstate.channels[0].count, 0x1234);
+        assert_eq!(unsafe { state.pic_master.chip.pic.imr }, 0xab);
+
+        vm.reset_irqchip().unwrap();
+        let state = vm.save_irqchip_state().unwrap();
+        assert_eq!(
+            state.pitstate.channels[0].count,
+            boot_state.pitstate.channels[0].count
+        );
+        assert_eq!(unsafe { state.pic_master.chip.pic.imr }, unsafe {
+            boot_state.pic_master.chip.pic.imr
+        });
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_vcpu_save_restore_state() {